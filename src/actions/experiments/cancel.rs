@@ -0,0 +1,129 @@
+use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
+use crate::experiments::{Experiment, Status};
+use crate::prelude::*;
+
+pub struct CancelExperiment {
+    pub name: String,
+}
+
+impl Action for CancelExperiment {
+    fn apply(self, ctx: &ActionsCtx) -> Fallible<()> {
+        let Some(mut ex) = Experiment::get(ctx.db, &self.name)? else {
+            return Err(ExperimentError::NotFound(self.name).into());
+        };
+        if !ex.status.can_transition_to(Status::Cancelled) || ex.status == Status::Cancelled {
+            return Err(ExperimentError::CanOnlyCancelActiveExperiments.into());
+        }
+
+        // The results already stored are kept for the partial report
+        ex.cancel(ctx.db)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CancelExperiment;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment, ExperimentError};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{Assignee, Experiment, Status};
+    use crate::results::{
+        DatabaseDB, EncodingType, ProgressData, ReadResults, TaskResult, TestResult,
+    };
+    use crate::server::agents::Agents;
+    use crate::server::tokens::Tokens;
+
+    #[test]
+    fn test_cancel_missing_experiment() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        let err = CancelExperiment {
+            name: "dummy".to_string(),
+        }
+        .apply(&ctx)
+        .unwrap_err();
+
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::NotFound("dummy".into()))
+        );
+    }
+
+    #[test]
+    fn test_cancel_experiment() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token".into(), "agent".into());
+        let _ = Agents::new(db.clone(), &tokens).unwrap();
+        let agent = Assignee::Agent("agent".to_string());
+
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let (_, ex) = Experiment::next(&db, &agent).unwrap().unwrap();
+        assert_eq!(ex.status, Status::Running);
+
+        // Complete one of the crates before cancelling
+        let krate = ex.get_uncompleted_crates(&db, None).unwrap()[0].clone();
+        let results = DatabaseDB::new(&db);
+        for toolchain in &ex.toolchains {
+            results
+                .store(
+                    &ex,
+                    &ProgressData {
+                        result: TaskResult {
+                            krate: krate.clone(),
+                            toolchain: toolchain.clone(),
+                            result: TestResult::TestPass,
+                            log: String::new(),
+                            duration_ms: None,
+                        },
+                        version: None,
+                    },
+                    EncodingType::Plain,
+                )
+                .unwrap();
+        }
+
+        CancelExperiment {
+            name: "dummy".to_string(),
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        // No more work is handed out, and the report is generated right away
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Cancelled);
+        assert!(ex.cancelled_at.is_some());
+        assert!(ex.get_uncompleted_crates(&db, None).unwrap().is_empty());
+        assert!(Experiment::next(&db, &agent).unwrap().is_none());
+        assert_eq!(
+            Experiment::ready_for_report(&db).unwrap().unwrap().name,
+            "dummy"
+        );
+
+        // The completed results are still there for the partial report
+        assert_eq!(ex.get_completed_crates(&db).unwrap(), vec![krate.clone()]);
+        for toolchain in &ex.toolchains {
+            assert_eq!(
+                results.load_test_result(&ex, toolchain, &krate).unwrap(),
+                Some(TestResult::TestPass)
+            );
+        }
+
+        // Only active experiments can be cancelled
+        let err = CancelExperiment {
+            name: "dummy".to_string(),
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::CanOnlyCancelActiveExperiments)
+        );
+    }
+}
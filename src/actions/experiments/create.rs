@@ -1,9 +1,45 @@
 use crate::actions::{experiments::ExperimentError, Action, ActionsCtx};
 use crate::db::QueryUtils;
-use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status};
+use crate::experiments::{
+    is_valid_label_key, Assignee, CapLints, CompletionWebhook, CrateSelect, Experiment,
+    GitHubIssue, Mode, Status,
+};
 use crate::prelude::*;
 use crate::toolchain::Toolchain;
 use chrono::Utc;
+use std::collections::BTreeMap;
+
+/// Maximum number of labels attached to an experiment.
+const MAX_LABELS: usize = 16;
+const MAX_LABEL_KEY_LEN: usize = 64;
+const MAX_LABEL_VALUE_LEN: usize = 256;
+
+/// Keep the labels small, and their keys usable in the label filter of the server.
+fn validate_labels(labels: &BTreeMap<String, String>) -> Result<(), ExperimentError> {
+    let invalid = |message: String| Err(ExperimentError::InvalidLabels(message));
+
+    if labels.len() > MAX_LABELS {
+        return invalid(format!("at most {MAX_LABELS} labels are allowed"));
+    }
+    for (key, value) in labels {
+        if key.is_empty() || key.len() > MAX_LABEL_KEY_LEN {
+            return invalid(format!(
+                "key `{key}` must be between 1 and {MAX_LABEL_KEY_LEN} bytes long"
+            ));
+        }
+        if !is_valid_label_key(key) {
+            return invalid(format!(
+                "key `{key}` can only contain ASCII letters, digits, `-` and `_`"
+            ));
+        }
+        if value.len() > MAX_LABEL_VALUE_LEN {
+            return invalid(format!(
+                "value of `{key}` must be at most {MAX_LABEL_VALUE_LEN} bytes long"
+            ));
+        }
+    }
+    Ok(())
+}
 
 pub struct CreateExperiment {
     pub name: String,
@@ -16,6 +52,15 @@ pub struct CreateExperiment {
     pub ignore_blacklist: bool,
     pub assign: Option<Assignee>,
     pub requirement: Option<String>,
+    pub max_regressions: Option<usize>,
+    pub webhook: Option<CompletionWebhook>,
+    pub skip_dev_deps: bool,
+    /// Toolchains compared with the first one in addition to the second one.
+    pub extra_toolchains: Vec<Toolchain>,
+    /// Free-form metadata, such as the PR number or who requested the experiment.
+    pub labels: BTreeMap<String, String>,
+    /// Run the regressed crates again on another agent before reporting them as regressed.
+    pub confirm_regressions: bool,
 }
 
 impl CreateExperiment {
@@ -34,6 +79,12 @@ impl CreateExperiment {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            max_regressions: None,
+            webhook: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         }
     }
 }
@@ -46,19 +97,41 @@ impl Action for CreateExperiment {
         }
 
         // Ensure no experiment with duplicate toolchains is created
-        if self.toolchains[0] == self.toolchains[1] {
-            return Err(ExperimentError::DuplicateToolchains.into());
+        let all_toolchains = self
+            .toolchains
+            .iter()
+            .chain(&self.extra_toolchains)
+            .collect::<Vec<_>>();
+        for (i, tc) in all_toolchains.iter().enumerate() {
+            if all_toolchains[..i].contains(tc) {
+                return Err(ExperimentError::DuplicateToolchains.into());
+            }
         }
 
-        let crates = crate::crates::lists::get_crates(&self.crates, ctx.db, ctx.config)?;
+        validate_labels(&self.labels)?;
+
+        // Record the seed of random samples, so they can be reproduced later
+        let select = self.crates.seeded();
+        let crates = crate::crates::lists::get_crates(&select, ctx.db, ctx.config)?;
 
+        if self.skip_dev_deps && self.mode.runs_tests() {
+            warn!(
+                "tests need dev-dependencies, they won't be skipped in the {} mode",
+                self.mode
+            );
+        }
+
+        let extra_toolchains = serde_json::to_string(&self.extra_toolchains)?;
+        let labels = serde_json::to_string(&self.labels)?;
         ctx.db.transaction(|transaction| {
             transaction.execute(
                 "INSERT INTO experiments \
                  (name, mode, cap_lints, toolchain_start, toolchain_end, priority, created_at, \
                  status, github_issue, github_issue_url, github_issue_number, ignore_blacklist, \
-                 assigned_to, requirement) \
-                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14);",
+                 assigned_to, requirement, max_regressions, webhook_url, webhook_secret, \
+                 sample_seed, skip_dev_deps, extra_toolchains, labels, confirm_regressions) \
+                 VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, ?10, ?11, ?12, ?13, ?14, ?15, ?16, \
+                 ?17, ?18, ?19, ?20, ?21, ?22);",
                 &[
                     &self.name,
                     &self.mode.to_str(),
@@ -74,6 +147,14 @@ impl Action for CreateExperiment {
                     &self.ignore_blacklist,
                     &self.assign.map(|a| a.to_string()),
                     &self.requirement,
+                    &self.max_regressions.map(|max| max as i64),
+                    &self.webhook.as_ref().map(|webhook| webhook.url.as_str()),
+                    &self.webhook.as_ref().and_then(|webhook| webhook.secret.as_deref()),
+                    &select.sample_seed().map(|seed| seed as i64),
+                    &self.skip_dev_deps,
+                    &extra_toolchains,
+                    &labels,
+                    &self.confirm_regressions,
                 ],
             )?;
 
@@ -102,7 +183,8 @@ mod tests {
     use crate::experiments::{
         Assignee, CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status,
     };
-    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_creation() {
@@ -130,6 +212,12 @@ mod tests {
             ignore_blacklist: true,
             assign: None,
             requirement: Some("linux".to_string()),
+            max_regressions: Some(100),
+            webhook: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -157,6 +245,43 @@ mod tests {
         assert!(ex.assigned_to.is_none());
         assert!(ex.ignore_blacklist);
         assert_eq!(ex.requirement, Some("linux".to_string()));
+        assert_eq!(ex.max_regressions, Some(100));
+        assert_eq!(ex.capped_at, None);
+        assert_eq!(ex.sample_seed, None);
+    }
+
+    #[test]
+    fn test_creation_records_sample_seed() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment {
+            crates: CrateSelect::Sample {
+                count: 10,
+                seed: Some(1234),
+            },
+            ..CreateExperiment::dummy("seeded")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "seeded").unwrap().unwrap();
+        assert_eq!(ex.sample_seed, Some(1234));
+
+        // A seed is picked when none is provided
+        CreateExperiment {
+            crates: CrateSelect::Sample {
+                count: 10,
+                seed: None,
+            },
+            ..CreateExperiment::dummy("unseeded")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "unseeded").unwrap().unwrap();
+        assert!(ex.sample_seed.is_some());
     }
 
     #[test]
@@ -210,6 +335,9 @@ mod tests {
                 skip_tests: false,
                 quiet: false,
                 broken: false,
+                miri: false,
+                memory_limit: None,
+                requires: Vec::new(),
             },
         );
         let ctx = ActionsCtx::new(&db, &config);
@@ -253,6 +381,12 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            max_regressions: None,
+            webhook: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         }
         .apply(&ctx)
         .unwrap_err();
@@ -263,6 +397,95 @@ mod tests {
         );
     }
 
+    #[test]
+    fn test_extra_toolchains() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let nightly: Toolchain = "nightly".parse().unwrap();
+        CreateExperiment {
+            extra_toolchains: vec![nightly.clone()],
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(ex.extra_toolchains, vec![nightly.clone()]);
+        assert_eq!(
+            ex.all_toolchains().collect::<Vec<_>>(),
+            vec![&*MAIN_TOOLCHAIN, &*TEST_TOOLCHAIN, &nightly]
+        );
+
+        // Extra toolchains can't repeat the other ones either
+        let err = CreateExperiment {
+            extra_toolchains: vec![TEST_TOOLCHAIN.clone()],
+            ..CreateExperiment::dummy("bar")
+        }
+        .apply(&ctx)
+        .unwrap_err();
+        assert_eq!(
+            err.downcast_ref(),
+            Some(&ExperimentError::DuplicateToolchains)
+        );
+    }
+
+    #[test]
+    fn test_labels() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let labels = [
+            ("pr", "12345"),
+            ("requester", "ferris"),
+            ("note", "checking the \"new\" solver, 2nd try"),
+        ]
+        .into_iter()
+        .map(|(key, value)| (key.to_string(), value.to_string()))
+        .collect::<BTreeMap<_, _>>();
+        CreateExperiment {
+            labels: labels.clone(),
+            ..CreateExperiment::dummy("foo")
+        }
+        .apply(&ctx)
+        .unwrap();
+
+        let ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        assert_eq!(ex.labels, labels);
+
+        // Experiments without labels load an empty map
+        CreateExperiment::dummy("bar").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "bar").unwrap().unwrap();
+        assert!(ex.labels.is_empty());
+
+        let invalid = |labels: BTreeMap<String, String>| {
+            let err = CreateExperiment {
+                labels,
+                ..CreateExperiment::dummy("baz")
+            }
+            .apply(&ctx)
+            .unwrap_err();
+            assert!(matches!(
+                err.downcast_ref(),
+                Some(&ExperimentError::InvalidLabels(_))
+            ));
+        };
+        invalid([("with space".to_string(), "value".to_string())].into());
+        invalid([("key".to_string(), "x".repeat(1000))].into());
+        invalid(
+            (0..100)
+                .map(|i| (format!("key{i}"), "value".to_string()))
+                .collect(),
+        );
+        assert!(!Experiment::exists(&db, "baz").unwrap());
+    }
+
     #[test]
     fn test_duplicate_name() {
         let db = Database::temp().unwrap();
@@ -283,6 +506,12 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            max_regressions: None,
+            webhook: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -299,6 +528,12 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            max_regressions: None,
+            webhook: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         }
         .apply(&ctx)
         .unwrap_err();
@@ -0,0 +1,319 @@
+use crate::actions::CreateExperiment;
+use crate::experiments::{Assignee, CapLints, CompletionWebhook, DeferredCrateSelect, Mode};
+use crate::prelude::*;
+use crate::toolchain::Toolchain;
+use std::collections::BTreeMap;
+
+#[derive(Debug, thiserror::Error)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub enum DefinitionError {
+    #[error("invalid experiment definition: {0}")]
+    Malformed(String),
+    #[error("invalid experiment definition: `{field}` {message}")]
+    InvalidField {
+        field: &'static str,
+        message: String,
+    },
+}
+
+fn invalid(field: &'static str, message: impl Into<String>) -> DefinitionError {
+    DefinitionError::InvalidField {
+        field,
+        message: message.into(),
+    }
+}
+
+/// Experiment definition submitted as a single JSON document, mirroring the flags of
+/// `define-ex`.
+#[derive(Debug, Deserialize)]
+#[serde(deny_unknown_fields)]
+struct ExperimentDefinition {
+    name: String,
+    toolchains: Vec<String>,
+    mode: Mode,
+    #[serde(default = "default_crates")]
+    crates: String,
+    #[serde(default = "default_cap_lints")]
+    cap_lints: CapLints,
+    #[serde(default)]
+    priority: i32,
+    #[serde(default)]
+    ignore_blacklist: bool,
+    #[serde(default)]
+    assign: Option<String>,
+    #[serde(default)]
+    requirement: Option<String>,
+    #[serde(default)]
+    max_regressions: Option<usize>,
+    #[serde(default)]
+    webhook_url: Option<String>,
+    #[serde(default)]
+    webhook_secret: Option<String>,
+    #[serde(default)]
+    skip_dev_deps: bool,
+    #[serde(default)]
+    labels: BTreeMap<String, String>,
+    #[serde(default)]
+    confirm_regressions: bool,
+    /// Applied to all the toolchains.
+    #[serde(default)]
+    target: Option<String>,
+    /// Applied to all the toolchains.
+    #[serde(default)]
+    rustflags: Option<String>,
+}
+
+fn default_crates() -> String {
+    "demo".into()
+}
+
+fn default_cap_lints() -> CapLints {
+    CapLints::Forbid
+}
+
+impl ExperimentDefinition {
+    fn toolchains(&self) -> Result<([Toolchain; 2], Vec<Toolchain>), DefinitionError> {
+        let mut toolchains = self
+            .toolchains
+            .iter()
+            .map(|tc| {
+                tc.parse::<Toolchain>()
+                    .map_err(|err| invalid("toolchains", format!("contains `{tc}`: {err}")))
+            })
+            .collect::<Result<Vec<_>, _>>()?;
+
+        for tc in &mut toolchains {
+            if let Some(target) = &self.target {
+                if tc.target.is_some() {
+                    return Err(invalid(
+                        "target",
+                        format!("conflicts with the target of toolchain `{tc}`"),
+                    ));
+                }
+                tc.target = Some(target.clone());
+            }
+            if let Some(rustflags) = &self.rustflags {
+                if tc.rustflags.is_some() {
+                    return Err(invalid(
+                        "rustflags",
+                        format!("conflicts with the rustflags of toolchain `{tc}`"),
+                    ));
+                }
+                tc.rustflags = Some(rustflags.clone());
+            }
+        }
+
+        if toolchains.is_empty() {
+            return Err(invalid("toolchains", "must not be empty"));
+        } else if toolchains.len() < 2 {
+            return Err(invalid(
+                "toolchains",
+                format!(
+                    "must contain at least 2 toolchains, found {}",
+                    toolchains.len()
+                ),
+            ));
+        }
+
+        // The toolchains after the first two are compared with the first one too
+        let extra = toolchains.split_off(2);
+        Ok((toolchains.try_into().unwrap(), extra))
+    }
+}
+
+impl CreateExperiment {
+    /// Load an experiment from its JSON definition. Only `name`, `toolchains` and `mode` are
+    /// required, the other fields default to the same values as the `define-ex` flags.
+    pub fn from_json(json: &str) -> Fallible<CreateExperiment> {
+        let def: ExperimentDefinition = serde_json::from_str(json)
+            .map_err(|err| DefinitionError::Malformed(err.to_string()))?;
+
+        if def.name.trim().is_empty() {
+            return Err(invalid("name", "must not be empty").into());
+        }
+        let (toolchains, extra_toolchains) = def.toolchains()?;
+        if def.max_regressions == Some(0) {
+            return Err(invalid("max_regressions", "must be at least 1").into());
+        }
+        if def.webhook_secret.is_some() && def.webhook_url.is_none() {
+            return Err(invalid("webhook_secret", "requires `webhook_url`").into());
+        }
+        let crates = def
+            .crates
+            .parse::<DeferredCrateSelect>()
+            .map_err(|err| invalid("crates", err.to_string()))?
+            .resolve()?;
+        let assign = def
+            .assign
+            .as_deref()
+            .map(|assign| assign.parse::<Assignee>())
+            .transpose()
+            .map_err(|err| invalid("assign", err.to_string()))?;
+
+        Ok(CreateExperiment {
+            name: def.name,
+            toolchains,
+            mode: def.mode,
+            crates,
+            cap_lints: def.cap_lints,
+            priority: def.priority,
+            github_issue: None,
+            ignore_blacklist: def.ignore_blacklist,
+            assign,
+            requirement: def.requirement,
+            max_regressions: def.max_regressions,
+            webhook: def.webhook_url.map(|url| CompletionWebhook {
+                url,
+                secret: def.webhook_secret,
+            }),
+            skip_dev_deps: def.skip_dev_deps,
+            extra_toolchains,
+            labels: def.labels,
+            confirm_regressions: def.confirm_regressions,
+        })
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DefinitionError;
+    use crate::actions::CreateExperiment;
+    use crate::experiments::{Assignee, CapLints, CompletionWebhook, CrateSelect, Mode};
+
+    fn error(json: &str) -> DefinitionError {
+        CreateExperiment::from_json(json)
+            .err()
+            .expect("the definition should be invalid")
+            .downcast()
+            .unwrap()
+    }
+
+    #[test]
+    fn test_valid_definition() {
+        let ex = CreateExperiment::from_json(
+            r#"{
+                "name": "pr-12345",
+                "toolchains": ["stable", "beta"],
+                "mode": "check-only",
+                "crates": "top-100",
+                "cap_lints": "warn",
+                "priority": 5,
+                "assign": "distributed",
+                "max_regressions": 10,
+                "webhook_url": "https://hooks.example.com/crater",
+                "webhook_secret": "hunter2",
+                "skip_dev_deps": true,
+                "labels": {"pr": "12345"},
+                "target": "x86_64-unknown-linux-gnu",
+                "rustflags": "-Zverbose"
+            }"#,
+        )
+        .unwrap();
+
+        assert_eq!(ex.name, "pr-12345");
+        assert_eq!(ex.mode, Mode::CheckOnly);
+        assert_eq!(ex.crates, CrateSelect::Top(100));
+        assert_eq!(ex.cap_lints, CapLints::Warn);
+        assert_eq!(ex.priority, 5);
+        assert_eq!(ex.assign, Some(Assignee::Distributed));
+        assert_eq!(ex.max_regressions, Some(10));
+        assert_eq!(
+            ex.webhook,
+            Some(CompletionWebhook {
+                url: "https://hooks.example.com/crater".into(),
+                secret: Some("hunter2".into()),
+            })
+        );
+        assert!(ex.skip_dev_deps);
+        assert_eq!(ex.labels["pr"], "12345");
+        for tc in &ex.toolchains {
+            assert_eq!(tc.target.as_deref(), Some("x86_64-unknown-linux-gnu"));
+            assert_eq!(tc.rustflags.as_deref(), Some("-Zverbose"));
+        }
+        assert_eq!(
+            ex.toolchains[0].to_string(),
+            "stable+target=x86_64-unknown-linux-gnu+rustflags=-Zverbose"
+        );
+
+        // Optional fields fall back to the defaults of `define-ex`
+        let ex = CreateExperiment::from_json(
+            r#"{"name": "minimal", "toolchains": ["stable", "beta"], "mode": "build-and-test"}"#,
+        )
+        .unwrap();
+        assert_eq!(ex.crates, CrateSelect::Demo);
+        assert_eq!(ex.cap_lints, CapLints::Forbid);
+        assert_eq!(ex.assign, None);
+        assert!(ex.extra_toolchains.is_empty());
+
+        // Toolchains after the first two are compared with the first one as well
+        let ex = CreateExperiment::from_json(
+            r#"{"name": "ex", "toolchains": ["stable", "beta", "nightly"], "mode": "check-only"}"#,
+        )
+        .unwrap();
+        assert_eq!(ex.toolchains[1].to_string(), "beta");
+        assert_eq!(ex.extra_toolchains.len(), 1);
+        assert_eq!(ex.extra_toolchains[0].to_string(), "nightly");
+    }
+
+    #[test]
+    fn test_invalid_definitions() {
+        let missing_mode = error(r#"{"name": "ex", "toolchains": ["stable", "beta"]}"#);
+        assert!(missing_mode.to_string().contains("missing field `mode`"));
+
+        let unknown = error(
+            r#"{"name": "ex", "toolchains": ["stable", "beta"], "mode": "check-only", "foo": 1}"#,
+        );
+        assert!(unknown.to_string().contains("unknown field `foo`"));
+
+        let bad_mode = error(r#"{"name": "ex", "toolchains": ["stable", "beta"], "mode": "x"}"#);
+        assert!(matches!(bad_mode, DefinitionError::Malformed(_)));
+
+        let field = |err| match err {
+            DefinitionError::InvalidField { field, .. } => field,
+            other => panic!("unexpected error: {other}"),
+        };
+        assert_eq!(
+            field(error(
+                r#"{"name": "ex", "toolchains": [], "mode": "check-only"}"#
+            )),
+            "toolchains"
+        );
+        assert_eq!(
+            field(error(
+                r#"{"name": "ex", "toolchains": ["stable"], "mode": "check-only"}"#
+            )),
+            "toolchains"
+        );
+        assert_eq!(
+            field(error(
+                r#"{"name": "", "toolchains": ["stable", "beta"], "mode": "check-only"}"#
+            )),
+            "name"
+        );
+        assert_eq!(
+            field(error(
+                r#"{"name": "ex", "toolchains": ["stable", "beta"], "mode": "check-only",
+                    "crates": "top-x"}"#
+            )),
+            "crates"
+        );
+        assert_eq!(
+            field(error(
+                r#"{"name": "ex", "toolchains": ["stable+target=foo", "beta"],
+                    "mode": "check-only", "target": "bar"}"#
+            )),
+            "target"
+        );
+        assert_eq!(
+            field(error(
+                r#"{"name": "ex", "toolchains": ["stable", "beta"], "mode": "check-only",
+                    "webhook_secret": "hunter2"}"#
+            )),
+            "webhook_secret"
+        );
+        assert_eq!(
+            error(r#"{"name": "ex", "toolchains": [], "mode": "check-only"}"#).to_string(),
+            "invalid experiment definition: `toolchains` must not be empty"
+        );
+    }
+}
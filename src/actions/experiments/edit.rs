@@ -52,7 +52,12 @@ impl Action for EditExperiment {
                     ex.toolchains[i] = tc;
 
                     // Ensure no duplicate toolchain is inserted
-                    if ex.toolchains[0] == ex.toolchains[1] {
+                    if ex
+                        .all_toolchains()
+                        .filter(|other| **other == ex.toolchains[i])
+                        .count()
+                        > 1
+                    {
                         return Err(ExperimentError::DuplicateToolchains.into());
                     }
 
@@ -78,6 +83,15 @@ impl Action for EditExperiment {
             // Try to update the list of crates
             // This is also done if ignore_blacklist is changed to recalculate the skipped crates
             let new_crates = if let Some(crates) = self.crates {
+                let crates = crates.seeded();
+                let seed = crates.sample_seed();
+                let changes = t.execute(
+                    "UPDATE experiments SET sample_seed = ?1 WHERE name = ?2;",
+                    &[&seed.map(|seed| seed as i64), &self.name],
+                )?;
+                assert_eq!(changes, 1);
+                ex.sample_seed = seed;
+
                 Some(crate::crates::lists::get_crates(
                     &crates, ctx.db, ctx.config,
                 )?)
@@ -172,6 +186,7 @@ mod tests {
     use crate::db::{Database, QueryUtils};
     use crate::experiments::{Assignee, CapLints, CrateSelect, Experiment, Mode, Status};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_edit_with_no_changes() {
@@ -205,6 +220,12 @@ mod tests {
             ignore_blacklist: false,
             assign: None,
             requirement: None,
+            max_regressions: None,
+            webhook: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         }
         .apply(&ctx)
         .unwrap();
@@ -276,6 +297,9 @@ mod tests {
                 skip_tests: false,
                 quiet: false,
                 broken: false,
+                miri: false,
+                memory_limit: None,
+                requires: Vec::new(),
             },
         );
         let ctx = ActionsCtx::new(&db, &config);
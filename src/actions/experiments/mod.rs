@@ -1,8 +1,12 @@
+mod cancel;
 mod create;
+mod definition;
 mod delete;
 mod edit;
 
+pub use self::cancel::CancelExperiment;
 pub use self::create::CreateExperiment;
+pub use self::definition::DefinitionError;
 pub use self::delete::DeleteExperiment;
 pub use self::edit::EditExperiment;
 
@@ -17,4 +21,8 @@ pub enum ExperimentError {
     DuplicateToolchains,
     #[error("it's only possible to edit queued experiments")]
     CanOnlyEditQueuedExperiments,
+    #[error("it's only possible to cancel queued or running experiments")]
+    CanOnlyCancelActiveExperiments,
+    #[error("invalid labels: {0}")]
+    InvalidLabels(String),
 }
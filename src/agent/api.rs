@@ -1,3 +1,4 @@
+use std::sync::Mutex;
 use std::time::Duration;
 
 use crate::agent::Capabilities;
@@ -69,6 +70,8 @@ pub struct AgentApi {
     url: String,
     token: String,
     random_id: String,
+    // Capabilities sent when registering, to register again after the server restarts
+    capabilities: Mutex<Option<Capabilities>>,
 }
 
 impl AgentApi {
@@ -77,6 +80,7 @@ impl AgentApi {
             url: url.to_string(),
             token: token.to_string(),
             random_id: format!("{:X}{:X}", rand::random::<u64>(), rand::random::<u64>()),
+            capabilities: Mutex::new(None),
         }
     }
 
@@ -92,14 +96,22 @@ impl AgentApi {
 
     fn retry<T, F: Fn(&Self) -> Fallible<T>>(&self, f: F) -> Fallible<T> {
         let mut retry_interval = 16u64;
+        let mut lost_connection = false;
         loop {
             match f(self) {
-                Ok(res) => return Ok(res),
+                Ok(res) => {
+                    if lost_connection {
+                        info!("reconnected to the server");
+                        self.register_again();
+                    }
+                    return Ok(res);
+                }
                 Err(err) => {
                     let retry = if let Some(AgentApiError::ServerUnavailable) = err.downcast_ref() {
                         true
                     } else if let Some(err) = err.downcast_ref::<::reqwest::Error>() {
-                        err.is_timeout() || err.is_connect()
+                        // Connections reset while the server restarts fail the request
+                        err.is_timeout() || err.is_connect() || err.is_request()
                     } else {
                         // We retry these errors. Ideally it's something the
                         // server would handle, but that's (unfortunately) hard
@@ -108,6 +120,7 @@ impl AgentApi {
                     };
 
                     if retry {
+                        lost_connection = true;
                         let sleep_for = Duration::from_millis(
                             rand::thread_rng().gen_range(500..(retry_interval * 1000)),
                         );
@@ -130,13 +143,28 @@ impl AgentApi {
         }
     }
 
+    fn register(&self, caps: &Capabilities) -> Fallible<AgentConfig> {
+        self.build_request(Method::POST, "config")
+            .json(&json!(caps))
+            .send()?
+            .to_api_response()
+    }
+
+    /// Register the agent again once the connection to the server is restored, as the server
+    /// might have restarted in the meantime.
+    fn register_again(&self) {
+        let caps = self.capabilities.lock().unwrap().clone();
+        if let Some(caps) = caps {
+            if let Err(err) = self.register(&caps) {
+                warn!("failed to register the agent again: {}", err);
+            }
+        }
+    }
+
     pub fn config(&self, caps: &Capabilities) -> Fallible<AgentConfig> {
-        self.retry(|this| {
-            this.build_request(Method::POST, "config")
-                .json(&json!(caps))
-                .send()?
-                .to_api_response()
-        })
+        let config = self.retry(|this| this.register(caps))?;
+        *self.capabilities.lock().unwrap() = Some(caps.clone());
+        Ok(config)
     }
 
     pub fn next_experiment(&self) -> Fallible<Experiment> {
@@ -172,23 +200,25 @@ impl AgentApi {
 
     pub fn record_progress(
         &self,
-        ex: &Experiment,
+        ex: &str,
         krate: &Crate,
         toolchain: &Toolchain,
         log: &[u8],
         result: &TestResult,
         version: Option<(&Crate, &Crate)>,
+        duration_ms: Option<u64>,
     ) -> Fallible<()> {
         self.retry(|this| {
             let _: bool = this
                 .build_request(Method::POST, "record-progress")
                 .json(&json!({
-                    "experiment-name": ex.name,
+                    "experiment-name": ex,
                     "result": {
                         "crate": krate,
                         "toolchain": toolchain,
                         "result": result,
                         "log": base64::engine::general_purpose::STANDARD.encode(log),
+                        "duration_ms": duration_ms,
                     },
                     "version": version
                 }))
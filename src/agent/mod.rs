@@ -1,5 +1,6 @@
 mod api;
 mod results;
+mod spool;
 
 use crate::agent::api::AgentApi;
 use crate::agent::results::ResultsUploader;
@@ -10,6 +11,7 @@ use crate::experiments::Experiment;
 use crate::prelude::*;
 use crate::utils;
 use crate::utils::disk_usage::DiskUsage;
+use crate::utils::size::Size;
 use failure::Error;
 use rustwide::Workspace;
 use std::collections::BTreeSet;
@@ -21,7 +23,7 @@ use std::time::{Duration, Instant};
 // Purge all the caches if the disk is more than 50% full.
 const PURGE_CACHES_THRESHOLD: f32 = 0.5;
 
-#[derive(Default, Serialize, Deserialize)]
+#[derive(Default, Clone, Serialize, Deserialize)]
 pub struct Capabilities {
     #[serde(default)]
     capabilities: BTreeSet<String>,
@@ -56,6 +58,32 @@ impl Capabilities {
 
         Ok(caps.into_iter().collect())
     }
+
+    /// Whether an agent with these capabilities can test a crate with the given requirements.
+    ///
+    /// Requirements must be advertised as-is, except `memory:<size>` which is also met by agents
+    /// advertising more memory than that.
+    pub fn satisfies(&self, requirements: &[String]) -> bool {
+        requirements.iter().all(|requirement| {
+            if self.capabilities.contains(requirement) {
+                return true;
+            }
+
+            let Some(required) = memory_capability(requirement) else {
+                return false;
+            };
+            self.capabilities
+                .iter()
+                .filter_map(|cap| memory_capability(cap))
+                .any(|available| available >= required)
+        })
+    }
+}
+
+/// Amount of bytes of a `memory:<size>` capability.
+fn memory_capability(capability: &str) -> Option<usize> {
+    let size = capability.strip_prefix("memory:")?;
+    size.parse::<Size>().ok().map(|size| size.to_bytes())
 }
 
 impl FromIterator<String> for Capabilities {
@@ -188,7 +216,14 @@ pub fn run(
     workspace: &Workspace,
 ) -> Fallible<()> {
     let agent = Agent::new(url, token, caps)?;
-    let db = results::ResultsUploader::new(&agent.api);
+    let db = results::ResultsUploader::new(&agent.api)?;
+
+    // Send the results finished before the agent was last stopped
+    match db.flush() {
+        Ok(0) => {}
+        Ok(sent) => info!("sent {} results left over by the previous run", sent),
+        Err(err) => utils::report_failure(&err),
+    }
 
     run_heartbeat(url, token);
     health_thread();
@@ -211,3 +246,29 @@ pub fn run(
         }
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::Capabilities;
+
+    fn requires(requirements: &[&str]) -> Vec<String> {
+        requirements.iter().map(|r| r.to_string()).collect()
+    }
+
+    #[test]
+    fn test_satisfies() {
+        let caps = Capabilities::new(&["linux", "target:wasm32", "memory:16G"]);
+
+        assert!(caps.satisfies(&[]));
+        assert!(caps.satisfies(&requires(&["target:wasm32"])));
+        assert!(caps.satisfies(&requires(&["linux", "target:wasm32"])));
+        assert!(!caps.satisfies(&requires(&["target:wasm32", "windows"])));
+        assert!(!Capabilities::new(&["linux"]).satisfies(&requires(&["target:wasm32"])));
+
+        // Agents with more memory than required are fine too
+        assert!(caps.satisfies(&requires(&["memory:8G"])));
+        assert!(caps.satisfies(&requires(&["memory:16384M"])));
+        assert!(!caps.satisfies(&requires(&["memory:32G"])));
+        assert!(!Capabilities::new(&["linux"]).satisfies(&requires(&["memory:1G"])));
+    }
+}
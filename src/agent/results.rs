@@ -1,5 +1,7 @@
 use crate::agent::api::AgentApi;
+use crate::agent::spool::{ResultsSpool, SpooledResult};
 use crate::crates::Crate;
+use crate::dirs::AGENT_SPOOL_DIR;
 use crate::experiments::Experiment;
 use crate::prelude::*;
 use crate::results::{EncodingType, TestResult, WriteResults};
@@ -7,19 +9,39 @@ use crate::toolchain::Toolchain;
 use rustwide::logging::{self, LogStorage};
 use std::collections::{hash_map::Entry::Occupied, HashMap};
 use std::sync::{Arc, Mutex};
+use std::time::Instant;
 
 #[derive(Clone)]
 pub struct ResultsUploader<'a> {
     api: &'a AgentApi,
-    versions: Arc<Mutex<HashMap<Crate, (Crate, bool)>>>,
+    spool: Arc<ResultsSpool>,
+    // Updated version of each crate, and how many of its results were sent so far
+    versions: Arc<Mutex<HashMap<Crate, (Crate, usize)>>>,
 }
 
 impl<'a> ResultsUploader<'a> {
-    pub fn new(api: &'a AgentApi) -> Self {
-        ResultsUploader {
+    pub fn new(api: &'a AgentApi) -> Fallible<Self> {
+        Ok(ResultsUploader {
             api,
+            spool: Arc::new(ResultsSpool::new(&AGENT_SPOOL_DIR)?),
             versions: Arc::new(Mutex::new(HashMap::new())),
-        }
+        })
+    }
+
+    /// Send the results which weren't acknowledged by the server yet, returning how many were
+    /// sent.
+    pub fn flush(&self) -> Fallible<usize> {
+        self.spool.flush(|entry| {
+            self.api.record_progress(
+                &entry.experiment,
+                &entry.krate,
+                &entry.toolchain,
+                entry.log.as_bytes(),
+                &entry.result,
+                entry.version.as_ref().map(|(old, new)| (old, new)),
+                entry.duration_ms,
+            )
+        })
     }
 }
 
@@ -38,7 +60,7 @@ impl<'a> WriteResults for ResultsUploader<'a> {
         self.versions
             .lock()
             .unwrap()
-            .insert(old.clone(), (new.clone(), false));
+            .insert(old.clone(), (new.clone(), 0));
         Ok(())
     }
 
@@ -54,7 +76,9 @@ impl<'a> WriteResults for ResultsUploader<'a> {
     where
         F: FnOnce() -> Fallible<TestResult>,
     {
+        let start = Instant::now();
         let result = logging::capture(storage, f)?;
+        let duration = start.elapsed();
         let output = storage.to_string();
 
         let mut updated = None;
@@ -65,27 +89,33 @@ impl<'a> WriteResults for ResultsUploader<'a> {
             if let Occupied(mut entry) = versions.entry(krate.clone()) {
                 let value = entry.get_mut();
 
-                if value.1 {
-                    // delete entry if we already processed both toolchains
+                if value.1 + 1 == ex.all_toolchains().count() {
+                    // delete entry if we already processed all the other toolchains
                     updated = Some(entry.remove().0);
                 } else {
                     updated = Some(value.0.clone());
-                    new_version = updated.as_ref();
-                    // mark we already sent the updated version to the server
-                    value.1 = true;
+                    if value.1 == 0 {
+                        // only the first result sends the updated version to the server
+                        new_version = updated.as_ref();
+                    }
+                    value.1 += 1;
                 }
             };
         }
 
+        // Spool the result before sending it, so it's not lost if the server can't be reached
+        self.spool.push(&SpooledResult {
+            experiment: ex.name.clone(),
+            krate: updated.as_ref().unwrap_or(krate).clone(),
+            toolchain: toolchain.clone(),
+            result: result.clone(),
+            log: output,
+            version: new_version.map(|new| (krate.clone(), new.clone())),
+            duration_ms: Some(duration.as_millis() as u64),
+        })?;
+
         info!("sending results to the crater server...");
-        self.api.record_progress(
-            ex,
-            updated.as_ref().unwrap_or(krate),
-            toolchain,
-            output.as_bytes(),
-            &result,
-            new_version.map(|new| (krate, new)),
-        )?;
+        self.flush()?;
 
         Ok(result)
     }
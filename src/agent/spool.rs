@@ -0,0 +1,156 @@
+use crate::crates::Crate;
+use crate::prelude::*;
+use crate::results::TestResult;
+use crate::toolchain::Toolchain;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicU64, Ordering};
+use std::sync::Mutex;
+use std::time::{SystemTime, UNIX_EPOCH};
+
+/// Result of a crate stored on disk until the server acknowledged it.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub(super) struct SpooledResult {
+    pub(super) experiment: String,
+    pub(super) krate: Crate,
+    pub(super) toolchain: Toolchain,
+    pub(super) result: TestResult,
+    pub(super) log: String,
+    pub(super) version: Option<(Crate, Crate)>,
+    // Missing from the results spooled by older agents
+    #[serde(default)]
+    pub(super) duration_ms: Option<u64>,
+}
+
+/// Local spool of the results not yet acknowledged by the server, so they're sent again after
+/// the connection is lost or the agent is restarted instead of being lost.
+pub(super) struct ResultsSpool {
+    dir: PathBuf,
+    counter: AtomicU64,
+    // Only one thread sends the spooled results at a time, otherwise they could be sent twice
+    flushing: Mutex<()>,
+}
+
+impl ResultsSpool {
+    pub(super) fn new(dir: &Path) -> Fallible<Self> {
+        fs::create_dir_all(dir)?;
+        Ok(ResultsSpool {
+            dir: dir.into(),
+            counter: AtomicU64::new(0),
+            flushing: Mutex::new(()),
+        })
+    }
+
+    pub(super) fn push(&self, entry: &SpooledResult) -> Fallible<()> {
+        // File names sort in the order the results were spooled, even across restarts
+        let now = SystemTime::now().duration_since(UNIX_EPOCH)?.as_nanos();
+        let counter = self.counter.fetch_add(1, Ordering::SeqCst);
+        let name = format!("{now:032}-{counter:08}.json");
+
+        // Write the entry atomically, so a crash never leaves a truncated one behind
+        let tmp = self.dir.join(format!("{name}.tmp"));
+        fs::write(&tmp, serde_json::to_vec(entry)?)?;
+        fs::rename(&tmp, self.dir.join(name))?;
+        Ok(())
+    }
+
+    fn pending(&self) -> Fallible<Vec<PathBuf>> {
+        let mut paths = Vec::new();
+        for entry in fs::read_dir(&self.dir)? {
+            let path = entry?.path();
+            if path.extension().map_or(false, |ext| ext == "json") {
+                paths.push(path);
+            }
+        }
+        paths.sort();
+        Ok(paths)
+    }
+
+    /// Send every spooled result, in order, removing each one once it's sent. Stops at the first
+    /// failure, leaving the remaining results for the next flush.
+    pub(super) fn flush(&self, send: impl Fn(&SpooledResult) -> Fallible<()>) -> Fallible<usize> {
+        let _flushing = self.flushing.lock().unwrap();
+
+        let mut sent = 0;
+        for path in self.pending()? {
+            let entry: SpooledResult = match serde_json::from_slice(&fs::read(&path)?) {
+                Ok(entry) => entry,
+                Err(err) => {
+                    warn!(
+                        "discarding corrupted spooled result {}: {}",
+                        path.display(),
+                        err
+                    );
+                    fs::remove_file(&path)?;
+                    continue;
+                }
+            };
+
+            send(&entry)?;
+            fs::remove_file(&path)?;
+            sent += 1;
+        }
+        Ok(sent)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ResultsSpool, SpooledResult};
+    use crate::crates::Crate;
+    use crate::prelude::*;
+    use crate::results::TestResult;
+    use crate::toolchain::MAIN_TOOLCHAIN;
+    use std::cell::RefCell;
+
+    fn entry(name: &str) -> SpooledResult {
+        SpooledResult {
+            experiment: "foo".into(),
+            krate: Crate::Local(name.into()),
+            toolchain: MAIN_TOOLCHAIN.clone(),
+            result: TestResult::TestPass,
+            log: format!("log of {name}"),
+            version: None,
+            duration_ms: Some(1000),
+        }
+    }
+
+    #[test]
+    fn test_resend_after_dropped_connection() {
+        let dir = tempfile::tempdir().unwrap();
+        let spool = ResultsSpool::new(dir.path()).unwrap();
+        for name in ["a", "b", "c"] {
+            spool.push(&entry(name)).unwrap();
+        }
+
+        // The connection drops after the first result is sent
+        let received = RefCell::new(Vec::new());
+        let err = spool
+            .flush(|entry| {
+                if received.borrow().len() == 1 {
+                    bail!("connection reset by peer");
+                }
+                received.borrow_mut().push(entry.clone());
+                Ok(())
+            })
+            .unwrap_err();
+        assert_eq!(err.to_string(), "connection reset by peer");
+
+        // An agent restarted after a crash finds the results left in the spool
+        let spool = ResultsSpool::new(dir.path()).unwrap();
+        let sent = spool
+            .flush(|entry| {
+                received.borrow_mut().push(entry.clone());
+                Ok(())
+            })
+            .unwrap();
+        assert_eq!(sent, 2);
+        assert_eq!(
+            received.into_inner(),
+            vec![entry("a"), entry("b"), entry("c")]
+        );
+
+        // Nothing is sent twice
+        assert_eq!(spool.flush(|_| panic!("nothing to send")).unwrap(), 0);
+    }
+}
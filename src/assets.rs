@@ -69,7 +69,10 @@ load_files! {
         "ui/500.html",
 
         "report/layout.html",
+        "report/diff.html",
+        "report/matrix.html",
         "report/downloads.html",
+        "report/error-codes.html",
         "report/results.html",
     ],
     assets: [
@@ -9,15 +9,18 @@
 //! application state employs ownership techniques to ensure that
 //! parallel access is consistent and race-free.
 
+use chrono::Utc;
 use clap::Parser;
 use crater::actions::{self, Action, ActionsCtx};
 use crater::agent::{self, Capabilities};
 use crater::config::Config;
 use crater::crates::Crate;
 use crater::db::Database;
-use crater::experiments::{Assignee, CapLints, DeferredCrateSelect, Experiment, Mode, Status};
-use crater::report;
-use crater::results::{DatabaseDB, DeleteResults};
+use crater::experiments::{
+    Assignee, CapLints, CompletionWebhook, DeferredCrateSelect, Experiment, Mode, Status,
+};
+use crater::report::{self, ReportWriter};
+use crater::results::{DatabaseDB, DeleteResults, ResultKinds};
 use crater::runner;
 use crater::server;
 use crater::toolchain::Toolchain;
@@ -51,6 +54,21 @@ impl FromStr for DockerEnv {
     }
 }
 
+// A `key=value` label attached to an experiment
+#[derive(Debug, Clone)]
+pub struct Label(String, String);
+
+impl FromStr for Label {
+    type Err = Error;
+
+    fn from_str(label: &str) -> Fallible<Label> {
+        match label.split_once('=') {
+            Some((key, value)) => Ok(Label(key.into(), value.into())),
+            None => bail!("labels must be formatted as key=value, found `{}`", label),
+        }
+    }
+}
+
 #[derive(Debug, Clone)]
 pub struct Dest(PathBuf);
 
@@ -75,6 +93,24 @@ fn default_capabilities_for_target() -> Capabilities {
     Capabilities::new(caps)
 }
 
+/// Prefix of the reports bucket the check object is written under, next to the reports.
+const CHECK_PREFIX: &str = "crater-check";
+
+/// Writer for an S3 prefix given on the command line, using the AWS credentials of the environment.
+/// The objects are public, like the ones of the reports published by the server.
+fn s3_writer(
+    prefix: &report::S3Prefix,
+    runtime: &report::ReportRuntime,
+) -> Fallible<report::S3Writer> {
+    Ok(report::S3Writer::create(
+        prefix.client(runtime),
+        prefix.bucket.clone(),
+        prefix.prefix.to_string_lossy().into_owned(),
+        report::MultipartConfig::default(),
+    )?
+    .with_acl(aws_sdk_s3::types::ObjectCannedAcl::PublicRead))
+}
+
 #[derive(Parser)]
 #[allow(clippy::large_enum_variant)]
 #[clap(name = "crater", about = "Kaboom!")]
@@ -99,6 +135,12 @@ pub enum Crater {
         tc1: Toolchain,
         #[clap(name = "tc-2")]
         tc2: Toolchain,
+        #[clap(
+            name = "extra-toolchain",
+            long = "extra-toolchain",
+            help = "Compare another toolchain with the first one, can be repeated."
+        )]
+        extra_toolchains: Vec<Toolchain>,
         #[clap(name = "mode", long = "mode", default_value_t = Mode::BuildAndTest)]
         mode: Mode,
         #[clap(
@@ -126,6 +168,52 @@ pub enum Crater {
         assign: Option<Assignee>,
         #[clap(name = "requirement", long = "requirement")]
         requirement: Option<String>,
+        #[clap(
+            name = "max-regressions",
+            long = "max-regressions",
+            help = "Stop handing out crates once this many crates regressed."
+        )]
+        max_regressions: Option<usize>,
+        #[clap(
+            name = "webhook-url",
+            long = "webhook-url",
+            help = "Notify this webhook instead of the global one when the experiment completes."
+        )]
+        webhook_url: Option<String>,
+        #[clap(
+            name = "webhook-secret",
+            long = "webhook-secret",
+            requires = "webhook-url",
+            help = "Sign the payloads sent to the webhook with this key."
+        )]
+        webhook_secret: Option<String>,
+        #[clap(
+            name = "skip-dev-deps",
+            long = "skip-dev-deps",
+            help = "Don't build the dev-dependencies of the crates in the build-only mode."
+        )]
+        skip_dev_deps: bool,
+        #[clap(
+            name = "label",
+            long = "label",
+            help = "Attach a key=value label to the experiment, can be repeated."
+        )]
+        labels: Vec<Label>,
+        #[clap(
+            name = "confirm-regressions",
+            long = "confirm-regressions",
+            help = "Run the regressed crates again on another agent to confirm the regressions."
+        )]
+        confirm_regressions: bool,
+    },
+
+    #[clap(
+        name = "define-ex-json",
+        about = "define an experiment from a JSON definition"
+    )]
+    DefineExJson {
+        #[clap(name = "definition")]
+        definition: PathBuf,
     },
 
     #[clap(name = "edit", about = "edit an experiment configuration")]
@@ -198,6 +286,21 @@ pub enum Crater {
         krate: Crate,
     },
 
+    #[clap(
+        name = "requeue",
+        about = "queue again the crates of an experiment with some kinds of results"
+    )]
+    Requeue {
+        #[clap(name = "experiment", long = "ex", default_value = "default")]
+        ex: Ex,
+        #[clap(
+            name = "results",
+            long = "results",
+            help = "Comma-separated kinds of results to requeue, e.g. fetch-error,timeout"
+        )]
+        results: ResultKinds,
+    },
+
     #[clap(name = "run-graph", about = "run a parallelized experiment")]
     RunGraph {
         #[clap(name = "experiment", long = "ex", default_value = "default")]
@@ -220,6 +323,77 @@ pub enum Crater {
         force: bool,
         #[clap(name = "output-templates", long = "output-templates")]
         output_templates: bool,
+        #[clap(
+            name = "dry-run",
+            long = "dry-run",
+            help = "Log the files of the report instead of writing them."
+        )]
+        dry_run: bool,
+        #[clap(
+            name = "regressions-only",
+            long = "regressions-only",
+            help = "Only include the regressed crates in the report."
+        )]
+        regressions_only: bool,
+    },
+
+    #[clap(
+        name = "regen-report",
+        about = "generate the report of an experiment again from its stored results",
+        long_about = "Generate the report of an experiment again from the results stored in the \
+                      database, without running anything nor changing the experiment. Incomplete \
+                      experiments are reported with the results available so far.\n\n\
+                      The destination is either a local directory or an S3 prefix like \
+                      s3://bucket/prefix?region=us-west-1, accessed with the AWS credentials \
+                      found in the environment."
+    )]
+    RegenReport {
+        #[clap(name = "experiment", long = "ex", default_value = "default")]
+        ex: Ex,
+        #[clap(name = "destination")]
+        dest: String,
+    },
+
+    #[clap(
+        name = "check-report-dest",
+        about = "check a report destination is writable",
+        long_about = "Check a report destination is writable, by writing a small object to it \
+                      and removing it afterwards.\n\n\
+                      The destination is either a local directory or an S3 prefix like \
+                      s3://bucket/prefix?region=us-west-1, accessed with the AWS credentials \
+                      found in the environment. Without a destination, the reports bucket of \
+                      tokens.toml is checked with the same credentials, ACL, storage class and \
+                      role the server uploads the reports with."
+    )]
+    CheckReportDest {
+        #[clap(name = "destination")]
+        dest: Option<String>,
+    },
+
+    #[clap(
+        name = "prune-reports",
+        about = "delete the old reports stored under an S3 prefix",
+        long_about = "Delete the reports stored under an S3 prefix which are older than the \
+                      retention period.\n\n\
+                      Each directory right below the prefix is a report, dated by the date in its \
+                      name if there's one (like 2024-01-31), and by its most recent object \
+                      otherwise. Nothing outside of the prefix is ever deleted."
+    )]
+    PruneReports {
+        #[clap(name = "destination")]
+        dest: String,
+        #[clap(
+            name = "retention-days",
+            long = "retention-days",
+            help = "Keep the reports written during this many days."
+        )]
+        retention_days: u32,
+        #[clap(
+            name = "dry-run",
+            long = "dry-run",
+            help = "Log the objects which would be deleted instead of deleting them."
+        )]
+        dry_run: bool,
     },
 
     #[clap(name = "server")]
@@ -308,6 +482,7 @@ impl Crater {
                 ref ex,
                 ref tc1,
                 ref tc2,
+                ref extra_toolchains,
                 ref mode,
                 ref crates,
                 ref cap_lints,
@@ -315,6 +490,12 @@ impl Crater {
                 ref ignore_blacklist,
                 ref assign,
                 ref requirement,
+                max_regressions,
+                ref webhook_url,
+                ref webhook_secret,
+                skip_dev_deps,
+                ref labels,
+                confirm_regressions,
             } => {
                 let config = Config::load()?;
                 let db = Database::open()?;
@@ -331,9 +512,29 @@ impl Crater {
                     ignore_blacklist: *ignore_blacklist,
                     assign: assign.clone(),
                     requirement: requirement.clone(),
+                    max_regressions,
+                    webhook: webhook_url.clone().map(|url| CompletionWebhook {
+                        url,
+                        secret: webhook_secret.clone(),
+                    }),
+                    skip_dev_deps,
+                    extra_toolchains: extra_toolchains.clone(),
+                    labels: labels
+                        .iter()
+                        .map(|Label(key, value)| (key.clone(), value.clone()))
+                        .collect(),
+                    confirm_regressions,
                 }
                 .apply(&ctx)?;
             }
+            Crater::DefineExJson { ref definition } => {
+                let config = Config::load()?;
+                let db = Database::open()?;
+                let ctx = ActionsCtx::new(&db, &config);
+
+                let json = std::fs::read_to_string(definition)?;
+                actions::CreateExperiment::from_json(&json)?.apply(&ctx)?;
+            }
             Crater::Edit {
                 ref name,
                 ref tc1,
@@ -402,7 +603,7 @@ impl Crater {
                     if let Some(tc) = tc {
                         result_db.delete_result(&experiment, tc, krate)?;
                     } else {
-                        for tc in &experiment.toolchains {
+                        for tc in experiment.all_toolchains() {
                             result_db.delete_result(&experiment, tc, krate)?;
                         }
                     }
@@ -412,6 +613,23 @@ impl Crater {
                     bail!("missing experiment {}", ex.0);
                 }
             }
+            Crater::Requeue {
+                ref ex,
+                ref results,
+            } => {
+                let db = Database::open()?;
+                let result_db = DatabaseDB::new(&db);
+
+                if let Some(mut experiment) = Experiment::get(&db, &ex.0)? {
+                    let requeued = result_db.requeue_results(&experiment, results)?;
+                    log::info!("requeued {} crates with {} results", requeued, results);
+                    if requeued > 0 {
+                        experiment.requeue(&db)?;
+                    }
+                } else {
+                    bail!("missing experiment {}", ex.0);
+                }
+            }
             Crater::RunGraph {
                 ref ex,
                 threads,
@@ -453,7 +671,11 @@ impl Crater {
                         &|| Ok(crates.lock().unwrap().pop()),
                     );
                     workspace.purge_all_build_dirs()?;
-                    res?;
+                    if let Err(err) = res {
+                        // Unless someone else already moved it, e.g. by cancelling it
+                        experiment.set_status_from(&db, Status::Running, Status::Failed)?;
+                        return Err(err);
+                    }
                 } else {
                     bail!("missing experiment {}", ex.0);
                 }
@@ -463,6 +685,8 @@ impl Crater {
                 ref dest,
                 force,
                 output_templates,
+                dry_run,
+                regressions_only,
             } => {
                 let config = Config::load()?;
                 let db = Database::open()?;
@@ -478,28 +702,131 @@ impl Crater {
                         );
                     }
 
-                    experiment.set_status(&db, Status::GeneratingReport)?;
-
                     let result_db = DatabaseDB::new(&db);
+                    let crates = experiment.get_crates(&db)?;
+                    let filter = if regressions_only {
+                        report::ReportFilter::RegressionsOnly
+                    } else {
+                        report::ReportFilter::All
+                    };
+
+                    if dry_run {
+                        report::gen(
+                            &result_db,
+                            &experiment,
+                            &crates,
+                            &report::DryRunWriter::new(),
+                            &config,
+                            output_templates,
+                            &filter,
+                        )?;
+                        return Ok(());
+                    }
+
+                    if !experiment.set_status_from(
+                        &db,
+                        experiment.status,
+                        Status::GeneratingReport,
+                    )? {
+                        bail!(
+                            "the status of experiment {} changed to {} in the meantime",
+                            ex.0,
+                            experiment.status
+                        );
+                    }
+
                     let res = report::gen(
                         &result_db,
                         &experiment,
-                        &experiment.get_crates(&db)?,
+                        &crates,
                         &report::FileWriter::create(dest.0.clone())?,
                         &config,
                         output_templates,
+                        &filter,
                     );
 
-                    if let Err(err) = res {
-                        experiment.set_status(&db, Status::ReportFailed)?;
-                        return Err(err);
+                    let next = if res.is_ok() {
+                        Status::Completed
                     } else {
-                        experiment.set_status(&db, Status::Completed)?;
+                        Status::ReportFailed
+                    };
+                    // The report can be retried while it's being generated, in which case the
+                    // new attempt decides the final status
+                    if !experiment.set_status_from(&db, Status::GeneratingReport, next)? {
+                        log::warn!(
+                            "the status of experiment {} changed to {} during the report",
+                            ex.0,
+                            experiment.status
+                        );
                     }
+                    res?;
                 } else {
                     bail!("missing experiment: {}", ex.0);
                 }
             }
+            Crater::RegenReport { ref ex, ref dest } => {
+                let config = Config::load()?;
+                let db = Database::open()?;
+                let Some(experiment) = Experiment::get(&db, &ex.0)? else {
+                    bail!("missing experiment: {}", ex.0);
+                };
+
+                if dest.starts_with("s3://") {
+                    let runtime = report::ReportRuntime::new()?;
+                    let writer =
+                        s3_writer(&dest.parse()?, &runtime)?.with_experiment(&experiment.name);
+                    let writer = report::BlockingWriter::new(writer, runtime);
+                    report::regen(&db, &experiment, &writer, &config)?;
+                } else {
+                    let writer = report::FileWriter::create(dest.into())?;
+                    report::regen(&db, &experiment, &writer, &config)?;
+                }
+                log::info!("regenerated the report of {} in {}", ex.0, dest);
+            }
+            Crater::CheckReportDest { dest: None } => {
+                let bucket = server::tokens::Tokens::load()?.reports_bucket;
+                let runtime = report::ReportRuntime::new()?;
+                let writer = bucket.writer(&runtime, CHECK_PREFIX.into())?;
+                match report::BlockingWriter::new(writer, runtime).check_writable() {
+                    Ok(()) => log::info!("the reports can be written to {}", bucket.bucket),
+                    Err(err) => bail!("the reports can't be written to {}: {}", bucket.bucket, err),
+                }
+            }
+            Crater::CheckReportDest {
+                dest: Some(ref dest),
+            } => {
+                let res = if dest.starts_with("s3://") {
+                    let runtime = report::ReportRuntime::new()?;
+                    let writer = s3_writer(&dest.parse()?, &runtime)?;
+                    report::BlockingWriter::new(writer, runtime).check_writable()
+                } else {
+                    report::FileWriter::create(dest.into())?.check_writable()
+                };
+
+                match res {
+                    Ok(()) => log::info!("the report can be written to {}", dest),
+                    Err(err) => bail!("the report can't be written to {}: {}", dest, err),
+                }
+            }
+            Crater::PruneReports {
+                ref dest,
+                retention_days,
+                dry_run,
+            } => {
+                let runtime = report::ReportRuntime::new()?;
+                let writer = s3_writer(&dest.parse()?, &runtime)?;
+                let cutoff = Utc::now() - chrono::Duration::days(retention_days.into());
+
+                let keys = runtime.block_on(writer.prune(cutoff, dry_run))?;
+                if dry_run {
+                    for key in &keys {
+                        log::info!("would delete {}", key);
+                    }
+                    log::info!("{} objects would be deleted from {}", keys.len(), dest);
+                } else {
+                    log::info!("deleted {} objects from {}", keys.len(), dest);
+                }
+            }
             Crater::Server { bind } => {
                 let config = Config::load()?;
                 server::run(
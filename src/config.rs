@@ -1,9 +1,12 @@
 use crate::crates::Crate;
 use crate::prelude::*;
+use crate::results::{CompressionOptions, EncodingType};
 use crate::utils::size::Size;
 use log::LevelFilter;
 use regex::Regex;
 use rustwide::logging::LogStorage;
+use serde::de::{Deserializer, Error as _};
+use serde::Deserialize as _;
 use std::collections::{HashMap, HashSet};
 use std::env;
 use std::ffi::OsStr;
@@ -32,17 +35,55 @@ pub struct CrateConfig {
     pub quiet: bool,
     #[serde(default = "default_false")]
     pub broken: bool,
+    /// Opt the crate into `miri` experiments.
+    #[serde(default = "default_false")]
+    pub miri: bool,
+    /// Overrides `sandbox.memory-limit` for this crate.
+    #[serde(default)]
+    pub memory_limit: Option<Size>,
+    /// Capabilities an agent must have to be handed this crate, e.g. `target:wasm32`.
+    #[serde(default)]
+    pub requires: Vec<String>,
 }
 
 fn default_false() -> bool {
     false
 }
 
+fn default_true() -> bool {
+    true
+}
+
+/// Compile the blacklist patterns, reporting which one is invalid instead of only the regex
+/// error.
+fn deserialize_blacklist<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Vec<Regex>, D::Error> {
+    Vec::<String>::deserialize(deserializer)?
+        .iter()
+        .map(|pattern| {
+            Regex::new(pattern).map_err(|err| {
+                D::Error::custom(format!(
+                    "invalid crate-blacklist pattern `{pattern}`: {err}"
+                ))
+            })
+        })
+        .collect()
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ServerConfig {
     pub bot_acl: BotACL,
     pub labels: ServerLabels,
+    /// How long the work in flight is waited for when the server is shut down, before the
+    /// uploads still running are aborted.
+    #[serde(default = "default_shutdown_timeout_secs")]
+    pub shutdown_timeout_secs: u64,
+}
+
+fn default_shutdown_timeout_secs() -> u64 {
+    120
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -69,12 +110,212 @@ pub struct DemoCrates {
     pub local_crates: Vec<String>,
 }
 
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ReportOptions {
+    /// Maximum number of crates listed in each page of the HTML report.
+    #[serde(default = "default_page_size")]
+    pub page_size: usize,
+    /// Also store the logs of each crate as a single zstd-compressed object, indexed by
+    /// `logs/manifest.json`.
+    #[serde(default)]
+    pub log_manifest: bool,
+    /// Encoding of the HTML pages and other text files of the report, uncompressed by default.
+    /// `gzip` is understood by every client, `brotli` compresses better.
+    #[serde(default = "default_text_encoding")]
+    pub text_encoding: EncodingType,
+    /// Maximum number of objects written at the same time, mostly bound by the network rather
+    /// than the number of cores.
+    #[serde(default = "default_write_concurrency")]
+    pub write_concurrency: usize,
+    /// Number of crates listed in the slowest crates section of the report, for each toolchain.
+    #[serde(default = "default_slowest_crates")]
+    pub slowest_crates: usize,
+    /// Write the logs of each crate under a prefix named after its category, like `regressed/`
+    /// or `fixed/`, instead of at the root of the report.
+    #[serde(default)]
+    pub split_by_category: bool,
+}
+
+fn default_page_size() -> usize {
+    1000
+}
+
+fn default_text_encoding() -> EncodingType {
+    EncodingType::Plain
+}
+
+fn default_write_concurrency() -> usize {
+    32
+}
+
+fn default_slowest_crates() -> usize {
+    20
+}
+
+impl Default for ReportOptions {
+    fn default() -> Self {
+        ReportOptions {
+            page_size: default_page_size(),
+            log_manifest: false,
+            text_encoding: default_text_encoding(),
+            write_concurrency: default_write_concurrency(),
+            slowest_crates: default_slowest_crates(),
+            split_by_category: false,
+        }
+    }
+}
+
+/// Settings of the `rustdoc` experiment mode.
+#[derive(Clone, Default, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct RustdocOptions {
+    /// Document the dependencies too, instead of passing `--no-deps`.
+    #[serde(default)]
+    pub include_deps: bool,
+    /// Build the crate before documenting it, so crates failing to build are reported as build
+    /// failures rather than documentation failures.
+    #[serde(default)]
+    pub build_first: bool,
+}
+
+/// Settings of the `check-only` experiment mode.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CheckOptions {
+    /// Also check the tests, examples and benchmarks, instead of only the libraries and binaries.
+    #[serde(default = "default_true")]
+    pub all_targets: bool,
+}
+
+impl Default for CheckOptions {
+    fn default() -> Self {
+        CheckOptions { all_targets: true }
+    }
+}
+
+/// Limits on the downloads of the crates' sources, shared by all the workers of an agent.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct FetchOptions {
+    /// Maximum number of crates downloaded at the same time.
+    #[serde(default = "default_max_concurrent_fetches")]
+    pub max_concurrent: usize,
+    /// Maximum number of downloads started each second, unlimited if missing.
+    #[serde(default)]
+    pub requests_per_second: Option<f64>,
+}
+
+fn default_max_concurrent_fetches() -> usize {
+    16
+}
+
+impl Default for FetchOptions {
+    fn default() -> Self {
+        FetchOptions {
+            max_concurrent: default_max_concurrent_fetches(),
+            requests_per_second: None,
+        }
+    }
+}
+
+/// Preservation of the build outputs of the regressed crates, to debug them locally.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct ArtifactsOptions {
+    /// Archive the target directory and the cargo commands of each regressed crate into
+    /// `artifacts/{crate}.tar.gz`, in the experiment's directory of `work/artifacts`.
+    #[serde(default)]
+    pub preserve_failed_artifacts: bool,
+    /// Maximum number of crates preserved by each run of an agent.
+    #[serde(default = "default_max_preserved_crates")]
+    pub max_crates: usize,
+    /// Archives larger than this are discarded.
+    #[serde(default = "default_max_archive_size")]
+    pub max_archive_size: Size,
+}
+
+fn default_max_preserved_crates() -> usize {
+    20
+}
+
+fn default_max_archive_size() -> Size {
+    Size::Megabytes(512)
+}
+
+impl Default for ArtifactsOptions {
+    fn default() -> Self {
+        ArtifactsOptions {
+            preserve_failed_artifacts: false,
+            max_crates: default_max_preserved_crates(),
+            max_archive_size: default_max_archive_size(),
+        }
+    }
+}
+
+/// Compiler cache shared by the builds of an agent, so the dependencies common to many crates
+/// aren't compiled again for each of them.
+#[derive(Clone, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct BuildCacheOptions {
+    /// Compile through sccache, with a cache in `work/cache/builds` for each toolchain, target
+    /// and rustflags mounted into the sandboxes. Ignored by `miri` experiments.
+    #[serde(default)]
+    pub enabled: bool,
+    /// Statically linked sccache binary of the agent, mounted into the sandboxes. The builds run
+    /// without the cache if it's missing.
+    #[serde(default = "default_sccache_path")]
+    pub sccache: PathBuf,
+    /// Size of each cache, past which the least recently used artifacts are evicted.
+    #[serde(default = "default_build_cache_size")]
+    pub max_size: Size,
+}
+
+fn default_sccache_path() -> PathBuf {
+    "/usr/local/bin/sccache".into()
+}
+
+fn default_build_cache_size() -> Size {
+    Size::Gigabytes(10)
+}
+
+impl Default for BuildCacheOptions {
+    fn default() -> Self {
+        BuildCacheOptions {
+            enabled: false,
+            sccache: default_sccache_path(),
+            max_size: default_build_cache_size(),
+        }
+    }
+}
+
 #[derive(Clone, Serialize, Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct SandboxConfig {
+    /// Memory available to each build, the build is killed and recorded as OOM past it.
+    #[serde(default = "default_memory_limit")]
     pub memory_limit: Size,
     pub build_log_max_size: Size,
     pub build_log_max_lines: usize,
+    /// Logs larger than this are stored with their middle truncated, keeping the beginning and
+    /// the end of the build.
+    #[serde(default = "default_stored_log_max_size")]
+    pub stored_log_max_size: Size,
+    /// Wall-clock time a crate can spend building and testing before being killed.
+    #[serde(default = "default_build_timeout_secs")]
+    pub build_timeout_secs: u64,
+}
+
+fn default_memory_limit() -> Size {
+    Size::Megabytes(1536)
+}
+
+fn default_stored_log_max_size() -> Size {
+    Size::Megabytes(10)
+}
+
+fn default_build_timeout_secs() -> u64 {
+    15 * 60
 }
 
 #[derive(Clone, Serialize, Deserialize)]
@@ -84,8 +325,29 @@ pub struct Config {
     pub crates: HashMap<String, CrateConfig>,
     pub github_repos: HashMap<String, CrateConfig>,
     pub local_crates: HashMap<String, CrateConfig>,
+    /// Crates whose name, or `name@version`, matches any of these patterns are skipped.
+    #[serde(
+        default,
+        deserialize_with = "deserialize_blacklist",
+        serialize_with = "serde_regex::serialize"
+    )]
+    pub crate_blacklist: Vec<Regex>,
     pub server: ServerConfig,
     pub sandbox: SandboxConfig,
+    #[serde(default)]
+    pub fetch: FetchOptions,
+    #[serde(default)]
+    pub compression: CompressionOptions,
+    #[serde(default)]
+    pub report: ReportOptions,
+    #[serde(default)]
+    pub rustdoc: RustdocOptions,
+    #[serde(default)]
+    pub check: CheckOptions,
+    #[serde(default)]
+    pub artifacts: ArtifactsOptions,
+    #[serde(default)]
+    pub build_cache: BuildCacheOptions,
 }
 
 impl Config {
@@ -107,12 +369,36 @@ impl Config {
             Crate::Registry(ref details) => self.crates.get(&details.name),
             Crate::GitHub(ref repo) => self.github_repos.get(&repo.slug()),
             Crate::Local(ref name) => self.local_crates.get(name),
-            Crate::Git(_) | Crate::Path(_) => unimplemented!("unsupported crate"),
+            Crate::AltRegistry(_) | Crate::Workspace(_) | Crate::Git(_) => None,
+            Crate::Path(_) => unimplemented!("unsupported crate"),
         }
     }
 
     pub fn should_skip(&self, c: &Crate) -> bool {
-        self.crate_config(c).map(|c| c.skip).unwrap_or(false)
+        self.crate_config(c).map(|c| c.skip).unwrap_or(false) || self.is_blacklisted(c)
+    }
+
+    fn is_blacklisted(&self, c: &Crate) -> bool {
+        if self.crate_blacklist.is_empty() {
+            return false;
+        }
+
+        let (name, version) = match *c {
+            Crate::Registry(ref details) => (details.name.clone(), Some(&details.version)),
+            Crate::AltRegistry(ref details) => (details.name.clone(), Some(&details.version)),
+            Crate::GitHub(ref repo) => (repo.slug(), None),
+            Crate::Local(ref name) => (name.clone(), None),
+            Crate::Git(_) | Crate::Path(_) | Crate::Workspace(_) => return false,
+        };
+        let versioned = version.map(|version| format!("{name}@{version}"));
+
+        self.crate_blacklist.iter().any(|pattern| {
+            pattern.is_match(&name)
+                || versioned
+                    .as_ref()
+                    .map(|versioned| pattern.is_match(versioned))
+                    .unwrap_or(false)
+        })
     }
 
     pub fn should_skip_tests(&self, c: &Crate) -> bool {
@@ -127,6 +413,31 @@ impl Config {
         self.crate_config(c).map(|c| c.broken).unwrap_or(false)
     }
 
+    pub fn runs_miri(&self, c: &Crate) -> bool {
+        self.crate_config(c).map(|c| c.miri).unwrap_or(false)
+    }
+
+    pub fn memory_limit(&self, c: &Crate) -> Size {
+        self.crate_config(c)
+            .and_then(|c| c.memory_limit)
+            .unwrap_or(self.sandbox.memory_limit)
+    }
+
+    pub fn crate_requirements(&self, c: &Crate) -> &[String] {
+        self.crate_config(c)
+            .map(|c| c.requires.as_slice())
+            .unwrap_or(&[])
+    }
+
+    /// Whether any crate can only be handed to some of the agents.
+    pub fn has_crate_requirements(&self) -> bool {
+        self.crates
+            .values()
+            .chain(self.github_repos.values())
+            .chain(self.local_crates.values())
+            .any(|c| !c.requires.is_empty())
+    }
+
     pub fn demo_crates(&self) -> &DemoCrates {
         &self.demo_crates
     }
@@ -243,11 +554,21 @@ impl Default for Config {
             crates: HashMap::new(),
             github_repos: HashMap::new(),
             local_crates: HashMap::new(),
+            crate_blacklist: Vec::new(),
             sandbox: SandboxConfig {
                 memory_limit: Size::Gigabytes(2),
                 build_log_max_size: Size::Megabytes(1),
                 build_log_max_lines: 1000,
+                stored_log_max_size: default_stored_log_max_size(),
+                build_timeout_secs: default_build_timeout_secs(),
             },
+            fetch: FetchOptions::default(),
+            compression: CompressionOptions::default(),
+            report: ReportOptions::default(),
+            rustdoc: RustdocOptions::default(),
+            check: CheckOptions::default(),
+            artifacts: ArtifactsOptions::default(),
+            build_cache: BuildCacheOptions::default(),
             server: ServerConfig {
                 bot_acl: BotACL {
                     rust_teams: false,
@@ -258,6 +579,7 @@ impl Default for Config {
                     experiment_queued: "".into(),
                     experiment_completed: "".into(),
                 },
+                shutdown_timeout_secs: default_shutdown_timeout_secs(),
             },
         }
     }
@@ -276,6 +598,7 @@ impl From<&'_ Config> for LogStorage {
 mod tests {
     use super::Config;
     use crate::crates::{Crate, GitHubRepo, RegistryCrate};
+    use crate::utils::size::Size;
 
     #[test]
     fn test_config() {
@@ -300,11 +623,22 @@ mod tests {
             "lazy_static = { skip = true }\n",
             "[github-repos]\n",
             "\"rust-lang/rust\" = { quiet = true }\n", // :(
-            "[local-crates]\n"
+            "[local-crates]\n",
+            "memory-hungry = { memory-limit = \"512M\" }\n",
+            "unsafe-code = { miri = true }\n"
         );
 
         let list: Config = ::toml::from_str(config).unwrap();
 
+        assert_eq!(
+            list.memory_limit(&Crate::Local("memory-hungry".into())),
+            Size::Megabytes(512)
+        );
+        assert_eq!(
+            list.memory_limit(&Crate::Local("build-pass".into())),
+            Size::Gigabytes(2)
+        );
+
         assert!(list.should_skip(&Crate::Registry(RegistryCrate {
             name: "lazy_static".into(),
             version: "42".into(),
@@ -314,6 +648,9 @@ mod tests {
             version: "42".into(),
         })));
 
+        assert!(list.runs_miri(&Crate::Local("unsafe-code".into())));
+        assert!(!list.runs_miri(&Crate::Local("memory-hungry".into())));
+
         assert!(list.is_quiet(&Crate::GitHub(GitHubRepo {
             org: "rust-lang".into(),
             name: "rust".into(),
@@ -325,4 +662,50 @@ mod tests {
             sha: None,
         })));
     }
+
+    #[test]
+    fn test_crate_blacklist() {
+        let config = concat!(
+            "crate-blacklist = [\"^slowpoke-\", \"^rand@0\\\\.1\\\\.\"]\n",
+            "[server.bot-acl]\n",
+            "rust-teams = false\n",
+            "github = []\n",
+            "[server.labels]\n",
+            "remove = \"\"\n",
+            "experiment-queued = \"\"\n",
+            "experiment-completed = \"\"\n",
+            "[demo-crates]\n",
+            "crates = []\n",
+            "github-repos = []\n",
+            "local-crates = []\n",
+            "[sandbox]\n",
+            "memory-limit = \"2G\"\n",
+            "build-log-max-size = \"2M\"\n",
+            "build-log-max-lines = 1000\n",
+            "[crates]\n",
+            "[github-repos]\n",
+            "[local-crates]\n"
+        );
+        let list: Config = ::toml::from_str(config).unwrap();
+        let krate = |name: &str, version: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: version.into(),
+            })
+        };
+
+        for name in &["slowpoke-core", "slowpoke-derive", "slowpoke-macros"] {
+            assert!(list.should_skip(&krate(name, "1.0.0")));
+        }
+        assert!(!list.should_skip(&krate("slowpoke", "1.0.0")));
+
+        assert!(list.should_skip(&krate("rand", "0.1.4")));
+        assert!(!list.should_skip(&krate("rand", "0.8.5")));
+
+        let invalid = config.replace("^slowpoke-", "slowpoke-(");
+        let err = ::toml::from_str::<Config>(&invalid).err().unwrap();
+        assert!(err
+            .to_string()
+            .contains("invalid crate-blacklist pattern `slowpoke-(`"));
+    }
 }
@@ -1,6 +1,6 @@
 use crate::config::Config;
 use crate::crates::sources::github::GitHubRepo;
-use crate::crates::{Crate, RegistryCrate};
+use crate::crates::{is_ssh_url, Crate, CrateSpec, GitRepo, RegistryCrate};
 use crate::db::{Database, QueryUtils};
 use crate::experiments::CrateSelect;
 use crate::prelude::*;
@@ -9,7 +9,7 @@ use rand::{seq::SliceRandom, thread_rng};
 use std::collections::HashSet;
 
 pub(crate) use crate::crates::sources::{
-    github::GitHubList, local::LocalList, registry::RegistryList,
+    github::GitHubList, local::LocalList, registry::RegistryList, workspace::WorkspaceList,
 };
 
 pub(crate) trait List {
@@ -93,7 +93,10 @@ pub(crate) fn get_crates(
                     Crate::Registry(RegistryCrate { ref name, .. }) => demo_registry.remove(name),
                     Crate::GitHub(ref repo) => demo_github.remove(&repo.slug()),
                     Crate::Local(ref name) => demo_local.remove(name),
-                    Crate::Git(_) | Crate::Path(_) => unimplemented!("unsupported crate"),
+                    Crate::Git(_)
+                    | Crate::Path(_)
+                    | Crate::AltRegistry(_)
+                    | Crate::Workspace(_) => unimplemented!("unsupported crate"),
                 };
 
                 if add {
@@ -115,6 +118,34 @@ pub(crate) fn get_crates(
         CrateSelect::List(list) => {
             let mut desired = list.clone();
 
+            // Private git repositories aren't part of any list, and are tested as is
+            desired.retain(|krate| {
+                if !is_ssh_url(krate) {
+                    return true;
+                }
+                crates.push(Crate::Git(GitRepo {
+                    url: krate.clone(),
+                    sha: None,
+                }));
+                false
+            });
+
+            // Crates with a pinned version are tested at that version, even if it's not the
+            // latest one in the registry list
+            desired.retain(|krate| match CrateSpec::parse_strict(krate) {
+                Ok(CrateSpec {
+                    name,
+                    version: Some(version),
+                }) => {
+                    crates.push(Crate::Registry(RegistryCrate {
+                        name,
+                        version: version.to_string(),
+                    }));
+                    false
+                }
+                _ => true,
+            });
+
             let mut all_crates = Vec::new();
             all_crates.append(&mut RegistryList::get(db)?);
             all_crates.append(&mut GitHubList::get(db)?);
@@ -144,6 +175,12 @@ pub(crate) fn get_crates(
             crates.shuffle(&mut rng);
             crates.truncate(*n as usize);
         }
+        CrateSelect::Sample { count, seed } => {
+            crates.append(&mut RegistryList::get(db)?);
+            crates.append(&mut GitHubList::get(db)?);
+
+            crates = sample(crates, *count as usize, seed.unwrap_or_else(rand::random));
+        }
         CrateSelect::Top(n) => {
             crates.append(&mut RegistryList::get(db)?);
             crates.truncate(*n as usize);
@@ -151,6 +188,9 @@ pub(crate) fn get_crates(
         CrateSelect::Local => {
             crates.append(&mut LocalList::get(db)?);
         }
+        CrateSelect::Workspace(root) => {
+            crates.append(&mut WorkspaceList::new(root.into()).fetch()?);
+        }
         CrateSelect::Dummy => crates.push(Crate::GitHub(GitHubRepo::dummy())),
     }
 
@@ -158,6 +198,32 @@ pub(crate) fn get_crates(
     Ok(crates)
 }
 
+/// Pick `count` crates out of `crates`, always picking the same ones for the same seed.
+///
+/// Every crate is ranked by a hash of its id keyed with the seed, so the sample doesn't depend
+/// on the order of the lists nor on the version of `rand`.
+pub(crate) fn sample(mut crates: Vec<Crate>, count: usize, seed: u64) -> Vec<Crate> {
+    fn mix(mut x: u64) -> u64 {
+        // splitmix64 finalizer
+        x = (x ^ (x >> 30)).wrapping_mul(0xbf58_476d_1ce4_e5b9);
+        x = (x ^ (x >> 27)).wrapping_mul(0x94d0_49bb_1331_11eb);
+        x ^ (x >> 31)
+    }
+
+    let key = mix(seed.wrapping_add(0x9e37_79b9_7f4a_7c15));
+    let rank = |krate: &Crate| {
+        // FNV-1a of the crate id, starting from the key
+        let hash = krate.id().bytes().fold(key, |hash, byte| {
+            (hash ^ u64::from(byte)).wrapping_mul(0x0100_0000_01b3)
+        });
+        mix(hash)
+    };
+
+    crates.sort_by_cached_key(|krate| (rank(krate), krate.id()));
+    crates.truncate(count);
+    crates
+}
+
 #[cfg(test)]
 pub(crate) fn setup_test_lists(db: &Database, config: &Config) -> Fallible<()> {
     use crate::actions::{Action, ActionsCtx, UpdateLists};
@@ -169,3 +235,46 @@ pub(crate) fn setup_test_lists(db: &Database, config: &Config) -> Fallible<()> {
     }
     .apply(&ActionsCtx::new(db, config))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::sample;
+    use crate::crates::{Crate, RegistryCrate};
+
+    fn population() -> Vec<Crate> {
+        (0..100)
+            .map(|i| {
+                Crate::Registry(RegistryCrate {
+                    name: format!("crate-{i}"),
+                    version: "1.0.0".into(),
+                })
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_sample_is_reproducible() {
+        let first = sample(population(), 10, 42);
+        assert_eq!(first.len(), 10);
+        assert_eq!(first, sample(population(), 10, 42));
+
+        // The order of the candidates doesn't matter
+        let mut reversed = population();
+        reversed.reverse();
+        assert_eq!(first, sample(reversed, 10, 42));
+    }
+
+    #[test]
+    fn test_sample_different_seeds() {
+        assert_ne!(sample(population(), 10, 1), sample(population(), 10, 2));
+    }
+
+    #[test]
+    fn test_sample_larger_than_population() {
+        let mut sampled = sample(population(), 1000, 42);
+        sampled.sort();
+        let mut all = population();
+        all.sort();
+        assert_eq!(sampled, all);
+    }
+}
@@ -1,5 +1,6 @@
 pub(crate) mod lists;
 mod sources;
+mod spec;
 
 use crate::dirs::LOCAL_CRATES_DIR;
 use crate::prelude::*;
@@ -10,8 +11,11 @@ use std::fmt;
 use std::path::Path;
 use std::str::FromStr;
 
+pub(crate) use crate::crates::sources::git::is_ssh_url;
 pub(crate) use crate::crates::sources::github::GitHubRepo;
-pub(crate) use crate::crates::sources::registry::RegistryCrate;
+pub(crate) use crate::crates::sources::registry::{AltRegistryCrate, RegistryCrate};
+pub(crate) use crate::crates::sources::workspace::WorkspaceMember;
+pub(crate) use crate::crates::spec::{CrateSpec, CrateSpecError};
 
 #[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
 pub struct GitRepo {
@@ -26,6 +30,8 @@ pub enum Crate {
     Local(String),
     Path(String),
     Git(GitRepo),
+    AltRegistry(AltRegistryCrate),
+    Workspace(WorkspaceMember),
 }
 
 impl Crate {
@@ -54,6 +60,18 @@ impl Crate {
                     format!("git/{}", utf8_percent_encode(&repo.url, NON_ALPHANUMERIC),)
                 }
             }
+            Crate::AltRegistry(ref details) => format!(
+                "alt/{}/{}/{}/{}",
+                utf8_percent_encode(&details.index_url, NON_ALPHANUMERIC),
+                utf8_percent_encode(&details.dl_url, NON_ALPHANUMERIC),
+                details.name,
+                details.version
+            ),
+            Crate::Workspace(ref member) => format!(
+                "ws/{}/{}",
+                utf8_percent_encode(&member.root, NON_ALPHANUMERIC),
+                utf8_percent_encode(&member.member, NON_ALPHANUMERIC)
+            ),
         }
     }
 
@@ -65,7 +83,15 @@ impl Crate {
             }
             Self::Local(name) => RustwideCrate::local(&LOCAL_CRATES_DIR.join(name)),
             Self::Path(path) => RustwideCrate::local(Path::new(&path)),
+            // Rustwide can't authenticate over SSH or check out a ref, so private repositories
+            // are cloned by `GitRepo::fetch` and then treated as local crates.
+            Self::Git(repo) if repo.is_ssh() => RustwideCrate::local(&repo.local_path()),
             Self::Git(repo) => RustwideCrate::git(&repo.url),
+            // Rustwide can't fetch from sparse indexes or authenticate, so the crate is
+            // downloaded by `AltRegistryCrate::download` and then treated as a local crate.
+            Self::AltRegistry(krate) => RustwideCrate::local(&krate.local_path()),
+            // The workspace is copied by `WorkspaceMember::stage` first
+            Self::Workspace(member) => RustwideCrate::local(&member.staged_path()),
         }
     }
 }
@@ -147,6 +173,9 @@ impl fmt::Display for Crate {
                     } else {
                         utf8_percent_encode(&repo.url, NON_ALPHANUMERIC).to_string()
                     },
+                Crate::AltRegistry(ref krate) =>
+                    format!("{}-{} ({})", krate.name, krate.version, krate.index_url),
+                Crate::Workspace(ref member) => format!("{} ({})", member.member, member.root),
             }
         )
     }
@@ -180,6 +209,16 @@ impl FromStr for Crate {
                 url: percent_decode_str(repo).decode_utf8()?.to_string(),
                 sha: None,
             })),
+            ["alt", index_url, dl_url, name, version] => Ok(Crate::AltRegistry(AltRegistryCrate {
+                index_url: percent_decode_str(index_url).decode_utf8()?.to_string(),
+                dl_url: percent_decode_str(dl_url).decode_utf8()?.to_string(),
+                name: name.to_string(),
+                version: version.to_string(),
+            })),
+            ["ws", root, member] => Ok(Crate::Workspace(WorkspaceMember {
+                root: percent_decode_str(root).decode_utf8()?.to_string(),
+                member: percent_decode_str(member).decode_utf8()?.to_string(),
+            })),
             ["local", name] => Ok(Crate::Local(name.to_string())),
             ["path", path] => Ok(Crate::Path(
                 percent_decode_str(path).decode_utf8()?.to_string(),
@@ -191,7 +230,7 @@ impl FromStr for Crate {
 
 #[cfg(test)]
 mod tests {
-    use super::{Crate, GitHubRepo, GitRepo, RegistryCrate};
+    use super::{AltRegistryCrate, Crate, GitHubRepo, GitRepo, RegistryCrate, WorkspaceMember};
     use cargo_metadata::PackageId;
     use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
     use std::convert::TryFrom;
@@ -287,6 +326,24 @@ mod tests {
             &format!("git/{}", utf8_percent_encode("url/with:stange?characters", NON_ALPHANUMERIC)) => Crate::Git(GitRepo{url: "url/with:stange?characters".to_string(), sha: None}),
             "git/url/sha" => Crate::Git(GitRepo{url: "url".to_string(), sha: Some("sha".to_string())}),
             "reg/name/version" => Crate::Registry(RegistryCrate{name: "name".to_string(), version: "version".to_string()}),
+            &format!(
+                "alt/{}/{}/name/version",
+                utf8_percent_encode("sparse+https://index.example.com/", NON_ALPHANUMERIC),
+                utf8_percent_encode("https://dl.example.com/{crate}", NON_ALPHANUMERIC),
+            ) => Crate::AltRegistry(AltRegistryCrate{
+                index_url: "sparse+https://index.example.com/".to_string(),
+                dl_url: "https://dl.example.com/{crate}".to_string(),
+                name: "name".to_string(),
+                version: "version".to_string(),
+            }),
+            &format!(
+                "ws/{}/{}",
+                utf8_percent_encode("/src/project", NON_ALPHANUMERIC),
+                utf8_percent_encode("crates/foo", NON_ALPHANUMERIC),
+            ) => Crate::Workspace(WorkspaceMember{
+                root: "/src/project".to_string(),
+                member: "crates/foo".to_string(),
+            }),
         }
     }
 }
@@ -0,0 +1,364 @@
+use crate::crates::GitRepo;
+use crate::dirs::GIT_CRATES_DIR;
+use crate::prelude::*;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::env;
+use std::fs;
+use std::path::{Path, PathBuf};
+use std::process::Command;
+use url::form_urlencoded;
+
+/// Environment variable containing the path of the SSH key used to clone private repositories.
+/// The keys of the running ssh-agent are used when it's not set.
+pub(crate) const GIT_SSH_KEY_ENV: &str = "CRATER_GIT_SSH_KEY";
+
+#[derive(Debug, thiserror::Error)]
+pub(crate) enum GitFetchError {
+    #[error("authentication to {0} failed, check the SSH key or agent can access it")]
+    AuthFailed(String),
+    #[error("repository {0} not found")]
+    NotFound(String),
+    #[error("revision {1} not found in {0}")]
+    RevisionNotFound(String, String),
+}
+
+/// Revision a repository is tested at, selected like cargo's git sources with the `branch`,
+/// `tag` or `rev` query parameter of the URL.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum GitRef {
+    DefaultBranch,
+    Branch(String),
+    Tag(String),
+    Rev(String),
+}
+
+impl GitRef {
+    /// Revision to check out once the repository is fetched.
+    fn revision(&self) -> String {
+        match self {
+            GitRef::DefaultBranch => "origin/HEAD".into(),
+            GitRef::Branch(branch) => format!("origin/{branch}"),
+            GitRef::Tag(tag) => format!("refs/tags/{tag}"),
+            GitRef::Rev(rev) => rev.clone(),
+        }
+    }
+}
+
+/// Where a git crate is cloned from and which revision is tested.
+#[derive(Debug, PartialEq, Eq)]
+pub(crate) struct GitSource {
+    pub(crate) remote: String,
+    pub(crate) reference: GitRef,
+}
+
+/// Whether the URL points to a repository accessed over SSH, either as `ssh://` (optionally
+/// prefixed with `git+`) or with the scp-like `user@host:path` syntax.
+pub(crate) fn is_ssh_url(url: &str) -> bool {
+    let url = url.strip_prefix("git+").unwrap_or(url);
+    if url.starts_with("ssh://") {
+        return true;
+    }
+    !url.contains("://")
+        && matches!(url.split_once(':'), Some((host, path)) if host.contains('@') && !path.is_empty())
+}
+
+/// How git authenticates to the SSH servers.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub(crate) enum SshAuth {
+    Key(PathBuf),
+    Agent,
+}
+
+impl SshAuth {
+    /// The key in `CRATER_GIT_SSH_KEY` if it's set, the running ssh-agent otherwise.
+    pub(crate) fn from_env() -> Self {
+        match env::var_os(GIT_SSH_KEY_ENV) {
+            Some(key) if !key.is_empty() => SshAuth::Key(key.into()),
+            _ => SshAuth::Agent,
+        }
+    }
+
+    /// Command git runs to connect to the server. Batch mode makes ssh fail instead of asking
+    /// for a password or a passphrase, and only unknown hosts are trusted on first use.
+    fn ssh_command(&self) -> String {
+        let mut command = "ssh -o BatchMode=yes -o StrictHostKeyChecking=accept-new".to_string();
+        if let SshAuth::Key(key) = self {
+            let key = key.to_string_lossy().replace('\'', "'\\''");
+            command.push_str(&format!(" -o IdentitiesOnly=yes -i '{key}'"));
+        }
+        command
+    }
+}
+
+/// git command authenticating with `auth`, run in `dir` if provided.
+fn git_command(auth: &SshAuth, dir: Option<&Path>, args: &[&str]) -> Command {
+    let mut command = Command::new("git");
+    if let Some(dir) = dir {
+        command.current_dir(dir);
+    }
+    command
+        .args(args)
+        .env("GIT_SSH_COMMAND", auth.ssh_command())
+        .env("GIT_TERMINAL_PROMPT", "0");
+    command
+}
+
+/// Explain a failed git command from its output, telling authentication failures apart from
+/// missing repositories and revisions.
+fn classify_failure(remote: &str, revision: &str, stderr: &str) -> Option<GitFetchError> {
+    const AUTH_FAILURES: &[&str] = &[
+        "Permission denied (publickey",
+        "Host key verification failed",
+        "Authentication failed",
+        "no such identity",
+    ];
+    const NOT_FOUND: &[&str] = &[
+        "Repository not found",
+        "repository not found",
+        "does not appear to be a git repository",
+        "does not exist",
+    ];
+    const REVISION_NOT_FOUND: &[&str] = &["unknown revision", "did not match any", "pathspec"];
+
+    if AUTH_FAILURES.iter().any(|msg| stderr.contains(msg)) {
+        Some(GitFetchError::AuthFailed(remote.into()))
+    } else if NOT_FOUND.iter().any(|msg| stderr.contains(msg)) {
+        Some(GitFetchError::NotFound(remote.into()))
+    } else if REVISION_NOT_FOUND.iter().any(|msg| stderr.contains(msg)) {
+        Some(GitFetchError::RevisionNotFound(
+            remote.into(),
+            revision.into(),
+        ))
+    } else {
+        None
+    }
+}
+
+impl GitRepo {
+    pub(crate) fn is_ssh(&self) -> bool {
+        is_ssh_url(&self.url)
+    }
+
+    /// Split the URL into the remote and the revision to test. A recorded sha takes precedence
+    /// over the ref in the URL.
+    pub(crate) fn source(&self) -> Fallible<GitSource> {
+        let url = self.url.strip_prefix("git+").unwrap_or(&self.url);
+        let (remote, query) = match url.split_once('?') {
+            Some((remote, query)) => (remote, query),
+            None => (url, ""),
+        };
+
+        let mut reference = GitRef::DefaultBranch;
+        for (key, value) in form_urlencoded::parse(query.as_bytes()) {
+            if reference != GitRef::DefaultBranch {
+                bail!("more than one ref in the git url {}", self.url);
+            }
+            reference = match &*key {
+                "branch" => GitRef::Branch(value.into_owned()),
+                "tag" => GitRef::Tag(value.into_owned()),
+                "rev" => GitRef::Rev(value.into_owned()),
+                other => bail!("unknown parameter `{}` in the git url {}", other, self.url),
+            };
+        }
+        if let Some(sha) = &self.sha {
+            reference = GitRef::Rev(sha.clone());
+        }
+
+        Ok(GitSource {
+            remote: remote.into(),
+            reference,
+        })
+    }
+
+    /// Cached clone of the repository, checked out at the tested revision before being handed
+    /// to rustwide.
+    pub(crate) fn local_path(&self) -> PathBuf {
+        GIT_CRATES_DIR.join(utf8_percent_encode(&self.url, NON_ALPHANUMERIC).to_string())
+    }
+
+    /// Clone the repository into `local_path`, or update the existing clone, authenticating
+    /// with `SshAuth::from_env`, and check out the tested revision.
+    pub(crate) fn fetch(&self) -> Fallible<()> {
+        let source = self.source()?;
+        let revision = source.reference.revision();
+        let auth = SshAuth::from_env();
+        let dest = self.local_path();
+        let run = |dir: Option<&Path>, args: &[&str]| -> Fallible<()> {
+            let output = git_command(&auth, dir, args).output()?;
+            if output.status.success() {
+                return Ok(());
+            }
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            match classify_failure(&source.remote, &revision, &stderr) {
+                Some(err) => Err(err.into()),
+                None => bail!("git {} failed: {}", args[0], stderr.trim()),
+            }
+        };
+
+        if dest.join(".git").is_dir() {
+            run(
+                Some(&dest),
+                &["fetch", "--force", "--tags", "--prune", "origin"],
+            )?;
+        } else {
+            if dest.exists() {
+                fs::remove_dir_all(&dest)?;
+            }
+            fs::create_dir_all(&*GIT_CRATES_DIR)?;
+            run(
+                None,
+                &[
+                    "clone",
+                    "--no-checkout",
+                    source.remote.as_str(),
+                    &*dest.to_string_lossy(),
+                ],
+            )?;
+        }
+
+        run(
+            Some(&dest),
+            &["checkout", "--force", "--detach", revision.as_str()],
+        )?;
+        run(Some(&dest), &["clean", "-ffdx"])?;
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{classify_failure, git_command, is_ssh_url, GitFetchError, GitRef, SshAuth};
+    use crate::crates::GitRepo;
+    use std::ffi::OsStr;
+    use std::path::Path;
+
+    fn repo(url: &str, sha: Option<&str>) -> GitRepo {
+        GitRepo {
+            url: url.into(),
+            sha: sha.map(String::from),
+        }
+    }
+
+    #[test]
+    fn test_is_ssh_url() {
+        assert!(is_ssh_url("ssh://git@example.com/org/repo.git"));
+        assert!(is_ssh_url("git+ssh://git@example.com:2222/org/repo.git"));
+        assert!(is_ssh_url("git@example.com:org/repo.git"));
+        assert!(is_ssh_url("git@example.com:org/repo.git?tag=v1.0.0"));
+        assert!(!is_ssh_url("https://github.com/org/repo"));
+        assert!(!is_ssh_url("git+https://user@example.com/org/repo"));
+        assert!(!is_ssh_url("/local/path:with-colon"));
+        assert!(!is_ssh_url("git@example.com:"));
+    }
+
+    #[test]
+    fn test_source() {
+        let source = repo("git+ssh://git@example.com/org/repo.git", None)
+            .source()
+            .unwrap();
+        assert_eq!(source.remote, "ssh://git@example.com/org/repo.git");
+        assert_eq!(source.reference, GitRef::DefaultBranch);
+
+        let refs = [
+            ("?branch=release/1.x", GitRef::Branch("release/1.x".into())),
+            ("?tag=v1.0.0", GitRef::Tag("v1.0.0".into())),
+            ("?rev=9823f01", GitRef::Rev("9823f01".into())),
+        ];
+        for (query, reference) in refs {
+            let source = repo(&format!("git@example.com:org/repo.git{query}"), None)
+                .source()
+                .unwrap();
+            assert_eq!(source.remote, "git@example.com:org/repo.git");
+            assert_eq!(source.reference, reference);
+        }
+
+        // The recorded sha wins over the ref of the url
+        let source = repo("git@example.com:org/repo.git?branch=main", Some("abcdef"))
+            .source()
+            .unwrap();
+        assert_eq!(source.reference, GitRef::Rev("abcdef".into()));
+
+        assert!(repo("git@example.com:org/repo.git?branch=a&tag=b", None)
+            .source()
+            .is_err());
+        assert!(repo("git@example.com:org/repo.git?depth=1", None)
+            .source()
+            .is_err());
+    }
+
+    #[test]
+    fn test_git_command() {
+        let env = |auth: &SshAuth| {
+            let command = git_command(auth, Some(Path::new("/tmp/repo")), &["fetch", "origin"]);
+            assert_eq!(command.get_program(), "git");
+            assert_eq!(
+                command.get_args().collect::<Vec<_>>(),
+                vec![OsStr::new("fetch"), OsStr::new("origin")]
+            );
+            assert_eq!(command.get_current_dir(), Some(Path::new("/tmp/repo")));
+            command
+                .get_envs()
+                .map(|(var, value)| {
+                    (
+                        var.to_string_lossy().into_owned(),
+                        value.unwrap().to_string_lossy().into_owned(),
+                    )
+                })
+                .collect::<Vec<_>>()
+        };
+
+        let key = env(&SshAuth::Key("/keys/deploy key's".into()));
+        assert!(key.contains(&("GIT_TERMINAL_PROMPT".into(), "0".into())));
+        assert!(key.contains(&(
+            "GIT_SSH_COMMAND".into(),
+            "ssh -o BatchMode=yes -o StrictHostKeyChecking=accept-new -o IdentitiesOnly=yes \
+             -i '/keys/deploy key'\\''s'"
+                .into()
+        )));
+
+        // The agent is reached through the inherited `SSH_AUTH_SOCK`
+        let agent = env(&SshAuth::Agent);
+        assert!(agent.contains(&(
+            "GIT_SSH_COMMAND".into(),
+            "ssh -o BatchMode=yes -o StrictHostKeyChecking=accept-new".into()
+        )));
+    }
+
+    #[test]
+    fn test_classify_failure() {
+        let classify = |stderr| classify_failure("git@example.com:org/repo.git", "v1", stderr);
+
+        assert!(matches!(
+            classify(
+                "git@example.com: Permission denied (publickey).\nfatal: Could not read from \
+                 remote repository."
+            ),
+            Some(GitFetchError::AuthFailed(_))
+        ));
+        assert!(matches!(
+            classify(
+                "Host key verification failed.\nfatal: Could not read from remote repository."
+            ),
+            Some(GitFetchError::AuthFailed(_))
+        ));
+        assert!(matches!(
+            classify("ERROR: Repository not found.\nfatal: Could not read from remote repository."),
+            Some(GitFetchError::NotFound(_))
+        ));
+        assert!(matches!(
+            classify("fatal: '/org/repo.git' does not appear to be a git repository"),
+            Some(GitFetchError::NotFound(_))
+        ));
+        assert!(matches!(
+            classify("error: pathspec 'v1' did not match any file(s) known to git"),
+            Some(GitFetchError::RevisionNotFound(_, _))
+        ));
+        assert!(classify("fatal: unable to access: Connection timed out").is_none());
+
+        assert_eq!(
+            GitFetchError::AuthFailed("git@example.com:org/repo.git".into()).to_string(),
+            "authentication to git@example.com:org/repo.git failed, check the SSH key or agent \
+             can access it"
+        );
+    }
+}
@@ -1,9 +1,23 @@
 use crate::crates::{lists::List, Crate};
-use crate::dirs::WORK_DIR;
+use crate::dirs::{ALT_REGISTRY_CRATES_DIR, WORK_DIR};
 use crate::prelude::*;
+use crate::utils;
 use crates_index::GitIndex;
+use flate2::read::GzDecoder;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use reqwest::header::AUTHORIZATION;
+use reqwest::Method;
 use std::collections::HashMap;
+use std::env;
 use std::fs::{self};
+use std::path::PathBuf;
+use tar::Archive;
+
+/// Environment variable containing the token used to authenticate with alternative registries.
+pub(crate) const REGISTRY_TOKEN_ENV: &str = "CRATER_REGISTRY_TOKEN";
+
+/// Sparse index of crates.io, letting a single crate be looked up without cloning the git index.
+const CRATES_IO_SPARSE_INDEX: &str = "https://index.crates.io";
 
 pub(crate) struct RegistryList;
 
@@ -64,3 +78,248 @@ pub struct RegistryCrate {
     pub name: String,
     pub version: String,
 }
+
+impl RegistryCrate {
+    /// Whether this version is yanked, according to the crates.io index.
+    pub(crate) fn is_yanked(&self) -> Fallible<bool> {
+        let url = format!("{}/{}", CRATES_IO_SPARSE_INDEX, index_path(&self.name));
+        let index_file = utils::http::get_sync(&url)?.text()?;
+        // Versions missing from the index are left to fail when they're downloaded
+        Ok(version_yanked(&index_file, &self.version)?.unwrap_or(false))
+    }
+}
+
+#[derive(Deserialize)]
+struct IndexVersion {
+    vers: String,
+    #[serde(default)]
+    yanked: bool,
+}
+
+/// Whether `version` is yanked in the index file of a crate, which contains a JSON object for
+/// each published version. Returns `None` if the version was never published.
+fn version_yanked(index_file: &str, version: &str) -> Fallible<Option<bool>> {
+    for line in index_file.lines().filter(|line| !line.trim().is_empty()) {
+        let entry: IndexVersion = serde_json::from_str(line)?;
+        if entry.vers == version {
+            return Ok(Some(entry.yanked));
+        }
+    }
+    Ok(None)
+}
+
+/// A crate published on a registry other than crates.io, speaking the same protocols.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+pub struct AltRegistryCrate {
+    /// URL of the registry index, prefixed with `sparse+` if it's a sparse index.
+    pub index_url: String,
+    /// Download endpoint of the registry, with the same format as the `dl` key of the index's
+    /// `config.json`.
+    pub dl_url: String,
+    pub name: String,
+    pub version: String,
+}
+
+/// Directory containing a crate in an index, without changing the casing of the name.
+fn prefix(name: &str) -> String {
+    match name.len() {
+        1 => "1".to_string(),
+        2 => "2".to_string(),
+        3 => format!("3/{}", &name[..1]),
+        _ => format!("{}/{}", &name[..2], &name[2..4]),
+    }
+}
+
+/// Path of a crate's file inside an index, using the same layout for git and sparse indexes.
+pub(crate) fn index_path(name: &str) -> String {
+    let name = name.to_lowercase();
+    format!("{}/{name}", prefix(&name))
+}
+
+impl AltRegistryCrate {
+    pub fn is_sparse(&self) -> bool {
+        self.index_url.starts_with("sparse+")
+    }
+
+    /// URL of the crate's index file, if the registry has a sparse index. Git indexes have to be
+    /// cloned, and the file is then at `index_path` inside the repository.
+    pub fn sparse_index_url(&self) -> Option<String> {
+        self.index_url
+            .strip_prefix("sparse+")
+            .map(|base| format!("{}/{}", base.trim_end_matches('/'), index_path(&self.name)))
+    }
+
+    /// URL the `.crate` file can be downloaded from.
+    pub fn download_url(&self) -> Fallible<String> {
+        const MARKERS: &[&str] = &["{crate}", "{version}", "{prefix}", "{lowerprefix}"];
+
+        if self.dl_url.contains("{sha256-checksum}") {
+            bail!(
+                "the {{sha256-checksum}} marker in {} isn't supported",
+                self.dl_url
+            );
+        }
+        if !MARKERS.iter().any(|marker| self.dl_url.contains(marker)) {
+            return Ok(format!(
+                "{}/{}/{}/download",
+                self.dl_url.trim_end_matches('/'),
+                self.name,
+                self.version
+            ));
+        }
+
+        let prefix = prefix(&self.name);
+        Ok(self
+            .dl_url
+            .replace("{crate}", &self.name)
+            .replace("{version}", &self.version)
+            .replace("{lowerprefix}", &prefix.to_lowercase())
+            .replace("{prefix}", &prefix))
+    }
+
+    /// Directory the crate's source is extracted into before being handed to rustwide.
+    pub(crate) fn local_path(&self) -> PathBuf {
+        ALT_REGISTRY_CRATES_DIR
+            .join(utf8_percent_encode(&self.index_url, NON_ALPHANUMERIC).to_string())
+            .join(format!("{}-{}", self.name, self.version))
+    }
+
+    /// Download the crate and extract it into `local_path`, authenticating with the token in
+    /// `CRATER_REGISTRY_TOKEN` if it's set.
+    pub(crate) fn download(&self) -> Fallible<()> {
+        let url = self.download_url()?;
+        let token = env::var_os(REGISTRY_TOKEN_ENV);
+        let response = utils::http::send_throttled(|| {
+            let request = utils::http::prepare_sync(Method::GET, &url);
+            match &token {
+                Some(token) => {
+                    request.header(AUTHORIZATION, format!("Bearer {}", token.to_string_lossy()))
+                }
+                None => request,
+            }
+        })?
+        .error_for_status()
+        .with_context(|_| format!("failed to download {} from {}", self.name, url))?;
+
+        let dest = self.local_path();
+        if dest.exists() {
+            fs::remove_dir_all(&dest)?;
+        }
+        fs::create_dir_all(&dest)?;
+
+        // Crate tarballs contain a single `{name}-{version}` directory, which is stripped
+        let root = format!("{}-{}", self.name, self.version);
+        let mut archive = Archive::new(GzDecoder::new(response));
+        for entry in archive.entries()? {
+            let mut entry = entry?;
+            let path = entry.path()?.into_owned();
+            let Ok(relative) = path.strip_prefix(&root) else {
+                bail!(
+                    "unexpected path in the tarball of {}: {:?}",
+                    self.name,
+                    path
+                );
+            };
+            if relative.as_os_str().is_empty() {
+                continue;
+            }
+            let target = dest.join(relative);
+            if let Some(parent) = target.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            entry.unpack(target)?;
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{index_path, version_yanked, AltRegistryCrate};
+
+    fn krate(index_url: &str, dl_url: &str, name: &str) -> AltRegistryCrate {
+        AltRegistryCrate {
+            index_url: index_url.into(),
+            dl_url: dl_url.into(),
+            name: name.into(),
+            version: "1.2.3".into(),
+        }
+    }
+
+    #[test]
+    fn test_index_path() {
+        assert_eq!(index_path("a"), "1/a");
+        assert_eq!(index_path("ab"), "2/ab");
+        assert_eq!(index_path("abc"), "3/a/abc");
+        assert_eq!(index_path("Serde"), "se/rd/serde");
+    }
+
+    #[test]
+    fn test_git_index_urls() {
+        let git = krate(
+            "https://git.example.com/index.git",
+            "https://dl.example.com/api/v1/crates",
+            "Serde",
+        );
+        assert!(!git.is_sparse());
+        assert_eq!(git.sparse_index_url(), None);
+        assert_eq!(
+            git.download_url().unwrap(),
+            "https://dl.example.com/api/v1/crates/Serde/1.2.3/download"
+        );
+
+        let templated = krate(
+            "https://git.example.com/index.git",
+            "https://dl.example.com/{lowerprefix}/{prefix}/{crate}-{version}.crate",
+            "Serde",
+        );
+        assert_eq!(
+            templated.download_url().unwrap(),
+            "https://dl.example.com/se/rd/Se/rd/Serde-1.2.3.crate"
+        );
+
+        let checksum = krate(
+            "https://git.example.com/index.git",
+            "https://dl.example.com/{sha256-checksum}",
+            "serde",
+        );
+        assert!(checksum.download_url().is_err());
+    }
+
+    #[test]
+    fn test_sparse_index_urls() {
+        let sparse = krate(
+            "sparse+https://index.example.com/",
+            "https://dl.example.com/{crate}/{version}",
+            "foo",
+        );
+        assert!(sparse.is_sparse());
+        assert_eq!(
+            sparse.sparse_index_url().unwrap(),
+            "https://index.example.com/3/f/foo"
+        );
+        assert_eq!(
+            sparse.download_url().unwrap(),
+            "https://dl.example.com/foo/1.2.3"
+        );
+    }
+
+    #[test]
+    fn test_version_yanked() {
+        let index_file = concat!(
+            r#"{"name":"foo","vers":"0.1.0","deps":[],"cksum":"00","features":{},"yanked":false}"#,
+            "\n",
+            r#"{"name":"foo","vers":"0.2.0","deps":[],"cksum":"01","features":{},"yanked":true}"#,
+            "\n",
+            r#"{"name":"foo","vers":"0.3.0","deps":[],"cksum":"02","features":{}}"#,
+            "\n",
+        );
+
+        assert_eq!(version_yanked(index_file, "0.1.0").unwrap(), Some(false));
+        assert_eq!(version_yanked(index_file, "0.2.0").unwrap(), Some(true));
+        assert_eq!(version_yanked(index_file, "0.3.0").unwrap(), Some(false));
+        assert_eq!(version_yanked(index_file, "0.4.0").unwrap(), None);
+        assert!(version_yanked("not json", "0.1.0").is_err());
+    }
+}
@@ -0,0 +1,263 @@
+use crate::crates::Crate;
+use crate::dirs::WORKSPACE_CRATES_DIR;
+use crate::prelude::*;
+use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
+use std::collections::BTreeSet;
+use std::fs;
+use std::path::{Path, PathBuf};
+use walkdir::WalkDir;
+
+/// A member of a local cargo workspace, or a standalone local crate.
+#[derive(Debug, Eq, PartialEq, Ord, PartialOrd, Hash, Serialize, Deserialize, Clone)]
+pub struct WorkspaceMember {
+    /// Root directory of the workspace.
+    pub root: String,
+    /// Path of the member relative to the root, `.` for the root package.
+    pub member: String,
+}
+
+impl WorkspaceMember {
+    /// Directory the workspace is copied into before being handed to rustwide.
+    pub(crate) fn staged_path(&self) -> PathBuf {
+        WORKSPACE_CRATES_DIR
+            .join(utf8_percent_encode(&self.root, NON_ALPHANUMERIC).to_string())
+            .join(utf8_percent_encode(&self.member, NON_ALPHANUMERIC).to_string())
+    }
+
+    /// Copy the whole workspace into `staged_path`, so path dependencies between members keep
+    /// resolving, and make the member the only default member so cargo commands run at the root
+    /// only act on it.
+    pub(crate) fn stage(&self) -> Fallible<()> {
+        let root = Path::new(&self.root);
+        let dest = self.staged_path();
+        if dest.exists() {
+            fs::remove_dir_all(&dest)?;
+        }
+        fs::create_dir_all(&dest)?;
+
+        let entries = WalkDir::new(root)
+            .min_depth(1)
+            .into_iter()
+            .filter_entry(|entry| {
+                entry.depth() != 1 || (entry.file_name() != "target" && entry.file_name() != ".git")
+            });
+        for entry in entries {
+            let entry = entry?;
+            let target = dest.join(entry.path().strip_prefix(root)?);
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(&target)?;
+            } else {
+                fs::copy(entry.path(), &target)
+                    .with_context(|_| format!("failed to copy {}", entry.path().display()))?;
+            }
+        }
+
+        let manifest_path = dest.join("Cargo.toml");
+        let mut manifest: toml::Value = toml::from_str(&fs::read_to_string(&manifest_path)?)?;
+        if let Some(workspace) = manifest.get_mut("workspace").and_then(|w| w.as_table_mut()) {
+            workspace.insert(
+                "default-members".into(),
+                toml::Value::Array(vec![self.member.clone().into()]),
+            );
+            fs::write(&manifest_path, toml::to_string(&manifest)?)?;
+        }
+
+        Ok(())
+    }
+}
+
+/// Source enumerating the members of a local workspace.
+pub(crate) struct WorkspaceList {
+    root: PathBuf,
+}
+
+impl WorkspaceList {
+    pub(crate) fn new(root: PathBuf) -> Self {
+        WorkspaceList { root }
+    }
+
+    /// List the members of the workspace, expanding the globs in `workspace.members` and
+    /// honoring `workspace.exclude`. A crate without a `[workspace]` is its own single member.
+    pub(crate) fn fetch(&self) -> Fallible<Vec<Crate>> {
+        let manifest_path = self.root.join("Cargo.toml");
+        let manifest: toml::Value = toml::from_str(
+            &fs::read_to_string(&manifest_path)
+                .with_context(|_| format!("failed to read {}", manifest_path.display()))?,
+        )?;
+
+        let mut members = BTreeSet::new();
+        if manifest.get("package").is_some() {
+            members.insert(PathBuf::from("."));
+        }
+
+        if let Some(workspace) = manifest.get("workspace") {
+            let excluded = string_array(workspace, "exclude")?
+                .into_iter()
+                .map(PathBuf::from)
+                .collect::<Vec<_>>();
+            for pattern in string_array(workspace, "members")? {
+                for member in expand_glob(&self.root, &pattern)? {
+                    if self.root.join(&member).join("Cargo.toml").is_file()
+                        && !excluded.iter().any(|excluded| member.starts_with(excluded))
+                    {
+                        members.insert(member);
+                    }
+                }
+            }
+        }
+
+        if members.is_empty() {
+            bail!(
+                "{} contains neither a package nor a workspace",
+                manifest_path.display()
+            );
+        }
+
+        let root = self.root.to_string_lossy().into_owned();
+        Ok(members
+            .into_iter()
+            .map(|member| {
+                Crate::Workspace(WorkspaceMember {
+                    root: root.clone(),
+                    member: member.to_string_lossy().into_owned(),
+                })
+            })
+            .collect())
+    }
+}
+
+fn string_array(table: &toml::Value, key: &str) -> Fallible<Vec<String>> {
+    match table.get(key) {
+        None => Ok(Vec::new()),
+        Some(toml::Value::Array(items)) => items
+            .iter()
+            .map(|item| {
+                item.as_str()
+                    .map(String::from)
+                    .ok_or_else(|| err_msg(format!("workspace.{key} must only contain strings")))
+            })
+            .collect(),
+        Some(_) => bail!("workspace.{} must be an array", key),
+    }
+}
+
+/// Expand a workspace member pattern into the matching paths, relative to `root`. Only the `*`
+/// and `?` wildcards are supported, which is what workspaces use in practice.
+fn expand_glob(root: &Path, pattern: &str) -> Fallible<Vec<PathBuf>> {
+    let mut paths = vec![PathBuf::new()];
+    for component in pattern.split('/').filter(|c| !c.is_empty() && *c != ".") {
+        if !component.contains(['*', '?']) {
+            for path in &mut paths {
+                path.push(component);
+            }
+            continue;
+        }
+
+        let mut expanded = Vec::new();
+        for path in paths {
+            let dir = root.join(&path);
+            if !dir.is_dir() {
+                continue;
+            }
+            for entry in fs::read_dir(dir)? {
+                let entry = entry?;
+                if entry.file_type()?.is_dir()
+                    && wildcard_match(component, &entry.file_name().to_string_lossy())
+                {
+                    expanded.push(path.join(entry.file_name()));
+                }
+            }
+        }
+        paths = expanded;
+    }
+    Ok(paths)
+}
+
+fn wildcard_match(pattern: &str, name: &str) -> bool {
+    let mut chars = name.chars();
+    match pattern.chars().next() {
+        None => name.is_empty(),
+        Some('*') => name
+            .char_indices()
+            .map(|(i, _)| i)
+            .chain(std::iter::once(name.len()))
+            .any(|i| wildcard_match(&pattern[1..], &name[i..])),
+        Some(p) => match chars.next() {
+            Some(c) if p == '?' || p == c => {
+                wildcard_match(&pattern[p.len_utf8()..], chars.as_str())
+            }
+            _ => false,
+        },
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{wildcard_match, WorkspaceList, WorkspaceMember};
+    use crate::crates::Crate;
+    use std::fs;
+    use std::path::Path;
+
+    fn write_crate(dir: &Path, name: &str) {
+        fs::create_dir_all(dir.join("src")).unwrap();
+        fs::write(
+            dir.join("Cargo.toml"),
+            format!("[package]\nname = \"{name}\"\nversion = \"0.1.0\"\n"),
+        )
+        .unwrap();
+        fs::write(dir.join("src").join("lib.rs"), "").unwrap();
+    }
+
+    fn members(root: &Path) -> Vec<String> {
+        WorkspaceList::new(root.into())
+            .fetch()
+            .unwrap()
+            .into_iter()
+            .map(|krate| match krate {
+                Crate::Workspace(WorkspaceMember { member, .. }) => member,
+                other => panic!("unexpected crate {other}"),
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_workspace_members() {
+        let root = tempfile::tempdir().unwrap();
+        fs::write(
+            root.path().join("Cargo.toml"),
+            "[workspace]\nmembers = [\"crates/*\", \"tools/cli\"]\nexclude = [\"crates/skipped\"]\n",
+        )
+        .unwrap();
+        write_crate(&root.path().join("crates/foo"), "foo");
+        write_crate(&root.path().join("crates/bar"), "bar");
+        write_crate(&root.path().join("crates/skipped"), "skipped");
+        write_crate(&root.path().join("tools/cli"), "cli");
+        // Directories matching the glob without a manifest aren't members
+        fs::create_dir_all(root.path().join("crates/empty")).unwrap();
+
+        assert_eq!(
+            members(root.path()),
+            vec!["crates/bar", "crates/foo", "tools/cli"]
+        );
+    }
+
+    #[test]
+    fn test_single_crate() {
+        let root = tempfile::tempdir().unwrap();
+        write_crate(root.path(), "single");
+        assert_eq!(members(root.path()), vec!["."]);
+
+        let empty = tempfile::tempdir().unwrap();
+        fs::write(empty.path().join("Cargo.toml"), "").unwrap();
+        assert!(WorkspaceList::new(empty.path().into()).fetch().is_err());
+    }
+
+    #[test]
+    fn test_wildcard_match() {
+        assert!(wildcard_match("*", "foo"));
+        assert!(wildcard_match("foo-*", "foo-bar"));
+        assert!(wildcard_match("f?o", "foo"));
+        assert!(!wildcard_match("foo-*", "bar-foo"));
+        assert!(!wildcard_match("f?o", "fo"));
+    }
+}
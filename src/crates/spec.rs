@@ -0,0 +1,180 @@
+use cargo_metadata::semver::Version;
+
+/// Longest crate name accepted by crates.io.
+const MAX_NAME_LEN: usize = 64;
+
+#[derive(Debug, thiserror::Error, PartialEq, Eq)]
+pub enum CrateSpecError {
+    #[error("missing crate name in `{0}`")]
+    EmptyName(String),
+    #[error("missing version in `{0}`")]
+    EmptyVersion(String),
+    #[error("unexpected whitespace in `{0}`")]
+    Whitespace(String),
+    #[error("crate name `{0}` is longer than {MAX_NAME_LEN} characters")]
+    NameTooLong(String),
+    #[error("crate name `{0}` must start with an ASCII letter")]
+    NameStart(String),
+    #[error("invalid character `{ch}` in crate name `{name}`")]
+    NameChar { name: String, ch: char },
+    #[error("version `{0}` must not start with `v`")]
+    VersionPrefix(String),
+    #[error("invalid version `{version}`: {reason}")]
+    Version { version: String, reason: String },
+}
+
+/// Crate of the registry to test, optionally pinned to a version.
+#[derive(Debug, PartialEq, Eq)]
+pub struct CrateSpec {
+    pub name: String,
+    pub version: Option<Version>,
+}
+
+impl CrateSpec {
+    /// Parse a `name` or `name@version` spec, checking the name against the crates.io naming
+    /// rules and the version against semver. Unlike cargo nothing is trimmed or normalized, so
+    /// malformed specs are rejected here instead of failing to be fetched later.
+    pub fn parse_strict(spec: &str) -> Result<CrateSpec, CrateSpecError> {
+        if spec.chars().any(char::is_whitespace) {
+            return Err(CrateSpecError::Whitespace(spec.into()));
+        }
+
+        let (name, version) = match spec.split_once('@') {
+            Some((name, version)) => (name, Some(version)),
+            None => (spec, None),
+        };
+        validate_name(name, spec)?;
+
+        let version = match version {
+            None => None,
+            Some("") => return Err(CrateSpecError::EmptyVersion(spec.into())),
+            Some(version) if version.starts_with(['v', 'V']) => {
+                return Err(CrateSpecError::VersionPrefix(version.into()));
+            }
+            Some(version) => {
+                Some(
+                    Version::parse(version).map_err(|err| CrateSpecError::Version {
+                        version: version.into(),
+                        reason: err.to_string(),
+                    })?,
+                )
+            }
+        };
+
+        Ok(CrateSpec {
+            name: name.into(),
+            version,
+        })
+    }
+}
+
+fn validate_name(name: &str, spec: &str) -> Result<(), CrateSpecError> {
+    let Some(first) = name.chars().next() else {
+        return Err(CrateSpecError::EmptyName(spec.into()));
+    };
+    if name.chars().count() > MAX_NAME_LEN {
+        return Err(CrateSpecError::NameTooLong(name.into()));
+    }
+    if !first.is_ascii_alphabetic() {
+        return Err(CrateSpecError::NameStart(name.into()));
+    }
+    if let Some(ch) = name
+        .chars()
+        .find(|&ch| !ch.is_ascii_alphanumeric() && ch != '-' && ch != '_')
+    {
+        return Err(CrateSpecError::NameChar {
+            name: name.into(),
+            ch,
+        });
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{CrateSpec, CrateSpecError};
+    use cargo_metadata::semver::Version;
+
+    #[test]
+    fn test_parse_strict() {
+        let long = "a".repeat(65);
+        let max = "a".repeat(64);
+
+        let valid = [
+            ("serde", "serde", None),
+            ("lazy_static@1.4.0", "lazy_static", Some("1.4.0")),
+            ("tokio-util@0.7.10", "tokio-util", Some("0.7.10")),
+            ("Inflector@0.11.4", "Inflector", Some("0.11.4")),
+            (
+                "a1@1.0.0-beta.2+build.5",
+                "a1",
+                Some("1.0.0-beta.2+build.5"),
+            ),
+            (max.as_str(), max.as_str(), None),
+        ];
+        for (spec, name, version) in valid {
+            assert_eq!(
+                CrateSpec::parse_strict(spec),
+                Ok(CrateSpec {
+                    name: name.into(),
+                    version: version.map(|v| Version::parse(v).unwrap()),
+                }),
+                "{spec}"
+            );
+        }
+
+        let invalid = [
+            ("", CrateSpecError::EmptyName("".into())),
+            ("@1.0.0", CrateSpecError::EmptyName("@1.0.0".into())),
+            ("serde@", CrateSpecError::EmptyVersion("serde@".into())),
+            (" serde", CrateSpecError::Whitespace(" serde".into())),
+            (
+                "serde @1.0.0",
+                CrateSpecError::Whitespace("serde @1.0.0".into()),
+            ),
+            (
+                "serde@1.0.0\n",
+                CrateSpecError::Whitespace("serde@1.0.0\n".into()),
+            ),
+            (
+                "serde@v1.0.0",
+                CrateSpecError::VersionPrefix("v1.0.0".into()),
+            ),
+            (long.as_str(), CrateSpecError::NameTooLong(long.clone())),
+            ("1password", CrateSpecError::NameStart("1password".into())),
+            ("_private", CrateSpecError::NameStart("_private".into())),
+            (
+                "sérde@1.0.0",
+                CrateSpecError::NameChar {
+                    name: "sérde".into(),
+                    ch: 'é',
+                },
+            ),
+            ("крейт", CrateSpecError::NameStart("крейт".into())),
+            (
+                "serde.json",
+                CrateSpecError::NameChar {
+                    name: "serde.json".into(),
+                    ch: '.',
+                },
+            ),
+            (
+                "serde@1.0@2.0",
+                CrateSpecError::Version {
+                    version: "1.0@2.0".into(),
+                    reason: Version::parse("1.0@2.0").unwrap_err().to_string(),
+                },
+            ),
+            (
+                "serde@1.0",
+                CrateSpecError::Version {
+                    version: "1.0".into(),
+                    reason: Version::parse("1.0").unwrap_err().to_string(),
+                },
+            ),
+        ];
+        for (spec, err) in invalid {
+            assert_eq!(CrateSpec::parse_strict(spec), Err(err), "{spec:?}");
+        }
+    }
+}
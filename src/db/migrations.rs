@@ -362,6 +362,104 @@ fn migrations() -> Vec<(&'static str, MigrationKind)> {
         "create_agent_assignment",
         MigrationKind::SQL("alter table agents add column latest_work_for text;"),
     ));
+    migrations.push((
+        "add_experiment_regressions_cap",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN max_regressions INTEGER;
+            ALTER TABLE experiments ADD COLUMN capped_at INTEGER;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_completed_at",
+        MigrationKind::SQL("ALTER TABLE results ADD COLUMN completed_at DATETIME;"),
+    ));
+
+    migrations.push((
+        "add_experiment_webhook",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN webhook_url TEXT;
+            ALTER TABLE experiments ADD COLUMN webhook_secret TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_sample_seed",
+        MigrationKind::SQL("ALTER TABLE experiments ADD COLUMN sample_seed INTEGER;"),
+    ));
+
+    migrations.push((
+        "add_experiment_skip_dev_deps",
+        MigrationKind::SQL(
+            "ALTER TABLE experiments ADD COLUMN skip_dev_deps INTEGER NOT NULL DEFAULT 0;",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_extra_toolchains",
+        MigrationKind::SQL(
+            "ALTER TABLE experiments ADD COLUMN extra_toolchains TEXT NOT NULL DEFAULT '[]';",
+        ),
+    ));
+
+    migrations.push((
+        "add_experiment_labels",
+        MigrationKind::SQL("ALTER TABLE experiments ADD COLUMN labels TEXT NOT NULL DEFAULT '{}';"),
+    ));
+
+    migrations.push((
+        "add_regression_confirmations",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE experiments ADD COLUMN confirm_regressions INTEGER NOT NULL DEFAULT 0;
+            ALTER TABLE experiment_crates ADD COLUMN confirmation TEXT;
+            ALTER TABLE experiment_crates ADD COLUMN first_agent TEXT;
+            ",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_duration",
+        MigrationKind::SQL("ALTER TABLE results ADD COLUMN duration_ms INTEGER;"),
+    ));
+
+    migrations.push((
+        "add_results_crate_index",
+        MigrationKind::SQL("CREATE INDEX results__crate ON results (crate);"),
+    ));
+
+    migrations.push((
+        "add_experiment_cancelled_at",
+        MigrationKind::SQL("ALTER TABLE experiments ADD COLUMN cancelled_at DATETIME;"),
+    ));
+
+    migrations.push((
+        "add_experiment_crates_crate_index",
+        MigrationKind::SQL(
+            "CREATE INDEX experiment_crates__experiment_crate \
+             ON experiment_crates (experiment, crate);",
+        ),
+    ));
+
+    migrations.push((
+        "add_results_original_log_size",
+        MigrationKind::SQL("ALTER TABLE results ADD COLUMN original_log_size INTEGER;"),
+    ));
+
+    migrations.push((
+        "add_results_seq",
+        MigrationKind::SQL(
+            "
+            ALTER TABLE results ADD COLUMN seq INTEGER;
+            UPDATE results SET seq = rowid;
+            CREATE INDEX results__experiment_seq ON results (experiment, seq);
+            ",
+        ),
+    ));
 
     migrations
 }
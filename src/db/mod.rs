@@ -64,6 +64,12 @@ impl Database {
         )
     }
 
+    /// Database stored at `path`, to check what's left in it once it's reopened.
+    #[cfg(test)]
+    pub fn open_at(path: &std::path::Path) -> Fallible<Self> {
+        Database::new(SqliteConnectionManager::file(path), None)
+    }
+
     fn new(conn: SqliteConnectionManager, tempfile: Option<NamedTempFile>) -> Fallible<Self> {
         let pool = Pool::builder()
             .connection_customizer(Box::new(ConnectionCustomizer))
@@ -10,4 +10,18 @@ lazy_static! {
             .into()
     };
     pub static ref LOCAL_CRATES_DIR: PathBuf = "local-crates".into();
+    pub static ref ALT_REGISTRY_CRATES_DIR: PathBuf = WORK_DIR.join("alt-registry-crates");
+    pub static ref WORKSPACE_CRATES_DIR: PathBuf = WORK_DIR.join("workspace-crates");
+    /// Clones of the git crates fetched over SSH, reused across experiments.
+    pub static ref GIT_CRATES_DIR: PathBuf = WORK_DIR.join("git-crates");
+    /// Results of the agent not yet acknowledged by the server.
+    pub static ref AGENT_SPOOL_DIR: PathBuf = WORK_DIR.join("agent-spool");
+    /// Crate lists fetched from a URL, kept to avoid downloading them again when unchanged.
+    pub static ref CRATE_LISTS_CACHE_DIR: PathBuf = WORK_DIR.join("cache").join("crate-lists");
+    /// Compiler caches shared by the builds of the agent, when `build-cache` is enabled.
+    pub static ref BUILD_CACHE_DIR: PathBuf = WORK_DIR.join("cache").join("builds");
+    /// Build outputs of the regressed crates, kept when `preserve-failed-artifacts` is enabled.
+    pub static ref PRESERVED_ARTIFACTS_DIR: PathBuf = WORK_DIR.join("artifacts");
+    /// Toolchains of the rustup home managed by rustwide in the work directory.
+    pub static ref RUSTUP_TOOLCHAINS_DIR: PathBuf = WORK_DIR.join("rustup-home").join("toolchains");
 }
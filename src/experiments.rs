@@ -1,19 +1,30 @@
-use crate::crates::Crate;
+use crate::agent::Capabilities;
+use crate::config::Config;
+use crate::crates::{is_ssh_url, Crate, CrateSpec, CrateSpecError};
 use crate::db::{Database, QueryUtils};
+use crate::dirs::CRATE_LISTS_CACHE_DIR;
 use crate::prelude::*;
 use crate::results::TestResult;
 use crate::toolchain::Toolchain;
 use crate::utils;
+use crate::utils::http::Conditional;
 use chrono::{DateTime, Utc};
 use rusqlite::Row;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashSet};
 use std::fmt;
 use std::str::FromStr;
+use std::time::Duration;
 use url::Url;
 
 //sqlite limit is ignored if the expression evaluates to a negative value
 static SQL_VARIABLE_LIMIT: usize = 500;
 
+// Minutes after which a crate handed out to an agent without any result is handed out again
+#[cfg(not(test))]
+const RUN_TIMEOUT: u32 = 20;
+#[cfg(test)]
+const RUN_TIMEOUT: u32 = 1;
+
 string_enum!(pub enum Status {
     Queued => "queued",
     Running => "running",
@@ -21,17 +32,94 @@ string_enum!(pub enum Status {
     GeneratingReport => "generating-report",
     ReportFailed => "report-failed",
     Completed => "completed",
+    Cancelled => "cancelled",
+    Failed => "failed",
+});
+
+impl Status {
+    /// Whether an experiment with this status can be moved to `next`. Setting the current status
+    /// again is always allowed.
+    pub fn can_transition_to(self, next: Status) -> bool {
+        use Status::*;
+        self == next
+            || matches!(
+                (self, next),
+                (Queued, Running | Cancelled)
+                    | (Running, NeedsReport | GeneratingReport | Cancelled)
+                    // Running the experiment or generating its report can fail at any point
+                    | (Queued | Running | NeedsReport | GeneratingReport, Failed)
+                    // The partial report of a cancelled experiment is still generated
+                    | (Cancelled, GeneratingReport)
+                    | (NeedsReport, GeneratingReport)
+                    | (GeneratingReport, Completed | ReportFailed | NeedsReport)
+                    | (ReportFailed, NeedsReport | GeneratingReport)
+                    // The report of a completed experiment can be generated again from the CLI
+                    | (Completed, GeneratingReport)
+                    // Retrying the experiment or requeueing some of its crates runs it again,
+                    // but not while its report is being generated
+                    | (Running | NeedsReport | ReportFailed | Completed | Cancelled | Failed, Queued)
+            )
+    }
+}
+
+string_enum!(pub enum Confirmation {
+    Pending => "pending",
+    Confirmed => "confirmed",
+    Flaky => "flaky",
 });
 
+impl Confirmation {
+    /// State of the confirmation of a crate's regression once `agent` completed it, given the
+    /// current state and the agent whose run regressed first. Only a run on another agent can
+    /// confirm the regression, the other runs leave it pending.
+    pub fn next(
+        current: Option<(Confirmation, &str)>,
+        agent: &str,
+        regressed: bool,
+    ) -> Option<Confirmation> {
+        match current {
+            None if regressed => Some(Confirmation::Pending),
+            None => None,
+            Some((Confirmation::Pending, first)) if first == agent => Some(Confirmation::Pending),
+            Some((Confirmation::Pending, _)) if regressed => Some(Confirmation::Confirmed),
+            Some((Confirmation::Pending, _)) => Some(Confirmation::Flaky),
+            Some((done, _)) => Some(done),
+        }
+    }
+}
+
 string_enum!(pub enum Mode {
     BuildAndTest => "build-and-test",
     BuildOnly => "build-only",
     CheckOnly => "check-only",
     Clippy => "clippy",
     Rustdoc => "rustdoc",
+    Miri => "miri",
     UnstableFeatures => "unstable-features",
+    TestOnly => "test-only",
 });
 
+impl Mode {
+    /// Human readable description of what's run on each crate.
+    pub fn command(&self) -> &'static str {
+        match self {
+            Mode::BuildAndTest => "cargo test",
+            Mode::BuildOnly => "cargo build",
+            Mode::CheckOnly => "cargo check",
+            Mode::Clippy => "cargo clippy",
+            Mode::Rustdoc => "cargo doc",
+            Mode::Miri => "cargo miri test",
+            Mode::UnstableFeatures => "unstable features",
+            Mode::TestOnly => "cargo test --no-fail-fast",
+        }
+    }
+
+    /// Whether the tests of the crates are run, which needs their dev-dependencies.
+    pub fn runs_tests(&self) -> bool {
+        matches!(self, Mode::BuildAndTest | Mode::TestOnly)
+    }
+}
+
 string_enum!(pub enum CapLints {
     Allow => "allow",
     Warn => "warn",
@@ -50,7 +138,14 @@ pub enum CrateSelect {
     Local,
     Dummy,
     Random(u32),
+    /// Reproducible random sample of `count` crates. Experiments always record a seed, one is
+    /// picked when they're created without it.
+    Sample {
+        count: u32,
+        seed: Option<u64>,
+    },
     List(HashSet<String>),
+    Workspace(String),
 }
 
 from_into_string!(CrateSelect);
@@ -71,15 +166,38 @@ impl FromStr for CrateSelect {
                 CrateSelect::Random(n)
             }
 
+            s if s.starts_with("sample-") => {
+                let rest = &s["sample-".len()..];
+                match rest.split_once("-seed-") {
+                    Some((count, seed)) => CrateSelect::Sample {
+                        count: count.parse()?,
+                        seed: Some(seed.parse()?),
+                    },
+                    None => CrateSelect::Sample {
+                        count: rest.parse()?,
+                        seed: None,
+                    },
+                }
+            }
+
             s if s.starts_with("list:") => {
-                let list = s["list:".len()..]
+                let list: HashSet<_> = s["list:".len()..]
                     .split(',')
                     .map(|s| s.to_owned())
                     .collect();
+                for krate in &list {
+                    if let Err(err) = validate_list_entry(krate) {
+                        bail!("invalid crate `{}`: {}", krate, err);
+                    }
+                }
 
                 CrateSelect::List(list)
             }
 
+            s if s.starts_with("workspace:") => {
+                CrateSelect::Workspace(s["workspace:".len()..].to_string())
+            }
+
             "full" => CrateSelect::Full,
             "demo" => CrateSelect::Demo,
             "local" => CrateSelect::Local,
@@ -100,6 +218,12 @@ impl fmt::Display for CrateSelect {
             CrateSelect::Top(n) => write!(f, "top-{n}"),
             CrateSelect::Local => write!(f, "local"),
             CrateSelect::Random(n) => write!(f, "random-{n}"),
+            CrateSelect::Sample { count, seed: None } => write!(f, "sample-{count}"),
+            CrateSelect::Sample {
+                count,
+                seed: Some(seed),
+            } => write!(f, "sample-{count}-seed-{seed}"),
+            CrateSelect::Workspace(root) => write!(f, "workspace:{root}"),
             CrateSelect::List(list) => {
                 let mut first = true;
                 write!(f, "list:")?;
@@ -119,17 +243,62 @@ impl fmt::Display for CrateSelect {
     }
 }
 
+const CRATE_LIST_FETCH_TIMEOUT: Duration = Duration::from_secs(30);
+
 impl CrateSelect {
+    /// Pick a seed for random samples without one, so the selection can be reproduced.
+    pub(crate) fn seeded(self) -> CrateSelect {
+        match self {
+            CrateSelect::Sample { count, seed: None } => CrateSelect::Sample {
+                count,
+                seed: Some(rand::random()),
+            },
+            other => other,
+        }
+    }
+
+    pub(crate) fn sample_seed(&self) -> Option<u64> {
+        match self {
+            CrateSelect::Sample { seed, .. } => *seed,
+            _ => None,
+        }
+    }
+
+    /// Parse a list with one crate per line, either a name, a `name@version` pinning the
+    /// version to test, or the SSH URL of a private git repository. Blank lines and `#`
+    /// comments are ignored.
     fn from_newline_separated_list(s: &str) -> Fallible<CrateSelect> {
         if s.contains(',') {
             bail!("Crate identifiers must not contain a comma");
         }
 
-        let crates = s.split_whitespace().map(|s| s.to_owned()).collect();
+        let mut crates = HashSet::new();
+        for (idx, line) in s.lines().enumerate() {
+            let line = line.split('#').next().unwrap();
+            for krate in line.split_whitespace() {
+                if is_ssh_url(krate) {
+                    crates.insert(krate.to_owned());
+                    continue;
+                }
+                if let Err(err) = validate_list_entry(krate) {
+                    bail!("invalid crate `{}` on line {}: {}", krate, idx + 1, err);
+                }
+                crates.insert(krate.to_owned());
+            }
+        }
         Ok(CrateSelect::List(crates))
     }
 }
 
+/// Check a crate of a list strictly, as it's only fetched once the experiment runs. GitHub
+/// repositories (`org/name`) and private git repositories are checked when they're fetched.
+fn validate_list_entry(krate: &str) -> Result<(), CrateSpecError> {
+    if is_ssh_url(krate) || krate.contains('/') {
+        return Ok(());
+    }
+    CrateSpec::parse_strict(krate).map(|_| ())
+}
+
 /// Either a `CrateSelect` or `Url` pointing to a list of crates.
 #[derive(Debug, PartialEq, Eq, Clone)]
 pub enum DeferredCrateSelect {
@@ -150,8 +319,40 @@ impl DeferredCrateSelect {
             DeferredCrateSelect::Indirect(url) => url,
         };
 
-        let body = utils::http::get_sync(url.as_str())?.text()?;
-        CrateSelect::from_newline_separated_list(&body)
+        CrateSelect::from_newline_separated_list(&fetch_crate_list(&url)?)
+    }
+}
+
+/// Fetch a crate list, reusing the cached copy when the server reports it didn't change.
+fn fetch_crate_list(url: &Url) -> Fallible<String> {
+    use sha1::{Digest, Sha1};
+
+    let key = format!("{:x}", Sha1::digest(url.as_str().as_bytes()));
+    let list_path = CRATE_LISTS_CACHE_DIR.join(format!("{key}.list"));
+    let etag_path = CRATE_LISTS_CACHE_DIR.join(format!("{key}.etag"));
+    let cached_etag = std::fs::read_to_string(&etag_path)
+        .ok()
+        .filter(|_| list_path.is_file());
+
+    match utils::http::get_sync_conditional(
+        url.as_str(),
+        cached_etag.as_deref(),
+        CRATE_LIST_FETCH_TIMEOUT,
+    )
+    .with_context(|_| format!("failed to fetch the crate list at {url}"))?
+    {
+        Conditional::NotModified => {
+            debug!("using the cached crate list for {}", url);
+            Ok(std::fs::read_to_string(&list_path)?)
+        }
+        Conditional::Modified { body, etag } => {
+            if let Some(etag) = etag {
+                std::fs::create_dir_all(&*CRATE_LISTS_CACHE_DIR)?;
+                std::fs::write(&list_path, &body)?;
+                std::fs::write(&etag_path, etag)?;
+            }
+            Ok(body)
+        }
     }
 }
 
@@ -242,6 +443,15 @@ pub struct GitHubIssue {
     pub number: i32,
 }
 
+/// Webhook receiving a notification when an experiment completes.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq, Eq)]
+pub struct CompletionWebhook {
+    pub url: String,
+    /// Key used to sign the payloads, never sent to the agents nor included in the reports.
+    #[serde(default, skip_serializing)]
+    pub secret: Option<String>,
+}
+
 #[derive(Serialize, Deserialize, Clone)]
 pub struct Experiment {
     pub name: String,
@@ -258,9 +468,60 @@ pub struct Experiment {
     pub report_url: Option<String>,
     pub ignore_blacklist: bool,
     pub requirement: Option<String>,
+    /// Stop handing out crates once this many crates regressed.
+    #[serde(default)]
+    pub max_regressions: Option<usize>,
+    /// Number of regressed crates when the experiment was capped by `max_regressions`.
+    #[serde(default)]
+    pub capped_at: Option<usize>,
+    /// When the experiment was cancelled, reporting only the crates completed until then.
+    #[serde(default)]
+    pub cancelled_at: Option<DateTime<Utc>>,
+    /// Webhook to notify instead of the global one when the experiment completes.
+    #[serde(default)]
+    pub webhook: Option<CompletionWebhook>,
+    /// Seed of the random sample of crates tested, to reproduce it with `sample-N-seed-S`.
+    #[serde(default)]
+    pub sample_seed: Option<u64>,
+    /// Don't build the dev-dependencies of the crates, unless the tests are run.
+    #[serde(default)]
+    pub skip_dev_deps: bool,
+    /// Toolchains compared with the first one in addition to the second one.
+    #[serde(default)]
+    pub extra_toolchains: Vec<Toolchain>,
+    /// Free-form metadata, such as the PR number or who requested the experiment.
+    #[serde(default)]
+    pub labels: BTreeMap<String, String>,
+    /// Run the regressed crates again on another agent before reporting them as regressed.
+    #[serde(default)]
+    pub confirm_regressions: bool,
+}
+
+/// Label keys can only contain ASCII letters, digits, `-` and `_`.
+pub(crate) fn is_valid_label_key(key: &str) -> bool {
+    key.chars()
+        .all(|c| c.is_ascii_alphanumeric() || c == '-' || c == '_')
 }
 
 impl Experiment {
+    /// Every toolchain the crates are built with, starting with the baseline one.
+    pub fn all_toolchains(&self) -> impl Iterator<Item = &Toolchain> {
+        self.toolchains.iter().chain(&self.extra_toolchains)
+    }
+
+    /// Toolchain whose result of a crate is compared with its result on `toolchain`, if that's
+    /// the start or the end toolchain. The extra toolchains are only compared in the matrix.
+    pub fn compared_toolchain(&self, toolchain: &Toolchain) -> Option<&Toolchain> {
+        let [start, end] = &self.toolchains;
+        if toolchain == start {
+            Some(end)
+        } else if toolchain == end {
+            Some(start)
+        } else {
+            None
+        }
+    }
+
     pub fn exists(db: &Database, name: &str) -> Fallible<bool> {
         Ok(db.exists("SELECT rowid FROM experiments WHERE name = ?1;", &[&name])?)
     }
@@ -281,7 +542,7 @@ impl Experiment {
         let record = db.get_row(
             "select * from experiments where name = (
                 select latest_work_for from agents where ('agent:' || agents.name) = ?1
-            ) and status = ?2 \
+            ) and status = ?2 and capped_at is null \
             limit 1",
             [&assignee.to_string(), Status::Running.to_str()],
             |r| ExperimentDBRecord::from_row(r),
@@ -312,6 +573,14 @@ impl Experiment {
                 // performance perspective but no more than that).
                 continue;
             }
+            // Queued experiments didn't start yet, and failed ones need to be retried first
+            if !ex.status.can_transition_to(Status::GeneratingReport) {
+                continue;
+            }
+            // The crates still running on the agents are dropped by cancelling
+            if ex.status == Status::Cancelled {
+                return Ok(Some(ex));
+            }
             let (completed, all) = ex.raw_progress(db)?;
             // FIXME: We often see more results than crates -- presumably, some
             // crates are run more than once and marked more than once, but the
@@ -320,6 +589,9 @@ impl Experiment {
             if completed >= all {
                 return Ok(Some(ex));
             }
+            if ex.capped_at.is_some() && ex.running_crates(db)? == 0 {
+                return Ok(Some(ex));
+            }
         }
 
         Ok(None)
@@ -471,7 +743,7 @@ impl Experiment {
             // to generate a report), which is pretty rare. So this fix is
             // enough to make sure we're not constantly feeding a finished
             // experiment to our agents.
-            if completed >= all {
+            if completed >= all || ex.capped_at.is_some() {
                 return Ok(None);
             }
             Ok(Some(ex))
@@ -480,6 +752,37 @@ impl Experiment {
         }
     }
 
+    /// Every experiment with the `key` label set to `value`, the newest first.
+    pub fn with_label(db: &Database, key: &str, value: &str) -> Fallible<Vec<Experiment>> {
+        // Keys never need escaping in the JSON path, as no experiment has other keys
+        if !is_valid_label_key(key) {
+            return Ok(Vec::new());
+        }
+        let records = db.query(
+            "SELECT * FROM experiments WHERE json_extract(labels, ?1) = ?2 \
+             ORDER BY created_at DESC;",
+            rusqlite::params![format!("$.\"{key}\""), value],
+            |r| ExperimentDBRecord::from_row(r),
+        )?;
+        records
+            .into_iter()
+            .map(|record| record.into_experiment())
+            .collect::<Fallible<_>>()
+    }
+
+    /// The `limit` most recently created experiments, the newest first.
+    pub fn recent(db: &Database, limit: u32) -> Fallible<Vec<Experiment>> {
+        let records = db.query(
+            "SELECT * FROM experiments ORDER BY created_at DESC, rowid DESC LIMIT ?1;",
+            [limit],
+            |r| ExperimentDBRecord::from_row(r),
+        )?;
+        records
+            .into_iter()
+            .map(|record| record.into_experiment())
+            .collect::<Fallible<_>>()
+    }
+
     pub fn get(db: &Database, name: &str) -> Fallible<Option<Experiment>> {
         let record = db.get_row("SELECT * FROM experiments WHERE name = ?1;", [&name], |r| {
             ExperimentDBRecord::from_row(r)
@@ -492,11 +795,44 @@ impl Experiment {
         }
     }
 
+    /// Move the experiment from `from` to `to`, reloading its status first. Returns `false`
+    /// without changing anything if the experiment isn't in `from` anymore, e.g. because the
+    /// retry-report command moved it while this handle was held.
+    pub fn set_status_from(&mut self, db: &Database, from: Status, to: Status) -> Fallible<bool> {
+        let current = Experiment::get(db, &self.name)?
+            .ok_or_else(|| err_msg(format!("experiment {} was deleted", self.name)))?;
+        self.status = current.status;
+        if self.status != from {
+            return Ok(false);
+        }
+
+        self.set_status(db, to)?;
+        Ok(true)
+    }
+
     pub fn set_status(&mut self, db: &Database, status: Status) -> Fallible<()> {
-        db.execute(
-            "UPDATE experiments SET status = ?1 WHERE name = ?2;",
-            &[&status.to_str(), &self.name.as_str()],
+        if !self.status.can_transition_to(status) {
+            bail!(
+                "the status of experiment {} can't change from {} to {}",
+                self.name,
+                self.status,
+                status
+            );
+        }
+
+        // Only update the status if nobody else changed it in the meantime
+        let changed = db.execute(
+            "UPDATE experiments SET status = ?1 WHERE name = ?2 AND status = ?3;",
+            &[&status.to_str(), &self.name.as_str(), &self.status.to_str()],
         )?;
+        if changed == 0 {
+            bail!(
+                "the status of experiment {} changed while moving it from {} to {}",
+                self.name,
+                self.status,
+                status
+            );
+        }
 
         let now = Utc::now();
 
@@ -517,6 +853,14 @@ impl Experiment {
                 )?;
                 self.completed_at = Some(now);
             }
+            // Retrying a cancelled experiment runs the rest of it
+            (_, Status::Queued) if self.cancelled_at.is_some() => {
+                db.execute(
+                    "UPDATE experiments SET cancelled_at = NULL WHERE name = ?1;",
+                    &[&self.name.as_str()],
+                )?;
+                self.cancelled_at = None;
+            }
             _ => (),
         }
 
@@ -524,6 +868,37 @@ impl Experiment {
         Ok(())
     }
 
+    /// Cancel the queued or running experiment: no crate is handed out anymore, and the results
+    /// of the ones still running are dropped. The report is generated with the results stored
+    /// until now.
+    pub fn cancel(&mut self, db: &Database) -> Fallible<()> {
+        self.set_status(db, Status::Cancelled)?;
+
+        let now = Utc::now();
+        db.execute(
+            "UPDATE experiments SET cancelled_at = ?1 WHERE name = ?2;",
+            &[&now, &self.name.as_str()],
+        )?;
+        self.cancelled_at = Some(now);
+        Ok(())
+    }
+
+    /// Queue the experiment again after some of its crates were requeued, so it's only
+    /// considered complete once they are done too.
+    pub fn requeue(&mut self, db: &Database) -> Fallible<()> {
+        match self.status {
+            Status::Queued | Status::Running => Ok(()),
+            _ => {
+                db.execute(
+                    "UPDATE experiments SET completed_at = NULL WHERE name = ?1;",
+                    &[&self.name.as_str()],
+                )?;
+                self.completed_at = None;
+                self.set_status(db, Status::Queued)
+            }
+        }
+    }
+
     pub fn set_assigned_to(
         &mut self,
         db: &Database,
@@ -564,7 +939,47 @@ impl Experiment {
             )?
             .unwrap();
 
-        Ok((results_len, crates_len * 2))
+        let toolchains_len = self.all_toolchains().count() as u32;
+        Ok((results_len, crates_len * toolchains_len))
+    }
+
+    /// Number of crates handed out to the agents which didn't time out yet, without all of their
+    /// results.
+    fn running_crates(&self, db: &Database) -> Fallible<u32> {
+        Ok(db
+            .get_row(
+                &format!(
+                    "SELECT COUNT(*) AS count FROM experiment_crates WHERE experiment = ?1 \
+                     AND skipped = 0 AND status = 'queued' \
+                     AND started_at > datetime('now', '-{RUN_TIMEOUT} minutes');"
+                ),
+                [&self.name.as_str()],
+                |r| r.get("count"),
+            )?
+            .unwrap())
+    }
+
+    /// Cap the experiment if at least `max_regressions` of its completed crates regressed, which
+    /// stops handing out crates and lets the report be generated once the running ones finish.
+    /// `regressions` is the number of regressed crates, counted as the results are stored so
+    /// they aren't all compared again after each batch. Returns whether the experiment is capped.
+    pub fn check_cap(&mut self, db: &Database, regressions: usize) -> Fallible<bool> {
+        let Some(max) = self.max_regressions else {
+            return Ok(false);
+        };
+        if self.capped_at.is_some() {
+            return Ok(true);
+        }
+        if regressions < max {
+            return Ok(false);
+        }
+
+        db.execute(
+            "UPDATE experiments SET capped_at = ?1 WHERE name = ?2;",
+            &[&(regressions as i64), &self.name.as_str()],
+        )?;
+        self.capped_at = Some(regressions);
+        Ok(true)
     }
 
     pub fn get_result_counts(&self, db: &Database) -> Fallible<Vec<(TestResult, u32)>> {
@@ -602,35 +1017,97 @@ impl Experiment {
         .collect::<Fallible<Vec<Crate>>>()
     }
 
+    pub fn get_completed_crates(&self, db: &Database) -> Fallible<Vec<Crate>> {
+        db.query(
+            "SELECT crate FROM experiment_crates WHERE experiment = ?1 AND status = ?2;",
+            [&self.name.as_str(), &Status::Completed.to_str()],
+            |r| r.get(0),
+        )?
+        .into_iter()
+        .map(|c: String| c.parse())
+        .collect::<Fallible<Vec<Crate>>>()
+    }
+
     pub fn get_uncompleted_crates(
         &self,
         db: &Database,
         limit: Option<u32>,
     ) -> Fallible<Vec<Crate>> {
-        let limit = limit.map(|l| l as i32).unwrap_or(-1);
-        #[cfg(not(test))]
-        const RUN_TIMEOUT: u32 = 20;
-        #[cfg(test)]
-        const RUN_TIMEOUT: u32 = 1;
+        self.take_uncompleted_crates(db, None, limit, None)
+    }
+
+    /// Like `get_uncompleted_crates`, but only returns the crates whose requirements are met by
+    /// the capabilities of the agent, and whose regression the agent isn't asked to confirm.
+    /// The other crates stay queued for other agents.
+    pub fn get_uncompleted_crates_for(
+        &self,
+        db: &Database,
+        config: &Config,
+        agent: &str,
+        caps: &Capabilities,
+        limit: Option<u32>,
+    ) -> Fallible<Vec<Crate>> {
+        if !config.has_crate_requirements() {
+            return self.take_uncompleted_crates(db, Some(agent), limit, None);
+        }
+
+        let accepts = |krate: &Crate| caps.satisfies(config.crate_requirements(krate));
+        self.take_uncompleted_crates(db, Some(agent), limit, Some(&accepts))
+    }
+
+    fn take_uncompleted_crates(
+        &self,
+        db: &Database,
+        agent: Option<&str>,
+        limit: Option<u32>,
+        accepts: Option<&dyn Fn(&Crate) -> bool>,
+    ) -> Fallible<Vec<Crate>> {
+        // Capped experiments only wait for the crates already handed out, and cancelled ones
+        // don't wait for anything
+        if self.capped_at.is_some() || self.cancelled_at.is_some() {
+            return Ok(Vec::new());
+        }
+
+        // Filtered crates can't be limited in the query, as they're filtered afterwards
+        let query_limit = match accepts {
+            Some(_) => -1,
+            None => limit.map(|l| l as i32).unwrap_or(-1),
+        };
 
         db.transaction(|transaction| {
-            //get the first 'limit' queued crates from the experiment crates list
+            //get the first 'limit' queued crates from the experiment crates list, ordered by their
+            //id so every run of the experiment hands them out in the same order
             let mut params: Vec<&dyn rusqlite::types::ToSql> = Vec::new();
-            let crates = transaction
+            let mut crates = transaction
                 .query(
                     &format!(
                         "SELECT crate FROM experiment_crates WHERE experiment = ?1
                             AND skipped = 0
                             AND status = 'queued'
                             AND (started_at is null or started_at <= datetime('now', '-{RUN_TIMEOUT} minutes'))
+                            AND (confirmation IS NOT 'pending' OR first_agent IS NOT ?3)
+                        ORDER BY crate
                         LIMIT ?2;",
                     ),
-                    rusqlite::params![self.name, limit],
+                    rusqlite::params![self.name, query_limit, agent],
                     |r| r.get("crate"),
                 )?
                 .into_iter()
                 .collect::<Vec<String>>();
 
+            if let Some(accepts) = accepts {
+                let mut accepted = Vec::new();
+                for krate in crates {
+                    if limit.map_or(false, |limit| accepted.len() >= limit as usize) {
+                        break;
+                    }
+                    if accepts(&krate.parse()?) {
+                        accepted.push(krate);
+                    }
+                }
+                crates = accepted;
+            }
+
             crates.iter().for_each(|krate| params.push(krate));
             let params_header: &[&dyn rusqlite::types::ToSql] = &[&self.name];
             //SQLite cannot handle queries with more than 999 variables
@@ -677,6 +1154,16 @@ struct ExperimentDBRecord {
     report_url: Option<String>,
     ignore_blacklist: bool,
     requirement: Option<String>,
+    max_regressions: Option<i64>,
+    capped_at: Option<i64>,
+    cancelled_at: Option<DateTime<Utc>>,
+    webhook_url: Option<String>,
+    webhook_secret: Option<String>,
+    sample_seed: Option<i64>,
+    skip_dev_deps: bool,
+    extra_toolchains: String,
+    labels: String,
+    confirm_regressions: bool,
 }
 
 impl ExperimentDBRecord {
@@ -699,6 +1186,16 @@ impl ExperimentDBRecord {
             report_url: row.get("report_url")?,
             ignore_blacklist: row.get("ignore_blacklist")?,
             requirement: row.get("requirement")?,
+            max_regressions: row.get("max_regressions")?,
+            capped_at: row.get("capped_at")?,
+            cancelled_at: row.get("cancelled_at")?,
+            webhook_url: row.get("webhook_url")?,
+            webhook_secret: row.get("webhook_secret")?,
+            sample_seed: row.get("sample_seed")?,
+            skip_dev_deps: row.get("skip_dev_deps")?,
+            extra_toolchains: row.get("extra_toolchains")?,
+            labels: row.get("labels")?,
+            confirm_regressions: row.get("confirm_regressions")?,
         })
     }
 
@@ -734,6 +1231,18 @@ impl ExperimentDBRecord {
             report_url: self.report_url,
             ignore_blacklist: self.ignore_blacklist,
             requirement: self.requirement,
+            max_regressions: self.max_regressions.map(|max| max as usize),
+            capped_at: self.capped_at.map(|count| count as usize),
+            cancelled_at: self.cancelled_at,
+            webhook: self.webhook_url.map(|url| CompletionWebhook {
+                url,
+                secret: self.webhook_secret,
+            }),
+            sample_seed: self.sample_seed.map(|seed| seed as u64),
+            skip_dev_deps: self.skip_dev_deps,
+            extra_toolchains: serde_json::from_str(&self.extra_toolchains)?,
+            labels: serde_json::from_str(&self.labels)?,
+            confirm_regressions: self.confirm_regressions,
         })
     }
 }
@@ -741,17 +1250,43 @@ impl ExperimentDBRecord {
 #[cfg(test)]
 mod tests {
     use super::{
-        Assignee, AssigneeParseError, CrateSelect, DeferredCrateSelect, Experiment, Status,
+        Assignee, AssigneeParseError, Confirmation, CrateSelect, DeferredCrateSelect, Experiment,
+        Status,
     };
     use crate::actions::{Action, ActionsCtx, CreateExperiment};
     use crate::agent::Capabilities;
-    use crate::config::Config;
-    use crate::db::Database;
+    use crate::config::{Config, CrateConfig};
+    use crate::crates::Crate;
+    use crate::db::{Database, QueryUtils};
+    use crate::results::{
+        DatabaseDB, EncodingType, FailureReason, ProgressData, TaskResult, TestResult,
+    };
     use crate::server::agents::Agents;
     use crate::server::tokens::Tokens;
     use std::collections::HashSet;
     use std::str::FromStr;
 
+    fn store_results(db: &Database, ex: &Experiment, krate: &Crate, results: [TestResult; 2]) {
+        for (toolchain, result) in ex.toolchains.iter().zip(results) {
+            DatabaseDB::new(db)
+                .store(
+                    ex,
+                    &ProgressData {
+                        result: TaskResult {
+                            krate: krate.clone(),
+                            toolchain: toolchain.clone(),
+                            result,
+                            log: String::new(),
+                            duration_ms: None,
+                        },
+                        version: None,
+                    },
+                    EncodingType::Plain,
+                )
+                .unwrap();
+        }
+    }
+
     #[test]
     fn test_crate_select_parsing() {
         let demo_crates: HashSet<_> = ["brson/hello-rs", "lazy_static"]
@@ -764,6 +1299,24 @@ mod tests {
             ("top-25", CrateSelect::Top(25)),
             ("random-87", CrateSelect::Random(87)),
             ("small-random", CrateSelect::Random(20)),
+            (
+                "sample-50",
+                CrateSelect::Sample {
+                    count: 50,
+                    seed: None,
+                },
+            ),
+            (
+                "sample-50-seed-1234",
+                CrateSelect::Sample {
+                    count: 50,
+                    seed: Some(1234),
+                },
+            ),
+            (
+                "workspace:/src/project",
+                CrateSelect::Workspace("/src/project".into()),
+            ),
             (
                 "list:brson/hello-rs,lazy_static",
                 CrateSelect::List(demo_crates.clone()),
@@ -772,6 +1325,9 @@ mod tests {
 
         for (s, output) in suite.into_iter() {
             assert_eq!(CrateSelect::from_str(s).unwrap(), output);
+            if !matches!(output, CrateSelect::List(_)) {
+                assert_eq!(output.to_string(), s.replace("small-random", "random-20"));
+            }
             assert_eq!(
                 DeferredCrateSelect::from_str(s).unwrap(),
                 DeferredCrateSelect::Direct(output),
@@ -797,6 +1353,51 @@ mod tests {
         .unwrap();
 
         assert_eq!(list, CrateSelect::List(demo_crates));
+
+        let list = CrateSelect::from_newline_separated_list(
+            "# crates for the nightly run\n\nlazy_static@1.4.0\n  \nserde # latest version\n\
+             git@example.com:org/private.git?tag=v1.0.0\n",
+        )
+        .unwrap();
+        assert_eq!(
+            list,
+            CrateSelect::List(
+                [
+                    "lazy_static@1.4.0",
+                    "serde",
+                    "git@example.com:org/private.git?tag=v1.0.0"
+                ]
+                .into_iter()
+                .map(String::from)
+                .collect()
+            )
+        );
+
+        let malformed = [
+            ("lazy_static@", "missing version in `lazy_static@`"),
+            ("@1.0.0", "missing crate name in `@1.0.0`"),
+            (
+                "lazy_static@v1.4.0",
+                "version `v1.4.0` must not start with `v`",
+            ),
+            (
+                "lazy.static",
+                "invalid character `.` in crate name `lazy.static`",
+            ),
+        ];
+        for (krate, reason) in malformed {
+            let err =
+                CrateSelect::from_newline_separated_list(&format!("serde\n{krate}\n")).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("invalid crate `{krate}` on line 2: {reason}")
+            );
+            let err = CrateSelect::from_str(&format!("list:serde,{krate}")).unwrap_err();
+            assert_eq!(
+                err.to_string(),
+                format!("invalid crate `{krate}`: {reason}")
+            );
+        }
     }
 
     #[test]
@@ -827,6 +1428,244 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_status_transitions() {
+        use Status::*;
+
+        let legal = [
+            (Queued, Running),
+            (Running, NeedsReport),
+            (Running, GeneratingReport),
+            (Running, Queued),
+            (NeedsReport, GeneratingReport),
+            (NeedsReport, Queued),
+            (GeneratingReport, Completed),
+            (GeneratingReport, ReportFailed),
+            (GeneratingReport, NeedsReport),
+            (ReportFailed, NeedsReport),
+            (ReportFailed, GeneratingReport),
+            (ReportFailed, Queued),
+            (Completed, GeneratingReport),
+            (Completed, Queued),
+            (Running, Running),
+            (Completed, Completed),
+            (Queued, Cancelled),
+            (Running, Cancelled),
+            (Cancelled, GeneratingReport),
+            (Cancelled, Queued),
+            (Queued, Failed),
+            (Running, Failed),
+            (NeedsReport, Failed),
+            (GeneratingReport, Failed),
+            (Failed, Queued),
+        ];
+        let illegal = [
+            (Queued, GeneratingReport),
+            (Queued, NeedsReport),
+            (Queued, Completed),
+            (Queued, ReportFailed),
+            (Running, Completed),
+            (Running, ReportFailed),
+            (NeedsReport, Running),
+            (NeedsReport, Completed),
+            (GeneratingReport, Running),
+            (ReportFailed, Completed),
+            (ReportFailed, Running),
+            (Completed, Running),
+            (Completed, NeedsReport),
+            (Completed, ReportFailed),
+            (NeedsReport, Cancelled),
+            (GeneratingReport, Cancelled),
+            (ReportFailed, Cancelled),
+            (Completed, Cancelled),
+            (Cancelled, Running),
+            (Cancelled, Completed),
+            (GeneratingReport, Queued),
+            (ReportFailed, Failed),
+            (Completed, Failed),
+            (Cancelled, Failed),
+            (Failed, Running),
+            (Failed, GeneratingReport),
+            (Failed, Completed),
+        ];
+
+        for (from, to) in legal {
+            assert!(from.can_transition_to(to), "{from} -> {to} should be legal");
+        }
+        for (from, to) in illegal {
+            assert!(
+                !from.can_transition_to(to),
+                "{from} -> {to} should be illegal"
+            );
+        }
+    }
+
+    #[test]
+    fn test_with_label() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let labelled = |name: &str, labels: &[(&str, &str)]| {
+            CreateExperiment {
+                labels: labels
+                    .iter()
+                    .map(|(key, value)| (key.to_string(), value.to_string()))
+                    .collect(),
+                ..CreateExperiment::dummy(name)
+            }
+            .apply(&ctx)
+            .unwrap();
+        };
+        labelled("pr-1", &[("pr", "1"), ("requester", "ferris")]);
+        labelled("pr-1-rerun", &[("pr", "1")]);
+        labelled("pr-2", &[("pr", "2"), ("requester", "ferris")]);
+        labelled("unlabelled", &[]);
+
+        let names = |key: &str, value: &str| {
+            let mut names = Experiment::with_label(&db, key, value)
+                .unwrap()
+                .into_iter()
+                .map(|ex| ex.name)
+                .collect::<Vec<_>>();
+            names.sort();
+            names
+        };
+        assert_eq!(names("pr", "1"), vec!["pr-1", "pr-1-rerun"]);
+        assert_eq!(names("requester", "ferris"), vec!["pr-1", "pr-2"]);
+        assert!(names("pr", "3").is_empty());
+        assert!(names("missing", "1").is_empty());
+        assert!(names("p\"r", "1").is_empty());
+    }
+
+    #[test]
+    fn test_confirmation_transitions() {
+        use Confirmation::*;
+
+        // Crates which didn't regress don't need any confirmation
+        assert_eq!(Confirmation::next(None, "agent-1", false), None);
+        assert_eq!(Confirmation::next(None, "agent-1", true), Some(Pending));
+
+        // Runs on the agent which found the regression don't count
+        let pending = Some((Pending, "agent-1"));
+        assert_eq!(Confirmation::next(pending, "agent-1", true), Some(Pending));
+        assert_eq!(Confirmation::next(pending, "agent-1", false), Some(Pending));
+
+        // Another agent agreeing confirms the regression, disagreeing makes it flaky
+        assert_eq!(
+            Confirmation::next(pending, "agent-2", true),
+            Some(Confirmed)
+        );
+        assert_eq!(Confirmation::next(pending, "agent-2", false), Some(Flaky));
+
+        // Later runs don't change the outcome
+        for done in [Confirmed, Flaky] {
+            for regressed in [true, false] {
+                assert_eq!(
+                    Confirmation::next(Some((done, "agent-1")), "agent-3", regressed),
+                    Some(done)
+                );
+            }
+        }
+    }
+
+    #[test]
+    fn test_set_status_rejects_illegal_transitions() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("foo").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        ex.set_status(&db, Status::Running).unwrap();
+
+        let err = ex.set_status(&db, Status::Completed).unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "the status of experiment foo can't change from running to completed"
+        );
+        assert_eq!(ex.status, Status::Running);
+
+        // A stale copy of the experiment can't overwrite a status changed in the meantime
+        let mut stale = Experiment::get(&db, "foo").unwrap().unwrap();
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
+        ex.set_status(&db, Status::Completed).unwrap();
+        assert!(stale.set_status(&db, Status::NeedsReport).is_err());
+        assert_eq!(
+            Experiment::get(&db, "foo").unwrap().unwrap().status,
+            Status::Completed
+        );
+    }
+
+    #[test]
+    fn test_set_status_from_after_retry_report() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("foo").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        ex.set_status(&db, Status::Running).unwrap();
+        assert!(ex
+            .set_status_from(&db, Status::Running, Status::GeneratingReport)
+            .unwrap());
+
+        // The retry-report command moves the experiment while its report is generated
+        let mut retried = Experiment::get(&db, "foo").unwrap().unwrap();
+        retried.set_status(&db, Status::NeedsReport).unwrap();
+
+        // The long-held handle doesn't overwrite the retry, nor does it fail
+        assert!(!ex
+            .set_status_from(&db, Status::GeneratingReport, Status::ReportFailed)
+            .unwrap());
+        assert_eq!(ex.status, Status::NeedsReport);
+        assert!(!ex
+            .set_status_from(&db, Status::GeneratingReport, Status::Completed)
+            .unwrap());
+        assert_eq!(
+            Experiment::get(&db, "foo").unwrap().unwrap().status,
+            Status::NeedsReport
+        );
+
+        // The reloaded handle can generate the report again
+        assert!(ex
+            .set_status_from(&db, Status::NeedsReport, Status::GeneratingReport)
+            .unwrap());
+        assert!(ex
+            .set_status_from(&db, Status::GeneratingReport, Status::ReportFailed)
+            .unwrap());
+        assert_eq!(
+            Experiment::get(&db, "foo").unwrap().unwrap().status,
+            Status::ReportFailed
+        );
+    }
+
+    #[test]
+    fn test_failed_status() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("foo").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        ex.set_status(&db, Status::Running).unwrap();
+        ex.set_status(&db, Status::Failed).unwrap();
+
+        // A failed experiment doesn't get a report until it's retried
+        assert!(Experiment::ready_for_report(&db).unwrap().is_none());
+        assert!(ex.set_status(&db, Status::GeneratingReport).is_err());
+
+        ex.set_status(&db, Status::Queued).unwrap();
+        assert_eq!(
+            Experiment::get(&db, "foo").unwrap().unwrap().status,
+            Status::Queued
+        );
+    }
+
     #[test]
     fn test_assigning_experiment() {
         let db = Database::temp().unwrap();
@@ -868,6 +1707,7 @@ mod tests {
         assert_eq!(ex.name.as_str(), "important");
 
         //Mark the experiment as completed, otherwise agent2 will still pick it as has uncompleted crates
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
         ex.set_status(&db, Status::Completed).unwrap();
 
         // Test the less important experiment is assigned to the next agent
@@ -878,6 +1718,7 @@ mod tests {
         assert_eq!(ex.assigned_to.clone().unwrap(), Assignee::Distributed);
 
         //Mark the experiment as completed, otherwise agent3 will still pick it as has uncompleted crates
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
         ex.set_status(&db, Status::Completed).unwrap();
 
         // Test no other experiment is available for the other agents
@@ -934,6 +1775,7 @@ mod tests {
 
         //Mark the experiment as completed, otherwise agent2 will still pick it
         //as it has uncompleted crates
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
         ex.set_status(&db, Status::Completed).unwrap();
 
         // Test that an experiment will be assigned to an agent with the required capabilities.
@@ -1008,6 +1850,100 @@ mod tests {
         assert_eq!(uncompleted_crates.len(), 0);
     }
 
+    #[test]
+    fn test_deterministic_dispatch_order() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("first").apply(&ctx).unwrap();
+        CreateExperiment::dummy("second").apply(&ctx).unwrap();
+
+        // Store the crates of the second experiment in the reverse order
+        let second = Experiment::get(&db, "second").unwrap().unwrap();
+        let mut crates = second.get_crates(&db).unwrap();
+        assert!(crates.len() > 1);
+        crates.reverse();
+        db.execute(
+            "DELETE FROM experiment_crates WHERE experiment = ?1;",
+            &[&"second"],
+        )
+        .unwrap();
+        for krate in &crates {
+            db.execute(
+                "INSERT INTO experiment_crates (experiment, crate, skipped, status) \
+                 VALUES (?1, ?2, 0, 'queued');",
+                &[&"second", &krate.id().as_str()],
+            )
+            .unwrap();
+        }
+
+        // The crates are handed out in the same order, a few at a time
+        let dispatch = |name: &str| {
+            let ex = Experiment::get(&db, name).unwrap().unwrap();
+            let mut order = Vec::new();
+            loop {
+                let batch = ex.get_uncompleted_crates(&db, Some(2)).unwrap();
+                if batch.is_empty() {
+                    break order;
+                }
+                order.extend(batch);
+            }
+        };
+        let first = dispatch("first");
+        assert_eq!(first.len(), crates.len());
+        assert_eq!(first, dispatch("second"));
+
+        let mut sorted = first.clone();
+        sorted.sort_by_key(|krate| krate.id());
+        assert_eq!(first, sorted);
+    }
+
+    #[test]
+    fn test_crates_with_requirements() {
+        let db = Database::temp().unwrap();
+        let mut config = Config::default();
+        config.local_crates.insert(
+            "build-pass".into(),
+            CrateConfig {
+                skip: false,
+                skip_tests: false,
+                quiet: false,
+                broken: false,
+                miri: false,
+                memory_limit: None,
+                requires: vec!["target:wasm32".into()],
+            },
+        );
+        let ctx = ActionsCtx::new(&db, &config);
+        let wasm = Crate::Local("build-pass".into());
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        // Agents without the capability get every other crate
+        let linux = Capabilities::new(&["linux"]);
+        let crates = ex
+            .get_uncompleted_crates_for(&db, &config, "agent-1", &linux, None)
+            .unwrap();
+        assert_eq!(crates.len(), ex.get_crates(&db).unwrap().len() - 1);
+        assert!(!crates.contains(&wasm));
+
+        // The crate stays queued until an agent with the capability asks for work
+        assert!(ex
+            .get_uncompleted_crates_for(&db, &config, "agent-1", &linux, None)
+            .unwrap()
+            .is_empty());
+        let wasm32 = Capabilities::new(&["linux", "target:wasm32"]);
+        assert_eq!(
+            ex.get_uncompleted_crates_for(&db, &config, "agent-2", &wasm32, None)
+                .unwrap(),
+            vec![wasm]
+        );
+    }
+
     // A failure is handled by re-queueing any running crates for a given agent,
     // to be picked up by the next agent to ask for them.
     #[test]
@@ -1028,4 +1964,72 @@ mod tests {
         assert_eq!(ex.status, Status::Running);
         assert!(!ex.get_uncompleted_crates(&db, None).unwrap().is_empty());
     }
+
+    #[test]
+    fn test_regressions_cap() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        let agent = Assignee::Agent("agent-1".to_string());
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        let mut tokens = Tokens::default();
+        tokens.agents.insert("token1".into(), "agent-1".into());
+        let _ = Agents::new(db.clone(), &tokens).unwrap();
+
+        CreateExperiment {
+            max_regressions: Some(1),
+            ..CreateExperiment::dummy("capped")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let mut ex = Experiment::next(&db, &agent).unwrap().unwrap().1;
+
+        // Crates which didn't regress don't count towards the cap
+        let passing = ex.get_uncompleted_crates(&db, Some(1)).unwrap().remove(0);
+        store_results(
+            &db,
+            &ex,
+            &passing,
+            [TestResult::TestPass, TestResult::TestPass],
+        );
+        assert!(!ex.check_cap(&db, 0).unwrap());
+        assert_eq!(ex.capped_at, None);
+
+        // A crate is still running when the cap is reached
+        let running = ex.get_uncompleted_crates(&db, Some(1)).unwrap().remove(0);
+        let regressed = ex.get_uncompleted_crates(&db, Some(1)).unwrap().remove(0);
+        store_results(
+            &db,
+            &ex,
+            &regressed,
+            [
+                TestResult::TestPass,
+                TestResult::BuildFail(FailureReason::Unknown),
+            ],
+        );
+        assert!(ex.check_cap(&db, 1).unwrap());
+        assert_eq!(ex.capped_at, Some(1));
+
+        // No new crate is handed out once capped, neither to the agent running the experiment
+        let ex = Experiment::get(&db, "capped").unwrap().unwrap();
+        assert_eq!(ex.capped_at, Some(1));
+        assert!(ex.get_uncompleted_crates(&db, None).unwrap().is_empty());
+        assert!(Experiment::next(&db, &agent).unwrap().is_none());
+        assert!(Experiment::ready_for_report(&db).unwrap().is_none());
+
+        // The report is generated once the running crate finishes
+        store_results(
+            &db,
+            &ex,
+            &running,
+            [TestResult::TestPass, TestResult::TestPass],
+        );
+        assert_eq!(
+            Experiment::ready_for_report(&db).unwrap().unwrap().name,
+            "capped"
+        );
+        assert_eq!(ex.get_completed_crates(&db).unwrap().len(), 3);
+    }
 }
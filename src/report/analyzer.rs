@@ -40,7 +40,7 @@ fn analyze_detailed(toolchain: usize, crates: Vec<CrateResult>) -> ReportCrates
     let mut root = Vec::new();
     for krate in crates {
         if let BuildFail(FailureReason::DependsOn(ref deps)) =
-            krate.runs[toolchain].as_ref().unwrap().res
+            krate.compared_runs()[toolchain].unwrap().res
         {
             for dep in deps {
                 tree.entry(dep.clone())
@@ -55,7 +55,7 @@ fn analyze_detailed(toolchain: usize, crates: Vec<CrateResult>) -> ReportCrates
     for krate in root {
         // record results only for root crates
         if let BuildFail(FailureReason::CompilerError(codes)) =
-            krate.runs[toolchain].clone().unwrap().res
+            krate.compared_runs()[toolchain].unwrap().res.clone()
         {
             for code in codes {
                 results
@@ -65,7 +65,7 @@ fn analyze_detailed(toolchain: usize, crates: Vec<CrateResult>) -> ReportCrates
             }
         } else {
             results
-                .entry(krate.runs[toolchain].as_ref().unwrap().res.clone())
+                .entry(krate.compared_runs()[toolchain].unwrap().res.clone())
                 .or_insert_with(Vec::new)
                 .push(krate)
         }
@@ -112,6 +112,7 @@ mod tests {
     use crate::results::{DummyDB, FailureReason::*};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
     use failure::Fallible;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_report_analysis() -> Fallible<()> {
@@ -166,6 +167,15 @@ mod tests {
             report_url: None,
             ignore_blacklist: false,
             requirement: None,
+            max_regressions: None,
+            capped_at: None,
+            cancelled_at: None,
+            webhook: None,
+            sample_seed: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         };
 
         let crates = record_crates! {db, ex,
@@ -0,0 +1,127 @@
+use crate::prelude::*;
+use crate::report::{ReportRuntime, ReportWriter, CHECK_CONTENT, CHECK_PATH};
+use crate::results::EncodingType;
+use mime::Mime;
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::io::Read;
+use std::path::Path;
+
+/// Async counterpart of `ReportWriter`, for backends performing their uploads asynchronously.
+///
+/// Callers already running inside an async context can use this directly, while everyone else
+/// can wrap the writer in a `BlockingWriter` to get a regular `ReportWriter`.
+#[allow(async_fn_in_trait)]
+pub trait AsyncReportWriter: Send + Sync {
+    async fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()>;
+    async fn write_string<P: AsRef<Path>>(
+        &self,
+        path: P,
+        s: Cow<'_, str>,
+        mime: &Mime,
+    ) -> Fallible<()>;
+
+    /// Async counterpart of `ReportWriter::write_stream`. The reader is read from the task
+    /// performing the upload, so it should be a file or an in-memory buffer.
+    async fn write_stream<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        mut reader: R,
+        _len: Option<u64>,
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+        self.write_bytes(path, &body, mime, encoding_type).await
+    }
+
+    /// Async counterpart of `ReportWriter::read_bytes`.
+    async fn read_bytes<P: AsRef<Path>>(&self, _path: P) -> Fallible<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// Async counterpart of `ReportWriter::object_url`.
+    async fn object_url<P: AsRef<Path>>(&self, _path: P) -> Fallible<Option<String>> {
+        Ok(None)
+    }
+
+    /// Async counterpart of `ReportWriter::check_writable`.
+    async fn check_writable(&self) -> Fallible<()> {
+        self.write_string(CHECK_PATH, CHECK_CONTENT.into(), &mime::TEXT_PLAIN_UTF_8)
+            .await
+    }
+}
+
+/// Adapter implementing `ReportWriter` for any `AsyncReportWriter`, by blocking on the provided
+/// runtime until each write completes. It must not be used from inside an async context.
+pub struct BlockingWriter<W: AsyncReportWriter> {
+    inner: W,
+    runtime: ReportRuntime,
+}
+
+impl<W: AsyncReportWriter> BlockingWriter<W> {
+    pub fn new(inner: W, runtime: ReportRuntime) -> Self {
+        BlockingWriter { inner, runtime }
+    }
+
+    pub fn inner(&self) -> &W {
+        &self.inner
+    }
+}
+
+impl<W: AsyncReportWriter> ReportWriter for BlockingWriter<W> {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.runtime
+            .block_on(self.inner.write_bytes(path, b, mime, encoding_type))
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.runtime
+            .block_on(self.inner.write_string(path, s, mime))
+    }
+
+    fn write_stream<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        reader: R,
+        len: Option<u64>,
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.runtime.block_on(
+            self.inner
+                .write_stream(path, reader, len, mime, encoding_type),
+        )
+    }
+
+    fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        self.runtime.block_on(self.inner.read_bytes(path))
+    }
+
+    fn object_url<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<String>> {
+        self.runtime.block_on(self.inner.object_url(path))
+    }
+
+    fn check_writable(&self) -> Fallible<()> {
+        self.runtime.block_on(self.inner.check_writable())
+    }
+}
+
+impl<W: AsyncReportWriter + Display> Display for BlockingWriter<W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
@@ -0,0 +1,75 @@
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+
+pub(super) const DEFAULT_FAILURE_THRESHOLD: u32 = 10;
+pub(super) const DEFAULT_COOLDOWN: Duration = Duration::from_secs(60);
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum State {
+    Closed,
+    /// Requests fail fast until the cooldown elapses.
+    Open {
+        since: Instant,
+    },
+    /// A single request is let through to probe whether the service recovered.
+    HalfOpen,
+}
+
+/// Circuit breaker shared by all the requests of a writer, so a service that's down fails the
+/// remaining writes quickly instead of having each of them retry for minutes.
+///
+/// It trips once `threshold` requests fail in a row with a transient error, and lets a probe
+/// through after `cooldown`: its success closes the breaker, its failure opens it again.
+pub(super) struct CircuitBreaker {
+    threshold: u32,
+    cooldown: Duration,
+    state: Mutex<(State, u32)>,
+}
+
+impl CircuitBreaker {
+    pub(super) fn new(threshold: u32, cooldown: Duration) -> Self {
+        CircuitBreaker {
+            threshold: threshold.max(1),
+            cooldown,
+            state: Mutex::new((State::Closed, 0)),
+        }
+    }
+
+    /// Whether a request can be sent, or should fail fast.
+    pub(super) fn allow(&self) -> bool {
+        let mut state = self.state.lock().unwrap();
+        match state.0 {
+            State::Closed => true,
+            State::Open { since } if since.elapsed() >= self.cooldown => {
+                state.0 = State::HalfOpen;
+                true
+            }
+            State::Open { .. } | State::HalfOpen => false,
+        }
+    }
+
+    /// Record a request which reached the service, even if it was refused.
+    pub(super) fn record_success(&self) {
+        *self.state.lock().unwrap() = (State::Closed, 0);
+    }
+
+    /// Record a request failing with a transient error.
+    pub(super) fn record_failure(&self) {
+        let mut state = self.state.lock().unwrap();
+        state.1 += 1;
+        let trip = match state.0 {
+            State::Closed => state.1 >= self.threshold,
+            State::Open { .. } | State::HalfOpen => true,
+        };
+        if trip {
+            state.0 = State::Open {
+                since: Instant::now(),
+            };
+        }
+    }
+
+    /// Number of requests which failed in a row.
+    pub(super) fn consecutive_failures(&self) -> u32 {
+        self.state.lock().unwrap().1
+    }
+}
@@ -0,0 +1,307 @@
+use crate::prelude::*;
+use crate::report::{crate_name_and_version, BuildTestResult, CrateResult, RawTestResults};
+use crate::results::{FailureReason, TestResult};
+use crate::toolchain::Toolchain;
+use std::cmp::Reverse;
+use std::time::Duration;
+
+/// Build time of a crate with a toolchain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateBuildTimeV1 {
+    pub name: String,
+    pub version: Option<String>,
+    pub build_ms: u64,
+    /// The crate was killed once it reached the build timeout, which is reported as its build
+    /// time.
+    pub timed_out: bool,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolchainBuildTimesV1 {
+    pub toolchain: String,
+    /// Slowest crates to build with the toolchain, slowest first.
+    pub slowest: Vec<CrateBuildTimeV1>,
+}
+
+/// Change of the build time of a crate between the start and the end toolchain.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildTimeDeltaV1 {
+    pub name: String,
+    pub version: Option<String>,
+    pub start_ms: u64,
+    pub end_ms: u64,
+    pub delta_ms: i64,
+    /// The crate timed out with at least one of the toolchains, so the delta is a lower bound.
+    pub timed_out: bool,
+}
+
+/// Slowest crates of an experiment, and how their build times changed between the toolchains.
+/// Only available when the build times were recorded, which results stored by older versions
+/// lack.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct BuildTimesV1 {
+    pub toolchains: Vec<ToolchainBuildTimesV1>,
+    /// Crates slowed down the most by the end toolchain, largest increase first.
+    pub largest_increases: Vec<BuildTimeDeltaV1>,
+    /// Sum of the deltas of all the crates timed with both toolchains.
+    pub total_delta_ms: i64,
+}
+
+fn is_timeout(res: &TestResult) -> bool {
+    matches!(
+        res,
+        TestResult::Timeout
+            | TestResult::BuildFail(FailureReason::Timeout)
+            | TestResult::TestFail(FailureReason::Timeout)
+    )
+}
+
+/// Build time of a run and whether it timed out. Timed out crates are reported with the timeout,
+/// even if their duration was recorded.
+fn build_time(run: &BuildTestResult, timeout: Duration) -> Option<(u64, bool)> {
+    if is_timeout(&run.res) {
+        Some((timeout.as_millis() as u64, true))
+    } else {
+        run.duration_ms.map(|ms| (ms, false))
+    }
+}
+
+impl BuildTimesV1 {
+    /// Build times of the `top` slowest crates with each toolchain, or `None` if no build time
+    /// was recorded.
+    pub fn new(
+        toolchains: &[Toolchain; 2],
+        raw: &RawTestResults,
+        timeout: Duration,
+        top: usize,
+    ) -> Option<Self> {
+        let recorded = raw
+            .crates
+            .iter()
+            .flat_map(|krate| krate.compared_runs().into_iter().flatten())
+            .any(|run| run.duration_ms.is_some());
+        if !recorded {
+            return None;
+        }
+
+        let times = |krate: &CrateResult, idx: usize| {
+            krate.compared_runs()[idx].and_then(|run| build_time(run, timeout))
+        };
+
+        let toolchains = toolchains
+            .iter()
+            .enumerate()
+            .map(|(idx, tc)| {
+                let mut slowest = raw
+                    .crates
+                    .iter()
+                    .filter_map(|krate| {
+                        let (build_ms, timed_out) = times(krate, idx)?;
+                        let (name, version) = crate_name_and_version(&krate.krate);
+                        Some(CrateBuildTimeV1 {
+                            name,
+                            version,
+                            build_ms,
+                            timed_out,
+                        })
+                    })
+                    .collect::<Vec<_>>();
+                // Ties are broken by name, so the report doesn't change between generations
+                slowest.sort_by(|a, b| {
+                    (Reverse(a.build_ms), &a.name, &a.version).cmp(&(
+                        Reverse(b.build_ms),
+                        &b.name,
+                        &b.version,
+                    ))
+                });
+                slowest.truncate(top);
+                ToolchainBuildTimesV1 {
+                    toolchain: tc.to_string(),
+                    slowest,
+                }
+            })
+            .collect();
+
+        let mut deltas = raw
+            .crates
+            .iter()
+            .filter_map(|krate| {
+                let (start_ms, start_timed_out) = times(krate, 0)?;
+                let (end_ms, end_timed_out) = times(krate, 1)?;
+                let (name, version) = crate_name_and_version(&krate.krate);
+                Some(BuildTimeDeltaV1 {
+                    name,
+                    version,
+                    start_ms,
+                    end_ms,
+                    delta_ms: end_ms as i64 - start_ms as i64,
+                    timed_out: start_timed_out || end_timed_out,
+                })
+            })
+            .collect::<Vec<_>>();
+        let total_delta_ms = deltas.iter().map(|delta| delta.delta_ms).sum();
+
+        deltas.retain(|delta| delta.delta_ms > 0);
+        deltas.sort_by(|a, b| {
+            (Reverse(a.delta_ms), &a.name, &a.version).cmp(&(
+                Reverse(b.delta_ms),
+                &b.name,
+                &b.version,
+            ))
+        });
+        deltas.truncate(top);
+
+        Some(BuildTimesV1 {
+            toolchains,
+            largest_increases: deltas,
+            total_delta_ms,
+        })
+    }
+}
+
+/// Duration in seconds, with a decimal, as shown in the markdown report.
+pub(super) fn format_ms(ms: u64) -> String {
+    format!("{}.{}s", ms / 1000, ms % 1000 / 100)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{format_ms, BuildTimeDeltaV1, BuildTimesV1, CrateBuildTimeV1};
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::report::{BuildTestResult, Comparison, CrateResult, RawTestResults};
+    use crate::results::TestResult;
+    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use std::time::Duration;
+
+    fn run(res: TestResult, duration_ms: Option<u64>) -> Option<BuildTestResult> {
+        Some(BuildTestResult {
+            res,
+            log: String::new(),
+            log_url: None,
+            duration_ms,
+            repro: None,
+        })
+    }
+
+    fn krate(name: &str, runs: [Option<BuildTestResult>; 2]) -> CrateResult {
+        let krate = Crate::Registry(RegistryCrate {
+            name: name.into(),
+            version: "1.0.0".into(),
+        });
+        CrateResult {
+            name: name.into(),
+            url: String::new(),
+            krate,
+            status: None,
+            res: Comparison::SameTestPass,
+            runs: [&*MAIN_TOOLCHAIN, &*TEST_TOOLCHAIN]
+                .map(|tc| tc.to_string())
+                .into_iter()
+                .zip(runs)
+                .collect(),
+        }
+    }
+
+    fn timed(name: &str, start_ms: u64, end_ms: u64) -> CrateResult {
+        krate(
+            name,
+            [
+                run(TestResult::TestPass, Some(start_ms)),
+                run(TestResult::TestPass, Some(end_ms)),
+            ],
+        )
+    }
+
+    fn slowest(name: &str, build_ms: u64, timed_out: bool) -> CrateBuildTimeV1 {
+        CrateBuildTimeV1 {
+            name: name.into(),
+            version: Some("1.0.0".into()),
+            build_ms,
+            timed_out,
+        }
+    }
+
+    #[test]
+    fn test_build_times() {
+        let toolchains = [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()];
+        let timeout = Duration::from_secs(60);
+        let raw = RawTestResults {
+            crates: vec![
+                timed("a", 1_000, 3_000),
+                timed("b", 5_000, 4_000),
+                timed("c", 2_000, 2_000),
+                timed("d", 10_000, 12_500),
+                // Timed out with the end toolchain, reported with the timeout
+                krate(
+                    "e",
+                    [
+                        run(TestResult::TestPass, Some(30_000)),
+                        run(TestResult::Timeout, Some(60_123)),
+                    ],
+                ),
+                // Only timed with one toolchain
+                krate("f", [run(TestResult::TestPass, Some(20_000)), None]),
+            ],
+        };
+
+        let times = BuildTimesV1::new(&toolchains, &raw, timeout, 3).unwrap();
+        assert_eq!(times.toolchains.len(), 2);
+        assert_eq!(times.toolchains[0].toolchain, MAIN_TOOLCHAIN.to_string());
+        assert_eq!(
+            times.toolchains[0].slowest,
+            vec![
+                slowest("e", 30_000, false),
+                slowest("f", 20_000, false),
+                slowest("d", 10_000, false),
+            ]
+        );
+        assert_eq!(
+            times.toolchains[1].slowest,
+            vec![
+                slowest("e", 60_000, true),
+                slowest("d", 12_500, false),
+                slowest("b", 4_000, false),
+            ]
+        );
+
+        // Only the increases are listed, while the total accounts for every crate timed with
+        // both toolchains
+        let delta = |name: &str, start_ms, end_ms, delta_ms, timed_out| BuildTimeDeltaV1 {
+            name: name.into(),
+            version: Some("1.0.0".into()),
+            start_ms,
+            end_ms,
+            delta_ms,
+            timed_out,
+        };
+        assert_eq!(
+            times.largest_increases,
+            vec![
+                delta("e", 30_000, 60_000, 30_000, true),
+                delta("d", 10_000, 12_500, 2_500, false),
+                delta("a", 1_000, 3_000, 2_000, false),
+            ]
+        );
+        assert_eq!(times.total_delta_ms, 2_000 - 1_000 + 2_500 + 30_000);
+
+        assert_eq!(format_ms(12_345), "12.3s");
+    }
+
+    #[test]
+    fn test_build_times_not_recorded() {
+        let toolchains = [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()];
+        let raw = RawTestResults {
+            crates: vec![krate(
+                "a",
+                [
+                    run(TestResult::TestPass, None),
+                    run(TestResult::Timeout, None),
+                ],
+            )],
+        };
+        assert_eq!(
+            BuildTimesV1::new(&toolchains, &raw, Duration::from_secs(60), 3),
+            None
+        );
+    }
+}
@@ -0,0 +1,164 @@
+use crate::prelude::*;
+use crate::report::ReportWriter;
+use crate::results::{read_object, EncodingType};
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use mime::Mime;
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::Seek;
+use std::path::Path;
+use std::sync::Mutex;
+use tar::{Builder as TarBuilder, Header as TarHeader};
+use tempfile::tempfile;
+
+pub const BUNDLE_PATH: &str = "report.tar.gz";
+
+/// Writer forwarding everything to another writer, while also adding every written file to a
+/// tarball of the whole report.
+///
+/// The tarball is compressed as entries are added and backed by a temporary file, which is
+/// streamed to the wrapped writer at the end, so the report is never fully buffered in memory.
+/// Encoded files are added to it decoded.
+pub struct BundleWriter<'a, W: ReportWriter> {
+    inner: &'a W,
+    archive: Mutex<TarBuilder<GzEncoder<File>>>,
+}
+
+impl<'a, W: ReportWriter> BundleWriter<'a, W> {
+    pub fn new(inner: &'a W) -> Fallible<Self> {
+        Ok(BundleWriter {
+            inner,
+            archive: Mutex::new(TarBuilder::new(GzEncoder::new(
+                tempfile()?,
+                Compression::default(),
+            ))),
+        })
+    }
+
+    /// Finish the tarball and stream it to the wrapped writer as `report.tar.gz`.
+    pub fn finish(self) -> Fallible<()> {
+        let mut file = self.archive.into_inner().unwrap().into_inner()?.finish()?;
+        file.rewind()?;
+        let len = file.metadata()?.len();
+
+        info!("writing {} ({} bytes)", BUNDLE_PATH, len);
+        self.inner.write_stream(
+            BUNDLE_PATH,
+            file,
+            Some(len),
+            &"application/gzip".parse().unwrap(),
+            EncodingType::Plain,
+        )
+    }
+
+    fn append(&self, path: &Path, data: &[u8]) -> Fallible<()> {
+        let mut header = TarHeader::new_gnu();
+        header.set_size(data.len() as u64);
+        header.set_mode(0o644);
+        header.set_cksum();
+        self.archive
+            .lock()
+            .unwrap()
+            .append_data(&mut header, path, data)
+            .with_context(|_| format!("failed to add {path:?} to {BUNDLE_PATH}"))?;
+        Ok(())
+    }
+}
+
+impl<W: ReportWriter> ReportWriter for BundleWriter<'_, W> {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.inner
+            .write_bytes(path.as_ref(), b, mime, encoding_type)?;
+        // The tarball has no place to record the encoding, so it stores the decoded files
+        let plain = match encoding_type {
+            EncodingType::Plain => Cow::Borrowed(b),
+            _ => Cow::Owned(read_object(b, encoding_type)?),
+        };
+        self.append(path.as_ref(), &plain)
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.inner
+            .write_string(path.as_ref(), Cow::Borrowed(&s), mime)?;
+        self.append(path.as_ref(), s.as_bytes())
+    }
+
+    fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        self.inner.read_bytes(path)
+    }
+
+    fn object_url<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<String>> {
+        self.inner.object_url(path)
+    }
+}
+
+impl<W: ReportWriter + Display> Display for BundleWriter<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{BundleWriter, BUNDLE_PATH};
+    use crate::report::{MemoryWriter, ReportWriter};
+    use crate::results::{read_object, EncodedLog, EncodingType};
+    use flate2::read::GzDecoder;
+    use std::io::Read;
+    use tar::Archive;
+
+    #[test]
+    fn test_bundle_writer() {
+        let writer = MemoryWriter::new();
+        let bundle = BundleWriter::new(&writer).unwrap();
+        let log = (0..=255).collect::<Vec<u8>>();
+        let encoded = EncodedLog::from_plain_slice(&log, EncodingType::Gzip).unwrap();
+
+        bundle
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        bundle
+            .write_bytes(
+                "stable/reg/foo-1.0.0/log.txt",
+                encoded.as_slice(),
+                &mime::TEXT_PLAIN_UTF_8,
+                EncodingType::Gzip,
+            )
+            .unwrap();
+        bundle.finish().unwrap();
+
+        let stored = writer.get(BUNDLE_PATH).unwrap();
+        assert_eq!(stored.mime.to_string(), "application/gzip");
+        assert_eq!(stored.encoding, EncodingType::Plain);
+
+        let mut archive = Archive::new(GzDecoder::new(stored.data.as_slice()));
+        let mut files = Vec::new();
+        for entry in archive.entries().unwrap() {
+            let mut entry = entry.unwrap();
+            let path = entry.path().unwrap().into_owned();
+            let mut content = Vec::new();
+            entry.read_to_end(&mut content).unwrap();
+            files.push((path, content));
+        }
+
+        // Every file written through the bundle is in the archive decoded, apart from the
+        // archive itself
+        assert_eq!(files.len(), writer.len() - 1);
+        for (path, content) in &files {
+            let stored = writer.get(path).unwrap();
+            assert_eq!(
+                read_object(stored.data.as_slice(), stored.encoding).unwrap(),
+                *content
+            );
+        }
+        assert!(files.contains(&("stable/reg/foo-1.0.0/log.txt".into(), log)));
+    }
+}
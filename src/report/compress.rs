@@ -0,0 +1,129 @@
+use crate::prelude::*;
+use crate::report::ReportWriter;
+use crate::results::{CompressionOptions, EncodedLog, EncodingType};
+use mime::Mime;
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::io::Read;
+use std::path::Path;
+
+/// Writer forwarding everything to another writer, compressing the text files written as strings
+/// (HTML pages, JSON, CSV...) with the configured encoding.
+///
+/// The encoding is stored with the files, so writers serving them over HTTP set the
+/// `Content-Encoding` header and browsers decompress them transparently.
+pub struct CompressWriter<'a, W: ReportWriter> {
+    inner: &'a W,
+    encoding: EncodingType,
+    options: CompressionOptions,
+}
+
+impl<'a, W: ReportWriter> CompressWriter<'a, W> {
+    pub fn new(inner: &'a W, encoding: EncodingType, options: CompressionOptions) -> Self {
+        CompressWriter {
+            inner,
+            encoding,
+            options,
+        }
+    }
+}
+
+fn is_text(mime: &Mime) -> bool {
+    mime.type_() == mime::TEXT || mime.subtype() == mime::JSON || mime.suffix() == Some(mime::JSON)
+}
+
+impl<W: ReportWriter> ReportWriter for CompressWriter<'_, W> {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.inner.write_bytes(path, b, mime, encoding_type)
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        if self.encoding == EncodingType::Plain || !is_text(mime) {
+            return self.inner.write_string(path, s, mime);
+        }
+
+        let encoded =
+            EncodedLog::from_plain_slice_with(s.as_bytes(), self.encoding, &self.options)?;
+        self.inner
+            .write_bytes(path, encoded.as_slice(), mime, self.encoding)
+    }
+
+    fn write_stream<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        reader: R,
+        len: Option<u64>,
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.inner
+            .write_stream(path, reader, len, mime, encoding_type)
+    }
+
+    fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        self.inner.read_bytes(path)
+    }
+
+    fn object_url<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<String>> {
+        self.inner.object_url(path)
+    }
+}
+
+impl<W: ReportWriter + Display> Display for CompressWriter<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::CompressWriter;
+    use crate::report::{MemoryWriter, ReportWriter};
+    use crate::results::{read_object, CompressionOptions, EncodingType};
+
+    #[test]
+    fn test_compress_text() {
+        let writer = MemoryWriter::new();
+        let compress =
+            CompressWriter::new(&writer, EncodingType::Brotli, CompressionOptions::default());
+        let page = "<tr><td>lazy_static-1.0.0</td><td>regressed</td></tr>\n".repeat(256);
+
+        compress
+            .write_string("index.html", page.as_str().into(), &mime::TEXT_HTML)
+            .unwrap();
+        compress
+            .write_string("report.svg", "<svg/>".into(), &mime::IMAGE_SVG)
+            .unwrap();
+        compress
+            .write_bytes(
+                "stable/reg/foo-1.0.0/log.txt",
+                b"log",
+                &mime::TEXT_PLAIN_UTF_8,
+                EncodingType::Gzip,
+            )
+            .unwrap();
+
+        // Text files are compressed, and decode back to the original content
+        let stored = writer.get("index.html").unwrap();
+        assert_eq!(stored.encoding, EncodingType::Brotli);
+        assert!(stored.data.len() < page.len());
+        assert_eq!(
+            read_object(stored.data.as_slice(), stored.encoding).unwrap(),
+            page.as_bytes()
+        );
+
+        // Other files are left alone
+        let stored = writer.get("report.svg").unwrap();
+        assert_eq!(stored.encoding, EncodingType::Plain);
+        assert_eq!(stored.data, b"<svg/>");
+        let stored = writer.get("stable/reg/foo-1.0.0/log.txt").unwrap();
+        assert_eq!(stored.encoding, EncodingType::Gzip);
+        assert_eq!(stored.data, b"log");
+    }
+}
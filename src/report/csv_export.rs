@@ -0,0 +1,105 @@
+use crate::prelude::*;
+use crate::report::summary::{crate_name_and_version, SummaryCategory};
+use crate::report::{BuildTestResult, RawTestResults, ReportWriter};
+use crate::results::EncodingType;
+
+pub const CSV_PATH: &str = "results.csv";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+struct CsvRow {
+    #[serde(rename = "crate")]
+    krate: String,
+    version: String,
+    start_toolchain_result: String,
+    end_toolchain_result: String,
+    category: SummaryCategory,
+    log_url: String,
+}
+
+fn run_result(run: Option<&BuildTestResult>) -> String {
+    run.map(|run| run.res.to_string()).unwrap_or_default()
+}
+
+fn csv_rows(raw: &RawTestResults) -> Vec<CsvRow> {
+    raw.crates
+        .iter()
+        .map(|krate| {
+            let (name, version) = crate_name_and_version(&krate.krate);
+            let [start, end] = krate.compared_runs();
+
+            CsvRow {
+                krate: name,
+                version: version.unwrap_or_default(),
+                start_toolchain_result: run_result(start),
+                end_toolchain_result: run_result(end),
+                category: SummaryCategory::for_crate(krate),
+                // The log of the end toolchain is the interesting one when triaging regressions
+                log_url: end
+                    .or(start)
+                    .map(|run| format!("{}/log.txt", run.log))
+                    .unwrap_or_default(),
+            }
+        })
+        .collect()
+}
+
+/// Serialize the rows as RFC 4180 CSV, quoting the fields containing commas, quotes or newlines.
+fn to_csv(rows: &[CsvRow]) -> Fallible<Vec<u8>> {
+    let mut writer = ::csv::WriterBuilder::new()
+        .terminator(::csv::Terminator::CRLF)
+        .from_writer(Vec::new());
+    for row in rows {
+        writer.serialize(row)?;
+    }
+    Ok(writer.into_inner().map_err(|e| e.into_error())?)
+}
+
+pub fn write_csv<W: ReportWriter>(raw: &RawTestResults, dest: &W) -> Fallible<()> {
+    dest.write_bytes(
+        CSV_PATH,
+        &to_csv(&csv_rows(raw))?,
+        &mime::TEXT_CSV_UTF_8,
+        EncodingType::Plain,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{to_csv, CsvRow};
+    use crate::report::SummaryCategory;
+
+    #[test]
+    fn test_csv_round_trip() {
+        let rows = vec![
+            CsvRow {
+                krate: "lazy_static".into(),
+                version: "1.0.0".into(),
+                start_toolchain_result: "test-pass".into(),
+                end_toolchain_result: "build-fail:unknown".into(),
+                category: SummaryCategory::Regressed,
+                log_url: "beta/reg/lazy_static-1.0.0/log.txt".into(),
+            },
+            CsvRow {
+                krate: "weird,\"name\"".into(),
+                version: "".into(),
+                start_toolchain_result: "".into(),
+                end_toolchain_result: "test-pass".into(),
+                category: SummaryCategory::Unchanged,
+                log_url: "beta/path/weird%2C%22name%22/log.txt".into(),
+            },
+        ];
+
+        let csv = to_csv(&rows).unwrap();
+        let text = String::from_utf8(csv.clone()).unwrap();
+        assert!(text.starts_with(
+            "crate,version,start_toolchain_result,end_toolchain_result,category,log_url\r\n"
+        ));
+        assert!(text.contains("\"weird,\"\"name\"\"\""));
+
+        let parsed = ::csv::Reader::from_reader(csv.as_slice())
+            .deserialize()
+            .collect::<Result<Vec<CsvRow>, _>>()
+            .unwrap();
+        assert_eq!(parsed, rows);
+    }
+}
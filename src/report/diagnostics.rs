@@ -0,0 +1,66 @@
+use crate::report::{Comparison, CrateResult};
+use crate::results::DiagnosticCode;
+use std::collections::BTreeSet;
+
+/// Diagnostic codes which appeared or went away between the start and the end toolchain.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct DiagnosticsDiff {
+    pub added: Vec<String>,
+    pub removed: Vec<String>,
+}
+
+impl DiagnosticsDiff {
+    pub fn between(before: &BTreeSet<DiagnosticCode>, after: &BTreeSet<DiagnosticCode>) -> Self {
+        DiagnosticsDiff {
+            added: after.difference(before).map(ToString::to_string).collect(),
+            removed: before.difference(after).map(ToString::to_string).collect(),
+        }
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Codes changed by the end toolchain for a regressed or fixed crate, if any.
+    pub(super) fn for_crate(krate: &CrateResult) -> Option<Self> {
+        if !matches!(krate.res, Comparison::Regressed | Comparison::Fixed) {
+            return None;
+        }
+
+        let codes = |run: usize| {
+            krate.compared_runs()[run]
+                .map(|run| run.res.diagnostic_codes())
+                .unwrap_or_default()
+        };
+        let diff = DiagnosticsDiff::between(&codes(0), &codes(1));
+        (!diff.is_empty()).then_some(diff)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::DiagnosticsDiff;
+    use crate::results::DiagnosticCode;
+    use std::collections::BTreeSet;
+
+    fn codes(codes: &[&str]) -> BTreeSet<DiagnosticCode> {
+        codes
+            .iter()
+            .map(|code| DiagnosticCode::from(code.to_string()))
+            .collect()
+    }
+
+    #[test]
+    fn test_diagnostics_diff() {
+        let diff = DiagnosticsDiff::between(
+            &codes(&["E0277", "E0308"]),
+            &codes(&["E0308", "E0425", "E0599"]),
+        );
+        assert_eq!(diff.added, vec!["E0425", "E0599"]);
+        assert_eq!(diff.removed, vec!["E0277"]);
+        assert!(!diff.is_empty());
+
+        let unchanged = DiagnosticsDiff::between(&codes(&["E0308"]), &codes(&["E0308"]));
+        assert!(unchanged.is_empty());
+    }
+}
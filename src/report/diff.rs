@@ -0,0 +1,297 @@
+use crate::assets;
+use crate::prelude::*;
+use crate::report::{CategoryCountsV1, ReportSummaryV1, ReportWriter, SummaryCategory};
+use indexmap::IndexMap;
+
+/// A crate whose category differs between two experiments. `before` or `after` is missing when
+/// the crate was only tested in one of them.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateDiff {
+    pub name: String,
+    pub version: Option<String>,
+    pub before: Option<SummaryCategory>,
+    pub after: Option<SummaryCategory>,
+}
+
+/// How many crates were in a category in each experiment.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CountDelta {
+    pub category: String,
+    pub before: usize,
+    pub after: usize,
+    pub delta: i64,
+}
+
+/// Crates which changed category between two runs, grouped by the kind of change.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportDiff {
+    pub before: String,
+    pub after: String,
+    pub counts: Vec<CountDelta>,
+    pub newly_regressed: Vec<CrateDiff>,
+    pub newly_fixed: Vec<CrateDiff>,
+    pub flipped: Vec<CrateDiff>,
+    pub added: Vec<CrateDiff>,
+    pub removed: Vec<CrateDiff>,
+}
+
+impl ReportDiff {
+    /// Compare the results of `before` with the ones of `after`, a later run of the experiment.
+    pub fn between(before: &ReportSummaryV1, after: &ReportSummaryV1) -> ReportDiff {
+        // Counted from the crates, as older summaries don't include the counts
+        let count = |summary: &ReportSummaryV1| {
+            CategoryCountsV1::count(
+                summary
+                    .crates
+                    .iter()
+                    .map(|krate| (krate.category, krate.comparison)),
+            )
+        };
+        let (counts_before, counts_after) = (count(before), count(after));
+        let counts = counts_before
+            .entries()
+            .into_iter()
+            .chain([("total", counts_before.total)])
+            .zip(
+                counts_after
+                    .entries()
+                    .into_iter()
+                    .chain([("total", counts_after.total)]),
+            )
+            .map(|((category, before), (_, after))| CountDelta {
+                category: category.into(),
+                before,
+                after,
+                delta: after as i64 - before as i64,
+            })
+            .collect();
+
+        let mut diff = ReportDiff {
+            before: before.experiment.clone(),
+            after: after.experiment.clone(),
+            counts,
+            newly_regressed: Vec::new(),
+            newly_fixed: Vec::new(),
+            flipped: Vec::new(),
+            added: Vec::new(),
+            removed: Vec::new(),
+        };
+
+        // Crates are matched by name only, as a rerun might test a newer version
+        let mut remaining = before
+            .crates
+            .iter()
+            .map(|krate| (krate.name.as_str(), krate))
+            .collect::<IndexMap<_, _>>();
+
+        for new in &after.crates {
+            let old = remaining.shift_remove(new.name.as_str());
+            let entry = CrateDiff {
+                name: new.name.clone(),
+                version: new.version.clone(),
+                before: old.map(|old| old.category),
+                after: Some(new.category),
+            };
+
+            match old {
+                None => diff.added.push(entry),
+                Some(old) if old.category == new.category => {}
+                Some(_) => match new.category {
+                    SummaryCategory::Regressed => diff.newly_regressed.push(entry),
+                    SummaryCategory::Fixed => diff.newly_fixed.push(entry),
+                    SummaryCategory::Unchanged
+                    | SummaryCategory::Broken
+                    | SummaryCategory::Timeout
+                    | SummaryCategory::OutOfMemory
+                    | SummaryCategory::FetchError => diff.flipped.push(entry),
+                },
+            }
+        }
+
+        diff.removed = remaining
+            .into_values()
+            .map(|old| CrateDiff {
+                name: old.name.clone(),
+                version: old.version.clone(),
+                before: Some(old.category),
+                after: None,
+            })
+            .collect();
+
+        diff
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.newly_regressed.is_empty()
+            && self.newly_fixed.is_empty()
+            && self.flipped.is_empty()
+            && self.added.is_empty()
+            && self.removed.is_empty()
+    }
+}
+
+#[derive(Serialize)]
+struct DiffSection<'a> {
+    id: &'static str,
+    title: &'static str,
+    crates: &'a [CrateDiff],
+}
+
+#[derive(Serialize)]
+struct DiffContext<'a> {
+    diff: &'a ReportDiff,
+    sections: Vec<DiffSection<'a>>,
+}
+
+/// Write `diff.json` and `diff.html` for the provided diff.
+pub fn write_diff_report<W: ReportWriter>(diff: &ReportDiff, dest: &W) -> Fallible<()> {
+    info!("writing diff between {} and {}", diff.before, diff.after);
+    dest.write_string(
+        "diff.json",
+        serde_json::to_string(diff)?.into(),
+        &mime::APPLICATION_JSON,
+    )?;
+
+    let sections = [
+        ("regressed", "Newly regressed", &diff.newly_regressed),
+        ("fixed", "Newly fixed", &diff.newly_fixed),
+        ("flipped", "Flipped", &diff.flipped),
+        ("added", "Added", &diff.added),
+        ("removed", "Removed", &diff.removed),
+    ]
+    .into_iter()
+    .filter(|(_, _, crates)| !crates.is_empty())
+    .map(|(id, title, crates)| DiffSection { id, title, crates })
+    .collect();
+
+    let context = DiffContext { diff, sections };
+    let rendered = assets::render_template("report/diff.html", &context)
+        .context("rendering template report/diff.html")?;
+    let html = minifier::html::minify(&rendered);
+    dest.write_string("diff.html", html.into(), &mime::TEXT_HTML)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_diff_report, CountDelta, CrateDiff, ReportDiff};
+    use crate::report::{
+        Comparison, CrateSummaryV1, MemoryWriter, ReportSummaryV1, SummaryCategory,
+    };
+
+    fn summary(name: &str, crates: &[(&str, Comparison)]) -> ReportSummaryV1 {
+        ReportSummaryV1 {
+            version: ReportSummaryV1::VERSION,
+            experiment: name.into(),
+            crates: crates
+                .iter()
+                .map(|&(name, comparison)| CrateSummaryV1 {
+                    name: name.into(),
+                    version: Some("1.0.0".into()),
+                    category: comparison.into(),
+                    comparison,
+                    results: Vec::new(),
+                    diagnostics: None,
+                })
+                .collect(),
+            omitted: 0,
+            counts: Default::default(),
+            labels: Default::default(),
+            build_times: None,
+            regressions_by_code: Vec::new(),
+        }
+    }
+
+    #[test]
+    fn test_report_diff() {
+        let before = summary(
+            "before",
+            &[
+                ("flaky", Comparison::Regressed),
+                ("stable", Comparison::SameTestPass),
+                ("broke", Comparison::SameTestPass),
+                ("gone", Comparison::Fixed),
+            ],
+        );
+        let after = summary(
+            "after",
+            &[
+                ("flaky", Comparison::SameTestPass),
+                ("stable", Comparison::SameTestPass),
+                ("broke", Comparison::Regressed),
+                ("new", Comparison::Broken),
+            ],
+        );
+
+        let diff = ReportDiff::between(&before, &after);
+        let entry = |name: &str, before, after| CrateDiff {
+            name: name.into(),
+            version: Some("1.0.0".into()),
+            before,
+            after,
+        };
+
+        assert_eq!(
+            diff.flipped,
+            vec![entry(
+                "flaky",
+                Some(SummaryCategory::Regressed),
+                Some(SummaryCategory::Unchanged)
+            )]
+        );
+        assert_eq!(
+            diff.newly_regressed,
+            vec![entry(
+                "broke",
+                Some(SummaryCategory::Unchanged),
+                Some(SummaryCategory::Regressed)
+            )]
+        );
+        assert!(diff.newly_fixed.is_empty());
+        let delta = |category: &str| {
+            diff.counts
+                .iter()
+                .find(|count| count.category == category)
+                .cloned()
+                .unwrap()
+        };
+        assert_eq!(
+            delta("regressed"),
+            CountDelta {
+                category: "regressed".into(),
+                before: 1,
+                after: 1,
+                delta: 0,
+            }
+        );
+        assert_eq!(delta("unchanged").delta, 0);
+        assert_eq!(delta("fixed").delta, -1);
+        assert_eq!(delta("broken").delta, 1);
+        assert_eq!(delta("total").delta, 0);
+        assert_eq!(
+            diff.added,
+            vec![entry("new", None, Some(SummaryCategory::Broken))]
+        );
+        assert_eq!(
+            diff.removed,
+            vec![entry("gone", Some(SummaryCategory::Fixed), None)]
+        );
+
+        let writer = MemoryWriter::new();
+        write_diff_report(&diff, &writer).unwrap();
+        let json = writer.get("diff.json").unwrap();
+        assert_eq!(
+            serde_json::from_slice::<ReportDiff>(&json.data).unwrap(),
+            diff
+        );
+        let html = String::from_utf8(writer.get("diff.html").unwrap().data).unwrap();
+        assert!(html.contains("flaky"));
+    }
+
+    #[test]
+    fn test_report_diff_identical() {
+        let results = summary("ex", &[("foo", Comparison::Regressed)]);
+        assert!(ReportDiff::between(&results, &results).is_empty());
+    }
+}
@@ -19,12 +19,15 @@ impl ResultName for FailureReason {
             FailureReason::CompilerError(_) => "compiler error".into(),
             FailureReason::DependsOn(_) => "faulty deps".into(),
             FailureReason::CompilerDiagnosticChange => "compiler diagnostic changed".into(),
+            FailureReason::UndefinedBehavior(_) => "undefined behavior".into(),
         }
     }
 
     fn long_name(&self) -> String {
         match self {
-            FailureReason::CompilerError(_) | FailureReason::DependsOn(_) => self.to_string(),
+            FailureReason::CompilerError(_)
+            | FailureReason::DependsOn(_)
+            | FailureReason::UndefinedBehavior(_) => self.to_string(),
             FailureReason::Unknown
             | FailureReason::NetworkAccess
             | FailureReason::Timeout
@@ -58,10 +61,17 @@ impl ResultName for TestResult {
             TestResult::BrokenCrate(reason) => reason.short_name(),
             TestResult::BuildFail(reason) => format!("build {}", reason.short_name()),
             TestResult::TestFail(reason) => format!("test {}", reason.short_name()),
+            TestResult::DocFail(reason) => format!("doc {}", reason.short_name()),
             TestResult::TestSkipped => "test skipped".into(),
             TestResult::TestPass => "test passed".into(),
             TestResult::Error => "error".into(),
             TestResult::Skipped => "skipped".into(),
+            TestResult::SkippedMsrv => "rust-version too new".into(),
+            TestResult::Yanked => "yanked".into(),
+            TestResult::Timeout => "timed out".into(),
+            TestResult::Warnings(_) => "clippy warnings".into(),
+            TestResult::Unavailable => "tool unavailable".into(),
+            TestResult::FetchError(_) => "fetch error".into(),
         }
     }
 
@@ -69,11 +79,20 @@ impl ResultName for TestResult {
         match self {
             TestResult::BuildFail(reason) => format!("build {}", reason.long_name()),
             TestResult::TestFail(reason) => format!("test {}", reason.long_name()),
+            TestResult::DocFail(reason) => format!("doc {}", reason.long_name()),
             TestResult::BrokenCrate(reason) => reason.long_name(),
+            TestResult::Warnings(lints) => format!("clippy warnings ({lints})"),
+            TestResult::FetchError(error) => format!("fetch error ({error})"),
+            TestResult::SkippedMsrv => {
+                "skipped (the toolchain is older than the crate's rust-version)".into()
+            }
+            TestResult::Yanked => "skipped (the version is yanked from the registry)".into(),
             TestResult::TestSkipped
             | TestResult::TestPass
             | TestResult::Error
-            | TestResult::Skipped => self.short_name(),
+            | TestResult::Skipped
+            | TestResult::Timeout
+            | TestResult::Unavailable => self.short_name(),
         }
     }
 }
@@ -103,6 +122,12 @@ impl ResultColor for Comparison {
             Comparison::Broken => Color::Single("#44176e"),
             Comparison::SpuriousRegressed => Color::Striped("#db3026", "#d5433b"),
             Comparison::SpuriousFixed => Color::Striped("#5630db", "#5d3dcf"),
+            Comparison::Timeout => Color::Single("#b5890d"),
+            Comparison::DocRegressed => Color::Single("#c2563a"),
+            Comparison::SameDocFail => Color::Single("#6e5a3c"),
+            Comparison::FetchError => Color::Single("#8c7a99"),
+            Comparison::Flaky => Color::Striped("#db3026", "#72a156"),
+            Comparison::Yanked => Color::Striped("#494b4a", "#8c7a99"),
         }
     }
 }
@@ -113,9 +138,16 @@ impl ResultColor for TestResult {
             TestResult::BrokenCrate(_) => Color::Single("#44176e"),
             TestResult::BuildFail(_) => Color::Single("#db3026"),
             TestResult::TestFail(_) => Color::Single("#65461e"),
+            TestResult::DocFail(_) => Color::Single("#c2563a"),
             TestResult::TestSkipped | TestResult::TestPass => Color::Single("#62a156"),
+            TestResult::Warnings(_) => Color::Single("#a1a156"),
             TestResult::Error => Color::Single("#d77026"),
-            TestResult::Skipped => Color::Single("#494b4a"),
+            TestResult::Skipped
+            | TestResult::SkippedMsrv
+            | TestResult::Yanked
+            | TestResult::Unavailable => Color::Single("#494b4a"),
+            TestResult::Timeout => Color::Single("#b5890d"),
+            TestResult::FetchError(_) => Color::Single("#8c7a99"),
         }
     }
 }
@@ -0,0 +1,162 @@
+use crate::report::{CrateResult, DiagnosticsDiff, RawTestResults, SummaryCategory};
+use std::cmp::Reverse;
+use std::collections::BTreeMap;
+
+/// Cluster of the regressed crates without a newly introduced code, like the ones failing to
+/// link or panicking.
+pub const OTHER_CODE: &str = "other";
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ClusteredCrateV1 {
+    pub name: String,
+    pub url: String,
+}
+
+/// Regressed crates which all hit the same newly introduced error code.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CodeClusterV1 {
+    pub code: String,
+    pub count: usize,
+    pub crates: Vec<ClusteredCrateV1>,
+}
+
+/// Primary code introduced by the end toolchain. The codes aren't recorded in the order the
+/// compiler emitted them, so the lowest one is picked.
+fn primary_code(krate: &CrateResult) -> Option<String> {
+    let codes = |run: usize| {
+        krate.compared_runs()[run]
+            .map(|run| run.res.diagnostic_codes())
+            .unwrap_or_default()
+    };
+    DiagnosticsDiff::between(&codes(0), &codes(1))
+        .added
+        .into_iter()
+        .next()
+}
+
+/// Regressed crates clustered by their primary new code, the largest clusters first and the
+/// crates without one last.
+pub fn regressions_by_code(raw: &RawTestResults) -> Vec<CodeClusterV1> {
+    let mut clusters: BTreeMap<String, Vec<ClusteredCrateV1>> = BTreeMap::new();
+    for krate in &raw.crates {
+        if SummaryCategory::for_crate(krate) != SummaryCategory::Regressed {
+            continue;
+        }
+        let code = primary_code(krate).unwrap_or_else(|| OTHER_CODE.into());
+        clusters.entry(code).or_default().push(ClusteredCrateV1 {
+            name: krate.name.clone(),
+            url: krate.url.clone(),
+        });
+    }
+
+    let mut clusters = clusters
+        .into_iter()
+        .map(|(code, crates)| CodeClusterV1 {
+            code,
+            count: crates.len(),
+            crates,
+        })
+        .collect::<Vec<_>>();
+    // The sort is stable, so clusters of the same size stay sorted by code
+    clusters.sort_by_key(|cluster| (cluster.code == OTHER_CODE, Reverse(cluster.count)));
+    clusters
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{regressions_by_code, CodeClusterV1, OTHER_CODE};
+    use crate::crates::Crate;
+    use crate::report::{BuildTestResult, Comparison, CrateResult, RawTestResults};
+    use crate::results::{DiagnosticCode, FailureReason, TestResult};
+
+    fn compiler_error(codes: &[&str]) -> TestResult {
+        TestResult::BuildFail(FailureReason::CompilerError(
+            codes
+                .iter()
+                .map(|code| DiagnosticCode::from(code.to_string()))
+                .collect(),
+        ))
+    }
+
+    fn krate(name: &str, res: Comparison, start: TestResult, end: TestResult) -> CrateResult {
+        let run = |res| {
+            Some(BuildTestResult {
+                res,
+                log: String::new(),
+                log_url: None,
+                duration_ms: None,
+                repro: None,
+            })
+        };
+        CrateResult {
+            name: name.into(),
+            url: format!("https://crates.io/crates/{name}"),
+            krate: Crate::Local(name.into()),
+            status: None,
+            res,
+            runs: [("stable".into(), run(start)), ("beta".into(), run(end))]
+                .into_iter()
+                .collect(),
+        }
+    }
+
+    fn names(cluster: &CodeClusterV1) -> Vec<&str> {
+        cluster.crates.iter().map(|c| c.name.as_str()).collect()
+    }
+
+    #[test]
+    fn test_regressions_by_code() {
+        let pass = TestResult::TestPass;
+        let regressed = |name, end| krate(name, Comparison::Regressed, pass.clone(), end);
+        let raw = RawTestResults {
+            crates: vec![
+                regressed("a", compiler_error(&["E0599"])),
+                regressed("b", compiler_error(&["E0599", "E0308"])),
+                regressed("c", compiler_error(&["E0599"])),
+                // Already failing with E0308, only E0599 is new
+                krate(
+                    "d",
+                    Comparison::Regressed,
+                    compiler_error(&["E0308"]),
+                    compiler_error(&["E0308", "E0599"]),
+                ),
+                regressed("e", compiler_error(&["E0425"])),
+                // Linker errors and panics have no code
+                regressed("f", TestResult::BuildFail(FailureReason::Unknown)),
+                regressed("g", TestResult::TestFail(FailureReason::Unknown)),
+                // Crates which didn't regress aren't clustered
+                krate(
+                    "h",
+                    Comparison::SameBuildFail,
+                    compiler_error(&["E0277"]),
+                    compiler_error(&["E0277"]),
+                ),
+                krate(
+                    "i",
+                    Comparison::Fixed,
+                    compiler_error(&["E0277"]),
+                    pass.clone(),
+                ),
+            ],
+        };
+
+        let clusters = regressions_by_code(&raw);
+        let summary = clusters
+            .iter()
+            .map(|cluster| (cluster.code.as_str(), cluster.count, names(cluster)))
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("E0599", 3, vec!["a", "c", "d"]),
+                // Ties are sorted by code
+                ("E0308", 1, vec!["b"]),
+                ("E0425", 1, vec!["e"]),
+                (OTHER_CODE, 2, vec!["f", "g"]),
+            ]
+        );
+        assert_eq!(clusters[0].crates[0].url, "https://crates.io/crates/a");
+
+        assert!(regressions_by_code(&RawTestResults { crates: Vec::new() }).is_empty());
+    }
+}
@@ -0,0 +1,246 @@
+use crate::prelude::*;
+use crate::report::s3::content_encoding;
+use crate::report::ReportWriter;
+use crate::results::EncodingType;
+use crate::utils;
+use mime::Mime;
+use reqwest::header::{AUTHORIZATION, CONTENT_RANGE, CONTENT_TYPE, LOCATION};
+use reqwest::{Method, StatusCode};
+use serde_json::json;
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::path::{Path, PathBuf};
+use std::str::FromStr;
+use url::{Host, Url};
+
+const GCS_ENDPOINT: &str = "https://storage.googleapis.com";
+const DEFAULT_RESUMABLE_THRESHOLD: usize = 50 * 1024 * 1024;
+// Chunks of a resumable upload must be a multiple of 256 KiB, except for the last one.
+const RESUMABLE_CHUNK_SIZE: usize = 32 * 256 * 1024;
+
+#[derive(Debug, thiserror::Error)]
+pub enum GcsError {
+    #[error("bad GCS url: {0}")]
+    BadUrl(String),
+    #[error("request to GCS failed with status {0}: {1}")]
+    RequestFailed(StatusCode, String),
+    #[error("GCS didn't return the location of the resumable upload session")]
+    MissingSession,
+}
+
+#[derive(Debug, Clone)]
+#[cfg_attr(test, derive(PartialEq, Eq))]
+pub struct GcsPrefix {
+    pub bucket: String,
+    pub prefix: PathBuf,
+}
+
+impl FromStr for GcsPrefix {
+    type Err = ::failure::Error;
+
+    fn from_str(url: &str) -> Fallible<GcsPrefix> {
+        let parsed = Url::parse(url).with_context(|_| GcsError::BadUrl(url.into()))?;
+
+        if parsed.scheme() != "gs"
+            || parsed.username() != ""
+            || parsed.password().is_some()
+            || parsed.port().is_some()
+            || parsed.query().is_some()
+            || parsed.fragment().is_some()
+        {
+            return Err(GcsError::BadUrl(url.into()).into());
+        }
+
+        let bucket = if let Some(Host::Domain(host)) = parsed.host() {
+            host.to_string()
+        } else {
+            return Err(GcsError::BadUrl(url.into()).into());
+        };
+
+        Ok(GcsPrefix {
+            bucket,
+            prefix: parsed
+                .path()
+                .get(1..)
+                .map(PathBuf::from)
+                .unwrap_or_default(),
+        })
+    }
+}
+
+impl Display for GcsPrefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format_args!("gs://{}/{}", self.bucket, self.prefix.display()).fmt(f)
+    }
+}
+
+pub struct GcsWriter {
+    bucket: String,
+    prefix: String,
+    token: String,
+    resumable_threshold: usize,
+}
+
+impl GcsWriter {
+    /// Create a writer uploading to `bucket`, authenticating with the provided OAuth 2 token.
+    pub fn create(bucket: String, prefix: String, token: String) -> Fallible<GcsWriter> {
+        Ok(GcsWriter {
+            bucket,
+            prefix,
+            token,
+            resumable_threshold: DEFAULT_RESUMABLE_THRESHOLD,
+        })
+    }
+
+    /// Set the size above which bodies are sent with a resumable upload.
+    pub fn with_resumable_threshold(mut self, threshold: usize) -> Self {
+        self.resumable_threshold = threshold;
+        self
+    }
+
+    fn request(&self, method: Method, url: &str) -> reqwest::blocking::RequestBuilder {
+        utils::http::prepare_sync(method, url)
+            .header(AUTHORIZATION, format!("Bearer {}", self.token))
+    }
+
+    fn upload_url(&self) -> String {
+        format!("{}/upload/storage/v1/b/{}/o", GCS_ENDPOINT, self.bucket)
+    }
+
+    fn upload_resumable(
+        &self,
+        name: &str,
+        body: &[u8],
+        mime: &Mime,
+        content_encoding: Option<&str>,
+    ) -> Fallible<()> {
+        let mut metadata = json!({
+            "name": name,
+            "contentType": mime.to_string(),
+        });
+        if let Some(encoding) = content_encoding {
+            metadata["contentEncoding"] = encoding.into();
+        }
+
+        let response = self
+            .request(Method::POST, &self.upload_url())
+            .query(&[("uploadType", "resumable")])
+            .json(&metadata)
+            .send()?;
+        let response = check_status(response)?;
+        let session = response
+            .headers()
+            .get(LOCATION)
+            .and_then(|location| location.to_str().ok())
+            .ok_or(GcsError::MissingSession)?
+            .to_string();
+
+        let mut start = 0;
+        for chunk in body.chunks(RESUMABLE_CHUNK_SIZE) {
+            let end = start + chunk.len();
+            let response = self
+                .request(Method::PUT, &session)
+                .header(
+                    CONTENT_RANGE,
+                    format!("bytes {}-{}/{}", start, end - 1, body.len()),
+                )
+                .body(chunk.to_vec())
+                .send()?;
+
+            // GCS answers 308 to every chunk but the last one
+            if response.status() != StatusCode::PERMANENT_REDIRECT {
+                check_status(response)?;
+            }
+            start = end;
+        }
+
+        Ok(())
+    }
+}
+
+fn check_status(response: reqwest::blocking::Response) -> Fallible<reqwest::blocking::Response> {
+    let status = response.status();
+    if status.is_success() {
+        Ok(response)
+    } else {
+        Err(GcsError::RequestFailed(status, response.text()?).into())
+    }
+}
+
+impl ReportWriter for GcsWriter {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        body: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let name = format!("{}/{}", self.prefix, path.as_ref().to_str().unwrap());
+        let content_encoding = content_encoding(encoding_type);
+
+        let res = if body.len() >= self.resumable_threshold {
+            self.upload_resumable(&name, body, mime, content_encoding)
+        } else {
+            let mut query = vec![("uploadType", "media"), ("name", name.as_str())];
+            if let Some(encoding) = content_encoding {
+                query.push(("contentEncoding", encoding));
+            }
+            self.request(Method::POST, &self.upload_url())
+                .query(&query)
+                .header(CONTENT_TYPE, mime.to_string())
+                .body(body.to_vec())
+                .send()
+                .map_err(failure::Error::from)
+                .and_then(check_status)
+                .map(|_| ())
+        };
+
+        res.with_context(|_| format!("failed to upload to {:?}", path.as_ref()))?;
+        Ok(())
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+    }
+}
+
+impl Display for GcsWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.prefix.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::GcsPrefix;
+    use std::str::FromStr;
+
+    #[test]
+    fn test_parse_gcsprefix() {
+        assert_eq!(
+            GcsPrefix::from_str("gs://bucket-name").unwrap(),
+            GcsPrefix {
+                bucket: "bucket-name".into(),
+                prefix: "".into(),
+            }
+        );
+        assert_eq!(
+            GcsPrefix::from_str("gs://bucket-name/path/prefix").unwrap(),
+            GcsPrefix {
+                bucket: "bucket-name".into(),
+                prefix: "path/prefix".into(),
+            }
+        );
+
+        for bad in &[
+            "https://example.com",
+            "s3://bucket-name/path/prefix",
+            "gs://user:pass@bucket/path/prefix",
+            "gs://bucket:80",
+            "gs://bucket/path/prefix?query",
+            "gs://bucket/path/prefix#fragment",
+        ] {
+            assert!(GcsPrefix::from_str(bad).is_err(), "valid bad url: {bad}");
+        }
+    }
+}
@@ -4,8 +4,8 @@ use crate::assets;
 use crate::experiments::Experiment;
 use crate::prelude::*;
 use crate::report::{
-    analyzer::ReportCrates, archives::Archive, Color, Comparison, CrateResult, ReportWriter,
-    ResultColor, ResultName, TestResults,
+    analyzer::ReportCrates, archives::Archive, CategoryCountsV1, CodeClusterV1, Color, Comparison,
+    CrateResult, DiagnosticsDiff, ReportWriter, ResultColor, ResultName, TestResults,
 };
 use crate::results::EncodingType;
 use indexmap::{IndexMap, IndexSet};
@@ -23,6 +23,7 @@ struct NavbarItem {
 enum CurrentPage {
     Summary,
     Full,
+    ErrorCodes,
     Downloads,
 }
 
@@ -52,6 +53,11 @@ impl CurrentPage {
                 url: "full.html",
                 active: *self == CurrentPage::Full,
             },
+            NavbarItem {
+                label: "Error codes",
+                url: "error-codes.html",
+                active: *self == CurrentPage::ErrorCodes,
+            },
             NavbarItem {
                 label: "Downloads",
                 url: "downloads.html",
@@ -61,17 +67,171 @@ impl CurrentPage {
     }
 }
 
+// (comparison, category color, ...)
+type CategoryHTML<'a> = (Comparison, usize, ReportCratesHTML<'a>);
+
 #[derive(Serialize)]
 struct ResultsContext<'a> {
     ex: &'a Experiment,
     nav: Vec<NavbarItem>,
-    // (comparison, category color, ...)
-    categories: Vec<(Comparison, usize, ReportCratesHTML<'a>)>,
+    categories: Vec<CategoryHTML<'a>>,
     info: IndexMap<Comparison, u32>,
     full: bool,
     crates_count: usize,
-    colors: IndexSet<Color>,
-    result_names: IndexSet<String>,
+    command: &'static str,
+    colors: &'a IndexSet<Color>,
+    result_names: &'a IndexSet<String>,
+    omitted: usize,
+    counts: Vec<CategoryCountHTML>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pagination: Option<Pagination>,
+}
+
+#[derive(Serialize)]
+struct CategoryCountHTML {
+    name: &'static str,
+    count: usize,
+    percentage: String,
+}
+
+fn category_counts(counts: &CategoryCountsV1) -> Vec<CategoryCountHTML> {
+    counts
+        .entries()
+        .into_iter()
+        .chain(std::iter::once(("total", counts.total)))
+        .map(|(name, count)| CategoryCountHTML {
+            name,
+            count,
+            percentage: format!("{:.1}", counts.percentage(count)),
+        })
+        .collect()
+}
+
+#[derive(Serialize, Debug, PartialEq, Eq)]
+struct Pagination {
+    current: usize,
+    total: usize,
+    prev: Option<String>,
+    next: Option<String>,
+}
+
+impl Pagination {
+    /// Navigation of page `current` (starting from 1) out of `total`, or `None` if there's only
+    /// a single page.
+    fn new(to: &str, current: usize, total: usize) -> Option<Pagination> {
+        if total <= 1 {
+            return None;
+        }
+        Some(Pagination {
+            current,
+            total,
+            prev: (current > 1).then(|| page_path(to, current - 1)),
+            next: (current < total).then(|| page_path(to, current + 1)),
+        })
+    }
+}
+
+/// Path of the n-th page (starting from 1) of the report written to `to`.
+fn page_path(to: &str, page: usize) -> String {
+    if page == 1 {
+        to.to_string()
+    } else if to == "index.html" {
+        format!("page-{page}.html")
+    } else {
+        format!("{}-page-{page}.html", to.trim_end_matches(".html"))
+    }
+}
+
+/// Split the crates of the report into pages of about `page_size` crates.
+///
+/// Plain listings are split at any crate, while the dependency trees and root results are only
+/// split between groups, so a page can exceed `page_size` if a single group doesn't fit in it.
+struct Paginator<'a> {
+    page_size: usize,
+    pages: Vec<Vec<CategoryHTML<'a>>>,
+    used: usize,
+}
+
+impl<'a> Paginator<'a> {
+    fn new(page_size: usize) -> Self {
+        Paginator {
+            page_size: page_size.max(1),
+            pages: vec![Vec::new()],
+            used: 0,
+        }
+    }
+
+    fn new_page(&mut self) {
+        self.pages.push(Vec::new());
+        self.used = 0;
+    }
+
+    fn push(&mut self, category: Comparison, color: usize, crates: ReportCratesHTML<'a>) {
+        self.pages
+            .last_mut()
+            .unwrap()
+            .push((category, color, crates));
+    }
+
+    fn add(&mut self, (category, color, crates): CategoryHTML<'a>) {
+        match crates {
+            ReportCratesHTML::Plain(crates) if !crates.is_empty() => {
+                let mut crates = crates.into_iter().peekable();
+                while crates.peek().is_some() {
+                    if self.used >= self.page_size {
+                        self.new_page();
+                    }
+                    let chunk = crates
+                        .by_ref()
+                        .take(self.page_size - self.used)
+                        .collect::<Vec<_>>();
+                    self.used += chunk.len();
+                    self.push(category, color, ReportCratesHTML::Plain(chunk));
+                }
+            }
+            ReportCratesHTML::Tree { tree, .. } if !tree.is_empty() => {
+                self.add_groups(category, color, tree, |tree| ReportCratesHTML::Tree {
+                    count: tree.len() as u32,
+                    tree,
+                })
+            }
+            ReportCratesHTML::RootResults { results, .. } if !results.is_empty() => self
+                .add_groups(category, color, results, |results| {
+                    ReportCratesHTML::RootResults {
+                        count: results.len() as u32,
+                        results,
+                    }
+                }),
+            empty => self.push(category, color, empty),
+        }
+    }
+
+    fn add_groups<F>(
+        &mut self,
+        category: Comparison,
+        color: usize,
+        groups: IndexMap<String, Vec<CrateResultHTML<'a>>>,
+        wrap: F,
+    ) where
+        F: Fn(IndexMap<String, Vec<CrateResultHTML<'a>>>) -> ReportCratesHTML<'a>,
+    {
+        let mut chunk = IndexMap::new();
+        for (key, group) in groups {
+            if self.used > 0 && self.used + group.len() > self.page_size {
+                if !chunk.is_empty() {
+                    self.push(category, color, wrap(std::mem::take(&mut chunk)));
+                }
+                self.new_page();
+            }
+            self.used += group.len();
+            chunk.insert(key, group);
+        }
+        self.push(category, color, wrap(chunk));
+    }
+
+    fn into_pages(self) -> Vec<Vec<CategoryHTML<'a>>> {
+        self.pages
+    }
 }
 
 #[derive(Serialize)]
@@ -79,10 +239,21 @@ struct DownloadsContext<'a> {
     ex: &'a Experiment,
     nav: Vec<NavbarItem>,
     crates_count: usize,
+    command: &'static str,
 
     available_archives: Vec<Archive>,
 }
 
+#[derive(Serialize)]
+struct ErrorCodesContext<'a> {
+    ex: &'a Experiment,
+    nav: Vec<NavbarItem>,
+    crates_count: usize,
+    command: &'static str,
+
+    clusters: &'a [CodeClusterV1],
+}
+
 #[derive(Serialize)]
 struct CrateResultHTML<'a> {
     name: &'a str,
@@ -91,15 +262,19 @@ struct CrateResultHTML<'a> {
     #[serde(skip_serializing_if = "Option::is_none")]
     status: Option<CrateVersionStatus>,
     color_idx: usize,
-    runs: [Option<BuildTestResultHTML<'a>>; 2],
+    runs: [Option<BuildTestResultHTML>; 2],
+    #[serde(skip_serializing_if = "Option::is_none")]
+    diagnostics: Option<DiagnosticsDiff>,
 }
 
 // Map TestResult to usize to avoid the presence of special characters in html
 #[derive(Serialize)]
-struct BuildTestResultHTML<'a> {
+struct BuildTestResultHTML {
     color_idx: usize,
     name_idx: usize,
-    log: &'a str,
+    log: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    repro: Option<String>,
 }
 
 fn to_html_crate_result<'a>(
@@ -108,19 +283,19 @@ fn to_html_crate_result<'a>(
     category_color: usize,
     result: &'a CrateResult,
 ) -> CrateResultHTML<'a> {
-    let mut runs = [None, None];
-
-    for (pos, run) in result.runs.iter().enumerate() {
-        if let Some(run) = run {
+    // The pages show the compared toolchains, the extra ones are in the matrix
+    let runs = result.compared_runs().map(|run| {
+        run.map(|run| {
             let (color_idx, _) = colors.insert_full(run.res.color());
             let (name_idx, _) = result_names.insert_full(run.res.short_name());
-            runs[pos] = Some(BuildTestResultHTML {
+            BuildTestResultHTML {
                 color_idx,
                 name_idx,
-                log: run.log.as_str(),
-            });
-        }
-    }
+                log: run.log_link(),
+                repro: run.repro.clone(),
+            }
+        })
+    });
 
     CrateResultHTML {
         name: result.name.as_str(),
@@ -129,6 +304,7 @@ fn to_html_crate_result<'a>(
         res: result.res,
         color_idx: category_color,
         runs,
+        diagnostics: DiagnosticsDiff::for_crate(result),
     }
 }
 
@@ -136,8 +312,11 @@ fn write_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    counts: &CategoryCountsV1,
     full: bool,
     to: &str,
+    omitted: usize,
+    page_size: usize,
     dest: &W,
     output_templates: bool,
 ) -> Fallible<()> {
@@ -237,33 +416,80 @@ fn write_report<W: ReportWriter>(
                 }
             }
         })
-        .collect();
+        .collect::<Vec<_>>();
 
-    let context = ResultsContext {
-        ex,
-        nav: if full {
-            CurrentPage::Full
-        } else {
-            CurrentPage::Summary
+    let mut paginator = Paginator::new(page_size);
+    for category in categories {
+        paginator.add(category);
+    }
+    let pages = paginator.into_pages();
+    let total = pages.len();
+
+    for (i, categories) in pages.into_iter().enumerate() {
+        let path = page_path(to, i + 1);
+        let context = ResultsContext {
+            ex,
+            nav: if full {
+                CurrentPage::Full
+            } else {
+                CurrentPage::Summary
+            }
+            .navbar(),
+            categories,
+            info: res.info.clone(),
+            full,
+            crates_count,
+            command: ex.mode.command(),
+            colors: &colors,
+            result_names: &result_names,
+            omitted,
+            counts: category_counts(counts),
+            pagination: Pagination::new(to, i + 1, total),
+        };
+
+        info!("generating {}", path);
+        let rendered = assets::render_template("report/results.html", &context)
+            .context("rendering template report/results.html")?;
+        let html = minifier::html::minify(&rendered);
+        dest.write_string(&path, html.into(), &mime::TEXT_HTML)?;
+
+        if output_templates {
+            dest.write_string(
+                [path.as_str(), ".context.json"].concat(),
+                serde_json::to_string(&context)?.into(),
+                &mime::APPLICATION_JSON,
+            )?;
         }
-        .navbar(),
-        categories,
-        info: res.info.clone(),
-        full,
+    }
+
+    Ok(())
+}
+
+fn write_error_codes<W: ReportWriter>(
+    ex: &Experiment,
+    crates_count: usize,
+    clusters: &[CodeClusterV1],
+    dest: &W,
+    output_templates: bool,
+) -> Fallible<()> {
+    let context = ErrorCodesContext {
+        ex,
+        nav: CurrentPage::ErrorCodes.navbar(),
         crates_count,
-        colors,
-        result_names,
+        command: ex.mode.command(),
+        clusters,
     };
 
-    info!("generating {}", to);
-    let rendered = assets::render_template("report/results.html", &context)
-        .context("rendering template report/results.html")?;
-    let html = minifier::html::minify(&rendered);
-    dest.write_string(to, html.into(), &mime::TEXT_HTML)?;
+    info!("generating error-codes.html");
+    let html = minifier::html::minify(&assets::render_template(
+        "report/error-codes.html",
+        &context,
+    )?);
+    dest.write_string("error-codes.html", html.into(), &mime::TEXT_HTML)?;
 
     if output_templates {
         dest.write_string(
-            [to, ".context.json"].concat(),
+            "error-codes.html.context.json",
             serde_json::to_string(&context)?.into(),
             &mime::APPLICATION_JSON,
         )?;
@@ -283,6 +509,7 @@ fn write_downloads<W: ReportWriter>(
         ex,
         nav: CurrentPage::Downloads.navbar(),
         crates_count,
+        command: ex.mode.command(),
         available_archives,
     };
 
@@ -305,7 +532,11 @@ pub fn write_html_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    counts: &CategoryCountsV1,
+    regressions_by_code: &[CodeClusterV1],
     available_archives: Vec<Archive>,
+    omitted: usize,
+    page_size: usize,
     dest: &W,
     output_templates: bool,
 ) -> Fallible<()> {
@@ -315,8 +546,11 @@ pub fn write_html_report<W: ReportWriter>(
         ex,
         crates_count,
         res,
+        counts,
         false,
         "index.html",
+        omitted,
+        page_size,
         dest,
         output_templates,
     )?;
@@ -324,8 +558,18 @@ pub fn write_html_report<W: ReportWriter>(
         ex,
         crates_count,
         res,
+        counts,
         true,
         "full.html",
+        omitted,
+        page_size,
+        dest,
+        output_templates,
+    )?;
+    write_error_codes(
+        ex,
+        crates_count,
+        regressions_by_code,
         dest,
         output_templates,
     )?;
@@ -347,3 +591,89 @@ pub fn write_html_report<W: ReportWriter>(
 
     Ok(())
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{page_path, CrateResultHTML, Pagination, Paginator, ReportCratesHTML};
+    use crate::report::Comparison;
+
+    fn crates(count: usize) -> Vec<CrateResultHTML<'static>> {
+        (0..count)
+            .map(|_| CrateResultHTML {
+                name: "foo",
+                url: "https://crates.io/crates/foo",
+                res: Comparison::Regressed,
+                status: None,
+                color_idx: 0,
+                runs: [None, None],
+                diagnostics: None,
+            })
+            .collect()
+    }
+
+    #[test]
+    fn test_pagination() {
+        let mut paginator = Paginator::new(1000);
+        paginator.add((
+            Comparison::Regressed,
+            0,
+            ReportCratesHTML::Plain(crates(2000)),
+        ));
+        paginator.add((Comparison::Fixed, 1, ReportCratesHTML::Plain(crates(500))));
+        let pages = paginator.into_pages();
+
+        let sizes = pages
+            .iter()
+            .map(|page| {
+                page.iter()
+                    .map(|(_, _, crates)| match crates {
+                        ReportCratesHTML::Plain(crates) => crates.len(),
+                        _ => unreachable!(),
+                    })
+                    .collect::<Vec<_>>()
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(sizes, vec![vec![1000], vec![1000], vec![500]]);
+
+        assert_eq!(
+            Pagination::new("index.html", 1, 3),
+            Some(Pagination {
+                current: 1,
+                total: 3,
+                prev: None,
+                next: Some("page-2.html".into()),
+            })
+        );
+        assert_eq!(
+            Pagination::new("index.html", 2, 3),
+            Some(Pagination {
+                current: 2,
+                total: 3,
+                prev: Some("index.html".into()),
+                next: Some("page-3.html".into()),
+            })
+        );
+        assert_eq!(
+            Pagination::new("index.html", 3, 3),
+            Some(Pagination {
+                current: 3,
+                total: 3,
+                prev: Some("page-2.html".into()),
+                next: None,
+            })
+        );
+        assert_eq!(page_path("full.html", 2), "full-page-2.html");
+    }
+
+    #[test]
+    fn test_single_page() {
+        let mut paginator = Paginator::new(1000);
+        paginator.add((
+            Comparison::Regressed,
+            0,
+            ReportCratesHTML::Plain(crates(10)),
+        ));
+        assert_eq!(paginator.into_pages().len(), 1);
+        assert_eq!(Pagination::new("index.html", 1, 1), None);
+    }
+}
@@ -0,0 +1,334 @@
+use crate::prelude::*;
+use crate::report::s3::content_encoding;
+use crate::report::{ReportWriter, REPORT_ENCODE_SET};
+use crate::results::EncodingType;
+use crate::utils;
+use base64::Engine;
+use mime::Mime;
+use percent_encoding::utf8_percent_encode;
+use reqwest::header::{
+    HeaderMap, HeaderName, HeaderValue, AUTHORIZATION, CONTENT_ENCODING, CONTENT_TYPE,
+};
+use reqwest::{Method, StatusCode};
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::path::Path;
+use std::thread;
+use std::time::Duration;
+use url::Url;
+
+const DEFAULT_MAX_RETRIES: u32 = 3;
+const DEFAULT_RETRY_DELAY: Duration = Duration::from_secs(1);
+
+#[derive(Debug, thiserror::Error)]
+pub enum HttpWriterError {
+    #[error("bad base url for the HTTP writer: {0}")]
+    BadUrl(String),
+    #[error("PUT {0} failed with status {1}: {2}")]
+    RequestFailed(String, StatusCode, String),
+}
+
+/// Writer uploading every file with a PUT to `{base_url}/{prefix}/{path}`, for WebDAV servers and
+/// artifact stores accepting plain HTTP uploads.
+pub struct HttpWriter {
+    base_url: String,
+    prefix: String,
+    headers: HeaderMap,
+    max_retries: u32,
+    retry_delay: Duration,
+}
+
+impl HttpWriter {
+    pub fn create(base_url: &str, prefix: String) -> Fallible<HttpWriter> {
+        let parsed =
+            Url::parse(base_url).with_context(|_| HttpWriterError::BadUrl(base_url.into()))?;
+        if !matches!(parsed.scheme(), "http" | "https") {
+            return Err(HttpWriterError::BadUrl(base_url.into()).into());
+        }
+
+        Ok(HttpWriter {
+            base_url: base_url.trim_end_matches('/').to_string(),
+            prefix: prefix.trim_matches('/').to_string(),
+            headers: HeaderMap::new(),
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+        })
+    }
+
+    /// Send the header with every request.
+    pub fn with_header(mut self, name: &str, value: &str) -> Fallible<Self> {
+        let mut value = HeaderValue::from_str(value)?;
+        value.set_sensitive(true);
+        self.headers
+            .insert(HeaderName::from_bytes(name.as_bytes())?, value);
+        Ok(self)
+    }
+
+    pub fn with_bearer_auth(self, token: &str) -> Fallible<Self> {
+        self.with_header(AUTHORIZATION.as_str(), &format!("Bearer {token}"))
+    }
+
+    pub fn with_basic_auth(self, user: &str, password: &str) -> Fallible<Self> {
+        let credentials =
+            base64::engine::general_purpose::STANDARD.encode(format!("{user}:{password}"));
+        self.with_header(AUTHORIZATION.as_str(), &format!("Basic {credentials}"))
+    }
+
+    /// Send the uploads failing with a server error or a network error again, at most
+    /// `max_retries` times. Uploads are idempotent, so retrying them is always safe.
+    pub fn with_retries(mut self, max_retries: u32, delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = delay;
+        self
+    }
+
+    fn url(&self, path: &Path) -> String {
+        let path = utf8_percent_encode(path.to_str().unwrap(), &REPORT_ENCODE_SET);
+        if self.prefix.is_empty() {
+            format!("{}/{}", self.base_url, path)
+        } else {
+            format!("{}/{}/{}", self.base_url, self.prefix, path)
+        }
+    }
+}
+
+/// Server errors and throttling might go away when trying again, client errors won't.
+fn is_transient(status: StatusCode) -> bool {
+    status.is_server_error() || status == StatusCode::TOO_MANY_REQUESTS
+}
+
+impl ReportWriter for HttpWriter {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        body: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let url = self.url(path.as_ref());
+        let content_encoding = content_encoding(encoding_type);
+
+        let mut attempt = 0;
+        loop {
+            let mut request = utils::http::prepare_sync(Method::PUT, &url)
+                .headers(self.headers.clone())
+                .header(CONTENT_TYPE, mime.to_string());
+            if let Some(encoding) = content_encoding {
+                request = request.header(CONTENT_ENCODING, encoding);
+            }
+
+            match request.body(body.to_vec()).send() {
+                Ok(response) if response.status().is_success() => return Ok(()),
+                Ok(response) if !is_transient(response.status()) || attempt >= self.max_retries => {
+                    let status = response.status();
+                    return Err(
+                        HttpWriterError::RequestFailed(url, status, response.text()?).into(),
+                    );
+                }
+                Err(err) if attempt >= self.max_retries => {
+                    bail!("failed to upload to {}: {}", url, err);
+                }
+                Ok(response) => warn!(
+                    "PUT {} failed with status {}, retrying (attempt {})",
+                    url,
+                    response.status(),
+                    attempt + 1
+                ),
+                Err(err) => warn!(
+                    "PUT {} failed: {}, retrying (attempt {})",
+                    url,
+                    err,
+                    attempt + 1
+                ),
+            }
+
+            attempt += 1;
+            thread::sleep(self.retry_delay * attempt);
+        }
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+    }
+}
+
+impl Display for HttpWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        if self.prefix.is_empty() {
+            self.base_url.fmt(f)
+        } else {
+            write!(f, "{}/{}", self.base_url, self.prefix)
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::HttpWriter;
+    use crate::report::ReportWriter;
+    use crate::results::{EncodedLog, EncodingType};
+    use std::collections::{HashMap, VecDeque};
+    use std::io::{BufRead, BufReader, Read, Write};
+    use std::net::TcpListener;
+    use std::sync::{Arc, Mutex};
+    use std::thread;
+    use std::time::Duration;
+
+    struct MockRequest {
+        method: String,
+        path: String,
+        headers: HashMap<String, String>,
+        body: Vec<u8>,
+    }
+
+    /// HTTP server recording the requests it receives, and answering them with the provided
+    /// statuses, then with 201 Created.
+    fn mock_server(statuses: &[u16]) -> (String, Arc<Mutex<Vec<MockRequest>>>) {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let url = format!("http://{}", listener.local_addr().unwrap());
+        let requests = Arc::new(Mutex::new(Vec::new()));
+        let statuses = Arc::new(Mutex::new(
+            statuses.iter().copied().collect::<VecDeque<_>>(),
+        ));
+
+        let recorded = requests.clone();
+        thread::spawn(move || {
+            for stream in listener.incoming() {
+                let mut stream = stream.unwrap();
+                let recorded = recorded.clone();
+                let statuses = statuses.clone();
+                thread::spawn(move || {
+                    let mut reader = BufReader::new(stream.try_clone().unwrap());
+                    loop {
+                        let mut line = String::new();
+                        if reader.read_line(&mut line).unwrap_or(0) == 0 {
+                            break;
+                        }
+                        let mut request_line = line.split_whitespace();
+                        let method = request_line.next().unwrap().to_string();
+                        let path = request_line.next().unwrap().to_string();
+
+                        let mut headers = HashMap::new();
+                        loop {
+                            let mut header = String::new();
+                            reader.read_line(&mut header).unwrap();
+                            let Some((name, value)) = header.trim_end().split_once(':') else {
+                                break;
+                            };
+                            headers.insert(name.to_ascii_lowercase(), value.trim().to_string());
+                        }
+                        let len = headers
+                            .get("content-length")
+                            .map_or(0, |len| len.parse().unwrap());
+                        let mut body = vec![0; len];
+                        reader.read_exact(&mut body).unwrap();
+                        recorded.lock().unwrap().push(MockRequest {
+                            method,
+                            path,
+                            headers,
+                            body,
+                        });
+
+                        let status = statuses.lock().unwrap().pop_front().unwrap_or(201);
+                        let reply = if status < 300 { "" } else { "quota exceeded" };
+                        write!(
+                            stream,
+                            "HTTP/1.1 {status} Mock\r\nContent-Length: {}\r\n\r\n{reply}",
+                            reply.len()
+                        )
+                        .unwrap();
+                    }
+                });
+            }
+        });
+
+        (url, requests)
+    }
+
+    fn writer(url: &str) -> HttpWriter {
+        HttpWriter::create(url, "reports/pr-1".into())
+            .unwrap()
+            .with_bearer_auth("secret")
+            .unwrap()
+            .with_header("X-Uploader", "crater")
+            .unwrap()
+            .with_retries(2, Duration::from_millis(1))
+    }
+
+    #[test]
+    fn test_put() {
+        let (url, requests) = mock_server(&[]);
+        let writer = writer(&url);
+
+        writer
+            .write_string("index.html", "<html></html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        let log = EncodedLog::from_plain_slice(b"build log", EncodingType::Gzip).unwrap();
+        writer
+            .write_bytes(
+                "stable/reg/foo-1.0.0/log.txt",
+                log.as_slice(),
+                &mime::TEXT_PLAIN_UTF_8,
+                EncodingType::Gzip,
+            )
+            .unwrap();
+
+        let requests = requests.lock().unwrap();
+        assert_eq!(requests.len(), 2);
+        for request in requests.iter() {
+            assert_eq!(request.method, "PUT");
+            assert_eq!(request.headers["authorization"], "Bearer secret");
+            assert_eq!(request.headers["x-uploader"], "crater");
+        }
+
+        let page = &requests[0];
+        assert_eq!(page.path, "/reports/pr-1/index.html");
+        assert_eq!(page.headers["content-type"], "text/html");
+        assert!(!page.headers.contains_key("content-encoding"));
+        assert_eq!(page.body, b"<html></html>");
+
+        let log_request = &requests[1];
+        assert_eq!(
+            log_request.path,
+            "/reports/pr-1/stable/reg/foo-1.0.0/log.txt"
+        );
+        assert_eq!(
+            log_request.headers["content-type"],
+            "text/plain; charset=utf-8"
+        );
+        assert_eq!(log_request.headers["content-encoding"], "gzip");
+        assert_eq!(log_request.body, log.as_slice());
+    }
+
+    #[test]
+    fn test_put_failures() {
+        // Server errors are retried
+        let (url, requests) = mock_server(&[503, 500]);
+        writer(&url)
+            .write_string("index.html", "<html></html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        assert_eq!(requests.lock().unwrap().len(), 3);
+
+        // Client errors aren't, and are reported with the body of the response
+        let (url, requests) = mock_server(&[403]);
+        let err = writer(&url)
+            .write_string("index.html", "<html></html>".into(), &mime::TEXT_HTML)
+            .unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            format!(
+                "PUT {url}/reports/pr-1/index.html failed with status 403 Forbidden: \
+                 quota exceeded"
+            )
+        );
+        assert_eq!(requests.lock().unwrap().len(), 1);
+
+        // Giving up after the last retry
+        let (url, requests) = mock_server(&[503, 503, 503]);
+        let err = writer(&url)
+            .write_string("index.html", "<html></html>".into(), &mime::TEXT_HTML)
+            .unwrap_err();
+        assert!(err.to_string().contains("503 Service Unavailable"));
+        assert_eq!(requests.lock().unwrap().len(), 3);
+    }
+}
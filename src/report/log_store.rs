@@ -0,0 +1,203 @@
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::summary::crate_name_and_version;
+use crate::report::{with_write_pool, ReportWriter, SanitizationContext};
+use crate::results::{EncodedLog, EncodingType, ReadResults};
+use crate::utils;
+use std::collections::BTreeMap;
+use std::io::Write;
+
+pub const LOG_MANIFEST_PATH: &str = "logs/manifest.json";
+
+/// Object containing the logs of a crate.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogManifestEntry {
+    /// Path of the object, relative to the root of the report.
+    pub key: String,
+    /// Size of the stored object, after compression.
+    pub size: usize,
+    pub encoding: EncodingType,
+}
+
+/// Index of the logs written by `write_log_store`, keyed by `name@version`, or only the name of
+/// the crates without a version.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct LogManifest {
+    pub crates: BTreeMap<String, LogManifestEntry>,
+}
+
+impl LogManifest {
+    pub fn parse(data: &[u8]) -> Fallible<Self> {
+        Ok(serde_json::from_slice(data)?)
+    }
+
+    /// Decompressed logs of `krate`, loading the object it's stored in with `fetch`.
+    pub fn read_log<F>(&self, krate: &str, fetch: F) -> Fallible<Option<Vec<u8>>>
+    where
+        F: FnOnce(&str) -> Fallible<Vec<u8>>,
+    {
+        let Some(entry) = self.crates.get(krate) else {
+            return Ok(None);
+        };
+
+        let data = fetch(&entry.key)?;
+        let log = match entry.encoding {
+            EncodingType::Plain => EncodedLog::Plain(data),
+            EncodingType::Gzip => EncodedLog::Gzip(data),
+            EncodingType::Zstd => EncodedLog::Zstd(data),
+            EncodingType::Brotli => EncodedLog::Brotli(data),
+        };
+        Ok(Some(log.to_plain()?))
+    }
+}
+
+fn manifest_key(krate: &Crate) -> String {
+    match crate_name_and_version(krate) {
+        (name, Some(version)) => format!("{name}@{version}"),
+        (name, None) => name,
+    }
+}
+
+/// Write the logs of every toolchain for each crate as a single zstd-compressed object, along
+/// with the manifest locating them.
+pub fn write_log_store<DB: ReadResults, W: ReportWriter>(
+    db: &DB,
+    ex: &Experiment,
+    crates: &[Crate],
+    dest: &W,
+    config: &Config,
+) -> Fallible<LogManifest> {
+    let mut manifest = LogManifest::default();
+
+    with_write_pool(dest, config.report.write_concurrency, |queue| {
+        for krate in crates {
+            if config.should_skip(krate) {
+                continue;
+            }
+
+            let mut combined = Vec::new();
+            for tc in &ex.toolchains {
+                let log = db
+                    .load_log(ex, tc, krate)
+                    .and_then(|log| log.map(|log| log.to_plain()).transpose())
+                    .with_context(|_| format!("failed to read log of {krate} on {tc}"));
+                match log {
+                    Ok(Some(log)) if !log.is_empty() => {
+                        writeln!(combined, "==== {tc} ====")?;
+                        combined.extend_from_slice(&log);
+                        combined.push(b'\n');
+                    }
+                    Ok(_) => {}
+                    Err(e) => utils::report_failure(&e),
+                }
+            }
+
+            // Crates without any captured log are left out instead of pointing to an empty object
+            if combined.is_empty() {
+                continue;
+            }
+
+            let name = manifest_key(krate);
+            let key = format!("logs/{}.log", SanitizationContext::Path.sanitize(&name));
+            let encoded = EncodedLog::from_plain_slice(&combined, EncodingType::Zstd)?;
+            manifest.crates.insert(
+                name,
+                LogManifestEntry {
+                    key: key.clone(),
+                    size: encoded.as_slice().len(),
+                    encoding: EncodingType::Zstd,
+                },
+            );
+            queue.write_bytes(
+                key,
+                encoded.as_slice().to_vec(),
+                &mime::TEXT_PLAIN_UTF_8,
+                EncodingType::Zstd,
+            )?;
+        }
+        Ok(())
+    })?;
+
+    // Only written once every log it points to is
+    dest.write_string(
+        LOG_MANIFEST_PATH,
+        serde_json::to_string(&manifest)?.into(),
+        &mime::APPLICATION_JSON,
+    )?;
+    Ok(manifest)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{manifest_key, write_log_store, LogManifest, LOG_MANIFEST_PATH};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::prelude::*;
+    use crate::report::MemoryWriter;
+    use crate::results::{DatabaseDB, EncodingType, TestResult, WriteResults};
+    use rustwide::logging::LogStorage;
+
+    #[test]
+    fn test_log_store_round_trip() {
+        rustwide::logging::init();
+
+        let config = Config::default();
+        let db = Database::temp().unwrap();
+        let writer = MemoryWriter::new();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // Create a dummy experiment
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let crates = ex.get_crates(&db).unwrap();
+
+        // Only the first two crates have logs
+        let results = DatabaseDB::new(&db);
+        for krate in &crates[..2] {
+            for (tc, encoding) in ex
+                .toolchains
+                .iter()
+                .zip([EncodingType::Gzip, EncodingType::Plain])
+            {
+                results
+                    .record_result(&ex, tc, krate, &LogStorage::from(&config), encoding, || {
+                        info!("{} on {}", krate, tc);
+                        Ok(TestResult::TestPass)
+                    })
+                    .unwrap();
+            }
+        }
+
+        let written = write_log_store(&results, &ex, &crates[..3], &writer, &config).unwrap();
+
+        let manifest = LogManifest::parse(&writer.get(LOG_MANIFEST_PATH).unwrap().data).unwrap();
+        assert_eq!(manifest, written);
+        assert_eq!(manifest.crates.len(), 2);
+        assert!(!manifest.crates.contains_key(&manifest_key(&crates[2])));
+
+        for krate in &crates[..2] {
+            let entry = &manifest.crates[&manifest_key(krate)];
+            let stored = writer.get(&entry.key).unwrap();
+            assert_eq!(stored.encoding, EncodingType::Zstd);
+            assert_eq!(stored.data.len(), entry.size);
+
+            let log = manifest
+                .read_log(&manifest_key(krate), |key| {
+                    Ok(writer.get(key).unwrap().data)
+                })
+                .unwrap()
+                .unwrap();
+            let log = String::from_utf8(log).unwrap();
+            for tc in &ex.toolchains {
+                assert!(log.contains(&format!("==== {tc} ====")));
+                assert!(log.contains(&format!("{krate} on {tc}")));
+            }
+        }
+    }
+}
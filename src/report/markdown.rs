@@ -1,9 +1,11 @@
 use crate::crates::Crate;
-use crate::experiments::Experiment;
+use crate::experiments::{Experiment, Mode};
 use crate::prelude::*;
 use crate::report::analyzer::{ReportConfig, ReportCrates, ToolchainSelect};
+use crate::report::build_times::format_ms;
 use crate::report::{
-    crate_to_url, BuildTestResult, Comparison, CrateResult, ReportWriter, ResultName, TestResults,
+    crate_to_url, BuildTestResult, BuildTimesV1, Comparison, CrateResult, ReportWriter, ResultName,
+    TestResults,
 };
 use crate::utils::serialize::to_vec;
 use indexmap::{IndexMap, IndexSet};
@@ -28,6 +30,7 @@ struct ResultsContext<'a> {
     info: IndexMap<Comparison, u32>,
     full: bool,
     crates_count: usize,
+    build_times: Option<&'a BuildTimesV1>,
 }
 
 fn write_crate(
@@ -44,23 +47,17 @@ fn write_crate(
         }
     };
 
+    let [start, end] = krate.compared_runs();
     let runs = [
-        krate.runs[0]
-            .as_ref()
+        start
             .map(get_run_name)
             .unwrap_or_else(|| "unavailable".into()),
-        krate.runs[0]
-            .as_ref()
-            .map(|run| run.log.to_owned())
+        start
+            .map(|run| run.log_link())
             .unwrap_or_else(|| "#".into()),
-        krate.runs[1]
-            .as_ref()
-            .map(get_run_name)
+        end.map(get_run_name)
             .unwrap_or_else(|| "unavailable".into()),
-        krate.runs[1]
-            .as_ref()
-            .map(|run| run.log.to_owned())
-            .unwrap_or_else(|| "#".into()),
+        end.map(|run| run.log_link()).unwrap_or_else(|| "#".into()),
     ];
 
     let prefix = if is_child { "  * " } else { "* " };
@@ -77,7 +74,7 @@ fn write_crate(
 
         writeln!(
             rendered,
-            "{}[{}{}]({}) {} {} **{}** [start]({}/log.txt) | [end]({}/log.txt)",
+            "{}[{}{}]({}) {} {} **{}** [start]({}) | [end]({})",
             prefix,
             krate.name,
             status_warning,
@@ -91,7 +88,7 @@ fn write_crate(
     } else {
         writeln!(
             rendered,
-            "{}[{}{}]({}) {} [start]({}/log.txt) | [end]({}/log.txt)",
+            "{}[{}{}]({}) {} [start]({}) | [end]({})",
             prefix, krate.name, status_warning, krate.url, comparison, runs[1], runs[3]
         )?;
     };
@@ -99,11 +96,83 @@ fn write_crate(
     Ok(())
 }
 
+fn write_build_times(rendered: &mut String, build_times: &BuildTimesV1) -> Fallible<()> {
+    let name = |name: &str, version: &Option<String>| match version {
+        Some(version) => format!("{name} {version}"),
+        None => name.to_string(),
+    };
+    let timed_out = |timed_out: bool| if timed_out { " (timed out)" } else { "" };
+
+    writeln!(rendered, "\n### Slowest crates")?;
+    for toolchain in &build_times.toolchains {
+        writeln!(rendered, "\n#### `{}`\n", toolchain.toolchain)?;
+        for krate in &toolchain.slowest {
+            writeln!(
+                rendered,
+                "* {}: {}{}",
+                name(&krate.name, &krate.version),
+                format_ms(krate.build_ms),
+                timed_out(krate.timed_out)
+            )?;
+        }
+    }
+
+    let total = format_ms(build_times.total_delta_ms.unsigned_abs());
+    let sign = if build_times.total_delta_ms < 0 {
+        "-"
+    } else {
+        "+"
+    };
+    writeln!(
+        rendered,
+        "\n#### Largest build time increases\n\nTotal build time delta: {sign}{total}\n"
+    )?;
+    for delta in &build_times.largest_increases {
+        writeln!(
+            rendered,
+            "* {}: {} -> {} (+{}){}",
+            name(&delta.name, &delta.version),
+            format_ms(delta.start_ms),
+            format_ms(delta.end_ms),
+            format_ms(delta.delta_ms as u64),
+            timed_out(delta.timed_out)
+        )?;
+    }
+
+    Ok(())
+}
+
 fn render_markdown(context: &ResultsContext) -> Fallible<String> {
     let mut rendered = String::new();
 
     //add title
     writeln!(rendered, "# Crater report for {}\n\n", context.ex.name)?;
+    writeln!(
+        rendered,
+        "{} crates tested with `{}`.\n",
+        context.crates_count,
+        context.ex.mode.command()
+    )?;
+    if context.ex.mode == Mode::CheckOnly {
+        writeln!(
+            rendered,
+            "Only `cargo check` was run, so the crates were type-checked but neither fully \
+             built nor tested.\n"
+        )?;
+    }
+    if let (Some(capped_at), Some(max)) = (context.ex.capped_at, context.ex.max_regressions) {
+        writeln!(
+            rendered,
+            "The experiment was capped after {capped_at} regressions (limit: {max}), \
+             so not every crate was tested.\n"
+        )?;
+    }
+    if context.ex.cancelled_at.is_some() {
+        writeln!(
+            rendered,
+            "The experiment was cancelled, so not every crate was tested.\n"
+        )?;
+    }
 
     for (comparison, results) in context.categories.iter() {
         writeln!(rendered, "\n### {comparison}")?;
@@ -136,6 +205,10 @@ fn render_markdown(context: &ResultsContext) -> Fallible<String> {
         }
     }
 
+    if let Some(build_times) = context.build_times {
+        write_build_times(&mut rendered, build_times)?;
+    }
+
     Ok(rendered)
 }
 
@@ -143,6 +216,7 @@ fn write_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    build_times: Option<&BuildTimesV1>,
     full: bool,
     to: &str,
     dest: &W,
@@ -182,6 +256,7 @@ fn write_report<W: ReportWriter>(
         info: res.info.clone(),
         full,
         crates_count,
+        build_times,
     };
 
     let markdown = render_markdown(&context)?;
@@ -203,6 +278,7 @@ pub fn write_markdown_report<W: ReportWriter>(
     ex: &Experiment,
     crates_count: usize,
     res: &TestResults,
+    build_times: Option<&BuildTimesV1>,
     dest: &W,
     output_templates: bool,
 ) -> Fallible<()> {
@@ -210,6 +286,7 @@ pub fn write_markdown_report<W: ReportWriter>(
         ex,
         crates_count,
         res,
+        build_times,
         false,
         "markdown.md",
         dest,
@@ -0,0 +1,171 @@
+use crate::assets;
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{compare, crate_name_and_version, Comparison, ReportWriter};
+use crate::results::{ReadResults, TestResult};
+use indexmap::IndexMap;
+
+/// Results of a crate on every toolchain of an experiment, compared with the first toolchain.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct MatrixRow {
+    pub name: String,
+    pub version: Option<String>,
+    /// Result on each toolchain, missing when the crate has no result for it.
+    pub results: IndexMap<String, Option<TestResult>>,
+    /// Comparison of each toolchain after the first one with the first one.
+    pub comparisons: IndexMap<String, Comparison>,
+    /// Toolchains the crate regressed on compared with the first one.
+    pub regressed_on: Vec<String>,
+}
+
+impl MatrixRow {
+    fn new(config: &Config, krate: &Crate, results: &[(String, Option<TestResult>)]) -> Self {
+        let (name, version) = crate_name_and_version(krate);
+        let (_, baseline) = &results[0];
+        let comparisons = results[1..]
+            .iter()
+            .map(|(tc, res)| {
+                let comparison = compare(config, krate, baseline.as_ref(), res.as_ref());
+                (tc.clone(), comparison)
+            })
+            .collect::<IndexMap<_, _>>();
+        let regressed_on = comparisons
+            .iter()
+            .filter(|(_, comparison)| **comparison == Comparison::Regressed)
+            .map(|(tc, _)| tc.clone())
+            .collect();
+
+        MatrixRow {
+            name,
+            version,
+            results: results.iter().cloned().collect(),
+            comparisons,
+            regressed_on,
+        }
+    }
+
+    pub fn is_regressed(&self) -> bool {
+        !self.regressed_on.is_empty()
+    }
+}
+
+/// Grid of the results of every crate on every toolchain, for experiments comparing more than
+/// two toolchains.
+#[derive(Debug, Clone, PartialEq, Serialize)]
+pub struct ResultsMatrix {
+    pub experiment: String,
+    pub toolchains: Vec<String>,
+    pub crates: Vec<MatrixRow>,
+}
+
+impl ResultsMatrix {
+    pub fn load<DB: ReadResults>(
+        db: &DB,
+        config: &Config,
+        ex: &Experiment,
+        crates: &[Crate],
+    ) -> Fallible<Self> {
+        let toolchains = ex
+            .all_toolchains()
+            .map(|tc| tc.to_string())
+            .collect::<Vec<_>>();
+        let mut rows = Vec::with_capacity(crates.len());
+        for krate in crates {
+            let results = ex
+                .all_toolchains()
+                .zip(&toolchains)
+                .map(|(tc, name)| Ok((name.clone(), db.load_test_result(ex, tc, krate)?)))
+                .collect::<Fallible<Vec<_>>>()?;
+            rows.push(MatrixRow::new(config, krate, &results));
+        }
+
+        Ok(ResultsMatrix {
+            experiment: ex.name.clone(),
+            toolchains,
+            crates: rows,
+        })
+    }
+}
+
+#[derive(Serialize)]
+struct MatrixContext<'a> {
+    matrix: &'a ResultsMatrix,
+    regressed: usize,
+}
+
+/// Write `matrix.json` and `matrix.html` for the provided matrix.
+pub fn write_matrix_report<W: ReportWriter>(matrix: &ResultsMatrix, dest: &W) -> Fallible<()> {
+    dest.write_string(
+        "matrix.json",
+        serde_json::to_string(matrix)?.into(),
+        &mime::APPLICATION_JSON,
+    )?;
+
+    let context = MatrixContext {
+        matrix,
+        regressed: matrix
+            .crates
+            .iter()
+            .filter(|row| row.is_regressed())
+            .count(),
+    };
+    let rendered = assets::render_template("report/matrix.html", &context)
+        .context("rendering template report/matrix.html")?;
+    let html = minifier::html::minify(&rendered);
+    dest.write_string("matrix.html", html.into(), &mime::TEXT_HTML)?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_matrix_report, MatrixRow, ResultsMatrix};
+    use crate::config::Config;
+    use crate::crates::Crate;
+    use crate::report::{Comparison, MemoryWriter};
+    use crate::results::{FailureReason, TestResult};
+
+    fn row(results: &[TestResult]) -> MatrixRow {
+        let results = ["stable", "beta", "nightly"]
+            .iter()
+            .zip(results)
+            .map(|(tc, res)| (tc.to_string(), Some(res.clone())))
+            .collect::<Vec<_>>();
+        MatrixRow::new(&Config::default(), &Crate::Local("foo".into()), &results)
+    }
+
+    #[test]
+    fn test_three_toolchains() {
+        let fail = TestResult::BuildFail(FailureReason::Unknown);
+
+        // Passing on the first two toolchains and failing on the last one
+        let regressed = row(&[TestResult::TestPass, TestResult::TestPass, fail.clone()]);
+        assert!(regressed.is_regressed());
+        assert_eq!(regressed.regressed_on, vec!["nightly".to_string()]);
+        assert_eq!(regressed.comparisons["beta"], Comparison::SameTestPass);
+        assert_eq!(regressed.comparisons["nightly"], Comparison::Regressed);
+
+        // Failing on the middle toolchain only
+        let regressed = row(&[TestResult::TestPass, fail.clone(), TestResult::TestPass]);
+        assert_eq!(regressed.regressed_on, vec!["beta".to_string()]);
+
+        // Failing on the baseline is a fix, not a regression
+        let fixed = row(&[fail, TestResult::TestPass, TestResult::TestPass]);
+        assert!(!fixed.is_regressed());
+        assert_eq!(fixed.comparisons["beta"], Comparison::Fixed);
+        assert_eq!(fixed.comparisons["nightly"], Comparison::Fixed);
+
+        let writer = MemoryWriter::new();
+        let matrix = ResultsMatrix {
+            experiment: "foo".into(),
+            toolchains: vec!["stable".into(), "beta".into(), "nightly".into()],
+            crates: vec![regressed, fixed],
+        };
+        write_matrix_report(&matrix, &writer).unwrap();
+        let html = String::from_utf8(writer.get("matrix.html").unwrap().data).unwrap();
+        assert!(html.contains("nightly"));
+        assert!(writer.get("matrix.json").is_some());
+    }
+}
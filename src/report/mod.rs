@@ -1,31 +1,87 @@
 use crate::config::Config;
 use crate::crates::Crate;
+use crate::db::Database;
 use crate::dirs::WORK_DIR;
-use crate::experiments::Experiment;
+use crate::experiments::{Confirmation, Experiment};
 use crate::prelude::*;
 use crate::report::analyzer::{analyze_report, ReportConfig, ToolchainSelect};
-use crate::results::{EncodedLog, EncodingType, FailureReason, ReadResults, TestResult};
+use crate::results::{
+    DatabaseDB, EncodedLog, EncodingType, FailureReason, ReadResults, TestResult,
+};
 use crate::toolchain::Toolchain;
 use crate::utils;
 use crates_index::GitIndex;
+use indexmap::IndexMap;
 use mime::Mime;
 use percent_encoding::{utf8_percent_encode, AsciiSet};
 use std::borrow::Cow;
-#[cfg(test)]
-use std::collections::HashMap;
+use std::collections::{HashMap, HashSet};
 use std::fmt::{self, Display};
 use std::fs;
+use std::future::Future;
+use std::hash::{Hash, Hasher};
+use std::io::Read;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicBool, Ordering};
+use std::sync::{Arc, Mutex};
+use std::time::Duration;
 
 mod analyzer;
 mod archives;
+mod async_writer;
+mod azure;
+mod breaker;
+mod build_times;
+mod bundle;
+mod compress;
+mod csv_export;
+mod diagnostics;
+mod diff;
 mod display;
+mod error_codes;
+mod gcs;
 mod html;
+mod http_put;
+mod log_store;
 mod markdown;
+mod matrix;
+mod multi;
+mod pool;
+mod repro;
+mod resume;
 mod s3;
-
+mod sftp;
+mod sqlite_export;
+mod summary;
+
+pub use self::async_writer::{AsyncReportWriter, BlockingWriter};
+pub use self::azure::{AzurePrefix, AzureWriter};
+pub use self::build_times::{
+    BuildTimeDeltaV1, BuildTimesV1, CrateBuildTimeV1, ToolchainBuildTimesV1,
+};
+pub use self::bundle::BundleWriter;
+pub use self::compress::CompressWriter;
+pub use self::diagnostics::DiagnosticsDiff;
+pub use self::diff::{write_diff_report, CountDelta, CrateDiff, ReportDiff};
 pub use self::display::{Color, ResultColor, ResultName};
-pub use self::s3::{S3Prefix, S3Writer};
+pub use self::error_codes::{ClusteredCrateV1, CodeClusterV1, OTHER_CODE};
+pub use self::gcs::{GcsPrefix, GcsWriter};
+pub use self::http_put::{HttpWriter, HttpWriterError};
+pub use self::log_store::{LogManifest, LogManifestEntry, LOG_MANIFEST_PATH};
+pub use self::matrix::{write_matrix_report, MatrixRow, ResultsMatrix};
+pub use self::multi::{DynReportWriter, MultiWriter};
+pub use self::pool::{with_write_pool, WriteQueue};
+pub use self::repro::COMMAND_LOG_PREFIX;
+pub use self::resume::{ReportProgress, ResumeWriter, PROGRESS_PATH};
+pub use self::s3::{
+    expand_prefix, AssumeRole, Attachment, MultipartConfig, S3Prefix, S3Writer,
+    ServerSideEncryption,
+};
+pub use self::sftp::{SftpCommand, SftpTransport, SftpWriter};
+pub(crate) use self::summary::crate_name_and_version;
+pub use self::summary::{
+    CategoryCountsV1, CrateSummaryV1, Outcome, ReportSummaryV1, SummaryCategory, ToolchainResultV1,
+};
 pub use analyzer::TestResults;
 
 pub(crate) const REPORT_ENCODE_SET: AsciiSet = percent_encoding::CONTROLS
@@ -40,13 +96,134 @@ pub(crate) const REPORT_ENCODE_SET: AsciiSet = percent_encoding::CONTROLS
     .add(b'}')
     .add(b'+');
 
-#[derive(Serialize, Deserialize)]
+/// Which crates to include in a generated report.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ReportFilter {
+    #[default]
+    All,
+    RegressionsOnly,
+    Comparisons(HashSet<Comparison>),
+}
+
+impl ReportFilter {
+    pub fn includes(&self, comparison: Comparison) -> bool {
+        match self {
+            ReportFilter::All => true,
+            ReportFilter::RegressionsOnly => comparison == Comparison::Regressed,
+            ReportFilter::Comparisons(comparisons) => comparisons.contains(&comparison),
+        }
+    }
+
+    /// Remove the crates excluded by the filter, returning how many were omitted.
+    fn apply(&self, raw: &mut RawTestResults) -> usize {
+        let before = raw.crates.len();
+        raw.crates.retain(|krate| self.includes(krate.res));
+        before - raw.crates.len()
+    }
+}
+
+/// Version of the format of `results.json`, to bump whenever its shape changes along with an
+/// upgrade step in `RawTestResults::from_versioned`. Newer versions are refused.
+pub const RESULTS_SCHEMA_VERSION: u32 = 2;
+
+/// Version 1 didn't record the names of the toolchains: its runs are keyed with these names,
+/// which are the start and the end toolchain in this order.
+const V1_RUN_KEYS: [&str; 2] = ["start", "end"];
+
+#[derive(Debug, thiserror::Error)]
+pub enum ResultsSchemaError {
+    #[error("invalid results schema version: {0}")]
+    InvalidVersion(serde_json::Value),
+    #[error("results schema version {0} is newer than the supported version {1}")]
+    UnsupportedVersion(u64, u32),
+    #[error("invalid runs of a crate in results schema version 1: {0}")]
+    InvalidV1Runs(serde_json::Value),
+}
+
 pub struct RawTestResults {
     pub crates: Vec<CrateResult>,
 }
 
+impl RawTestResults {
+    /// Read results of a supported schema version.
+    fn from_versioned(mut value: serde_json::Value) -> Fallible<Self> {
+        // Documents written before the version was recorded have the shape of version 1
+        let version = match value.get("schema_version") {
+            None => 1,
+            Some(version) => version
+                .as_u64()
+                .ok_or_else(|| ResultsSchemaError::InvalidVersion(version.clone()))?,
+        };
+        if version > u64::from(RESULTS_SCHEMA_VERSION) {
+            return Err(
+                ResultsSchemaError::UnsupportedVersion(version, RESULTS_SCHEMA_VERSION).into(),
+            );
+        }
+        if version < 2 {
+            Self::upgrade_v1(&mut value)?;
+        }
+
+        #[derive(Deserialize)]
+        struct Current {
+            crates: Vec<CrateResult>,
+        }
+        let current: Current = serde_json::from_value(value)?;
+        Ok(RawTestResults {
+            crates: current.crates,
+        })
+    }
+
+    /// Version 2 keys the runs of each crate by toolchain, instead of a `[start, end]` pair.
+    fn upgrade_v1(value: &mut serde_json::Value) -> Fallible<()> {
+        let crates = value
+            .get_mut("crates")
+            .and_then(|crates| crates.as_array_mut())
+            .into_iter()
+            .flatten();
+        for krate in crates {
+            let Some(runs) = krate.get_mut("runs") else {
+                continue;
+            };
+            let pair = match runs.take() {
+                serde_json::Value::Array(pair) if pair.len() == V1_RUN_KEYS.len() => pair,
+                other => return Err(ResultsSchemaError::InvalidV1Runs(other).into()),
+            };
+            *runs = V1_RUN_KEYS
+                .iter()
+                .map(|key| key.to_string())
+                .zip(pair)
+                .collect::<serde_json::Map<_, _>>()
+                .into();
+        }
+        Ok(())
+    }
+}
+
+impl Serialize for RawTestResults {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        #[derive(Serialize)]
+        struct Versioned<'a> {
+            schema_version: u32,
+            crates: &'a [CrateResult],
+        }
+
+        Versioned {
+            schema_version: RESULTS_SCHEMA_VERSION,
+            crates: &self.crates,
+        }
+        .serialize(serializer)
+    }
+}
+
+impl<'de> Deserialize<'de> for RawTestResults {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        let value = serde_json::Value::deserialize(deserializer)?;
+        RawTestResults::from_versioned(value).map_err(serde::de::Error::custom)
+    }
+}
+
 #[cfg_attr(test, derive(Debug))]
-#[derive(Serialize, Deserialize, Clone, PartialEq, Eq, Hash)]
+#[derive(Serialize, Deserialize, Clone, PartialEq, Eq)]
 pub struct CrateResult {
     name: String,
     url: String,
@@ -54,7 +231,23 @@ pub struct CrateResult {
     #[serde(skip_serializing_if = "Option::is_none")]
     status: Option<CrateVersionStatus>,
     pub res: Comparison,
-    runs: [Option<BuildTestResult>; 2],
+    /// Result on each toolchain of the experiment keyed by the toolchain's name, in the order of
+    /// the experiment: the start toolchain first, then the end one and the extra ones.
+    runs: IndexMap<String, Option<BuildTestResult>>,
+}
+
+// A crate appears only once in a report, and the runs can't be hashed
+impl Hash for CrateResult {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.krate.hash(state);
+    }
+}
+
+impl CrateResult {
+    /// Results of the crate on the start and on the end toolchain, the ones `res` compares.
+    fn compared_runs(&self) -> [Option<&BuildTestResult>; 2] {
+        [0, 1].map(|idx| self.runs.get_index(idx).and_then(|(_, run)| run.as_ref()))
+    }
 }
 
 string_enum!(enum CrateVersionStatus {
@@ -77,6 +270,12 @@ string_enum!(pub enum Comparison {
     SameTestPass => "test-pass",
     SpuriousRegressed => "spurious-regressed",
     SpuriousFixed => "spurious-fixed",
+    Timeout => "timeout",
+    DocRegressed => "doc-regressed",
+    SameDocFail => "doc-fail",
+    FetchError => "fetch-error",
+    Flaky => "flaky",
+    Yanked => "yanked",
 });
 
 impl Comparison {
@@ -87,19 +286,27 @@ impl Comparison {
             | Comparison::Unknown
             | Comparison::Error
             | Comparison::SpuriousRegressed
-            | Comparison::SpuriousFixed => true,
+            | Comparison::SpuriousFixed
+            | Comparison::Timeout
+            | Comparison::DocRegressed
+            | Comparison::FetchError
+            | Comparison::Flaky
+            | Comparison::Yanked => true,
             Comparison::Skipped
             | Comparison::Broken
             | Comparison::SameBuildFail
             | Comparison::SameTestFail
             | Comparison::SameTestSkipped
-            | Comparison::SameTestPass => false,
+            | Comparison::SameTestPass
+            | Comparison::SameDocFail => false,
         }
     }
 
     pub fn report_config(self) -> ReportConfig {
         match self {
-            Comparison::Regressed => ReportConfig::Complete(ToolchainSelect::End),
+            Comparison::Regressed | Comparison::DocRegressed => {
+                ReportConfig::Complete(ToolchainSelect::End)
+            }
             Comparison::Fixed => ReportConfig::Complete(ToolchainSelect::Start),
             Comparison::Unknown
             | Comparison::Error
@@ -110,7 +317,12 @@ impl Comparison {
             | Comparison::SameBuildFail
             | Comparison::SameTestFail
             | Comparison::SameTestSkipped
-            | Comparison::SameTestPass => ReportConfig::Simple,
+            | Comparison::SameTestPass
+            | Comparison::Timeout
+            | Comparison::SameDocFail
+            | Comparison::FetchError
+            | Comparison::Flaky
+            | Comparison::Yanked => ReportConfig::Simple,
         }
     }
 }
@@ -120,6 +332,23 @@ impl Comparison {
 struct BuildTestResult {
     res: TestResult,
     log: String,
+    /// Link to the log, when the writer doesn't serve it relatively to the report pages.
+    #[serde(skip)]
+    log_url: Option<String>,
+    /// Wall-clock time of the build, when it was recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    duration_ms: Option<u64>,
+    /// Shell command reproducing the build, only extracted from the logs of the regressions.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    repro: Option<String>,
+}
+
+impl BuildTestResult {
+    fn log_link(&self) -> String {
+        self.log_url
+            .clone()
+            .unwrap_or_else(|| format!("{}/log.txt", self.log))
+    }
 }
 
 /// The type of sanitization required for a string.
@@ -174,6 +403,18 @@ fn crate_to_path_fragment(
             path.push("git");
             path.push(dest.sanitize(&repo.url).into_owned());
         }
+        Crate::AltRegistry(ref details) => {
+            path.push("alt");
+            path.push(dest.sanitize(&details.index_url).into_owned());
+
+            let name = format!("{}-{}", details.name, details.version);
+            path.push(dest.sanitize(&name).into_owned());
+        }
+        Crate::Workspace(ref member) => {
+            path.push("ws");
+            path.push(dest.sanitize(&member.root).into_owned());
+            path.push(dest.sanitize(&member.member).into_owned());
+        }
     }
 
     path
@@ -228,7 +469,7 @@ pub fn generate_report<DB: ReadResults>(
         .iter()
         .map(|krate| {
             // Any errors here will turn into unknown results
-            let crate_results = ex.toolchains.iter().map(|tc| -> Fallible<BuildTestResult> {
+            let load_run = |tc: &Toolchain| -> Fallible<BuildTestResult> {
                 let res = db
                     .load_test_result(ex, tc, krate)?
                     .ok_or_else(|| err_msg("no result"))?;
@@ -239,18 +480,43 @@ pub fn generate_report<DB: ReadResults>(
                         .to_str()
                         .unwrap()
                         .replace('\'', "/"), // Normalize paths in reports generated on Windows
+                    log_url: None,
+                    duration_ms: db
+                        .load_duration(ex, tc, krate)?
+                        .map(|duration| duration.as_millis() as u64),
+                    repro: None,
                 })
-            });
+            };
             // Convert errors to Nones
-            let mut crate_results = crate_results.map(|r| r.ok());
-            let crate1 = crate_results.next().unwrap();
-            let crate2 = crate_results.next().unwrap();
-            let comp = compare(
-                config,
-                krate,
-                crate1.as_ref().map(|b| &b.res),
-                crate2.as_ref().map(|b| &b.res),
-            );
+            let mut runs = ex
+                .all_toolchains()
+                .map(|tc| (tc.to_string(), load_run(tc).ok()))
+                .collect::<IndexMap<_, _>>();
+            // The results of flaky crates are the ones of the run which didn't regress
+            let comp = match db.load_confirmation(ex, krate)? {
+                Some(Confirmation::Flaky) => Comparison::Flaky,
+                _ => {
+                    let [start, end] = ex
+                        .toolchains
+                        .each_ref()
+                        .map(|tc| runs[&tc.to_string()].as_ref().map(|run| &run.res));
+                    compare(config, krate, start, end)
+                }
+            };
+
+            // Loading the logs is expensive, so only the regressions get the commands to
+            // reproduce them
+            if matches!(comp, Comparison::Regressed | Comparison::DocRegressed) {
+                for (run, tc) in runs.values_mut().zip(&ex.toolchains) {
+                    if let Some(run) = run {
+                        run.repro =
+                            repro::load_repro_command(db, ex, tc, krate).unwrap_or_else(|err| {
+                                warn!("failed to load the log of {} with {}: {}", krate, tc, err);
+                                None
+                            });
+                    }
+                }
+            }
 
             Ok(CrateResult {
                 name: crate_to_name(krate),
@@ -259,7 +525,7 @@ pub fn generate_report<DB: ReadResults>(
                     .unwrap_or(Some(CrateVersionStatus::MissingFromIndex)),
                 krate: krate.clone(),
                 res: comp,
-                runs: [crate1, crate2],
+                runs,
             })
         })
         .collect::<Fallible<Vec<_>>>()?;
@@ -269,37 +535,61 @@ pub fn generate_report<DB: ReadResults>(
 
 const PROGRESS_FRACTION: usize = 50; // write progress every ~1/N crates
 
+/// Sub-prefixes of the report the logs of the crates are written to, when the report is split
+/// by category. Without a prefix the logs are at the root of the report.
+#[derive(Default)]
+struct LogPrefixes(HashMap<Crate, &'static str>);
+
+impl LogPrefixes {
+    /// Prefixes of the crates of the report, named after their summary category if the report is
+    /// split by category.
+    fn new(config: &Config, raw: &RawTestResults) -> Self {
+        if !config.report.split_by_category {
+            return LogPrefixes::default();
+        }
+        LogPrefixes(
+            raw.crates
+                .iter()
+                .map(|krate| {
+                    let category = SummaryCategory::for_crate(krate);
+                    (krate.krate.clone(), category.to_str())
+                })
+                .collect(),
+        )
+    }
+
+    /// Point the results to the logs in their prefix.
+    fn apply(&self, raw: &mut RawTestResults) {
+        for krate in &mut raw.crates {
+            if let Some(prefix) = self.0.get(&krate.krate) {
+                for run in krate.runs.values_mut().flatten() {
+                    run.log = format!("{prefix}/{}", run.log);
+                }
+            }
+        }
+    }
+
+    fn log_path(&self, tc: &Toolchain, krate: &Crate) -> PathBuf {
+        let path = crate_to_path_fragment(tc, krate, SanitizationContext::Path).join("log.txt");
+        match self.0.get(krate) {
+            Some(prefix) => Path::new(prefix).join(path),
+            None => path,
+        }
+    }
+}
+
 fn write_logs<DB: ReadResults, W: ReportWriter>(
     db: &DB,
     ex: &Experiment,
     crates: &[Crate],
+    prefixes: &LogPrefixes,
     dest: &W,
     config: &Config,
 ) -> Fallible<()> {
     let num_crates = crates.len();
     let progress_every = (num_crates / PROGRESS_FRACTION) + 1;
 
-    let errors = std::sync::Mutex::new(vec![]);
-    std::thread::scope(|s| {
-        let mut channels = vec![];
-        // This isn't really related to the number of cores on the system, since these threads are
-        // mostly driving network-related traffic. 8 is a reasonable number to not overwhelm
-        // systems while keeping things moving much faster than fully serial uploads.
-        for _ in 0..8 {
-            let (tx, rx) = std::sync::mpsc::sync_channel::<(PathBuf, Vec<u8>, EncodingType)>(32);
-            channels.push(tx);
-            let errors = &errors;
-            s.spawn(move || {
-                while let Ok((log_path, data, encoding)) = rx.recv() {
-                    if let Err(e) =
-                        dest.write_bytes(log_path, &data, &mime::TEXT_PLAIN_UTF_8, encoding)
-                    {
-                        errors.lock().unwrap().push(e);
-                    }
-                }
-            });
-        }
-
+    with_write_pool(dest, config.report.write_concurrency, |queue| {
         for (i, krate) in crates.iter().enumerate() {
             if i % progress_every == 0 {
                 info!("wrote logs for {}/{} crates", i, num_crates)
@@ -309,46 +599,27 @@ fn write_logs<DB: ReadResults, W: ReportWriter>(
                 continue;
             }
 
-            for tc in &ex.toolchains {
-                let log_path =
-                    crate_to_path_fragment(tc, krate, SanitizationContext::Path).join("log.txt");
+            for tc in ex.all_toolchains() {
+                let log_path = prefixes.log_path(tc, krate);
                 let content = db
                     .load_log(ex, tc, krate)
                     .and_then(|c| c.ok_or_else(|| err_msg("missing logs")))
                     .with_context(|_| format!("failed to read log of {krate} on {tc}"));
-                let content = match content {
-                    Ok(c) => c,
+                let (data, encoding) = match content {
+                    Ok(EncodedLog::Plain(data)) => (data, EncodingType::Plain),
+                    Ok(EncodedLog::Gzip(data)) => (data, EncodingType::Gzip),
+                    Ok(EncodedLog::Zstd(data)) => (data, EncodingType::Zstd),
+                    Ok(EncodedLog::Brotli(data)) => (data, EncodingType::Brotli),
                     Err(e) => {
                         utils::report_failure(&e);
                         continue;
                     }
                 };
-
-                match content {
-                    EncodedLog::Plain(data) => {
-                        channels[i % channels.len()]
-                            .send((log_path, data, EncodingType::Plain))
-                            .unwrap();
-                    }
-                    EncodedLog::Gzip(data) => {
-                        channels[i % channels.len()]
-                            .send((log_path, data, EncodingType::Gzip))
-                            .unwrap();
-                    }
-                }
+                queue.write_bytes(log_path, data, &mime::TEXT_PLAIN_UTF_8, encoding)?;
             }
         }
-    });
-
-    let mut errors = errors.into_inner().unwrap();
-    for error in errors.iter() {
-        utils::report_failure(&failure::format_err!("Logging upload failed: {:?}", error));
-    }
-    if !errors.is_empty() {
-        return Err(errors.remove(0));
-    }
-
-    Ok(())
+        Ok(())
+    })
 }
 
 pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
@@ -358,16 +629,42 @@ pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
     dest: &W,
     config: &Config,
     output_templates: bool,
+    filter: &ReportFilter,
 ) -> Fallible<TestResults> {
-    let raw = generate_report(db, config, ex, crates)?;
+    let mut raw = generate_report(db, config, ex, crates)?;
+    let omitted = filter.apply(&mut raw);
+    if omitted > 0 {
+        info!("{} crates omitted from the report by the filter", omitted);
+    }
+    let prefixes = LogPrefixes::new(config, &raw);
+    prefixes.apply(&mut raw);
+    // Only the logs of the crates shown in the report are needed
+    let included = raw
+        .crates
+        .iter()
+        .map(|krate| krate.krate.clone())
+        .collect::<Vec<_>>();
 
     info!("writing results to {}", dest);
+    let resume = ResumeWriter::new(dest)?;
+    let compress = CompressWriter::new(&resume, config.report.text_encoding, config.compression);
+    let bundle = BundleWriter::new(&compress)?;
+    let dest = &bundle;
     info!("writing metadata");
     dest.write_string(
         "results.json",
         serde_json::to_string(&raw)?.into(),
         &mime::APPLICATION_JSON,
     )?;
+    let build_times = BuildTimesV1::new(
+        &ex.toolchains,
+        &raw,
+        Duration::from_secs(config.sandbox.build_timeout_secs),
+        config.report.slowest_crates,
+    );
+    summary::write_summary(ex, &raw, omitted, build_times.as_ref(), dest)?;
+    csv_export::write_csv(&raw, dest)?;
+    sqlite_export::write_sqlite(&raw, dest)?;
     dest.write_string(
         "config.json",
         serde_json::to_string(&ex)?.into(),
@@ -379,26 +676,139 @@ pub fn gen<DB: ReadResults, W: ReportWriter + Display>(
         &mime::TEXT_PLAIN_UTF_8,
     )?;
 
+    link_logs(ex, &mut raw, &prefixes, dest)?;
+    let counts = CategoryCountsV1::for_results(&raw);
+    let regressions_by_code = error_codes::regressions_by_code(&raw);
     let res = analyze_report(raw);
     info!("writing archives");
-    let available_archives = archives::write_logs_archives(db, ex, crates, dest, config)?;
+    let available_archives = archives::write_logs_archives(db, ex, &included, dest, config)?;
     info!("writing html files");
     html::write_html_report(
         ex,
         crates.len(),
         &res,
+        &counts,
+        &regressions_by_code,
         available_archives,
+        omitted,
+        config.report.page_size,
         dest,
         output_templates,
     )?;
     info!("writing markdown files");
-    markdown::write_markdown_report(ex, crates.len(), &res, dest, output_templates)?;
+    markdown::write_markdown_report(
+        ex,
+        crates.len(),
+        &res,
+        build_times.as_ref(),
+        dest,
+        output_templates,
+    )?;
+    if !ex.extra_toolchains.is_empty() {
+        info!("writing the toolchain matrix");
+        let matrix = ResultsMatrix::load(db, config, ex, &included)?;
+        write_matrix_report(&matrix, dest)?;
+    }
     info!("writing logs");
-    write_logs(db, ex, crates, dest, config)?;
+    write_logs(db, ex, &included, &prefixes, dest, config)?;
+    if config.report.log_manifest {
+        info!("writing the log store");
+        log_store::write_log_store(db, ex, &included, dest, config)?;
+    }
+    info!("writing the report bundle");
+    bundle.finish()?;
+    resume.finish()?;
 
     Ok(res)
 }
 
+/// Generate the report of an experiment again from its stored results, for example after the
+/// templates changed. Neither the results nor the status of the experiment are changed, so it
+/// can be run as many times as needed, even while the experiment is running: the crates without
+/// results yet are reported as such.
+pub fn regen<W: ReportWriter + Display>(
+    db: &Database,
+    ex: &Experiment,
+    dest: &W,
+    config: &Config,
+) -> Fallible<TestResults> {
+    let (completed, all) = ex.raw_progress(db)?;
+    if completed != all {
+        warn!(
+            "the experiment {} is incomplete ({}/{} results), the report only includes the \
+             available results",
+            ex.name, completed, all
+        );
+    }
+
+    // Only the crates tested before the cap was reached or the experiment was cancelled are part
+    // of the report
+    let crates = if ex.capped_at.is_some() || ex.cancelled_at.is_some() {
+        ex.get_completed_crates(db)?
+    } else {
+        ex.get_crates(db)?
+    };
+    gen(
+        &DatabaseDB::new(db),
+        ex,
+        &crates,
+        dest,
+        config,
+        false,
+        &ReportFilter::All,
+    )
+}
+
+/// Record the links to the logs for the writers serving them with their own URLs.
+fn link_logs<W: ReportWriter>(
+    ex: &Experiment,
+    raw: &mut RawTestResults,
+    prefixes: &LogPrefixes,
+    dest: &W,
+) -> Fallible<()> {
+    for krate in &mut raw.crates {
+        for (run, tc) in krate.runs.values_mut().zip(ex.all_toolchains()) {
+            if let Some(run) = run {
+                run.log_url = dest.object_url(prefixes.log_path(tc, &krate.krate))?;
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Number of crates which regressed among `crates`, ignoring the ones not tested with both
+/// compared toolchains yet. The results on the extra toolchains don't count.
+pub fn count_regressions<DB: ReadResults>(
+    db: &DB,
+    config: &Config,
+    ex: &Experiment,
+    crates: &[Crate],
+) -> Fallible<usize> {
+    let mut regressions = 0;
+    for krate in crates {
+        let mut results = Vec::with_capacity(ex.toolchains.len());
+        for tc in &ex.toolchains {
+            results.push(db.load_test_result(ex, tc, krate)?);
+        }
+        if let [Some(start), Some(end)] = results.as_slice() {
+            if compare(config, krate, Some(start), Some(end)) == Comparison::Regressed {
+                regressions += 1;
+            }
+        }
+    }
+    Ok(regressions)
+}
+
+/// Category of a crate, given its results with the start and the end toolchain.
+pub fn crate_category(
+    config: &Config,
+    krate: &Crate,
+    start: &TestResult,
+    end: &TestResult,
+) -> SummaryCategory {
+    SummaryCategory::from_results(compare(config, krate, Some(start), Some(end)), start, end)
+}
+
 /// Generates a list of regressed crate names that can be passed to crater via
 /// `crates=list:...` to retry those.
 fn gen_retry_list(res: &RawTestResults) -> String {
@@ -416,7 +826,11 @@ fn gen_retry_list(res: &RawTestResults) -> String {
         match krate {
             Crate::Registry(details) => writeln!(out, "{}", details.name).unwrap(),
             Crate::GitHub(repo) => writeln!(out, "{}/{}", repo.org, repo.name).unwrap(),
-            Crate::Local(_) | Crate::Git(_) | Crate::Path(_) => {}
+            Crate::Local(_)
+            | Crate::Git(_)
+            | Crate::Path(_)
+            | Crate::AltRegistry(_)
+            | Crate::Workspace(_) => {}
         }
     }
 
@@ -446,6 +860,12 @@ fn crate_to_name(c: &Crate) -> String {
                 utf8_percent_encode(&repo.url, &REPORT_ENCODE_SET).to_string()
             }
         }
+        Crate::AltRegistry(ref details) => format!("{}-{}", details.name, details.version),
+        Crate::Workspace(ref member) => utf8_percent_encode(
+            &format!("{}/{}", member.root, member.member),
+            &REPORT_ENCODE_SET,
+        )
+        .to_string(),
     }
 }
 
@@ -469,10 +889,16 @@ fn crate_to_url(c: &Crate) -> String {
         ),
         Crate::Path(ref path) => utf8_percent_encode(path, &REPORT_ENCODE_SET).to_string(),
         Crate::Git(ref repo) => repo.url.clone(),
+        Crate::AltRegistry(ref details) => details.download_url().unwrap_or_default(),
+        Crate::Workspace(ref member) => utf8_percent_encode(
+            &format!("{}/{}", member.root, member.member),
+            &REPORT_ENCODE_SET,
+        )
+        .to_string(),
     }
 }
 
-fn compare(
+pub(crate) fn compare(
     config: &Config,
     krate: &Crate,
     r1: Option<&TestResult>,
@@ -482,6 +908,24 @@ fn compare(
 
     match (r1, r2) {
         (Some(res1), Some(res2)) => match (res1, res2) {
+            // Only lints appearing with the new toolchain are regressions, otherwise crates with
+            // warnings are compared as if they passed.
+            (Warnings(before), Warnings(after)) if !after.0.is_subset(&before.0) => {
+                Comparison::Regressed
+            }
+            (Warnings(before), Warnings(after)) if before != after => Comparison::Fixed,
+            (Warnings(_), Warnings(_)) => Comparison::SameTestPass,
+            (TestPass, Warnings(_)) => Comparison::Regressed,
+            (Warnings(_), TestPass) => Comparison::Fixed,
+            (Warnings(_), _) => compare(config, krate, Some(&TestPass), r2),
+            (_, Warnings(_)) => compare(config, krate, r1, Some(&TestPass)),
+
+            // Miri reporting a new kind of undefined behavior is a regression
+            (
+                TestFail(FailureReason::UndefinedBehavior(before)),
+                TestFail(FailureReason::UndefinedBehavior(after)),
+            ) if !after.is_subset(before) => Comparison::Regressed,
+
             // ICE -> ICE is not a regression, but anything else to an ICE is.
             (BuildFail(FailureReason::ICE), BuildFail(FailureReason::ICE)) => {
                 Comparison::SameBuildFail
@@ -528,13 +972,37 @@ fn compare(
             | (TestSkipped, BuildFail(_))
             | (TestFail(_), BuildFail(_)) => Comparison::Regressed,
 
+            // Documentation failures are kept apart from build failures, which win when both are
+            // involved as the documentation can't be built anyway.
+            (DocFail(_), DocFail(_)) => Comparison::SameDocFail,
+            (TestPass, DocFail(reason)) if reason.is_spurious() => Comparison::SpuriousRegressed,
+            (TestPass, DocFail(_)) => Comparison::DocRegressed,
+            (DocFail(reason), TestPass) if reason.is_spurious() => Comparison::SpuriousFixed,
+            (BuildFail(reason), DocFail(_)) if reason.is_spurious() => Comparison::SpuriousFixed,
+            (DocFail(_), TestPass) | (BuildFail(_), DocFail(_)) => Comparison::Fixed,
+            (DocFail(reason1), BuildFail(reason2))
+                if reason1.is_spurious() || reason2.is_spurious() =>
+            {
+                Comparison::SpuriousRegressed
+            }
+            (DocFail(_), BuildFail(_)) => Comparison::Regressed,
+
+            // The version was yanked from the registry, so it wasn't built at all
+            (Yanked, _) | (_, Yanked) => Comparison::Yanked,
+            // The crate couldn't be downloaded, so nothing can be said about the toolchains
+            (FetchError(_), _) | (_, FetchError(_)) => Comparison::FetchError,
             (Error, _) | (_, Error) => Comparison::Error,
-            (Skipped, _) | (_, Skipped) => Comparison::Skipped,
+            (Unavailable, _) | (_, Unavailable) => Comparison::Unknown,
+            // Toolchains too old for the crate can't be compared either
+            (Skipped | SkippedMsrv, _) | (_, Skipped | SkippedMsrv) => Comparison::Skipped,
             (BrokenCrate(_), _) | (_, BrokenCrate(_)) => Comparison::Broken,
+            (Timeout, _) | (_, Timeout) => Comparison::Timeout,
             (TestFail(_), TestSkipped)
             | (TestPass, TestSkipped)
             | (TestSkipped, TestFail(_))
-            | (TestSkipped, TestPass) => {
+            | (TestSkipped, TestPass)
+            | (DocFail(_), TestFail(_) | TestSkipped)
+            | (TestFail(_) | TestSkipped, DocFail(_)) => {
                 panic!("can't compare {res1} and {res2}");
             }
         },
@@ -543,6 +1011,53 @@ fn compare(
     }
 }
 
+/// Progress of an upload, reported to the progress callback of the writers supporting one.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct UploadProgress {
+    /// Path of the object being uploaded.
+    pub path: PathBuf,
+    /// Bytes of the object uploaded so far.
+    pub written: usize,
+    /// Size of the whole object.
+    pub total: usize,
+}
+
+pub type ProgressCallback = Arc<dyn Fn(UploadProgress) + Send + Sync>;
+
+/// Signal asking the writers supporting it to abort the uploads they're running, cleaning up
+/// what they already uploaded instead of finishing them.
+#[derive(Clone, Default)]
+pub struct AbortSignal(Arc<AbortState>);
+
+#[derive(Default)]
+struct AbortState {
+    aborted: AtomicBool,
+    notify: tokio::sync::Notify,
+}
+
+impl AbortSignal {
+    pub fn abort(&self) {
+        self.0.aborted.store(true, Ordering::SeqCst);
+        self.0.notify.notify_waiters();
+    }
+
+    pub fn is_aborted(&self) -> bool {
+        self.0.aborted.load(Ordering::SeqCst)
+    }
+
+    /// Resolve once `abort` is called.
+    pub async fn aborted(&self) {
+        loop {
+            // Created before checking the flag, so a concurrent `abort` isn't missed
+            let notified = self.0.notify.notified();
+            if self.is_aborted() {
+                return;
+            }
+            notified.await;
+        }
+    }
+}
+
 pub trait ReportWriter: Send + Sync {
     fn write_bytes<P: AsRef<Path>>(
         &self,
@@ -552,8 +1067,66 @@ pub trait ReportWriter: Send + Sync {
         encoding_type: EncodingType,
     ) -> Fallible<()>;
     fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()>;
+
+    /// Write the body read from `reader`, whose length is `len` when it's known upfront. Writers
+    /// able to upload it piece by piece never hold the whole body in memory, while the other
+    /// ones read it entirely before writing it.
+    fn write_stream<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        mut reader: R,
+        _len: Option<u64>,
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let mut body = Vec::new();
+        reader.read_to_end(&mut body)?;
+        self.write_bytes(path, &body, mime, encoding_type)
+    }
+
+    /// Read back the bytes stored at `path`, as they were written. Returns `None` if nothing is
+    /// stored there, or if the writer can't read objects back.
+    fn read_bytes<P: AsRef<Path>>(&self, _path: P) -> Fallible<Option<Vec<u8>>> {
+        Ok(None)
+    }
+
+    /// URL the report pages link to for the object at `path`. Returns `None` to link to it with
+    /// a path relative to the pages.
+    fn object_url<P: AsRef<Path>>(&self, _path: P) -> Fallible<Option<String>> {
+        Ok(None)
+    }
+
+    /// Check the report can be written to the destination, by writing a small object at
+    /// `CHECK_PATH`. Writers able to remove it afterwards do so.
+    fn check_writable(&self) -> Fallible<()> {
+        self.write_string(CHECK_PATH, CHECK_CONTENT.into(), &mime::TEXT_PLAIN_UTF_8)
+    }
+}
+
+/// Path of the object written to check a report destination is writable.
+pub const CHECK_PATH: &str = ".crater-check";
+pub(crate) const CHECK_CONTENT: &str = "written by crater to check the destination is writable\n";
+
+/// Handle to the tokio runtime used by the report writers to drive their async operations.
+/// Clones share the same runtime, so a single one can be created and handed to every writer.
+#[derive(Clone)]
+pub struct ReportRuntime(Arc<tokio::runtime::Runtime>);
+
+impl ReportRuntime {
+    pub fn new() -> Fallible<Self> {
+        Ok(ReportRuntime(Arc::new(tokio::runtime::Runtime::new()?)))
+    }
+
+    pub fn block_on<F: Future>(&self, future: F) -> F::Output {
+        self.0.block_on(future)
+    }
 }
 
+/// Writer storing the report in a local directory.
+///
+/// Files are written to a temporary file first and then renamed, so an interrupted run never
+/// leaves a partially written file behind. Compressed bodies are stored as is, with their
+/// encoding recorded in a sidecar `<file>.encoding` file.
 pub struct FileWriter(PathBuf);
 
 impl FileWriter {
@@ -561,30 +1134,76 @@ impl FileWriter {
         fs::create_dir_all(&dest)?;
         Ok(FileWriter(dest))
     }
-    fn create_prefix(&self, path: &Path) -> Fallible<()> {
-        if let Some(parent) = path.parent() {
-            fs::create_dir_all(self.0.join(parent))?;
-        }
+
+    /// Read back a file written by this writer, decoding it if it was stored compressed.
+    pub fn read<P: AsRef<Path>>(&self, path: P) -> Fallible<Vec<u8>> {
+        let path = self.0.join(path.as_ref());
+        let encoding = match fs::read_to_string(encoding_sidecar(&path)) {
+            Ok(encoding) => encoding.trim().parse()?,
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => EncodingType::Plain,
+            Err(err) => return Err(err.into()),
+        };
+        crate::results::read_object(fs::File::open(&path)?, encoding)
+    }
+
+    fn write_atomic(&self, path: &Path, data: &[u8]) -> Fallible<()> {
+        use std::io::Write;
+
+        let path = self.0.join(path);
+        let parent = path.parent().unwrap_or(&self.0);
+        fs::create_dir_all(parent)?;
+
+        let mut tmp = tempfile::NamedTempFile::new_in(parent)?;
+        tmp.write_all(data)?;
+        tmp.as_file().sync_all()?;
+        tmp.persist(&path)
+            .with_context(|_| format!("failed to write {}", path.display()))?;
         Ok(())
     }
 }
 
+fn encoding_sidecar(path: &Path) -> PathBuf {
+    let mut sidecar = path.as_os_str().to_owned();
+    sidecar.push(".encoding");
+    sidecar.into()
+}
+
 impl ReportWriter for FileWriter {
     fn write_bytes<P: AsRef<Path>>(
         &self,
         path: P,
         b: &[u8],
         _: &Mime,
-        _: EncodingType,
+        encoding_type: EncodingType,
     ) -> Fallible<()> {
-        self.create_prefix(path.as_ref())?;
-        fs::write(self.0.join(path.as_ref()), b)?;
-        Ok(())
+        let path = path.as_ref();
+        let sidecar = encoding_sidecar(path);
+        match encoding_type {
+            EncodingType::Plain => match fs::remove_file(self.0.join(&sidecar)) {
+                Err(err) if err.kind() != std::io::ErrorKind::NotFound => return Err(err.into()),
+                _ => {}
+            },
+            encoding => self.write_atomic(&sidecar, encoding.to_str().as_bytes())?,
+        }
+        self.write_atomic(path, b)
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+    }
+
+    fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        match fs::read(self.0.join(path.as_ref())) {
+            Ok(data) => Ok(Some(data)),
+            Err(err) if err.kind() == std::io::ErrorKind::NotFound => Ok(None),
+            Err(err) => Err(err.into()),
+        }
     }
 
-    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, _: &Mime) -> Fallible<()> {
-        self.create_prefix(path.as_ref())?;
-        fs::write(self.0.join(path.as_ref()), s.as_ref().as_bytes())?;
+    fn check_writable(&self) -> Fallible<()> {
+        self.write_string(CHECK_PATH, CHECK_CONTENT.into(), &mime::TEXT_PLAIN_UTF_8)
+            .with_context(|_| format!("can't write to {}", self.0.display()))?;
+        fs::remove_file(self.0.join(CHECK_PATH))?;
         Ok(())
     }
 }
@@ -595,6 +1214,140 @@ impl Display for FileWriter {
     }
 }
 
+/// An object written to a `MemoryWriter`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct StoredObject {
+    pub data: Vec<u8>,
+    pub mime: Mime,
+    pub encoding: EncodingType,
+}
+
+/// Writer keeping everything in memory, to inspect the output of report generation in tests.
+/// Clones share the same storage.
+#[derive(Clone, Default)]
+pub struct MemoryWriter {
+    objects: Arc<Mutex<HashMap<PathBuf, StoredObject>>>,
+}
+
+impl MemoryWriter {
+    pub fn new() -> Self {
+        MemoryWriter::default()
+    }
+
+    pub fn get<P: AsRef<Path>>(&self, path: P) -> Option<StoredObject> {
+        self.objects.lock().unwrap().get(path.as_ref()).cloned()
+    }
+
+    pub fn paths(&self) -> Vec<PathBuf> {
+        let mut paths = self
+            .objects
+            .lock()
+            .unwrap()
+            .keys()
+            .cloned()
+            .collect::<Vec<_>>();
+        paths.sort();
+        paths
+    }
+
+    pub fn len(&self) -> usize {
+        self.objects.lock().unwrap().len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+impl ReportWriter for MemoryWriter {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.objects.lock().unwrap().insert(
+            path.as_ref().to_path_buf(),
+            StoredObject {
+                data: b.to_vec(),
+                mime: mime.clone(),
+                encoding: encoding_type,
+            },
+        );
+        Ok(())
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+    }
+
+    fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        Ok(self.get(path).map(|object| object.data))
+    }
+}
+
+impl Display for MemoryWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "memory://".fmt(f)
+    }
+}
+
+type DryRunHook = Box<dyn Fn(&Path, usize, &Mime, EncodingType) + Send + Sync>;
+
+/// Writer discarding everything, only logging what would've been written.
+#[derive(Default)]
+pub struct DryRunWriter {
+    hook: Option<DryRunHook>,
+}
+
+impl DryRunWriter {
+    pub fn new() -> Self {
+        DryRunWriter::default()
+    }
+
+    /// Call `hook` with the path, size, mime type and encoding of every write.
+    pub fn with_hook<F>(mut self, hook: F) -> Self
+    where
+        F: Fn(&Path, usize, &Mime, EncodingType) + Send + Sync + 'static,
+    {
+        self.hook = Some(Box::new(hook));
+        self
+    }
+}
+
+impl ReportWriter for DryRunWriter {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        info!(
+            "dry run: would write {} ({} bytes, {}, {})",
+            path.as_ref().display(),
+            b.len(),
+            mime,
+            encoding_type
+        );
+        if let Some(hook) = &self.hook {
+            hook(path.as_ref(), b.len(), mime, encoding_type);
+        }
+        Ok(())
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+    }
+}
+
+impl Display for DryRunWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        "dry-run://".fmt(f)
+    }
+}
+
 #[cfg(test)]
 #[derive(Default)]
 pub struct DummyWriter {
@@ -611,6 +1364,15 @@ impl DummyWriter {
             .unwrap()
             .clone()
     }
+
+    pub fn paths(&self) -> std::collections::BTreeSet<PathBuf> {
+        self.results
+            .lock()
+            .unwrap()
+            .keys()
+            .map(|(path, _)| path.clone())
+            .collect()
+    }
 }
 
 #[cfg(test)]
@@ -655,6 +1417,7 @@ mod tests {
     use crate::results::{BrokenReason, DummyDB, FailureReason, TestResult};
     use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
     use crates_index::GitIndex;
+    use std::collections::BTreeMap;
 
     #[test]
     fn test_crate_to_path_fragment() {
@@ -787,7 +1550,14 @@ mod tests {
 
     #[test]
     fn test_compare() {
-        use crate::results::{FailureReason::*, TestResult::*};
+        use crate::results::{DiagnosticCode, FailureReason::*, Lints, TestResult::*};
+        use std::collections::BTreeSet;
+
+        let lints =
+            |names: &[&str]| Lints(names.iter().map(|name| name.parse().unwrap()).collect());
+        let ub = |kinds: &[&str]| -> BTreeSet<DiagnosticCode> {
+            kinds.iter().map(|kind| kind.parse().unwrap()).collect()
+        };
 
         macro_rules! test_compare {
             ($cmp:ident, $config:expr, $reg:expr, [$($a:expr, $b:expr => $c:ident;)*]) => {
@@ -868,7 +1638,16 @@ mod tests {
                 TestSkipped, Skipped => Skipped;
                 TestFail(Unknown), Skipped => Skipped;
                 BuildFail(Unknown), Skipped => Skipped;
+                SkippedMsrv, TestPass => Skipped;
+                SkippedMsrv, BuildFail(Unknown) => Skipped;
+                BuildFail(Unknown), SkippedMsrv => Skipped;
+                SkippedMsrv, SkippedMsrv => Skipped;
 
+                // Yanked
+                Yanked, Yanked => Yanked;
+                Yanked, TestPass => Yanked;
+                TestPass, Yanked => Yanked;
+                Yanked, BuildFail(Unknown) => Yanked;
 
                 // Broken
                 BrokenCrate(BrokenReason::Unknown), TestPass => Broken;
@@ -879,6 +1658,58 @@ mod tests {
                 TestSkipped, BrokenCrate(BrokenReason::Unknown) => Broken;
                 TestFail(Unknown), BrokenCrate(BrokenReason::Unknown) => Broken;
                 BuildFail(Unknown), BrokenCrate(BrokenReason::Unknown) => Broken;
+
+                // Timeout
+                TestResult::Timeout, TestPass => Timeout;
+                TestResult::Timeout, BuildFail(Unknown) => Timeout;
+                TestPass, TestResult::Timeout => Timeout;
+                TestFail(Unknown), TestResult::Timeout => Timeout;
+                TestResult::Timeout, TestResult::Timeout => Timeout;
+                Error, TestResult::Timeout => Error;
+                TestResult::Timeout, Skipped => Skipped;
+                TestResult::Timeout, BrokenCrate(BrokenReason::Unknown) => Broken;
+
+                // Clippy warnings
+                Warnings(lints(&["clippy::a"])), Warnings(lints(&["clippy::a", "clippy::b"])) => Regressed;
+                Warnings(lints(&["clippy::a"])), Warnings(lints(&["clippy::b"])) => Regressed;
+                Warnings(lints(&["clippy::a", "clippy::b"])), Warnings(lints(&["clippy::a"])) => Fixed;
+                Warnings(lints(&["clippy::a"])), Warnings(lints(&["clippy::a"])) => SameTestPass;
+                TestPass, Warnings(lints(&["clippy::a"])) => Regressed;
+                Warnings(lints(&["clippy::a"])), TestPass => Fixed;
+                Warnings(lints(&["clippy::a"])), BuildFail(Unknown) => Regressed;
+                BuildFail(Unknown), Warnings(lints(&["clippy::a"])) => Fixed;
+                Warnings(lints(&["clippy::a"])), Error => Error;
+
+                // Undefined behavior
+                TestFail(UndefinedBehavior(ub(&["memory leaked"]))), TestFail(UndefinedBehavior(ub(&["memory leaked", "out-of-bounds pointer use"]))) => Regressed;
+                TestFail(UndefinedBehavior(ub(&["memory leaked"]))), TestFail(UndefinedBehavior(ub(&["memory leaked"]))) => SameTestFail;
+                TestFail(UndefinedBehavior(ub(&["memory leaked", "out-of-bounds pointer use"]))), TestFail(UndefinedBehavior(ub(&["memory leaked"]))) => SameTestFail;
+                TestPass, TestFail(UndefinedBehavior(ub(&["memory leaked"]))) => Regressed;
+                TestFail(UndefinedBehavior(ub(&["memory leaked"]))), TestPass => Fixed;
+
+                // Documentation
+                DocFail(Unknown), DocFail(Unknown) => SameDocFail;
+                TestPass, DocFail(Unknown) => DocRegressed;
+                TestPass, DocFail(OOM) => SpuriousRegressed;
+                DocFail(Unknown), TestPass => Fixed;
+                DocFail(OOM), TestPass => SpuriousFixed;
+                BuildFail(Unknown), DocFail(Unknown) => Fixed;
+                DocFail(Unknown), BuildFail(Unknown) => Regressed;
+                DocFail(Unknown), BuildFail(OOM) => SpuriousRegressed;
+                DocFail(OOM), BuildFail(Unknown) => SpuriousRegressed;
+                BuildFail(OOM), DocFail(Unknown) => SpuriousFixed;
+                DocFail(Unknown), Error => Error;
+                Skipped, DocFail(Unknown) => Skipped;
+
+                // Unavailable tools
+                Unavailable, TestPass => Unknown;
+                Warnings(lints(&["clippy::a"])), Unavailable => Unknown;
+                Unavailable, Unavailable => Unknown;
+
+                // Download failures
+                TestPass, FetchError("timed out".into()) => FetchError;
+                FetchError("timed out".into()), BuildFail(Unknown) => FetchError;
+                FetchError("timed out".into()), Error => FetchError;
             ]
         );
 
@@ -891,6 +1722,9 @@ mod tests {
                 skip_tests: false,
                 quiet: false,
                 broken: false,
+                miri: false,
+                memory_limit: None,
+                requires: Vec::new(),
             },
         );
         assert_eq!(compare(&config, &reg, None, None), Comparison::Skipped);
@@ -926,6 +1760,15 @@ mod tests {
             report_url: None,
             ignore_blacklist: false,
             requirement: None,
+            max_regressions: None,
+            capped_at: None,
+            cancelled_at: None,
+            webhook: None,
+            sample_seed: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
         };
 
         let mut db = DummyDB::default();
@@ -980,7 +1823,16 @@ mod tests {
         );
 
         let writer = DummyWriter::default();
-        gen(&db, &ex, &[gh, reg], &writer, &config, false).unwrap();
+        gen(
+            &db,
+            &ex,
+            &[gh, reg],
+            &writer,
+            &config,
+            false,
+            &ReportFilter::All,
+        )
+        .unwrap();
 
         assert_eq!(
             writer.get("config.json", &mime::APPLICATION_JSON),
@@ -1010,19 +1862,19 @@ mod tests {
         );
         assert_eq!(gh_result.res, Comparison::Regressed);
         assert_eq!(
-            gh_result.runs[0].as_ref().unwrap().res,
+            gh_result.runs["stable"].as_ref().unwrap().res,
             TestResult::TestPass
         );
         assert_eq!(
-            gh_result.runs[1].as_ref().unwrap().res,
+            gh_result.runs["beta"].as_ref().unwrap().res,
             TestResult::BuildFail(FailureReason::Unknown)
         );
         assert_eq!(
-            Path::new(gh_result.runs[0].as_ref().unwrap().log.as_str()),
+            Path::new(gh_result.runs["stable"].as_ref().unwrap().log.as_str()),
             Path::new("stable/gh/brson.hello-rs")
         );
         assert_eq!(
-            Path::new(gh_result.runs[1].as_ref().unwrap().log.as_str()),
+            Path::new(gh_result.runs["beta"].as_ref().unwrap().log.as_str()),
             Path::new("beta/gh/brson.hello-rs")
         );
 
@@ -1033,19 +1885,19 @@ mod tests {
         );
         assert_eq!(reg_result.res, Comparison::Regressed);
         assert_eq!(
-            reg_result.runs[0].as_ref().unwrap().res,
+            reg_result.runs["stable"].as_ref().unwrap().res,
             TestResult::TestPass
         );
         assert_eq!(
-            reg_result.runs[1].as_ref().unwrap().res,
+            reg_result.runs["beta"].as_ref().unwrap().res,
             TestResult::BuildFail(FailureReason::Unknown)
         );
         assert_eq!(
-            Path::new(reg_result.runs[0].as_ref().unwrap().log.as_str()),
+            Path::new(reg_result.runs["stable"].as_ref().unwrap().log.as_str()),
             Path::new("stable/reg/syn-1.0.0")
         );
         assert_eq!(
-            Path::new(reg_result.runs[1].as_ref().unwrap().log.as_str()),
+            Path::new(reg_result.runs["beta"].as_ref().unwrap().log.as_str()),
             Path::new("beta/reg/syn-1.0.0")
         );
 
@@ -1053,5 +1905,584 @@ mod tests {
             writer.get("retry-regressed-list.txt", &mime::TEXT_PLAIN_UTF_8),
             b"brson/hello-rs\nsyn\n",
         );
+
+        let summary = EncodedLog::Gzip(writer.get("summary.json", &mime::APPLICATION_JSON));
+        let summary: ReportSummaryV1 =
+            serde_json::from_slice(&summary.to_plain().unwrap()).unwrap();
+        assert_eq!(summary.version, ReportSummaryV1::VERSION);
+        assert_eq!(summary.crates.len(), 2);
+        assert_eq!(summary.crates[1].name, "syn");
+        assert_eq!(summary.crates[1].version.as_deref(), Some("1.0.0"));
+        assert_eq!(summary.crates[1].category, SummaryCategory::Regressed);
+
+        let csv = String::from_utf8(writer.get("results.csv", &mime::TEXT_CSV_UTF_8)).unwrap();
+        assert_eq!(csv.lines().count(), 3);
+        assert!(csv.contains(
+            "syn,1.0.0,test-pass,build-fail:unknown,regressed,beta/reg/syn-1.0.0/log.txt"
+        ));
+    }
+
+    #[test]
+    fn test_regen() {
+        use crate::actions::{Action, ActionsCtx, CreateExperiment};
+        use crate::results::{ProgressData, TaskResult};
+        use base64::Engine;
+        use std::collections::BTreeSet;
+
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        // Only some of the crates have results, as if the experiment was still running
+        let results = DatabaseDB::new(&db);
+        for (name, end) in [
+            ("build-pass", TestResult::TestPass),
+            ("build-fail", TestResult::BuildFail(FailureReason::Unknown)),
+        ] {
+            for (tc, res) in ex.toolchains.iter().zip([TestResult::TestPass, end]) {
+                let data = ProgressData {
+                    result: TaskResult {
+                        krate: Crate::Local(name.into()),
+                        toolchain: tc.clone(),
+                        result: res,
+                        log: base64::engine::general_purpose::STANDARD.encode(name),
+                        duration_ms: None,
+                    },
+                    version: None,
+                };
+                results.store(&ex, &data, EncodingType::Plain).unwrap();
+            }
+        }
+
+        let regen = || {
+            let writer = DummyWriter::default();
+            regen(&db, &ex, &writer, &config).unwrap();
+            writer
+        };
+        let writer = regen();
+        let paths = writer.paths();
+        for expected in [
+            "results.json",
+            "summary.json",
+            "results.csv",
+            "config.json",
+            "retry-regressed-list.txt",
+            "index.html",
+            "full.html",
+            "downloads.html",
+            "markdown.md",
+            "stable/local/build-pass/log.txt",
+            "beta/local/build-pass/log.txt",
+            "stable/local/build-fail/log.txt",
+            "beta/local/build-fail/log.txt",
+        ] {
+            assert!(paths.contains(Path::new(expected)), "missing {expected}");
+        }
+        // No log is written for the crates without results
+        let logs = paths
+            .iter()
+            .filter(|path| path.ends_with("log.txt"))
+            .collect::<BTreeSet<_>>();
+        assert_eq!(logs.len(), 4);
+        assert_eq!(
+            writer.get("beta/local/build-fail/log.txt", &mime::TEXT_PLAIN_UTF_8),
+            b"build-fail"
+        );
+
+        // Regenerating again writes the same report, and leaves the experiment untouched
+        assert_eq!(regen().paths(), paths);
+        let after = Experiment::get(&db, "dummy").unwrap().unwrap();
+        assert_eq!(after.status, ex.status);
+    }
+
+    #[test]
+    fn test_extra_toolchains_report() {
+        let config = Config::default();
+        let nightly: Toolchain = "nightly".parse().unwrap();
+        let ex = Experiment {
+            name: "foo".to_string(),
+            toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Forbid,
+            priority: 0,
+            created_at: ::chrono::Utc::now(),
+            started_at: None,
+            completed_at: None,
+            github_issue: None,
+            status: Status::GeneratingReport,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            requirement: None,
+            max_regressions: None,
+            capped_at: None,
+            cancelled_at: None,
+            webhook: None,
+            sample_seed: None,
+            skip_dev_deps: false,
+            extra_toolchains: vec![nightly.clone()],
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
+        };
+
+        let fail = || TestResult::BuildFail(FailureReason::Unknown);
+        let nightly_regression = Crate::Local("nightly-regression".into());
+        let beta_regression = Crate::Local("beta-regression".into());
+        let mut db = DummyDB::default();
+        for (krate, results) in [
+            (
+                &nightly_regression,
+                [TestResult::TestPass, TestResult::TestPass, fail()],
+            ),
+            (
+                &beta_regression,
+                [TestResult::TestPass, fail(), TestResult::TestPass],
+            ),
+        ] {
+            for (tc, res) in ex.all_toolchains().zip(results) {
+                db.add_dummy_result(&ex, krate.clone(), tc.clone(), res);
+            }
+        }
+        let crates = [beta_regression, nightly_regression];
+
+        let raw = generate_report(&db, &config, &ex, &crates).unwrap();
+        let [beta, nightly_only] = &raw.crates[..] else {
+            panic!("expected two crates");
+        };
+
+        // Every toolchain has a run, but only the end one is compared with the start one
+        assert_eq!(
+            nightly_only.runs.keys().collect::<Vec<_>>(),
+            ["stable", "beta", "nightly"]
+        );
+        assert_eq!(nightly_only.runs["nightly"].as_ref().unwrap().res, fail());
+        assert_eq!(nightly_only.res, Comparison::SameTestPass);
+        assert_eq!(
+            SummaryCategory::for_crate(nightly_only),
+            SummaryCategory::Unchanged
+        );
+        assert_eq!(beta.res, Comparison::Regressed);
+        assert_eq!(
+            beta.compared_runs().map(|run| run.unwrap().res.clone()),
+            [TestResult::TestPass, fail()]
+        );
+
+        assert_eq!(count_regressions(&db, &config, &ex, &crates).unwrap(), 1);
+    }
+
+    #[test]
+    fn test_report_filter() {
+        let result = |name: &str, res| CrateResult {
+            name: name.into(),
+            url: String::new(),
+            krate: Crate::Local(name.into()),
+            status: None,
+            res,
+            runs: IndexMap::new(),
+        };
+        let mut raw = RawTestResults {
+            crates: vec![
+                result("regressed", Comparison::Regressed),
+                result("unchanged", Comparison::SameTestPass),
+            ],
+        };
+
+        let omitted = ReportFilter::RegressionsOnly.apply(&mut raw);
+        assert_eq!(omitted, 1);
+        assert_eq!(raw.crates.len(), 1);
+        assert_eq!(raw.crates[0].name, "regressed");
+
+        let ex = Experiment {
+            name: "foo".to_string(),
+            toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Forbid,
+            priority: 0,
+            created_at: ::chrono::Utc::now(),
+            started_at: None,
+            completed_at: None,
+            github_issue: None,
+            status: Status::GeneratingReport,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            requirement: None,
+            max_regressions: None,
+            capped_at: None,
+            cancelled_at: None,
+            webhook: None,
+            sample_seed: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
+        };
+        let summary = ReportSummaryV1::new(&ex, &raw, omitted);
+        assert_eq!(summary.omitted, 1);
+        assert_eq!(summary.crates.len(), 1);
+        assert_eq!(summary.crates[0].category, SummaryCategory::Regressed);
+
+        let filter = ReportFilter::Comparisons(
+            [Comparison::Regressed, Comparison::SameTestPass]
+                .into_iter()
+                .collect(),
+        );
+        assert!(filter.includes(Comparison::SameTestPass));
+        assert!(!filter.includes(Comparison::Fixed));
+    }
+
+    #[test]
+    fn test_split_by_category() {
+        let ex = Experiment {
+            name: "foo".to_string(),
+            toolchains: [MAIN_TOOLCHAIN.clone(), TEST_TOOLCHAIN.clone()],
+            mode: Mode::BuildAndTest,
+            cap_lints: CapLints::Forbid,
+            priority: 0,
+            created_at: ::chrono::Utc::now(),
+            started_at: None,
+            completed_at: None,
+            github_issue: None,
+            status: Status::GeneratingReport,
+            assigned_to: None,
+            report_url: None,
+            ignore_blacklist: false,
+            requirement: None,
+            max_regressions: None,
+            capped_at: None,
+            cancelled_at: None,
+            webhook: None,
+            sample_seed: None,
+            skip_dev_deps: false,
+            extra_toolchains: Vec::new(),
+            labels: BTreeMap::new(),
+            confirm_regressions: false,
+        };
+
+        let mut db = DummyDB::default();
+        let mut result = |name: &str, res, end: TestResult| {
+            let krate = Crate::Local(name.into());
+            for (tc, tc_result) in ex.toolchains.iter().zip([TestResult::TestPass, end]) {
+                db.add_dummy_log(
+                    &ex,
+                    krate.clone(),
+                    tc.clone(),
+                    EncodedLog::Plain(format!("{name} log").into_bytes()),
+                );
+                db.add_dummy_result(&ex, krate.clone(), tc.clone(), tc_result);
+            }
+            CrateResult {
+                name: name.into(),
+                url: String::new(),
+                krate: krate.clone(),
+                status: None,
+                res,
+                runs: ex
+                    .toolchains
+                    .iter()
+                    .map(|tc| {
+                        let run = BuildTestResult {
+                            res: TestResult::TestPass,
+                            log: crate_to_path_fragment(tc, &krate, SanitizationContext::Url)
+                                .to_str()
+                                .unwrap()
+                                .into(),
+                            log_url: None,
+                            duration_ms: None,
+                            repro: None,
+                        };
+                        (tc.to_string(), Some(run))
+                    })
+                    .collect(),
+            }
+        };
+        let mut raw = RawTestResults {
+            crates: vec![
+                result(
+                    "regressed",
+                    Comparison::Regressed,
+                    TestResult::BuildFail(FailureReason::Unknown),
+                ),
+                result("unchanged", Comparison::SameTestPass, TestResult::TestPass),
+            ],
+        };
+        let crates = raw
+            .crates
+            .iter()
+            .map(|krate| krate.krate.clone())
+            .collect::<Vec<_>>();
+
+        // Opt-in, the logs stay at the root of the report by default
+        let mut config = Config::default();
+        assert!(LogPrefixes::new(&config, &raw).0.is_empty());
+
+        config.report.split_by_category = true;
+        let prefixes = LogPrefixes::new(&config, &raw);
+        prefixes.apply(&mut raw);
+        let writer = DummyWriter::default();
+        write_logs(&db, &ex, &crates, &prefixes, &writer, &config).unwrap();
+
+        assert_eq!(
+            writer.get(
+                "regressed/stable/local/regressed/log.txt",
+                &mime::TEXT_PLAIN_UTF_8
+            ),
+            b"regressed log"
+        );
+        assert_eq!(
+            writer.get(
+                "unchanged/beta/local/unchanged/log.txt",
+                &mime::TEXT_PLAIN_UTF_8
+            ),
+            b"unchanged log"
+        );
+        // The pages link to the logs in their prefix
+        assert_eq!(
+            raw.crates[0].runs["beta"].as_ref().unwrap().log_link(),
+            "regressed/beta/local/regressed/log.txt"
+        );
+        assert_eq!(
+            raw.crates[1].runs["stable"].as_ref().unwrap().log_link(),
+            "unchanged/stable/local/unchanged/log.txt"
+        );
+    }
+
+    #[test]
+    fn test_results_schema_version() {
+        // Written before the schema version was recorded
+        let legacy = serde_json::json!({
+            "crates": [{
+                "name": "lazy_static-1.0.0",
+                "url": "https://crates.io/crates/lazy_static/1.0.0",
+                "krate": {"Registry": {"name": "lazy_static", "version": "1.0.0"}},
+                "res": "regressed",
+                "runs": [
+                    {"res": "test-pass", "log": "stable/reg/lazy_static-1.0.0"},
+                    null,
+                ],
+            }],
+        });
+        let unversioned: RawTestResults = serde_json::from_value(legacy).unwrap();
+        assert_eq!(
+            unversioned.crates,
+            vec![CrateResult {
+                name: "lazy_static-1.0.0".into(),
+                url: "https://crates.io/crates/lazy_static/1.0.0".into(),
+                krate: Crate::Registry(RegistryCrate {
+                    name: "lazy_static".into(),
+                    version: "1.0.0".into(),
+                }),
+                status: None,
+                res: Comparison::Regressed,
+                runs: IndexMap::from([
+                    (
+                        "start".to_string(),
+                        Some(BuildTestResult {
+                            res: TestResult::TestPass,
+                            log: "stable/reg/lazy_static-1.0.0".into(),
+                            log_url: None,
+                            duration_ms: None,
+                            repro: None,
+                        }),
+                    ),
+                    ("end".to_string(), None),
+                ]),
+            }]
+        );
+
+        // The current version is written, and read back as is
+        let serialized = serde_json::to_value(&unversioned).unwrap();
+        assert_eq!(serialized["schema_version"], RESULTS_SCHEMA_VERSION);
+        let read: RawTestResults = serde_json::from_value(serialized).unwrap();
+        assert_eq!(read.crates, unversioned.crates);
+
+        // Newer versions are refused instead of being misread
+        let newer = serde_json::json!({
+            "schema_version": RESULTS_SCHEMA_VERSION + 1,
+            "crates": [],
+        });
+        let err = serde_json::from_value::<RawTestResults>(newer)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("newer than the supported version"));
+        let invalid = serde_json::json!({"schema_version": "1", "crates": []});
+        assert!(serde_json::from_value::<RawTestResults>(invalid).is_err());
+    }
+
+    #[test]
+    fn test_results_v1_upgrade() {
+        let v1: RawTestResults =
+            serde_json::from_str(include_str!("fixtures/results-v1.json")).unwrap();
+        assert_eq!(v1.crates.len(), 3);
+        let regressed = &v1.crates[0];
+        assert_eq!(regressed.name, "beta-regression (local)");
+        assert_eq!(regressed.res, Comparison::Regressed);
+        assert_eq!(regressed.runs.keys().collect::<Vec<_>>(), ["start", "end"]);
+        assert_eq!(
+            regressed
+                .compared_runs()
+                .map(|run| run.unwrap().res.clone()),
+            [
+                TestResult::TestPass,
+                TestResult::BuildFail(FailureReason::Unknown)
+            ]
+        );
+        assert_eq!(
+            regressed.runs["end"].as_ref().unwrap().log,
+            "beta/local/beta-regression"
+        );
+
+        // Written back with the current version, which is read as the same results
+        let v2 = serde_json::to_value(&v1).unwrap();
+        assert_eq!(v2["schema_version"], RESULTS_SCHEMA_VERSION);
+        assert_eq!(v2["crates"][0]["runs"]["end"]["res"], "build-fail:unknown");
+        let read: RawTestResults = serde_json::from_value(v2).unwrap();
+        assert_eq!(read.crates, v1.crates);
+
+        // Only pairs of runs can be upgraded
+        let invalid = serde_json::json!({"schema_version": 1, "crates": [{"runs": [null]}]});
+        let err = serde_json::from_value::<RawTestResults>(invalid)
+            .err()
+            .unwrap();
+        assert!(err.to_string().contains("invalid runs of a crate"));
+    }
+
+    #[test]
+    fn test_file_writer() {
+        let dir = tempfile::tempdir().unwrap();
+        let writer = FileWriter::create(dir.path().join("report")).unwrap();
+        let log = b"error: could not compile `foo`\n".repeat(16);
+
+        writer
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        for (path, encoding) in [
+            ("stable/reg/foo-1.0.0/log.txt", EncodingType::Gzip),
+            ("beta/reg/foo-1.0.0/log.txt", EncodingType::Zstd),
+            ("beta/reg/bar-1.0.0/log.txt", EncodingType::Plain),
+        ] {
+            let encoded = EncodedLog::from_plain_slice(&log, encoding).unwrap();
+            writer
+                .write_bytes(path, encoded.as_slice(), &mime::TEXT_PLAIN_UTF_8, encoding)
+                .unwrap();
+
+            // The compressed bytes are stored as is, and decoded when read back
+            let stored = fs::read(dir.path().join("report").join(path)).unwrap();
+            assert_eq!(stored, encoded.as_slice());
+            assert_eq!(writer.read(path).unwrap(), log);
+        }
+        assert_eq!(writer.read("index.html").unwrap(), b"<html>");
+
+        // Overwriting a compressed file with a plain one drops the sidecar
+        writer
+            .write_bytes(
+                "stable/reg/foo-1.0.0/log.txt",
+                b"plain",
+                &mime::TEXT_PLAIN_UTF_8,
+                EncodingType::Plain,
+            )
+            .unwrap();
+        assert_eq!(
+            writer.read("stable/reg/foo-1.0.0/log.txt").unwrap(),
+            b"plain"
+        );
+
+        // No temporary file is left around
+        let mut files = walkdir::WalkDir::new(dir.path())
+            .into_iter()
+            .map(|entry| entry.unwrap())
+            .filter(|entry| entry.file_type().is_file())
+            .map(|entry| {
+                entry
+                    .path()
+                    .strip_prefix(dir.path().join("report"))
+                    .unwrap()
+                    .to_string_lossy()
+                    .into_owned()
+            })
+            .collect::<Vec<_>>();
+        files.sort();
+        assert_eq!(
+            files,
+            vec![
+                "beta/reg/bar-1.0.0/log.txt",
+                "beta/reg/foo-1.0.0/log.txt",
+                "beta/reg/foo-1.0.0/log.txt.encoding",
+                "index.html",
+                "stable/reg/foo-1.0.0/log.txt",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_memory_writer() {
+        let writer = MemoryWriter::new();
+        assert!(writer.is_empty());
+        assert_eq!(writer.to_string(), "memory://");
+
+        writer
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        writer
+            .clone()
+            .write_bytes("logs/a.txt", b"abc", &mime::TEXT_PLAIN, EncodingType::Gzip)
+            .unwrap();
+
+        assert_eq!(
+            writer.paths(),
+            vec![PathBuf::from("index.html"), PathBuf::from("logs/a.txt")]
+        );
+        assert_eq!(
+            writer.get("index.html").unwrap(),
+            StoredObject {
+                data: b"<html>".to_vec(),
+                mime: mime::TEXT_HTML,
+                encoding: EncodingType::Plain,
+            }
+        );
+        let log = writer.get("logs/a.txt").unwrap();
+        assert_eq!(log.data, b"abc");
+        assert_eq!(log.encoding, EncodingType::Gzip);
+        assert!(writer.get("missing").is_none());
+    }
+
+    #[test]
+    fn test_dry_run_writer() {
+        let writes = Arc::new(Mutex::new(Vec::new()));
+        let recorded = writes.clone();
+        let writer = DryRunWriter::new().with_hook(move |path, len, mime, encoding| {
+            recorded
+                .lock()
+                .unwrap()
+                .push((path.to_path_buf(), len, mime.clone(), encoding));
+        });
+
+        writer
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        writer
+            .write_bytes("log.txt", &[0; 42], &mime::TEXT_PLAIN, EncodingType::Gzip)
+            .unwrap();
+
+        assert_eq!(
+            *writes.lock().unwrap(),
+            vec![
+                (
+                    PathBuf::from("index.html"),
+                    6,
+                    mime::TEXT_HTML,
+                    EncodingType::Plain
+                ),
+                (
+                    PathBuf::from("log.txt"),
+                    42,
+                    mime::TEXT_PLAIN,
+                    EncodingType::Gzip
+                ),
+            ]
+        );
     }
 }
@@ -0,0 +1,186 @@
+use crate::prelude::*;
+use crate::report::ReportWriter;
+use crate::results::EncodingType;
+use mime::Mime;
+use std::borrow::Cow;
+use std::fmt::{self, Display};
+use std::path::Path;
+
+/// Object-safe counterpart of `ReportWriter`.
+///
+/// `ReportWriter`'s methods are generic over the path type, so `dyn ReportWriter` can't exist.
+/// This trait takes a plain `&Path` instead, and is implemented for every `ReportWriter`, which
+/// allows storing different writers behind a `Box<dyn DynReportWriter>`.
+pub trait DynReportWriter: Send + Sync + Display {
+    fn write_bytes_dyn(
+        &self,
+        path: &Path,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()>;
+    fn write_string_dyn(&self, path: &Path, s: Cow<str>, mime: &Mime) -> Fallible<()>;
+}
+
+impl<W: ReportWriter + Display> DynReportWriter for W {
+    fn write_bytes_dyn(
+        &self,
+        path: &Path,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        self.write_bytes(path, b, mime, encoding_type)
+    }
+
+    fn write_string_dyn(&self, path: &Path, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_string(path, s, mime)
+    }
+}
+
+/// Writer forwarding everything to multiple writers.
+///
+/// A failure in one of the writers doesn't prevent the others from being written to: all the
+/// errors are collected and reported together once every writer has been tried.
+pub struct MultiWriter {
+    writers: Vec<Box<dyn DynReportWriter>>,
+}
+
+impl MultiWriter {
+    pub fn new(writers: Vec<Box<dyn DynReportWriter>>) -> Self {
+        MultiWriter { writers }
+    }
+
+    fn for_each<F>(&self, path: &Path, f: F) -> Fallible<()>
+    where
+        F: Fn(&dyn DynReportWriter) -> Fallible<()>,
+    {
+        let errors = self
+            .writers
+            .iter()
+            .filter_map(|writer| {
+                f(writer.as_ref())
+                    .err()
+                    .map(|err| format!("{writer}: {err}"))
+            })
+            .collect::<Vec<_>>();
+
+        if errors.is_empty() {
+            Ok(())
+        } else {
+            bail!("failed to write {:?}: {}", path, errors.join("; "));
+        }
+    }
+}
+
+impl ReportWriter for MultiWriter {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let path = path.as_ref();
+        self.for_each(path, |writer| {
+            writer.write_bytes_dyn(path, b, mime, encoding_type)
+        })
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        let path = path.as_ref();
+        self.for_each(path, |writer| {
+            writer.write_string_dyn(path, Cow::Borrowed(&*s), mime)
+        })
+    }
+}
+
+impl Display for MultiWriter {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        let names = self
+            .writers
+            .iter()
+            .map(|writer| writer.to_string())
+            .collect::<Vec<_>>();
+        names.join(", ").fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{DynReportWriter, MultiWriter};
+    use crate::prelude::*;
+    use crate::report::{MemoryWriter, ReportWriter};
+    use crate::results::EncodingType;
+    use mime::Mime;
+    use std::borrow::Cow;
+    use std::fmt::{self, Display};
+    use std::path::Path;
+
+    struct FailingWriter;
+
+    impl ReportWriter for FailingWriter {
+        fn write_bytes<P: AsRef<Path>>(
+            &self,
+            _: P,
+            _: &[u8],
+            _: &Mime,
+            _: EncodingType,
+        ) -> Fallible<()> {
+            bail!("broken backend");
+        }
+
+        fn write_string<P: AsRef<Path>>(&self, _: P, _: Cow<str>, _: &Mime) -> Fallible<()> {
+            bail!("broken backend");
+        }
+    }
+
+    impl Display for FailingWriter {
+        fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+            "failing".fmt(f)
+        }
+    }
+
+    #[test]
+    fn test_multi_writer() {
+        let first = MemoryWriter::new();
+        let second = MemoryWriter::new();
+        let writers: Vec<Box<dyn DynReportWriter>> =
+            vec![Box::new(first.clone()), Box::new(second.clone())];
+        let writer = MultiWriter::new(writers);
+
+        writer
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        writer
+            .write_bytes("log.txt", b"log", &mime::TEXT_PLAIN, EncodingType::Gzip)
+            .unwrap();
+
+        assert_eq!(first.paths(), second.paths());
+        for path in first.paths() {
+            assert_eq!(first.get(&path), second.get(&path));
+        }
+        assert_eq!(first.len(), 2);
+    }
+
+    #[test]
+    fn test_multi_writer_errors() {
+        let first = MemoryWriter::new();
+        let second = MemoryWriter::new();
+        let writers: Vec<Box<dyn DynReportWriter>> = vec![
+            Box::new(first.clone()),
+            Box::new(FailingWriter),
+            Box::new(second.clone()),
+        ];
+        let writer = MultiWriter::new(writers);
+
+        let err = writer
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap_err();
+        assert!(err.to_string().contains("failing: broken backend"));
+
+        // The writers after the failing one are still written to
+        assert!(first.get("index.html").is_some());
+        assert!(second.get("index.html").is_some());
+    }
+}
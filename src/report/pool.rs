@@ -0,0 +1,187 @@
+use crate::prelude::*;
+use crate::report::ReportWriter;
+use crate::results::EncodingType;
+use crate::utils;
+use crossbeam_channel::Sender;
+use mime::Mime;
+use std::path::PathBuf;
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+
+struct Job {
+    index: usize,
+    path: PathBuf,
+    data: Vec<u8>,
+    mime: Mime,
+    encoding: EncodingType,
+}
+
+/// Queue of the objects written by the pool of `with_write_pool`.
+pub struct WriteQueue {
+    tx: Sender<Job>,
+    submitted: AtomicUsize,
+}
+
+impl WriteQueue {
+    /// Queue the object to be written by the pool, waiting for a worker to be free when every
+    /// worker is busy and the queue is full.
+    pub fn write_bytes<P: Into<PathBuf>>(
+        &self,
+        path: P,
+        data: Vec<u8>,
+        mime: &Mime,
+        encoding: EncodingType,
+    ) -> Fallible<()> {
+        let index = self.submitted.fetch_add(1, Ordering::SeqCst);
+        self.tx
+            .send(Job {
+                index,
+                path: path.into(),
+                data,
+                mime: mime.clone(),
+                encoding,
+            })
+            .map_err(|_| err_msg("the report writers stopped"))
+    }
+}
+
+/// Write the objects queued by `f` with `concurrency` writes in flight at most, instead of one
+/// at a time. All the writes are attempted even if some fail, and the error of the first queued
+/// write which failed is returned once they're all done.
+///
+/// Every queued object is written when this returns, so the files which must only appear once
+/// everything else is written, like indexes and progress markers, can be written afterwards.
+pub fn with_write_pool<W, F, T>(dest: &W, concurrency: usize, f: F) -> Fallible<T>
+where
+    W: ReportWriter,
+    F: FnOnce(&WriteQueue) -> Fallible<T>,
+{
+    let concurrency = concurrency.max(1);
+    let errors = Mutex::new(Vec::new());
+
+    let res = std::thread::scope(|s| {
+        let (tx, rx) = crossbeam_channel::bounded::<Job>(concurrency);
+        for _ in 0..concurrency {
+            let rx = rx.clone();
+            let errors = &errors;
+            s.spawn(move || {
+                while let Ok(job) = rx.recv() {
+                    let res = dest
+                        .write_bytes(&job.path, &job.data, &job.mime, job.encoding)
+                        .with_context(|_| format!("failed to write {}", job.path.display()));
+                    if let Err(err) = res {
+                        errors.lock().unwrap().push((job.index, err.into()));
+                    }
+                }
+            });
+        }
+
+        // Dropping the queue once `f` returns lets the workers exit after writing everything
+        let queue = WriteQueue {
+            tx,
+            submitted: AtomicUsize::new(0),
+        };
+        f(&queue)
+    });
+
+    let mut errors: Vec<(usize, failure::Error)> = errors.into_inner().unwrap();
+    errors.sort_by_key(|(index, _)| *index);
+    for (_, err) in &errors {
+        utils::report_failure(err);
+    }
+    let value = res?;
+    match errors.into_iter().next() {
+        Some((_, err)) => Err(err),
+        None => Ok(value),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::with_write_pool;
+    use crate::prelude::*;
+    use crate::report::ReportWriter;
+    use crate::results::EncodingType;
+    use mime::Mime;
+    use std::borrow::Cow;
+    use std::path::{Path, PathBuf};
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::Mutex;
+    use std::time::Duration;
+
+    #[derive(Default)]
+    struct SlowWriter {
+        in_flight: AtomicUsize,
+        max_in_flight: AtomicUsize,
+        written: Mutex<Vec<PathBuf>>,
+    }
+
+    impl ReportWriter for SlowWriter {
+        fn write_bytes<P: AsRef<Path>>(
+            &self,
+            path: P,
+            _b: &[u8],
+            _mime: &Mime,
+            _encoding_type: EncodingType,
+        ) -> Fallible<()> {
+            let in_flight = self.in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+            self.max_in_flight.fetch_max(in_flight, Ordering::SeqCst);
+            std::thread::sleep(Duration::from_millis(10));
+            self.in_flight.fetch_sub(1, Ordering::SeqCst);
+
+            if path.as_ref().starts_with("fail") {
+                bail!("refused");
+            }
+            self.written.lock().unwrap().push(path.as_ref().into());
+            Ok(())
+        }
+
+        fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+            self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+        }
+    }
+
+    #[test]
+    fn test_concurrency_bound() {
+        let writer = SlowWriter::default();
+        with_write_pool(&writer, 4, |queue| {
+            for i in 0..40 {
+                queue.write_bytes(
+                    format!("{i}.txt"),
+                    Vec::new(),
+                    &mime::TEXT_PLAIN,
+                    EncodingType::Plain,
+                )?;
+            }
+            Ok(())
+        })
+        .unwrap();
+
+        assert_eq!(writer.written.lock().unwrap().len(), 40);
+        let max_in_flight = writer.max_in_flight.load(Ordering::SeqCst);
+        assert!(max_in_flight > 1, "the writes weren't concurrent");
+        assert!(max_in_flight <= 4, "{max_in_flight} writes in flight");
+    }
+
+    #[test]
+    fn test_first_error() {
+        let writer = SlowWriter::default();
+        let err = with_write_pool(&writer, 4, |queue| {
+            for path in [
+                "a.txt",
+                "fail/first.txt",
+                "b.txt",
+                "fail/second.txt",
+                "c.txt",
+            ] {
+                queue.write_bytes(path, Vec::new(), &mime::TEXT_PLAIN, EncodingType::Plain)?;
+            }
+            Ok(())
+        })
+        .unwrap_err();
+
+        // The other objects are still written
+        assert_eq!(err.to_string(), "failed to write fail/first.txt");
+        assert_eq!(writer.written.lock().unwrap().len(), 3);
+    }
+}
@@ -0,0 +1,74 @@
+use crate::crates::Crate;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::results::ReadResults;
+use crate::toolchain::Toolchain;
+
+/// Prefix of the lines of the build logs recording the cargo commands run by the runner, as they
+/// would be typed in a shell.
+pub const COMMAND_LOG_PREFIX: &str = "running cargo command: ";
+
+/// One-line shell command reproducing a build from the crate's source, running the cargo
+/// commands recorded in its log one after the other. The commands carry the toolchain, the
+/// target and the flags and environment the build was run with, with the secrets redacted.
+/// Logs stored before the commands were recorded have none.
+pub(super) fn repro_command(log: &str) -> Option<String> {
+    let commands = log
+        .lines()
+        .filter_map(|line| {
+            let start = line.find(COMMAND_LOG_PREFIX)? + COMMAND_LOG_PREFIX.len();
+            Some(line[start..].trim_end())
+        })
+        .collect::<Vec<_>>();
+
+    if commands.is_empty() {
+        None
+    } else {
+        Some(commands.join(" && "))
+    }
+}
+
+/// Command reproducing the build of the crate with the toolchain, extracted from its log.
+pub(super) fn load_repro_command<DB: ReadResults>(
+    db: &DB,
+    ex: &Experiment,
+    toolchain: &Toolchain,
+    krate: &Crate,
+) -> Fallible<Option<String>> {
+    let Some(log) = db.load_log(ex, toolchain, krate)? else {
+        return Ok(None);
+    };
+    Ok(repro_command(&String::from_utf8_lossy(&log.to_plain()?)))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{repro_command, COMMAND_LOG_PREFIX};
+
+    #[test]
+    fn test_repro_command() {
+        let log = format!(
+            "[INFO] testing foo-1.0.0 against beta+target=x86_64-unknown-linux-musl for pr-1\n\
+             [INFO] {COMMAND_LOG_PREFIX}RUSTFLAGS='--cap-lints=forbid -Zfoo' \
+             RUSTDOCFLAGS=--cap-lints=forbid API_TOKEN='<redacted>' MY_VAR=1 cargo +beta build \
+             --frozen --message-format=json --target x86_64-unknown-linux-musl\n\
+             [INFO] [stderr]    Compiling foo v1.0.0\n\
+             [INFO] {COMMAND_LOG_PREFIX}RUSTFLAGS='--cap-lints=forbid -Zfoo' \
+             RUSTDOCFLAGS=--cap-lints=forbid API_TOKEN='<redacted>' MY_VAR=1 cargo +beta test \
+             --frozen --target x86_64-unknown-linux-musl\n\
+             [INFO] [stderr] error[E0308]: mismatched types\n"
+        );
+
+        assert_eq!(
+            repro_command(&log).unwrap(),
+            "RUSTFLAGS='--cap-lints=forbid -Zfoo' RUSTDOCFLAGS=--cap-lints=forbid \
+             API_TOKEN='<redacted>' MY_VAR=1 cargo +beta build --frozen --message-format=json \
+             --target x86_64-unknown-linux-musl && RUSTFLAGS='--cap-lints=forbid -Zfoo' \
+             RUSTDOCFLAGS=--cap-lints=forbid API_TOKEN='<redacted>' MY_VAR=1 cargo +beta test \
+             --frozen --target x86_64-unknown-linux-musl"
+        );
+
+        // Logs stored before the commands were recorded
+        assert_eq!(repro_command("[INFO] [stderr] error: oops\n"), None);
+    }
+}
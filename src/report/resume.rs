@@ -0,0 +1,238 @@
+use crate::prelude::*;
+use crate::report::ReportWriter;
+use crate::results::EncodingType;
+use mime::Mime;
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::fmt::{self, Display};
+use std::io::Read;
+use std::path::Path;
+use std::sync::Mutex;
+
+pub const PROGRESS_PATH: &str = ".crater-report-progress.json";
+
+/// Number of newly written keys after which the progress marker is saved again.
+const SAVE_EVERY: usize = 64;
+
+/// Keys written so far by a report generation, stored alongside the report.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportProgress {
+    pub completed: BTreeSet<String>,
+    /// Whether the report generation which wrote the marker completed successfully.
+    #[serde(default)]
+    pub finished: bool,
+}
+
+struct State {
+    progress: ReportProgress,
+    unsaved: usize,
+}
+
+/// Writer forwarding everything to another writer, skipping the keys a previous interrupted run
+/// already wrote.
+///
+/// The written keys are recorded in a progress marker through the wrapped writer, which is read
+/// back when the next run starts. Writers unable to read objects back always start from scratch.
+pub struct ResumeWriter<'a, W: ReportWriter> {
+    inner: &'a W,
+    state: Mutex<State>,
+}
+
+impl<'a, W: ReportWriter> ResumeWriter<'a, W> {
+    pub fn new(inner: &'a W) -> Fallible<Self> {
+        let progress = match inner.read_bytes(PROGRESS_PATH)? {
+            Some(data) => serde_json::from_slice::<ReportProgress>(&data)
+                .with_context(|_| format!("failed to parse {PROGRESS_PATH}"))?,
+            None => ReportProgress::default(),
+        };
+
+        let progress = if progress.finished {
+            ReportProgress::default()
+        } else {
+            if !progress.completed.is_empty() {
+                info!(
+                    "resuming the report generation, skipping {} already written files",
+                    progress.completed.len()
+                );
+            }
+            progress
+        };
+
+        Ok(ResumeWriter {
+            inner,
+            state: Mutex::new(State {
+                progress,
+                unsaved: 0,
+            }),
+        })
+    }
+
+    /// Mark the report generation as completed, so the next run starts from scratch.
+    pub fn finish(self) -> Fallible<()> {
+        let mut state = self.state.into_inner().unwrap();
+        state.progress.finished = true;
+        save(self.inner, &state.progress)
+    }
+
+    fn is_completed(&self, path: &Path) -> bool {
+        let completed = self
+            .state
+            .lock()
+            .unwrap()
+            .progress
+            .completed
+            .contains(&key(path));
+        if completed {
+            debug!("skipping {}, already written", path.display());
+        }
+        completed
+    }
+
+    fn record(&self, path: &Path) -> Fallible<()> {
+        let mut state = self.state.lock().unwrap();
+        state.progress.completed.insert(key(path));
+        state.unsaved += 1;
+        if state.unsaved >= SAVE_EVERY {
+            state.unsaved = 0;
+            save(self.inner, &state.progress)?;
+        }
+        Ok(())
+    }
+}
+
+fn key(path: &Path) -> String {
+    path.to_string_lossy().replace('\\', "/")
+}
+
+fn save<W: ReportWriter>(dest: &W, progress: &ReportProgress) -> Fallible<()> {
+    dest.write_string(
+        PROGRESS_PATH,
+        serde_json::to_string(progress)?.into(),
+        &mime::APPLICATION_JSON,
+    )
+}
+
+impl<W: ReportWriter> ReportWriter for ResumeWriter<'_, W> {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        if self.is_completed(path.as_ref()) {
+            return Ok(());
+        }
+        self.inner
+            .write_bytes(path.as_ref(), b, mime, encoding_type)?;
+        self.record(path.as_ref())
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        if self.is_completed(path.as_ref()) {
+            return Ok(());
+        }
+        self.inner.write_string(path.as_ref(), s, mime)?;
+        self.record(path.as_ref())
+    }
+
+    fn write_stream<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        reader: R,
+        len: Option<u64>,
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        if self.is_completed(path.as_ref()) {
+            return Ok(());
+        }
+        self.inner
+            .write_stream(path.as_ref(), reader, len, mime, encoding_type)?;
+        self.record(path.as_ref())
+    }
+
+    fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        self.inner.read_bytes(path)
+    }
+
+    fn object_url<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<String>> {
+        self.inner.object_url(path)
+    }
+}
+
+impl<W: ReportWriter + Display> Display for ResumeWriter<'_, W> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.inner.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{ReportProgress, ResumeWriter, PROGRESS_PATH};
+    use crate::report::{MemoryWriter, ReportWriter};
+    use std::collections::BTreeSet;
+    use std::path::PathBuf;
+
+    fn keys() -> Vec<String> {
+        (0..10)
+            .map(|i| format!("stable/reg/foo-{i}/log.txt"))
+            .collect()
+    }
+
+    #[test]
+    fn test_resume_writer_skips_completed_keys() {
+        let keys = keys();
+        let (done, remaining) = keys.split_at(keys.len() / 2);
+
+        let writer = MemoryWriter::new();
+        let marker = ReportProgress {
+            completed: done.iter().cloned().collect(),
+            finished: false,
+        };
+        writer
+            .write_string(
+                PROGRESS_PATH,
+                serde_json::to_string(&marker).unwrap().into(),
+                &mime::APPLICATION_JSON,
+            )
+            .unwrap();
+
+        let resume = ResumeWriter::new(&writer).unwrap();
+        for key in &keys {
+            resume
+                .write_string(key, "log".into(), &mime::TEXT_PLAIN_UTF_8)
+                .unwrap();
+        }
+        resume.finish().unwrap();
+
+        let mut expected = remaining.iter().map(PathBuf::from).collect::<Vec<_>>();
+        expected.push(PathBuf::from(PROGRESS_PATH));
+        expected.sort();
+        assert_eq!(writer.paths(), expected);
+
+        let marker: ReportProgress =
+            serde_json::from_slice(&writer.get(PROGRESS_PATH).unwrap().data).unwrap();
+        assert!(marker.finished);
+        assert_eq!(
+            marker.completed,
+            keys.iter().cloned().collect::<BTreeSet<_>>()
+        );
+    }
+
+    #[test]
+    fn test_resume_writer_restarts_after_finished_run() {
+        let writer = MemoryWriter::new();
+        let first = ResumeWriter::new(&writer).unwrap();
+        first
+            .write_string("index.html", "first".into(), &mime::TEXT_HTML)
+            .unwrap();
+        first.finish().unwrap();
+
+        let second = ResumeWriter::new(&writer).unwrap();
+        second
+            .write_string("index.html", "second".into(), &mime::TEXT_HTML)
+            .unwrap();
+        assert_eq!(writer.get("index.html").unwrap().data, b"second");
+    }
+}
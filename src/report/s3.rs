@@ -1,18 +1,260 @@
 use crate::prelude::*;
-use crate::report::ReportWriter;
+use crate::report::breaker::{CircuitBreaker, DEFAULT_COOLDOWN, DEFAULT_FAILURE_THRESHOLD};
+use crate::report::{
+    AbortSignal, AsyncReportWriter, ProgressCallback, ReportRuntime, UploadProgress, CHECK_CONTENT,
+    CHECK_PATH,
+};
 use crate::results::EncodingType;
+use aws_config::sts::AssumeRoleProvider;
+use aws_config::SdkConfig;
+use aws_sdk_s3::config::http::HttpResponse;
+use aws_sdk_s3::config::retry::RetryConfig;
+use aws_sdk_s3::config::BehaviorVersion;
+use aws_sdk_s3::error::{ProvideErrorMetadata, SdkError};
+use aws_sdk_s3::presigning::PresigningConfig;
+use aws_sdk_s3::types::{
+    ChecksumAlgorithm, Delete, ObjectCannedAcl, ObjectIdentifier,
+    ServerSideEncryption as AwsServerSideEncryption, StorageClass,
+};
 use aws_sdk_s3::Client as S3Client;
+use base64::Engine;
+use chrono::{DateTime, NaiveDate, TimeZone, Utc};
+use futures_util::future::Either;
+use futures_util::{Stream, StreamExt, TryStreamExt};
 use mime::Mime;
+use rand::Rng;
+use regex::Regex;
+use sha1::{Digest, Sha1};
 use std::borrow::Cow;
+use std::collections::{BTreeMap, HashMap};
 use std::fmt::{self, Display};
+use std::future::Future;
+use std::io::Read;
 use std::path::{Path, PathBuf};
 use std::str::FromStr;
-use url::{Host, Url};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Arc;
+use std::time::Duration;
+use url::{form_urlencoded, Host, Url};
+
+const DEFAULT_RETRIES: u32 = 4;
+const DEFAULT_RETRY_BASE_DELAY: Duration = Duration::from_millis(500);
+const DEFAULT_RETRY_MAX_DELAY: Duration = Duration::from_secs(30);
+const DEFAULT_UPLOAD_CONCURRENCY: usize = 8;
+const DEFAULT_REQUEST_TIMEOUT: Duration = Duration::from_secs(60);
+// Completing a multipart upload makes S3 assemble all the parts, which takes a while for big
+// objects.
+const DEFAULT_COMPLETE_TIMEOUT: Duration = Duration::from_secs(300);
+
+// S3 requires every part of a multipart upload except the last one to be at least 5 MB.
+const MIN_PART_SIZE: usize = 5 * 1024 * 1024;
+// S3 rejects multipart uploads with more parts than this.
+const MAX_PARTS: usize = 10_000;
+// Part sizes grown to fit within `MAX_PARTS` are rounded up to a multiple of this.
+const PART_SIZE_ALIGNMENT: usize = 1024 * 1024;
+
+// S3 rejects `DeleteObjects` requests with more keys than this.
+const MAX_DELETE_BATCH: usize = 1000;
+
+lazy_static! {
+    // Dates embedded in report keys, like the ones expanded from the `{date}` or
+    // `{yyyy}/{mm}/{dd}` placeholders
+    static ref KEY_DATE_RE: Regex = Regex::new(r"(\d{4})[-/](\d{2})[-/](\d{2})").unwrap();
+}
+
+// Custom metadata storing the hash of the uploaded content, as the ETag of multipart uploads
+// isn't the MD5 of the content.
+const CONTENT_HASH_METADATA: &str = "crater-content-hash";
+
+// Custom metadata tracing which experiment and crater revision uploaded each object.
+const EXPERIMENT_METADATA: &str = "experiment";
+const CRATER_VERSION_METADATA: &str = "crater-version";
+
+// S3 limits the user metadata of an object to 2KB, headers included, so the values are capped
+// well below that.
+const MAX_METADATA_VALUE_LEN: usize = 256;
 
 #[derive(Debug, thiserror::Error)]
 pub enum S3Error {
     #[error("bad S3 url: {0}")]
     BadUrl(String),
+    #[error("multipart chunk size of {0} bytes is below the S3 minimum of {MIN_PART_SIZE} bytes")]
+    ChunkTooSmall(usize),
+    #[error("unknown placeholder `{{{0}}}` in the S3 prefix")]
+    UnknownPlaceholder(String),
+    #[error("unclosed placeholder in the S3 prefix `{0}`")]
+    UnclosedPlaceholder(String),
+    #[error("no credentials to assume the role {0} with")]
+    NoBaseCredentials(String),
+    #[error(
+        "S3 unavailable after {0} consecutive failed requests, failing fast until it recovers"
+    )]
+    Unavailable(u32),
+}
+
+/// Expand the placeholders in an S3 prefix, so uploads can be organized by date and experiment.
+///
+/// The supported placeholders are `{experiment}`, `{date}` (as `yyyy-mm-dd`), `{yyyy}`, `{mm}`
+/// and `{dd}`, with the date taken from `now` in UTC.
+pub fn expand_prefix(template: &str, experiment: &str, now: DateTime<Utc>) -> Fallible<String> {
+    let mut expanded = String::new();
+    let mut rest = template;
+    while let Some(start) = rest.find('{') {
+        expanded.push_str(&rest[..start]);
+        let Some(len) = rest[start..].find('}') else {
+            return Err(S3Error::UnclosedPlaceholder(template.into()).into());
+        };
+        let placeholder = &rest[start + 1..start + len];
+        match placeholder {
+            "experiment" => expanded.push_str(experiment),
+            "date" => expanded.push_str(&now.format("%Y-%m-%d").to_string()),
+            "yyyy" => expanded.push_str(&now.format("%Y").to_string()),
+            "mm" => expanded.push_str(&now.format("%m").to_string()),
+            "dd" => expanded.push_str(&now.format("%d").to_string()),
+            other => return Err(S3Error::UnknownPlaceholder(other.into()).into()),
+        }
+        rest = &rest[start + len + 1..];
+    }
+    expanded.push_str(rest);
+    Ok(expanded)
+}
+
+/// Controls when and how `S3Writer` splits a body into a multipart upload.
+#[derive(Debug, Clone, Copy)]
+pub struct MultipartConfig {
+    /// Bodies of at least this many bytes are uploaded with a multipart upload.
+    pub threshold: usize,
+    /// Size of each uploaded part, except for the last one which may be smaller. Bodies too
+    /// large to fit in the maximum number of parts use bigger parts, see `part_size`.
+    pub chunk_size: usize,
+}
+
+impl MultipartConfig {
+    /// Size of the parts a body of `body_len` bytes is split into: `chunk_size`, unless that
+    /// would need more than the 10,000 parts S3 allows, in which case the parts are grown just
+    /// enough to fit.
+    pub fn part_size(&self, body_len: usize) -> usize {
+        if body_len.div_ceil(self.chunk_size) <= MAX_PARTS {
+            return self.chunk_size;
+        }
+        let needed = body_len.div_ceil(MAX_PARTS);
+        needed.div_ceil(PART_SIZE_ALIGNMENT) * PART_SIZE_ALIGNMENT
+    }
+}
+
+impl Default for MultipartConfig {
+    fn default() -> Self {
+        MultipartConfig {
+            threshold: 50 * 1024 * 1024,
+            chunk_size: 20 * 1024 * 1024,
+        }
+    }
+}
+
+/// Server-side encryption applied to every object written by `S3Writer`.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum ServerSideEncryption {
+    /// Use the default encryption configured on the bucket.
+    #[default]
+    None,
+    /// Encrypt with keys managed by S3.
+    Aes256,
+    /// Encrypt with the provided AWS KMS key.
+    AwsKms { key_id: String },
+}
+
+impl ServerSideEncryption {
+    fn algorithm(&self) -> Option<AwsServerSideEncryption> {
+        match self {
+            ServerSideEncryption::None => None,
+            ServerSideEncryption::Aes256 => Some(AwsServerSideEncryption::Aes256),
+            ServerSideEncryption::AwsKms { .. } => Some(AwsServerSideEncryption::AwsKms),
+        }
+    }
+
+    fn kms_key_id(&self) -> Option<String> {
+        match self {
+            ServerSideEncryption::AwsKms { key_id } => Some(key_id.clone()),
+            _ => None,
+        }
+    }
+}
+
+/// IAM role assumed by `S3Writer` to upload the objects, for buckets owned by another account.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct AssumeRole {
+    pub role_arn: String,
+    /// Required by the role's trust policy when it's assumed by a third party.
+    pub external_id: Option<String>,
+}
+
+impl AssumeRole {
+    /// Client with the same configuration as `client`, authenticating with temporary credentials
+    /// of the role obtained from STS with the credentials of `client`. They're requested again by
+    /// the client when they're about to expire.
+    fn client(&self, client: S3Client, runtime: &ReportRuntime) -> Fallible<S3Client> {
+        let config = client.config();
+        let base = config
+            .credentials_provider()
+            .ok_or_else(|| S3Error::NoBaseCredentials(self.role_arn.clone()))?;
+
+        // STS is reached with the same region and HTTP client as S3
+        let mut sts_config = SdkConfig::builder();
+        sts_config
+            .set_behavior_version(Some(BehaviorVersion::latest()))
+            .set_region(config.region().cloned())
+            .set_credentials_provider(Some(base))
+            .set_http_client(config.http_client())
+            .set_sleep_impl(config.sleep_impl())
+            .set_time_source(config.time_source());
+
+        let mut provider = AssumeRoleProvider::builder(self.role_arn.clone())
+            .session_name("crater")
+            .configure(&sts_config.build());
+        if let Some(external_id) = &self.external_id {
+            provider = provider.external_id(external_id.clone());
+        }
+        let provider = runtime.block_on(provider.build());
+
+        Ok(S3Client::from_conf(
+            config.to_builder().credentials_provider(provider).build(),
+        ))
+    }
+}
+
+/// Which objects written by `S3Writer` browsers are told to download instead of displaying.
+#[derive(Debug, Clone, Default, PartialEq, Eq)]
+pub enum Attachment {
+    /// Don't send a `Content-Disposition`, letting browsers decide.
+    #[default]
+    Never,
+    Always,
+    /// Only the objects with one of these mime types, ignoring their parameters.
+    ForMimes(Vec<Mime>),
+}
+
+impl Attachment {
+    /// `Content-Disposition` of the object at `path`, named after its file name.
+    fn content_disposition(&self, path: &Path, mime: &Mime) -> Option<String> {
+        let attachment = match self {
+            Attachment::Never => false,
+            Attachment::Always => true,
+            Attachment::ForMimes(mimes) => mimes
+                .iter()
+                .any(|candidate| candidate.essence_str() == mime.essence_str()),
+        };
+        if !attachment {
+            return None;
+        }
+
+        Some(match path.file_name() {
+            Some(name) => {
+                let name = name.to_string_lossy().replace(['"', '\\'], "_");
+                format!("attachment; filename=\"{name}\"")
+            }
+            None => "attachment".into(),
+        })
+    }
 }
 
 #[derive(Debug, Clone)]
@@ -20,6 +262,8 @@ pub enum S3Error {
 pub struct S3Prefix {
     pub bucket: String,
     pub prefix: PathBuf,
+    pub region: Option<String>,
+    pub endpoint: Option<String>,
 }
 
 impl FromStr for S3Prefix {
@@ -32,12 +276,21 @@ impl FromStr for S3Prefix {
             || parsed.username() != ""
             || parsed.password().is_some()
             || parsed.port().is_some()
-            || parsed.query().is_some()
             || parsed.fragment().is_some()
         {
             return Err(S3Error::BadUrl(url.into()).into());
         }
 
+        let mut region = None;
+        let mut endpoint = None;
+        for (key, value) in parsed.query_pairs() {
+            match &*key {
+                "region" => region = Some(value.into_owned()),
+                "endpoint" => endpoint = Some(value.into_owned()),
+                _ => return Err(S3Error::BadUrl(url.into()).into()),
+            }
+        }
+
         let bucket = if let Some(Host::Domain(host)) = parsed.host() {
             host.to_string()
         } else {
@@ -51,187 +304,2378 @@ impl FromStr for S3Prefix {
                 .get(1..)
                 .map(PathBuf::from)
                 .unwrap_or_default(),
+            region,
+            endpoint,
         })
     }
 }
 
+impl S3Prefix {
+    /// Client for the region and endpoint of the prefix, with the credentials found in the
+    /// environment.
+    pub fn client(&self, runtime: &ReportRuntime) -> S3Client {
+        let mut config = aws_config::from_env();
+        if let Some(region) = &self.region {
+            config = config.region(aws_sdk_s3::config::Region::new(region.clone()));
+        }
+        if let Some(endpoint) = &self.endpoint {
+            config = config.endpoint_url(endpoint.clone());
+        }
+        S3Client::new(&runtime.block_on(config.load()))
+    }
+}
+
+/// Client with the retries of the SDK disabled. Requests are retried by `retry_with_timeout`,
+/// retrying each attempt in the SDK too would make failures take far longer than its timeouts
+/// and backoff.
+fn without_sdk_retries(client: S3Client) -> S3Client {
+    S3Client::from_conf(
+        client
+            .config()
+            .to_builder()
+            .retry_config(RetryConfig::disabled())
+            .build(),
+    )
+}
+
 pub struct S3Writer {
     bucket: String,
     prefix: String,
     client: S3Client,
-    runtime: tokio::runtime::Runtime,
+    multipart: MultipartConfig,
+    encryption: ServerSideEncryption,
+    acl: Option<ObjectCannedAcl>,
+    storage_class: Option<StorageClass>,
+    retries: u32,
+    retry_base_delay: Duration,
+    retry_max_delay: Duration,
+    request_timeout: Duration,
+    complete_timeout: Duration,
+    upload_concurrency: usize,
+    checksums: bool,
+    progress: Option<ProgressCallback>,
+    skip_unchanged: bool,
+    attachment: Attachment,
+    presigning: Option<PresigningConfig>,
+    abort: Option<AbortSignal>,
+    breaker: CircuitBreaker,
+    experiment: Option<String>,
+    crater_version: String,
 }
 
 impl S3Writer {
-    pub fn create(client: S3Client, bucket: String, prefix: String) -> Fallible<S3Writer> {
+    /// Writer uploading the objects under `prefix` in `bucket`, unencrypted and with the default
+    /// ACL and storage class of the bucket.
+    pub fn create(
+        client: S3Client,
+        bucket: String,
+        prefix: String,
+        multipart: MultipartConfig,
+    ) -> Fallible<S3Writer> {
+        if multipart.chunk_size < MIN_PART_SIZE {
+            return Err(S3Error::ChunkTooSmall(multipart.chunk_size).into());
+        }
+
         Ok(S3Writer {
             bucket,
             prefix,
-            client,
-            runtime: tokio::runtime::Runtime::new()?,
+            client: without_sdk_retries(client),
+            multipart,
+            encryption: ServerSideEncryption::None,
+            acl: None,
+            storage_class: None,
+            retries: DEFAULT_RETRIES,
+            retry_base_delay: DEFAULT_RETRY_BASE_DELAY,
+            retry_max_delay: DEFAULT_RETRY_MAX_DELAY,
+            request_timeout: DEFAULT_REQUEST_TIMEOUT,
+            complete_timeout: DEFAULT_COMPLETE_TIMEOUT,
+            upload_concurrency: DEFAULT_UPLOAD_CONCURRENCY,
+            checksums: true,
+            progress: None,
+            skip_unchanged: false,
+            attachment: Attachment::Never,
+            presigning: None,
+            abort: None,
+            breaker: CircuitBreaker::new(DEFAULT_FAILURE_THRESHOLD, DEFAULT_COOLDOWN),
+            experiment: None,
+            crater_version: crate::GIT_REVISION.unwrap_or("unknown").into(),
         })
     }
-}
 
-impl ReportWriter for S3Writer {
-    fn write_bytes<P: AsRef<Path>>(
+    /// Set how the uploaded objects are encrypted at rest.
+    pub fn with_encryption(mut self, encryption: ServerSideEncryption) -> Self {
+        self.encryption = encryption;
+        self
+    }
+
+    /// Set the canned ACL of the uploaded objects.
+    pub fn with_acl(mut self, acl: ObjectCannedAcl) -> Self {
+        self.acl = Some(acl);
+        self
+    }
+
+    /// Set the storage class of the uploaded objects.
+    ///
+    /// Infrequent access classes like `STANDARD_IA` bill every object as if it were at least
+    /// 128KB large, which most pages and logs of a report aren't.
+    pub fn with_storage_class(mut self, storage_class: StorageClass) -> Self {
+        self.storage_class = Some(storage_class);
+        self
+    }
+
+    /// Upload the objects with the credentials of the assumed `role` instead of the ones the
+    /// client was configured with.
+    pub fn with_role(mut self, role: AssumeRole, runtime: &ReportRuntime) -> Fallible<Self> {
+        self.client = role.client(self.client, runtime)?;
+        Ok(self)
+    }
+
+    /// Expand the placeholders in the prefix passed to `create`, as documented in
+    /// `expand_prefix`. Unknown placeholders are rejected.
+    pub fn with_prefix_placeholders(
+        mut self,
+        experiment: &str,
+        now: DateTime<Utc>,
+    ) -> Fallible<Self> {
+        self.prefix = expand_prefix(&self.prefix, experiment, now)?;
+        Ok(self)
+    }
+
+    /// Set how many times a request failing with a transient error is retried, and the delay
+    /// before the first retry. The delay doubles on each following retry, up to the one set with
+    /// `with_max_retry_delay`.
+    pub fn with_retries(mut self, retries: u32, base_delay: Duration) -> Self {
+        self.retries = retries;
+        self.retry_base_delay = base_delay;
+        self
+    }
+
+    /// Set the longest delay between two retries of a request, 30 seconds by default.
+    pub fn with_max_retry_delay(mut self, max_delay: Duration) -> Self {
+        self.retry_max_delay = max_delay;
+        self
+    }
+
+    /// Set how long a single request may take before it's abandoned and retried, and the same
+    /// limit for the request completing a multipart upload.
+    pub fn with_timeouts(mut self, request: Duration, complete: Duration) -> Self {
+        self.request_timeout = request;
+        self.complete_timeout = complete;
+        self
+    }
+
+    /// Set how many parts of a multipart upload are uploaded at the same time.
+    pub fn with_upload_concurrency(mut self, concurrency: usize) -> Self {
+        self.upload_concurrency = concurrency.max(1);
+        self
+    }
+
+    /// Set whether a CRC32 checksum is sent along with each uploaded object and part, letting S3
+    /// reject bodies corrupted in transit.
+    pub fn with_checksums(mut self, checksums: bool) -> Self {
+        self.checksums = checksums;
+        self
+    }
+
+    /// Set a callback invoked after each uploaded object, or after each uploaded part of the
+    /// multipart uploads, with the number of bytes of the object uploaded so far.
+    pub fn with_progress<F>(mut self, callback: F) -> Self
+    where
+        F: Fn(UploadProgress) + Send + Sync + 'static,
+    {
+        self.progress = Some(Arc::new(callback));
+        self
+    }
+
+    /// Set whether objects whose stored content hash matches the one being written are skipped
+    /// instead of uploaded again. This costs an additional request for each object.
+    pub fn with_skip_unchanged(mut self, skip_unchanged: bool) -> Self {
+        self.skip_unchanged = skip_unchanged;
+        self
+    }
+
+    /// Set which objects are sent with a `Content-Disposition: attachment`, so that browsers
+    /// download them instead of trying to display them.
+    pub fn with_attachment(mut self, attachment: Attachment) -> Self {
+        self.attachment = attachment;
+        self
+    }
+
+    /// Link the report pages to presigned URLs of the logs, valid for `expires_in`, so they can
+    /// be read from private buckets.
+    ///
+    /// The URLs are signed locally while the report is generated, which costs no request even
+    /// for the largest reports. S3 limits their validity to 7 days though, after which the links
+    /// stop working until the report is generated again.
+    pub fn with_presigned_urls(mut self, expires_in: Duration) -> Fallible<Self> {
+        self.presigning = Some(PresigningConfig::expires_in(expires_in)?);
+        Ok(self)
+    }
+
+    /// Abort the multipart uploads in progress once `signal` is triggered, deleting the parts
+    /// already uploaded instead of completing them. No multipart upload is started afterwards.
+    pub fn with_abort_signal(mut self, signal: AbortSignal) -> Self {
+        self.abort = Some(signal);
+        self
+    }
+
+    /// Fail every request fast once `threshold` requests in a row failed with a transient error,
+    /// instead of retrying each of them while S3 is down. A single request is let through again
+    /// after `cooldown`, and the requests resume if it succeeds.
+    pub fn with_circuit_breaker(mut self, threshold: u32, cooldown: Duration) -> Self {
+        self.breaker = CircuitBreaker::new(threshold, cooldown);
+        self
+    }
+
+    /// Tag every uploaded object with the name of the experiment it belongs to, next to the
+    /// crater revision uploading it.
+    pub fn with_experiment(mut self, experiment: &str) -> Self {
+        self.experiment = Some(experiment.into());
+        self
+    }
+
+    /// Custom metadata attached to each uploaded object, with the content hash when it's used to
+    /// skip the unchanged objects.
+    fn object_metadata(&self, hash: Option<String>) -> HashMap<String, String> {
+        let mut metadata = HashMap::from([(
+            CRATER_VERSION_METADATA.into(),
+            metadata_value(&self.crater_version),
+        )]);
+        if let Some(experiment) = &self.experiment {
+            metadata.insert(EXPERIMENT_METADATA.into(), metadata_value(experiment));
+        }
+        if let Some(hash) = hash {
+            metadata.insert(CONTENT_HASH_METADATA.into(), hash);
+        }
+        metadata
+    }
+
+    fn is_aborted(&self) -> bool {
+        self.abort.as_ref().is_some_and(|abort| abort.is_aborted())
+    }
+
+    /// Hash of the object stored at `key` by a previous upload, if any.
+    async fn stored_hash(&self, key: &str) -> Option<String> {
+        let request = || {
+            self.client
+                .head_object()
+                .key(key)
+                .bucket(self.bucket.clone())
+                .send()
+        };
+        match self.retry(request).await {
+            Ok(head) => head.metadata()?.get(CONTENT_HASH_METADATA).cloned(),
+            // Missing objects, and errors, mean the object has to be uploaded
+            Err(_) => None,
+        }
+    }
+
+    fn report_progress(&self, path: &Path, written: usize, total: usize) {
+        if let Some(progress) = &self.progress {
+            progress(UploadProgress {
+                path: path.into(),
+                written,
+                total,
+            });
+        }
+    }
+
+    fn checksum_algorithm(&self) -> Option<ChecksumAlgorithm> {
+        self.checksums.then_some(ChecksumAlgorithm::Crc32)
+    }
+
+    fn checksum(&self, data: &[u8]) -> Option<String> {
+        self.checksums.then(|| {
+            base64::engine::general_purpose::STANDARD.encode(crc32fast::hash(data).to_be_bytes())
+        })
+    }
+
+    /// Upload the body with a multipart upload, made of the provided parts. The parts are only
+    /// pulled from the stream as the previous ones are uploaded. The upload is aborted if it
+    /// fails, so no part is left around.
+    async fn multipart_upload<S>(
         &self,
-        path: P,
-        body: &[u8],
+        path: &Path,
+        parts: S,
+        total: Option<usize>,
         mime: &Mime,
-        encoding_type: EncodingType,
-    ) -> Fallible<()> {
-        // At least 50 MB, then use a multipart upload...
-        if body.len() >= 50 * 1024 * 1024 {
-            let mut request = self
-                .client
+        content_encoding: Option<&str>,
+        content_disposition: Option<String>,
+        metadata: HashMap<String, String>,
+    ) -> Fallible<()>
+    where
+        S: Stream<Item = Fallible<bytes::Bytes>>,
+    {
+        if self.is_aborted() {
+            failure::bail!("Failed to upload to {:?}: the upload was aborted", path);
+        }
+
+        let request = || {
+            self.client
                 .create_multipart_upload()
-                .acl(aws_sdk_s3::types::ObjectCannedAcl::PublicRead)
-                .key(format!(
-                    "{}/{}",
-                    self.prefix,
-                    path.as_ref().to_str().unwrap()
-                ))
+                .set_checksum_algorithm(self.checksum_algorithm())
+                .set_metadata(Some(metadata.clone()))
+                .set_acl(self.acl.clone())
+                .set_storage_class(self.storage_class.clone())
+                .key(self.key(path))
                 .content_type(mime.to_string())
-                .bucket(self.bucket.clone());
-            match encoding_type {
-                EncodingType::Plain => {}
-                EncodingType::Gzip => {
-                    request = request.content_encoding("gzip");
+                .set_content_encoding(content_encoding.map(String::from))
+                .set_content_disposition(content_disposition.clone())
+                .set_server_side_encryption(self.encryption.algorithm())
+                .set_ssekms_key_id(self.encryption.kms_key_id())
+                .bucket(self.bucket.clone())
+                .send()
+        };
+        let upload = match self.retry(request).await {
+            Ok(u) => u,
+            Err(e) => {
+                failure::bail!("Failed to upload to {:?}: {}", path, describe_error(&e));
+            }
+        };
+
+        let upload_id = upload.upload_id().unwrap();
+        let key = upload.key().unwrap();
+        let uploaded = self.upload_parts(path, parts, total, key, upload_id);
+        let res = match &self.abort {
+            Some(abort) => {
+                // Requests stuck in flight are dropped as soon as the upload is aborted
+                let aborted = abort.aborted();
+                futures_util::pin_mut!(uploaded, aborted);
+                match futures_util::future::select(uploaded, aborted).await {
+                    Either::Left((res, _)) => res,
+                    Either::Right(((), _)) => Err(err_msg("the upload was aborted")),
                 }
             }
-            let upload = match self.runtime.block_on(request.send()) {
-                Ok(u) => u,
-                Err(e) => {
-                    failure::bail!("Failed to upload to {:?}: {:?}", path.as_ref(), e);
+            None => uploaded.await,
+        };
+        if let Err(e) = res {
+            // Don't leave the failed upload around, as its parts would be billed until a
+            // lifecycle rule cleans them up.
+            let request = self
+                .client
+                .abort_multipart_upload()
+                .upload_id(upload_id)
+                .key(key)
+                .bucket(self.bucket.clone())
+                .send();
+            if let Err(abort) = request.await {
+                warn!(
+                    "failed to abort the multipart upload of {:?}: {:?}",
+                    path, abort
+                );
+            }
+            failure::bail!("Failed to upload to {:?}: {:?}", path, e);
+        }
+
+        Ok(())
+    }
+
+    /// Upload all the parts of an already created multipart upload, and complete it. `total` is
+    /// the length of the whole body, when it's known.
+    async fn upload_parts<S>(
+        &self,
+        path: &Path,
+        parts: S,
+        total: Option<usize>,
+        key: &str,
+        upload_id: &str,
+    ) -> Fallible<()>
+    where
+        S: Stream<Item = Fallible<bytes::Bytes>>,
+    {
+        let written = &AtomicUsize::new(0);
+        let mut parts = parts
+            .zip(futures_util::stream::iter(1..))
+            .map(|(chunk, part)| {
+                async move {
+                    let chunk = chunk?;
+                    if self.is_aborted() {
+                        failure::bail!("the upload was aborted");
+                    }
+                    let len = chunk.len();
+                    let checksum = self.checksum(&chunk);
+                    let request = || {
+                        self.client
+                            .upload_part()
+                            .part_number(part)
+                            .set_checksum_algorithm(self.checksum_algorithm())
+                            .set_checksum_crc32(checksum.clone())
+                            .body(chunk.clone().into())
+                            .upload_id(upload_id)
+                            .key(key)
+                            .bucket(self.bucket.clone())
+                            .send()
+                    };
+                    let uploaded = self.retry(request).await?;
+                    // The parts are polled from the same task, so the callback is never called
+                    // concurrently and always sees a growing count
+                    let written = written.fetch_add(len, Ordering::SeqCst) + len;
+                    self.report_progress(path, written, total.unwrap_or(written));
+                    Ok::<_, failure::Error>(
+                        aws_sdk_s3::types::CompletedPart::builder()
+                            .e_tag(uploaded.e_tag.unwrap())
+                            .set_checksum_crc32(checksum)
+                            .part_number(part)
+                            .build(),
+                    )
                 }
+            })
+            .buffer_unordered(self.upload_concurrency)
+            .try_collect::<Vec<_>>()
+            .await?;
+        // Parts finish in any order, but S3 wants them sorted by part number
+        parts.sort_by_key(|p| p.part_number());
+
+        let parts = aws_sdk_s3::types::CompletedMultipartUpload::builder()
+            .set_parts(Some(parts))
+            .build();
+        let request = || {
+            self.client
+                .complete_multipart_upload()
+                .multipart_upload(parts.clone())
+                .upload_id(upload_id)
+                .key(key)
+                .bucket(self.bucket.clone())
+                .send()
+        };
+        self.retry_with_timeout(self.complete_timeout, request)
+            .await?;
+
+        Ok(())
+    }
+
+    fn key(&self, path: &Path) -> String {
+        format!("{}/{}", self.prefix, path.to_str().unwrap())
+    }
+
+    /// Delete the reports stored under the prefix which are older than `cutoff`, as selected by
+    /// `expired_report_keys`. With `dry_run` nothing is deleted, and the keys which would have
+    /// been are returned.
+    pub async fn prune(&self, cutoff: DateTime<Utc>, dry_run: bool) -> Fallible<Vec<String>> {
+        let list_prefix = listing_prefix(&self.prefix);
+        let mut objects = Vec::new();
+        let mut continuation_token = None;
+        loop {
+            let request = || {
+                self.client
+                    .list_objects_v2()
+                    .bucket(self.bucket.clone())
+                    .prefix(list_prefix.clone())
+                    .set_continuation_token(continuation_token.clone())
+                    .send()
+            };
+            let output = match self.retry(request).await {
+                Ok(output) => output,
+                Err(err) => failure::bail!(
+                    "can't list the objects of s3://{}/{}: {}",
+                    self.bucket,
+                    self.prefix,
+                    describe_error(&err)
+                ),
             };
 
-            let chunk_size = 20 * 1024 * 1024;
-            let mut part = 1;
-            let mut start = 0;
-            let mut parts = aws_sdk_s3::types::CompletedMultipartUpload::builder();
-            while start < body.len() {
-                let chunk = &body[start..std::cmp::min(start + chunk_size, body.len())];
-                let chunk = bytes::Bytes::copy_from_slice(chunk);
-
-                let request = self
-                    .client
-                    .upload_part()
-                    .part_number(part)
-                    .body(chunk.into())
-                    .upload_id(upload.upload_id().unwrap())
-                    .key(upload.key().unwrap())
-                    .bucket(self.bucket.clone());
-                match self.runtime.block_on(request.send()) {
-                    Ok(p) => {
-                        parts = parts.parts(
-                            aws_sdk_s3::types::CompletedPart::builder()
-                                .e_tag(p.e_tag.clone().unwrap())
-                                .part_number(part)
-                                .build(),
-                        )
-                    }
-                    Err(e) => {
-                        failure::bail!("Failed to upload to {:?}: {:?}", path.as_ref(), e);
-                    }
+            for object in output.contents() {
+                let (Some(key), Some(last_modified)) = (object.key(), object.last_modified())
+                else {
+                    continue;
                 };
+                objects.push(ReportObject {
+                    key: key.into(),
+                    last_modified: Utc
+                        .timestamp_opt(last_modified.secs(), 0)
+                        .single()
+                        .unwrap_or_else(Utc::now),
+                });
+            }
 
-                start += chunk_size;
-                part += 1;
+            continuation_token = output.next_continuation_token().map(String::from);
+            if !output.is_truncated().unwrap_or(false) || continuation_token.is_none() {
+                break;
             }
+        }
 
-            let request = self
-                .client
-                .complete_multipart_upload()
-                .multipart_upload(parts.build())
-                .upload_id(upload.upload_id().unwrap())
-                .key(upload.key().unwrap())
-                .bucket(self.bucket.clone());
-            match self.runtime.block_on(request.send()) {
-                Ok(_) => (),
-                Err(e) => {
-                    failure::bail!("Failed to upload to {:?}: {:?}", path.as_ref(), e);
-                }
-            };
+        let expired = expired_report_keys(&self.prefix, &objects, cutoff);
+        if dry_run {
+            return Ok(expired);
+        }
 
-            Ok(())
-        } else {
-            let mut request = self
-                .client
-                .put_object()
-                .body(aws_sdk_s3::primitives::ByteStream::from(
-                    bytes::Bytes::copy_from_slice(body),
+        for batch in expired.chunks(MAX_DELETE_BATCH) {
+            let delete = Delete::builder()
+                .set_objects(Some(
+                    batch
+                        .iter()
+                        .map(|key| ObjectIdentifier::builder().key(key).build())
+                        .collect::<Result<_, _>>()?,
                 ))
-                .acl(aws_sdk_s3::types::ObjectCannedAcl::PublicRead)
-                .key(format!(
-                    "{}/{}",
+                .quiet(true)
+                .build()?;
+            let request = || {
+                self.client
+                    .delete_objects()
+                    .bucket(self.bucket.clone())
+                    .delete(delete.clone())
+                    .send()
+            };
+            let output = match self.retry(request).await {
+                Ok(output) => output,
+                Err(err) => failure::bail!(
+                    "can't delete the objects of s3://{}/{}: {}",
+                    self.bucket,
                     self.prefix,
-                    path.as_ref().to_str().unwrap()
-                ))
-                .content_type(mime.to_string())
-                .bucket(self.bucket.clone());
-            match encoding_type {
-                EncodingType::Plain => {}
-                EncodingType::Gzip => {
-                    request = request.content_encoding("gzip");
-                }
+                    describe_error(&err)
+                ),
+            };
+            if let Some(error) = output.errors().first() {
+                failure::bail!(
+                    "failed to delete {} objects, including {}: {}",
+                    output.errors().len(),
+                    error.key().unwrap_or("?"),
+                    error.code().unwrap_or("unknown error")
+                );
             }
-            match self.runtime.block_on(request.send()) {
-                Ok(_) => Ok(()),
-                Err(e) => {
-                    failure::bail!("Failed to upload to {:?}: {:?}", path.as_ref(), e);
+            info!("deleted {} objects", batch.len());
+        }
+
+        Ok(expired)
+    }
+
+    /// Run the request built by `f`, retrying it with exponential backoff and jitter as long as
+    /// it fails with a transient error.
+    async fn retry<T, E, F, Fut>(&self, f: F) -> Result<T, SdkError<E, HttpResponse>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, HttpResponse>>>,
+        E: ProvideErrorMetadata,
+    {
+        self.retry_with_timeout(self.request_timeout, f).await
+    }
+
+    /// Delay before retrying a request for the `attempt`-th time, doubling on each attempt up
+    /// to the maximum delay. Half of it is random, so the requests failing together aren't all
+    /// retried at the same time.
+    fn retry_delay(&self, attempt: u32) -> Duration {
+        let delay = 2u32
+            .checked_pow(attempt)
+            .and_then(|factor| self.retry_base_delay.checked_mul(factor))
+            .map_or(self.retry_max_delay, |delay| {
+                delay.min(self.retry_max_delay)
+            });
+        let half = delay / 2;
+        half + rand::thread_rng().gen_range(Duration::ZERO..=delay - half)
+    }
+
+    /// Like `retry`, abandoning each attempt taking longer than `timeout`. Hung requests fail
+    /// with a timeout error, which is retried like the other transient errors.
+    ///
+    /// Every attempt goes through the circuit breaker, failing without being sent while it's
+    /// open.
+    async fn retry_with_timeout<T, E, F, Fut>(
+        &self,
+        timeout: Duration,
+        mut f: F,
+    ) -> Result<T, SdkError<E, HttpResponse>>
+    where
+        F: FnMut() -> Fut,
+        Fut: Future<Output = Result<T, SdkError<E, HttpResponse>>>,
+        E: ProvideErrorMetadata,
+    {
+        let mut attempt = 0;
+        loop {
+            if !self.breaker.allow() {
+                return Err(SdkError::construction_failure(S3Error::Unavailable(
+                    self.breaker.consecutive_failures(),
+                )));
+            }
+            let res = match tokio::time::timeout(timeout, f()).await {
+                Ok(res) => res,
+                Err(_) => Err(SdkError::timeout_error(format!(
+                    "the S3 request timed out after {timeout:?}"
+                ))),
+            };
+            // Requests refused by S3 still show it's reachable
+            match &res {
+                Err(err) if is_retryable(err) => self.breaker.record_failure(),
+                _ => self.breaker.record_success(),
+            }
+            match res {
+                Err(err) if attempt < self.retries && is_retryable(&err) => {
+                    let delay = self.retry_delay(attempt);
+                    warn!(
+                        "S3 request failed (attempt {}), retrying in {:?}: {:?}",
+                        attempt + 1,
+                        delay,
+                        err.code()
+                    );
+                    tokio::time::sleep(delay).await;
+                    attempt += 1;
                 }
+                res => return res,
             }
         }
     }
+}
 
-    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
-        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+/// Object stored under a prefix, as listed before pruning the old reports.
+#[derive(Debug, Clone)]
+struct ReportObject {
+    key: String,
+    last_modified: DateTime<Utc>,
+}
+
+/// Prefix of the keys stored under `prefix`, including the trailing separator so a prefix never
+/// matches the keys of a sibling sharing its name as a prefix.
+fn listing_prefix(prefix: &str) -> String {
+    let prefix = prefix.trim_matches('/');
+    if prefix.is_empty() {
+        String::new()
+    } else {
+        format!("{prefix}/")
     }
 }
 
-impl Display for S3Prefix {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        format_args!("s3://{}/{}", self.bucket, self.prefix.display()).fmt(f)
+/// Date embedded in the first path component of a key relative to the pruned prefix, along with
+/// where it ends in the key.
+fn embedded_date(key: &str) -> Option<(DateTime<Utc>, usize)> {
+    let captures = KEY_DATE_RE.captures(key)?;
+    let date = captures.get(0)?;
+    if date.start() > key.find('/').unwrap_or(key.len()) {
+        return None;
     }
+    let date = NaiveDate::from_ymd_opt(
+        captures[1].parse().ok()?,
+        captures[2].parse().ok()?,
+        captures[3].parse().ok()?,
+    )?;
+    Some((
+        Utc.from_utc_datetime(&date.and_hms_opt(0, 0, 0)?),
+        captures.get(0)?.end(),
+    ))
 }
 
-impl Display for S3Writer {
-    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        self.prefix.fmt(f)
+/// Keys of the reports stored under `prefix` which are older than `cutoff`.
+///
+/// Each report is the directory right below the prefix, or the one named after the date embedded
+/// in the keys (like `2024-01-31/` or `2024/01/31/`). Its age is that date if there's one, and
+/// the time its most recent object was written otherwise. Reports are only deleted as a whole,
+/// and keys outside the prefix are never selected.
+fn expired_report_keys(
+    prefix: &str,
+    objects: &[ReportObject],
+    cutoff: DateTime<Utc>,
+) -> Vec<String> {
+    let list_prefix = listing_prefix(prefix);
+    let mut reports: BTreeMap<&str, (DateTime<Utc>, Vec<&str>)> = BTreeMap::new();
+    for object in objects {
+        let Some(relative) = object.key.strip_prefix(&list_prefix) else {
+            continue;
+        };
+        if relative.is_empty() {
+            continue;
+        }
+
+        let (date, date_end) = match embedded_date(relative) {
+            Some((date, end)) => (date, end),
+            None => (object.last_modified, 0),
+        };
+        let report_end = relative[date_end..]
+            .find('/')
+            .map(|pos| date_end + pos)
+            .unwrap_or(relative.len());
+
+        let report = reports
+            .entry(&relative[..report_end])
+            .or_insert((date, Vec::new()));
+        report.0 = report.0.max(date);
+        report.1.push(&object.key);
     }
+
+    reports
+        .into_values()
+        .filter(|(date, _)| *date < cutoff)
+        .flat_map(|(_, keys)| keys.into_iter().map(String::from))
+        .collect()
 }
 
-#[cfg(test)]
-mod tests {
-    use super::S3Prefix;
-    use std::str::FromStr;
+/// Hash of the content of an object, along with the headers it's served with.
+fn content_hash(
+    body: &[u8],
+    mime: &Mime,
+    encoding_type: EncodingType,
+    content_disposition: Option<&str>,
+) -> String {
+    let mut hasher = Sha1::new();
+    hasher.update(body);
+    hasher.update(format!("\0{mime}\0{encoding_type}"));
+    // Only hashed when set, so the hashes of the objects uploaded before it existed still match
+    if let Some(content_disposition) = content_disposition {
+        hasher.update(format!("\0{content_disposition}"));
+    }
+    hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect()
+}
 
-    #[test]
-    fn test_parse_s3prefix() {
-        assert_eq!(
-            S3Prefix::from_str("s3://bucket-name").unwrap(),
-            S3Prefix {
-                bucket: "bucket-name".into(),
-                prefix: "".into(),
+/// Value of a custom metadata, sent as a header: anything but printable ASCII is replaced, and
+/// long values are cut.
+fn metadata_value(value: &str) -> String {
+    value
+        .chars()
+        .map(|c| {
+            if c.is_ascii_graphic() || c == ' ' {
+                c
+            } else {
+                '_'
             }
-        );
-        assert_eq!(
-            S3Prefix::from_str("s3://bucket-name/path/prefix").unwrap(),
-            S3Prefix {
-                bucket: "bucket-name".into(),
-                prefix: "path/prefix".into(),
+        })
+        .take(MAX_METADATA_VALUE_LEN)
+        .collect()
+}
+
+/// Whether a failed request is worth retrying: timeouts, dropped connections, server errors and
+/// throttling are, while client errors like `AccessDenied` will fail again the same way.
+fn is_retryable<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> bool {
+    match err {
+        SdkError::TimeoutError(_) | SdkError::DispatchFailure(_) | SdkError::ResponseError(_) => {
+            true
+        }
+        SdkError::ServiceError(ctx) => {
+            let status = ctx.raw().status().as_u16();
+            status >= 500
+                || status == 429
+                || matches!(
+                    ctx.err().code(),
+                    Some("SlowDown" | "Throttling" | "ThrottlingException" | "RequestTimeout")
+                )
+        }
+        _ => false,
+    }
+}
+
+/// `Content-Encoding` header of the objects with the encoding, shared by all the writers.
+pub(super) fn content_encoding(encoding_type: EncodingType) -> Option<&'static str> {
+    match encoding_type {
+        EncodingType::Plain => None,
+        EncodingType::Gzip => Some("gzip"),
+        EncodingType::Zstd => Some("zstd"),
+        EncodingType::Brotli => Some("br"),
+    }
+}
+
+/// Read the next `size` bytes of `reader`, or fewer once the end of the body is reached.
+fn read_part<R: Read>(reader: &mut R, size: usize) -> Fallible<bytes::Bytes> {
+    let mut part = Vec::with_capacity(size);
+    reader.take(size as u64).read_to_end(&mut part)?;
+    Ok(part.into())
+}
+
+/// Explanation of why a request failed, for the errors a misconfigured destination causes.
+fn describe_error<E: ProvideErrorMetadata>(err: &SdkError<E, HttpResponse>) -> String {
+    // HEAD requests have no body, so only their status code is available
+    let status = err.raw_response().map(|res| res.status().as_u16());
+    match (err.code(), status) {
+        (Some("AccessDenied" | "AllAccessDisabled"), _) | (None, Some(403)) => {
+            "access denied, check the credentials and the permissions of the bucket".into()
+        }
+        (Some("InvalidAccessKeyId" | "SignatureDoesNotMatch" | "ExpiredToken"), _) => {
+            "the credentials are invalid".into()
+        }
+        (Some("NoSuchBucket"), _) | (None, Some(404)) => "the bucket doesn't exist".into(),
+        (Some("PermanentRedirect"), _) | (None, Some(301)) => {
+            "the bucket is in another region".into()
+        }
+        (Some(code), _) => format!("the request failed with {code}"),
+        (None, _) if matches!(err, SdkError::TimeoutError(_)) => "the request timed out".into(),
+        // Requests which weren't sent, like the ones failed fast by the circuit breaker
+        (None, _) if matches!(err, SdkError::ConstructionFailure(_)) => {
+            match std::error::Error::source(err) {
+                Some(source) => source.to_string(),
+                None => format!("the request failed: {err:?}"),
             }
-        );
+        }
+        (None, _) => format!("the request failed: {err:?}"),
+    }
+}
 
-        for bad in &[
-            "https://example.com",
-            "s3://user:pass@bucket/path/prefix",
-            "s3://bucket:80",
-            "s3://bucket/path/prefix?query#fragment",
-        ] {
-            assert!(S3Prefix::from_str(bad).is_err(), "valid bad url: {bad}");
+impl AsyncReportWriter for S3Writer {
+    async fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        body: &[u8],
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let content_encoding = content_encoding(encoding_type);
+        let content_disposition = self.attachment.content_disposition(path.as_ref(), mime);
+
+        let hash = self
+            .skip_unchanged
+            .then(|| content_hash(body, mime, encoding_type, content_disposition.as_deref()));
+        if let Some(hash) = &hash {
+            if self.stored_hash(&self.key(path.as_ref())).await.as_ref() == Some(hash) {
+                debug!("skipping the upload of unchanged {:?}", path.as_ref());
+                self.report_progress(path.as_ref(), body.len(), body.len());
+                return Ok(());
+            }
         }
+        let metadata = self.object_metadata(hash);
+
+        // Large bodies are split up and sent with a multipart upload...
+        if body.len() >= self.multipart.threshold {
+            let part_size = self.multipart.part_size(body.len());
+            let parts = futures_util::stream::iter(
+                body.chunks(part_size)
+                    .map(|chunk| Ok(bytes::Bytes::copy_from_slice(chunk))),
+            );
+            self.multipart_upload(
+                path.as_ref(),
+                parts,
+                Some(body.len()),
+                mime,
+                content_encoding,
+                content_disposition,
+                metadata,
+            )
+            .await
+        } else {
+            let checksum = self.checksum(body);
+            let len = body.len();
+            let body = bytes::Bytes::copy_from_slice(body);
+            let request = || {
+                self.client
+                    .put_object()
+                    .set_checksum_algorithm(self.checksum_algorithm())
+                    .set_checksum_crc32(checksum.clone())
+                    .body(aws_sdk_s3::primitives::ByteStream::from(body.clone()))
+                    .set_metadata(Some(metadata.clone()))
+                    .set_acl(self.acl.clone())
+                    .set_storage_class(self.storage_class.clone())
+                    .key(self.key(path.as_ref()))
+                    .content_type(mime.to_string())
+                    .set_content_encoding(content_encoding.map(String::from))
+                    .set_content_disposition(content_disposition.clone())
+                    .set_server_side_encryption(self.encryption.algorithm())
+                    .set_ssekms_key_id(self.encryption.kms_key_id())
+                    .bucket(self.bucket.clone())
+                    .send()
+            };
+            match self.retry(request).await {
+                Ok(_) => {
+                    self.report_progress(path.as_ref(), len, len);
+                    Ok(())
+                }
+                Err(e) => {
+                    failure::bail!(
+                        "Failed to upload to {:?}: {}",
+                        path.as_ref(),
+                        describe_error(&e)
+                    );
+                }
+            }
+        }
+    }
+
+    async fn write_string<P: AsRef<Path>>(
+        &self,
+        path: P,
+        s: Cow<'_, str>,
+        mime: &Mime,
+    ) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+            .await
+    }
+
+    /// Bodies larger than a part are sent with a multipart upload, reading each part from
+    /// `reader` only once there's room to upload it. Streams of unknown length use parts of
+    /// `chunk_size` bytes, so they can't be larger than `MAX_PARTS` of them. The unchanged
+    /// objects aren't skipped for streamed bodies, as that would require reading them twice.
+    async fn write_stream<P: AsRef<Path>, R: Read>(
+        &self,
+        path: P,
+        mut reader: R,
+        len: Option<u64>,
+        mime: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let part_size = match len {
+            Some(len) if (len as usize) < self.multipart.threshold => {
+                let mut body = Vec::with_capacity(len as usize);
+                reader.read_to_end(&mut body)?;
+                return self.write_bytes(path, &body, mime, encoding_type).await;
+            }
+            Some(len) => self.multipart.part_size(len as usize),
+            None => self.multipart.chunk_size,
+        };
+
+        // Bodies fitting in a single part are uploaded like any other
+        let first = read_part(&mut reader, part_size)?;
+        if first.len() < part_size {
+            return self.write_bytes(path, &first, mime, encoding_type).await;
+        }
+
+        let rest = std::iter::from_fn(move || match read_part(&mut reader, part_size) {
+            Ok(part) if part.is_empty() => None,
+            res => Some(res),
+        });
+        let parts = futures_util::stream::iter(std::iter::once(Ok(first)).chain(rest));
+        self.multipart_upload(
+            path.as_ref(),
+            parts,
+            len.map(|len| len as usize),
+            mime,
+            content_encoding(encoding_type),
+            self.attachment.content_disposition(path.as_ref(), mime),
+            self.object_metadata(None),
+        )
+        .await
+    }
+
+    async fn read_bytes<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<Vec<u8>>> {
+        let request = || {
+            self.client
+                .get_object()
+                .key(self.key(path.as_ref()))
+                .bucket(self.bucket.clone())
+                .send()
+        };
+        match self.retry(request).await {
+            Ok(object) => Ok(Some(object.body.collect().await?.into_bytes().to_vec())),
+            Err(err)
+                if err
+                    .as_service_error()
+                    .map(|err| err.is_no_such_key())
+                    .unwrap_or(false) =>
+            {
+                Ok(None)
+            }
+            Err(e) => failure::bail!("Failed to read {:?}: {:?}", path.as_ref(), e),
+        }
+    }
+
+    async fn object_url<P: AsRef<Path>>(&self, path: P) -> Fallible<Option<String>> {
+        let Some(presigning) = &self.presigning else {
+            return Ok(None);
+        };
+        // Signed locally with the credentials of the client, without sending any request
+        let request = self
+            .client
+            .get_object()
+            .key(self.key(path.as_ref()))
+            .bucket(self.bucket.clone())
+            .presigned(presigning.clone())
+            .await?;
+        Ok(Some(request.uri().to_string()))
+    }
+
+    async fn check_writable(&self) -> Fallible<()> {
+        let request = || self.client.head_bucket().bucket(self.bucket.clone()).send();
+        if let Err(err) = self.retry(request).await {
+            failure::bail!(
+                "can't access the bucket {}: {}",
+                self.bucket,
+                describe_error(&err)
+            );
+        }
+
+        // Written with the same settings as the report, as the bucket policy might require them
+        let key = self.key(Path::new(CHECK_PATH));
+        let request = || {
+            self.client
+                .put_object()
+                .body(aws_sdk_s3::primitives::ByteStream::from_static(
+                    CHECK_CONTENT.as_bytes(),
+                ))
+                .set_metadata(Some(self.object_metadata(None)))
+                .set_acl(self.acl.clone())
+                .set_storage_class(self.storage_class.clone())
+                .key(key.clone())
+                .content_type(mime::TEXT_PLAIN_UTF_8.to_string())
+                .set_server_side_encryption(self.encryption.algorithm())
+                .set_ssekms_key_id(self.encryption.kms_key_id())
+                .bucket(self.bucket.clone())
+                .send()
+        };
+        if let Err(err) = self.retry(request).await {
+            failure::bail!(
+                "can't write to s3://{}/{}: {}",
+                self.bucket,
+                self.prefix,
+                describe_error(&err)
+            );
+        }
+
+        // Permission to delete objects isn't needed to upload reports
+        let request = || {
+            self.client
+                .delete_object()
+                .key(key.clone())
+                .bucket(self.bucket.clone())
+                .send()
+        };
+        if let Err(err) = self.retry(request).await {
+            warn!("failed to delete {}: {}", key, describe_error(&err));
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for S3Prefix {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        format_args!("s3://{}/{}", self.bucket, self.prefix.display()).fmt(f)?;
+
+        let mut query = form_urlencoded::Serializer::new(String::new());
+        if let Some(region) = &self.region {
+            query.append_pair("region", region);
+        }
+        if let Some(endpoint) = &self.endpoint {
+            query.append_pair("endpoint", endpoint);
+        }
+        let query = query.finish();
+        if !query.is_empty() {
+            format_args!("?{query}").fmt(f)?;
+        }
+
+        Ok(())
+    }
+}
+
+impl Display for S3Writer {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.prefix.fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        describe_error, expand_prefix, expired_report_keys, AssumeRole, Attachment,
+        MultipartConfig, ReportObject, S3Prefix, S3Writer, ServerSideEncryption, MAX_PARTS,
+    };
+    use crate::report::{
+        AbortSignal, AsyncReportWriter, BlockingWriter, ReportRuntime, ReportWriter, UploadProgress,
+    };
+    use crate::results::EncodingType;
+    use aws_sdk_s3::config::http::HttpResponse;
+    use aws_sdk_s3::config::retry::RetryConfig;
+    use aws_sdk_s3::config::{BehaviorVersion, Credentials, Region};
+    use aws_sdk_s3::error::SdkError;
+    use aws_sdk_s3::operation::put_object::PutObjectError;
+    use aws_sdk_s3::primitives::SdkBody;
+    use aws_sdk_s3::types::{ObjectCannedAcl, StorageClass};
+    use aws_smithy_runtime::client::http::test_util::infallible_client_fn;
+    use base64::Engine;
+    use chrono::{TimeZone, Utc};
+    use std::collections::{BTreeMap, HashMap, VecDeque};
+    use std::io::{self, Cursor, Read};
+    use std::str::FromStr;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::sync::{Arc, Mutex};
+    use std::time::{Duration, Instant};
+    use url::form_urlencoded;
+
+    const MB: usize = 1024 * 1024;
+
+    fn sync(writer: S3Writer) -> BlockingWriter<S3Writer> {
+        BlockingWriter::new(writer, ReportRuntime::new().unwrap())
+    }
+
+    /// Fake S3 endpoint recording the method and query string of each request it receives. It
+    /// answers with the queued statuses first (200 meaning success), and with successful
+    /// responses afterwards.
+    #[derive(Clone, Default)]
+    struct MockS3 {
+        requests: Arc<Mutex<Vec<String>>>,
+        headers: Arc<Mutex<Vec<http::HeaderMap>>>,
+        failures: Arc<Mutex<VecDeque<u16>>>,
+        completed_upload: Arc<Mutex<Option<String>>>,
+        stored_hash: Arc<Mutex<Option<String>>>,
+        parts: Arc<Mutex<BTreeMap<u32, Vec<u8>>>>,
+        /// Bodies of the requests sent to STS, answered with temporary credentials.
+        sts_requests: Arc<Mutex<Vec<String>>>,
+    }
+
+    impl MockS3 {
+        fn failing_with(statuses: &[u16]) -> Self {
+            let mock = MockS3::default();
+            mock.failures.lock().unwrap().extend(statuses);
+            mock
+        }
+
+        fn client(&self) -> aws_sdk_s3::Client {
+            let requests = self.requests.clone();
+            let headers = self.headers.clone();
+            let failures = self.failures.clone();
+            let completed_upload = self.completed_upload.clone();
+            let stored_hash = self.stored_hash.clone();
+            let parts = self.parts.clone();
+            let sts_requests = self.sts_requests.clone();
+            let http_client = infallible_client_fn(move |req: http::Request<SdkBody>| {
+                if req
+                    .uri()
+                    .host()
+                    .is_some_and(|host| host.starts_with("sts."))
+                {
+                    let sent = req.body().bytes().unwrap_or_default();
+                    sts_requests
+                        .lock()
+                        .unwrap()
+                        .push(String::from_utf8_lossy(sent).into_owned());
+                    return http::Response::builder()
+                        .status(200)
+                        .body(SdkBody::from(
+                            "<AssumeRoleResponse><AssumeRoleResult><Credentials>\
+                                <AccessKeyId>assumed-access</AccessKeyId>\
+                                <SecretAccessKey>assumed-secret</SecretAccessKey>\
+                                <SessionToken>assumed-token</SessionToken>\
+                                <Expiration>2100-01-01T00:00:00Z</Expiration>\
+                             </Credentials><AssumedRoleUser>\
+                                <AssumedRoleId>role-id:crater</AssumedRoleId>\
+                                <Arn>arn:aws:sts::123456789012:assumed-role/reports/crater</Arn>\
+                             </AssumedRoleUser></AssumeRoleResult></AssumeRoleResponse>",
+                        ))
+                        .unwrap();
+                }
+
+                let query = req.uri().query().unwrap_or("").to_string();
+                requests
+                    .lock()
+                    .unwrap()
+                    .push(format!("{} {}", req.method(), query));
+                headers.lock().unwrap().push(req.headers().clone());
+
+                let status = failures.lock().unwrap().pop_front().unwrap_or(200);
+                if status != 200 {
+                    let code = match status {
+                        403 => "AccessDenied",
+                        503 => "SlowDown",
+                        _ => "InternalError",
+                    };
+                    return http::Response::builder()
+                        .status(status)
+                        .body(SdkBody::from(format!(
+                            "<Error><Code>{code}</Code><Message>mock</Message></Error>"
+                        )))
+                        .unwrap();
+                }
+
+                if req.method() == http::Method::HEAD && req.uri().path() == "/" {
+                    // HeadBucket
+                    return http::Response::builder()
+                        .status(200)
+                        .body(SdkBody::empty())
+                        .unwrap();
+                }
+                if req.method() == http::Method::HEAD {
+                    let response = http::Response::builder();
+                    return match &*stored_hash.lock().unwrap() {
+                        Some(hash) => response
+                            .status(200)
+                            .header("x-amz-meta-crater-content-hash", hash)
+                            .body(SdkBody::empty()),
+                        None => response.status(404).body(SdkBody::empty()),
+                    }
+                    .unwrap();
+                }
+
+                let body = if req.method() == http::Method::POST && query.contains("uploads") {
+                    "<InitiateMultipartUploadResult>\
+                        <Bucket>bucket</Bucket><Key>prefix/key</Key><UploadId>upload</UploadId>\
+                     </InitiateMultipartUploadResult>"
+                } else if req.method() == http::Method::POST {
+                    let sent = req.body().bytes().unwrap_or_default();
+                    *completed_upload.lock().unwrap() =
+                        Some(String::from_utf8_lossy(sent).into_owned());
+                    "<CompleteMultipartUploadResult></CompleteMultipartUploadResult>"
+                } else {
+                    ""
+                };
+                let part = query
+                    .split('&')
+                    .find_map(|param| param.strip_prefix("partNumber="))
+                    .unwrap_or("0");
+                if req.method() == http::Method::PUT && part != "0" {
+                    let sent = req.body().bytes().unwrap_or_default();
+                    parts
+                        .lock()
+                        .unwrap()
+                        .insert(part.parse().unwrap(), sent.to_vec());
+                }
+                http::Response::builder()
+                    .status(200)
+                    .header("ETag", format!("\"etag-{part}\""))
+                    .body(SdkBody::from(body))
+                    .unwrap()
+            });
+
+            let config = aws_sdk_s3::Config::builder()
+                .behavior_version(BehaviorVersion::latest())
+                .region(Region::new("us-east-1"))
+                .credentials_provider(Credentials::new("access", "secret", None, None, "test"))
+                .retry_config(RetryConfig::disabled())
+                .http_client(http_client)
+                .build();
+            aws_sdk_s3::Client::from_conf(config)
+        }
+
+        fn writer(&self) -> S3Writer {
+            S3Writer::create(
+                self.client(),
+                "bucket".into(),
+                "prefix".into(),
+                MultipartConfig::default(),
+            )
+            .unwrap()
+            .with_retries(3, Duration::from_millis(1))
+        }
+
+        fn header(&self, request: usize, name: &str) -> Option<String> {
+            self.headers.lock().unwrap()[request]
+                .get(name)
+                .map(|value| value.to_str().unwrap().to_string())
+        }
+
+        fn requests(&self) -> Vec<String> {
+            self.requests.lock().unwrap().clone()
+        }
+
+        fn clear(&self) {
+            self.requests.lock().unwrap().clear();
+            self.headers.lock().unwrap().clear();
+        }
+    }
+
+    #[test]
+    fn test_multipart_threshold() {
+        let mock = MockS3::default();
+        let writer = S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig {
+                threshold: 6 * MB,
+                chunk_size: 5 * MB,
+            },
+        )
+        .unwrap();
+        let writer = sync(writer);
+
+        // Below the threshold a single request is enough
+        writer
+            .write_bytes("small", &[0; MB], &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert_eq!(mock.requests(), vec!["PUT x-id=PutObject"]);
+        mock.requests.lock().unwrap().clear();
+
+        // Just above the threshold the body is split into two parts
+        writer
+            .write_bytes(
+                "large",
+                &vec![0; 6 * MB + 1],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .unwrap();
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 4);
+        assert!(requests[0].starts_with("POST uploads"));
+        assert!(requests[1..3].iter().all(|r| r.starts_with("PUT ")));
+        assert!(requests[1..3].iter().any(|r| r.contains("partNumber=1")));
+        assert!(requests[1..3].iter().any(|r| r.contains("partNumber=2")));
+        assert!(requests[3].starts_with("POST ") && requests[3].contains("uploadId=upload"));
+    }
+
+    #[test]
+    fn test_concurrent_parts_are_completed_in_order() {
+        let mock = MockS3::default();
+        let writer = mock.writer().with_upload_concurrency(4);
+        let writer = sync(writer);
+        writer
+            .write_bytes(
+                "large",
+                &vec![0; 100 * MB],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        // 100 MB in 20 MB chunks, plus the create and complete requests
+        assert_eq!(mock.requests().len(), 7);
+        let completed = mock.completed_upload.lock().unwrap().clone().unwrap();
+        let positions = (1..=5)
+            .map(|part| completed.find(&format!("<PartNumber>{part}</PartNumber>")))
+            .collect::<Option<Vec<_>>>()
+            .unwrap();
+        assert!(positions.windows(2).all(|w| w[0] < w[1]));
+    }
+
+    #[test]
+    fn test_upload_progress() {
+        let mock = MockS3::default();
+        let events = Arc::new(Mutex::new(Vec::<UploadProgress>::new()));
+        let recorded = events.clone();
+        let writer = mock
+            .writer()
+            .with_upload_concurrency(4)
+            .with_progress(move |progress| recorded.lock().unwrap().push(progress));
+        let writer = sync(writer);
+
+        writer
+            .write_bytes("small", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert_eq!(
+            *events.lock().unwrap(),
+            vec![UploadProgress {
+                path: "small".into(),
+                written: 4,
+                total: 4,
+            }]
+        );
+        events.lock().unwrap().clear();
+
+        let total = 90 * MB + 1;
+        writer
+            .write_bytes(
+                "large",
+                &vec![0; total],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        // One event for each of the 5 parts, with the count growing by the size of a part
+        let events = events.lock().unwrap();
+        assert_eq!(events.len(), 5);
+        assert!(events.windows(2).all(|w| w[0].written < w[1].written));
+        assert!(events
+            .iter()
+            .all(|e| e.path.to_str() == Some("large") && e.total == total));
+        let sizes = std::iter::once(events[0].written)
+            .chain(events.windows(2).map(|w| w[1].written - w[0].written))
+            .collect::<Vec<_>>();
+        assert_eq!(sizes.iter().sum::<usize>(), total);
+        assert_eq!(events.last().unwrap().written, total);
+    }
+
+    #[test]
+    fn test_skip_unchanged() {
+        const HASH: &str = "x-amz-meta-crater-content-hash";
+
+        let mock = MockS3::default();
+        let writer = S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig {
+                threshold: 6 * MB,
+                chunk_size: 5 * MB,
+            },
+        )
+        .unwrap()
+        .with_skip_unchanged(true);
+        let writer = sync(writer);
+        let only_head = |mock: &MockS3| {
+            let requests = mock.requests();
+            requests.len() == 1 && requests[0].starts_with("HEAD")
+        };
+
+        // Nothing is stored yet, so the object is uploaded along with its hash
+        writer
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 2);
+        assert!(requests[0].starts_with("HEAD"));
+        assert_eq!(requests[1], "PUT x-id=PutObject");
+        *mock.stored_hash.lock().unwrap() = mock.header(1, HASH);
+        mock.clear();
+
+        // Once stored, writing the same content again is skipped
+        writer
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert!(only_head(&mock));
+        mock.clear();
+
+        // A different content or encoding is uploaded
+        writer
+            .write_bytes("file", b"other", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        writer
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Gzip)
+            .unwrap();
+        assert_eq!(mock.requests().len(), 4);
+        mock.clear();
+
+        // Multipart uploads store the hash when created, and are skipped the same way
+        let large = vec![0; 6 * MB];
+        *mock.stored_hash.lock().unwrap() = None;
+        writer
+            .write_bytes("large", &large, &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert!(mock.requests()[1].starts_with("POST uploads"));
+        *mock.stored_hash.lock().unwrap() = mock.header(1, HASH);
+        mock.clear();
+
+        writer
+            .write_bytes("large", &large, &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert!(only_head(&mock));
+    }
+
+    #[test]
+    fn test_write_stream() {
+        let mock = MockS3::default();
+        let writer = S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig {
+                threshold: 6 * MB,
+                chunk_size: 5 * MB,
+            },
+        )
+        .unwrap()
+        .with_checksums(false);
+        let writer = sync(writer);
+
+        // A large stream of unknown length is sent in parts of the chunk size
+        let body = (0..12 * MB + 345)
+            .map(|i| (i % 251) as u8)
+            .collect::<Vec<_>>();
+        writer
+            .write_stream(
+                "large",
+                body.as_slice(),
+                None,
+                &mime::APPLICATION_OCTET_STREAM,
+                EncodingType::Plain,
+            )
+            .unwrap();
+        let parts = mock.parts.lock().unwrap().clone();
+        assert_eq!(parts.keys().copied().collect::<Vec<_>>(), vec![1, 2, 3]);
+        assert_eq!(parts[&1].len(), 5 * MB);
+        assert_eq!(parts.into_values().flatten().collect::<Vec<_>>(), body);
+        assert!(mock
+            .completed_upload
+            .lock()
+            .unwrap()
+            .as_ref()
+            .unwrap()
+            .contains("<PartNumber>3</PartNumber>"));
+
+        // Small streams are sent with a single request
+        mock.clear();
+        writer
+            .write_stream(
+                "small",
+                &b"hello"[..],
+                Some(5),
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .unwrap();
+        assert_eq!(mock.requests(), vec!["PUT x-id=PutObject"]);
+    }
+
+    #[test]
+    fn test_abort_failed_multipart_upload() {
+        // Creating the upload and the first part succeed, the second part is rejected
+        let mock = MockS3::failing_with(&[200, 200, 403]);
+        let writer = mock.writer().with_upload_concurrency(1);
+        let writer = sync(writer);
+        assert!(writer
+            .write_bytes(
+                "large",
+                &vec![0; 100 * MB],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .is_err());
+
+        let requests = mock.requests();
+        let aborts = requests
+            .iter()
+            .filter(|r| r.starts_with("DELETE ") && r.contains("uploadId=upload"))
+            .count();
+        assert_eq!(aborts, 1);
+        assert!(!requests
+            .iter()
+            .any(|r| r.starts_with("POST ") && r.contains("uploadId=upload")));
+    }
+
+    /// Reader triggering the abort signal once more than `after` bytes were read from it.
+    struct AbortingReader {
+        inner: Cursor<Vec<u8>>,
+        signal: AbortSignal,
+        after: u64,
+    }
+
+    impl Read for AbortingReader {
+        fn read(&mut self, buf: &mut [u8]) -> io::Result<usize> {
+            let read = self.inner.read(buf)?;
+            if self.inner.position() > self.after {
+                self.signal.abort();
+            }
+            Ok(read)
+        }
+    }
+
+    #[test]
+    fn test_abort_signal() {
+        let mock = MockS3::default();
+        let signal = AbortSignal::default();
+        let writer = mock
+            .writer()
+            .with_upload_concurrency(1)
+            .with_abort_signal(signal.clone());
+        let writer = sync(writer);
+
+        // The upload is aborted while the second part is read
+        let reader = AbortingReader {
+            inner: Cursor::new(vec![0; 100 * MB]),
+            signal: signal.clone(),
+            after: 30 * MB as u64,
+        };
+        assert!(writer
+            .write_stream(
+                "large",
+                reader,
+                Some(100 * MB as u64),
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .is_err());
+        let requests = mock.requests();
+        let aborts = requests
+            .iter()
+            .filter(|r| r.starts_with("DELETE ") && r.contains("uploadId=upload"))
+            .count();
+        assert_eq!(aborts, 1);
+        assert!(!requests
+            .iter()
+            .any(|r| r.starts_with("POST ") && r.contains("uploadId=upload")));
+
+        // No multipart upload is started once aborted
+        mock.clear();
+        assert!(writer
+            .write_bytes(
+                "large",
+                &vec![0; 100 * MB],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .is_err());
+        assert!(mock.requests().is_empty());
+    }
+
+    #[test]
+    fn test_check_writable() {
+        let mock = MockS3::default();
+        sync(mock.writer()).check_writable().unwrap();
+
+        let requests = mock.requests();
+        assert_eq!(requests.len(), 3);
+        assert!(requests[0].starts_with("HEAD "));
+        assert_eq!(requests[1], "PUT x-id=PutObject");
+        assert_eq!(requests[2], "DELETE x-id=DeleteObject");
+    }
+
+    #[test]
+    fn test_check_writable_access_denied() {
+        // The bucket exists, but writing to it is denied
+        let mock = MockS3::failing_with(&[200, 403]);
+        let err = sync(mock.writer()).check_writable().unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "can't write to s3://bucket/prefix: access denied, check the credentials and the \
+             permissions of the bucket"
+        );
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_retry_transient_errors() {
+        let mock = MockS3::failing_with(&[500, 503]);
+        sync(mock.writer())
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert_eq!(mock.requests().len(), 3);
+
+        // Give up once the retries are exhausted
+        let mock = MockS3::failing_with(&[500, 500, 500, 500]);
+        assert!(sync(mock.writer())
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .is_err());
+        assert_eq!(mock.requests().len(), 4);
+    }
+
+    #[test]
+    fn test_retry_delay_cap() {
+        let writer = MockS3::default()
+            .writer()
+            .with_retries(100, Duration::from_secs(1))
+            .with_max_retry_delay(Duration::from_secs(10));
+
+        let first = writer.retry_delay(0);
+        assert!(first >= Duration::from_millis(500) && first <= Duration::from_secs(1));
+        let third = writer.retry_delay(2);
+        assert!(third >= Duration::from_secs(2) && third <= Duration::from_secs(4));
+
+        // Later attempts wait at most the maximum delay, even past the overflow of the backoff
+        for attempt in [4, 31, 32, 99] {
+            let delay = writer.retry_delay(attempt);
+            assert!(delay >= Duration::from_secs(5), "{delay:?}");
+            assert!(delay <= Duration::from_secs(10), "{delay:?}");
+        }
+    }
+
+    #[test]
+    fn test_no_sdk_retries() {
+        let mock = MockS3::failing_with(&[503, 503, 503]);
+        let config = mock
+            .client()
+            .config()
+            .to_builder()
+            .retry_config(
+                RetryConfig::standard()
+                    .with_max_attempts(3)
+                    .with_initial_backoff(Duration::from_millis(1)),
+            )
+            .build();
+        let writer = S3Writer::create(
+            aws_sdk_s3::Client::from_conf(config),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig::default(),
+        )
+        .unwrap()
+        .with_retries(0, Duration::from_millis(1));
+
+        // Only the writer's own retries resend the request
+        assert!(sync(writer)
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .is_err());
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_retry_hung_requests() {
+        let writer = MockS3::default()
+            .writer()
+            .with_timeouts(Duration::from_millis(50), Duration::from_millis(50));
+        let attempts = AtomicUsize::new(0);
+
+        // The request never completes, so every attempt times out and is retried
+        let start = Instant::now();
+        let res = ReportRuntime::new().unwrap().block_on(writer.retry(|| {
+            attempts.fetch_add(1, Ordering::SeqCst);
+            std::future::pending::<Result<(), SdkError<PutObjectError, HttpResponse>>>()
+        }));
+        let err = res.unwrap_err();
+        assert!(matches!(err, SdkError::TimeoutError(_)));
+        assert_eq!(describe_error(&err), "the request timed out");
+        assert_eq!(attempts.load(Ordering::SeqCst), 4);
+        assert!(start.elapsed() < Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_no_retry_on_client_errors() {
+        let mock = MockS3::failing_with(&[403]);
+        let writer = mock.writer().with_retries(3, Duration::from_secs(60));
+        let writer = sync(writer);
+
+        let start = Instant::now();
+        assert!(writer
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .is_err());
+        assert!(start.elapsed() < Duration::from_secs(30));
+        assert_eq!(mock.requests().len(), 1);
+    }
+
+    #[test]
+    fn test_circuit_breaker() {
+        let mock = MockS3::failing_with(&[503, 503, 503]);
+        let writer = mock
+            .writer()
+            .with_retries(0, Duration::from_millis(1))
+            .with_circuit_breaker(3, Duration::from_millis(200));
+        let writer = sync(writer);
+        let write =
+            |name: &str| writer.write_bytes(name, b"data", &mime::TEXT_PLAIN, EncodingType::Plain);
+
+        // Sustained failures trip the breaker...
+        for name in ["a", "b", "c"] {
+            assert!(write(name).is_err());
+        }
+        assert_eq!(mock.requests().len(), 3);
+
+        // ...failing the following writes without sending them
+        let err = write("d").unwrap_err();
+        assert_eq!(
+            err.to_string(),
+            "Failed to upload to \"d\": S3 unavailable after 3 consecutive failed requests, \
+             failing fast until it recovers"
+        );
+        assert_eq!(mock.requests().len(), 3);
+
+        // Once the cooldown elapsed a probe is sent, and its success closes the breaker
+        std::thread::sleep(Duration::from_millis(250));
+        write("e").unwrap();
+        write("f").unwrap();
+        assert_eq!(mock.requests().len(), 5);
+    }
+
+    #[test]
+    fn test_server_side_encryption() {
+        const SSE: &str = "x-amz-server-side-encryption";
+        const SSE_KEY: &str = "x-amz-server-side-encryption-aws-kms-key-id";
+
+        let cases = [
+            (ServerSideEncryption::None, None, None),
+            (ServerSideEncryption::Aes256, Some("AES256"), None),
+            (
+                ServerSideEncryption::AwsKms {
+                    key_id: "my-key".into(),
+                },
+                Some("aws:kms"),
+                Some("my-key"),
+            ),
+        ];
+        for (encryption, algorithm, key) in cases {
+            let mock = MockS3::default();
+            let writer = S3Writer::create(
+                mock.client(),
+                "bucket".into(),
+                "prefix".into(),
+                MultipartConfig {
+                    threshold: 6 * MB,
+                    chunk_size: 5 * MB,
+                },
+            )
+            .unwrap()
+            .with_encryption(encryption);
+            let writer = sync(writer);
+            writer
+                .write_bytes("small", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+                .unwrap();
+            writer
+                .write_bytes(
+                    "large",
+                    &vec![0; 6 * MB],
+                    &mime::TEXT_PLAIN,
+                    EncodingType::Plain,
+                )
+                .unwrap();
+
+            // Both the single upload and the multipart upload initialization are encrypted
+            for request in [0, 1] {
+                assert_eq!(mock.header(request, SSE).as_deref(), algorithm);
+                assert_eq!(mock.header(request, SSE_KEY).as_deref(), key);
+            }
+        }
+    }
+
+    #[test]
+    fn test_assume_role() {
+        const AUTHORIZATION: &str = "authorization";
+        const SECURITY_TOKEN: &str = "x-amz-security-token";
+
+        let upload = |role: Option<AssumeRole>| {
+            let mock = MockS3::default();
+            let mut writer = S3Writer::create(
+                mock.client(),
+                "bucket".into(),
+                "prefix".into(),
+                MultipartConfig::default(),
+            )
+            .unwrap();
+            if let Some(role) = role {
+                writer = writer
+                    .with_role(role, &ReportRuntime::new().unwrap())
+                    .unwrap();
+            }
+            sync(writer)
+                .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+                .unwrap();
+            mock
+        };
+
+        // Without a role the credentials of the client are used as is
+        let mock = upload(None);
+        assert!(mock.sts_requests.lock().unwrap().is_empty());
+        let authorization = mock.header(0, AUTHORIZATION).unwrap();
+        assert!(authorization.contains("Credential=access/"));
+        assert_eq!(mock.header(0, SECURITY_TOKEN), None);
+
+        // With one the role is assumed first, and its credentials sign the upload
+        let mock = upload(Some(AssumeRole {
+            role_arn: "arn:aws:iam::123456789012:role/reports".into(),
+            external_id: Some("external".into()),
+        }));
+        let sts_requests = mock.sts_requests.lock().unwrap().clone();
+        assert_eq!(sts_requests.len(), 1);
+        let params = form_urlencoded::parse(sts_requests[0].as_bytes())
+            .into_owned()
+            .collect::<HashMap<_, _>>();
+        assert_eq!(params["Action"], "AssumeRole");
+        assert_eq!(params["RoleArn"], "arn:aws:iam::123456789012:role/reports");
+        assert_eq!(params["ExternalId"], "external");
+        let authorization = mock.header(0, AUTHORIZATION).unwrap();
+        assert!(authorization.contains("Credential=assumed-access/"));
+        assert_eq!(
+            mock.header(0, SECURITY_TOKEN).as_deref(),
+            Some("assumed-token")
+        );
+    }
+
+    #[test]
+    fn test_storage_class() {
+        const STORAGE_CLASS: &str = "x-amz-storage-class";
+
+        let cases = [
+            (None, None),
+            (Some(StorageClass::StandardIa), Some("STANDARD_IA")),
+            (
+                Some(StorageClass::IntelligentTiering),
+                Some("INTELLIGENT_TIERING"),
+            ),
+        ];
+        for (storage_class, header) in cases {
+            let mock = MockS3::default();
+            let mut writer = S3Writer::create(
+                mock.client(),
+                "bucket".into(),
+                "prefix".into(),
+                MultipartConfig {
+                    threshold: 6 * MB,
+                    chunk_size: 5 * MB,
+                },
+            )
+            .unwrap();
+            if let Some(storage_class) = storage_class {
+                writer = writer.with_storage_class(storage_class);
+            }
+            let writer = sync(writer);
+            writer
+                .write_bytes("small", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+                .unwrap();
+            writer
+                .write_bytes(
+                    "large",
+                    &vec![0; 6 * MB],
+                    &mime::TEXT_PLAIN,
+                    EncodingType::Plain,
+                )
+                .unwrap();
+
+            // Both the single upload and the multipart upload initialization set the class
+            for request in [0, 1] {
+                assert_eq!(mock.header(request, STORAGE_CLASS).as_deref(), header);
+            }
+        }
+    }
+
+    #[test]
+    fn test_object_metadata() {
+        const EXPERIMENT: &str = "x-amz-meta-experiment";
+        const VERSION: &str = "x-amz-meta-crater-version";
+
+        let mock = MockS3::default();
+        let writer = S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig {
+                threshold: 6 * MB,
+                chunk_size: 5 * MB,
+            },
+        )
+        .unwrap()
+        .with_experiment("pr-123");
+        let version = crate::GIT_REVISION.unwrap_or("unknown");
+        assert_eq!(
+            writer.object_metadata(None),
+            HashMap::from([
+                ("experiment".to_string(), "pr-123".to_string()),
+                ("crater-version".to_string(), version.to_string()),
+            ])
+        );
+
+        let writer = sync(writer);
+        writer
+            .write_bytes("small", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        writer
+            .write_bytes(
+                "large",
+                &vec![0; 6 * MB],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .unwrap();
+
+        // Both the single upload and the multipart upload initialization are tagged
+        let requests = mock.requests();
+        assert_eq!(requests[0], "PUT x-id=PutObject");
+        assert!(requests[1].starts_with("POST uploads"));
+        for request in [0, 1] {
+            assert_eq!(mock.header(request, EXPERIMENT).as_deref(), Some("pr-123"));
+            assert_eq!(mock.header(request, VERSION).as_deref(), Some(version));
+        }
+
+        // Values are kept short and sendable as headers
+        let writer = MockS3::default()
+            .writer()
+            .with_experiment(&format!("pr-\u{e9}{}", "x".repeat(1000)));
+        let experiment = &writer.object_metadata(None)["experiment"];
+        assert_eq!(experiment.len(), 256);
+        assert!(experiment.starts_with("pr-_xxx"));
+    }
+
+    #[test]
+    fn test_content_disposition() {
+        const DISPOSITION: &str = "content-disposition";
+
+        let cases = [
+            (Attachment::Never, None, None),
+            (
+                Attachment::ForMimes(vec![mime::TEXT_PLAIN]),
+                Some("attachment; filename=\"log.txt\""),
+                None,
+            ),
+            (
+                Attachment::Always,
+                Some("attachment; filename=\"log.txt\""),
+                Some("attachment; filename=\"index.html\""),
+            ),
+        ];
+        for (attachment, log, html) in cases {
+            let mock = MockS3::default();
+            let writer = S3Writer::create(
+                mock.client(),
+                "bucket".into(),
+                "prefix".into(),
+                MultipartConfig {
+                    threshold: 6 * MB,
+                    chunk_size: 5 * MB,
+                },
+            )
+            .unwrap()
+            .with_attachment(attachment);
+            let writer = sync(writer);
+            writer
+                .write_bytes(
+                    "stable/reg/foo-1.0.0/log.txt",
+                    b"data",
+                    &mime::TEXT_PLAIN_UTF_8,
+                    EncodingType::Gzip,
+                )
+                .unwrap();
+            writer
+                .write_bytes(
+                    "beta/reg/foo-1.0.0/log.txt",
+                    &vec![0; 6 * MB],
+                    &mime::TEXT_PLAIN_UTF_8,
+                    EncodingType::Plain,
+                )
+                .unwrap();
+            writer
+                .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+                .unwrap();
+
+            // Both the single upload and the multipart upload initialization carry it
+            let requests = mock.requests();
+            assert_eq!(mock.header(0, DISPOSITION).as_deref(), log);
+            assert_eq!(mock.header(1, DISPOSITION).as_deref(), log);
+            assert_eq!(
+                mock.header(requests.len() - 1, DISPOSITION).as_deref(),
+                html
+            );
+        }
+    }
+
+    #[test]
+    fn test_content_encoding() {
+        let mock = MockS3::default();
+        let writer = sync(mock.writer());
+        let cases = [
+            (EncodingType::Plain, None),
+            (EncodingType::Gzip, Some("gzip")),
+            (EncodingType::Zstd, Some("zstd")),
+            (EncodingType::Brotli, Some("br")),
+        ];
+        for (request, (encoding, header)) in cases.into_iter().enumerate() {
+            writer
+                .write_bytes("index.html", b"data", &mime::TEXT_HTML, encoding)
+                .unwrap();
+            assert_eq!(mock.header(request, "content-encoding").as_deref(), header);
+        }
+    }
+
+    #[test]
+    fn test_presigned_urls() {
+        let mock = MockS3::default();
+        assert_eq!(sync(mock.writer()).object_url("index.html").unwrap(), None);
+
+        let writer = mock
+            .writer()
+            .with_presigned_urls(Duration::from_secs(3600))
+            .unwrap();
+        let url = sync(writer)
+            .object_url("stable/reg/foo-1.0.0/log.txt")
+            .unwrap()
+            .unwrap();
+        let url = url::Url::parse(&url).unwrap();
+        assert!(url.path().ends_with("/prefix/stable/reg/foo-1.0.0/log.txt"));
+        let query = url.query_pairs().into_owned().collect::<HashMap<_, _>>();
+        assert_eq!(query["X-Amz-Algorithm"], "AWS4-HMAC-SHA256");
+        assert_eq!(query["X-Amz-Expires"], "3600");
+        assert!(query["X-Amz-Credential"].starts_with("access/"));
+        assert_eq!(query["X-Amz-Signature"].len(), 64);
+
+        // Presigning happens locally
+        assert!(mock.requests().is_empty());
+
+        // S3 doesn't accept presigned URLs valid for more than a week
+        assert!(mock
+            .writer()
+            .with_presigned_urls(Duration::from_secs(8 * 24 * 3600))
+            .is_err());
+    }
+
+    #[test]
+    fn test_object_acl() {
+        let cases = [
+            (None, None),
+            (Some(ObjectCannedAcl::Private), Some("private")),
+        ];
+        for (acl, expected) in cases {
+            let mock = MockS3::default();
+            let mut writer = S3Writer::create(
+                mock.client(),
+                "bucket".into(),
+                "prefix".into(),
+                MultipartConfig {
+                    threshold: 6 * MB,
+                    chunk_size: 5 * MB,
+                },
+            )
+            .unwrap();
+            if let Some(acl) = acl {
+                writer = writer.with_acl(acl);
+            }
+            let writer = sync(writer);
+            writer
+                .write_bytes("small", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+                .unwrap();
+            writer
+                .write_bytes(
+                    "large",
+                    &vec![0; 6 * MB],
+                    &mime::TEXT_PLAIN,
+                    EncodingType::Plain,
+                )
+                .unwrap();
+
+            for request in [0, 1] {
+                assert_eq!(mock.header(request, "x-amz-acl").as_deref(), expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_checksums() {
+        const CHECKSUM: &str = "x-amz-checksum-crc32";
+        // CRC32 of "data", base64 encoded
+        let expected =
+            base64::engine::general_purpose::STANDARD.encode(0xadf3f363u32.to_be_bytes());
+
+        let mock = MockS3::default();
+        sync(mock.writer())
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert_eq!(mock.header(0, CHECKSUM), Some(expected));
+
+        let mock = MockS3::default();
+        sync(mock.writer().with_checksums(false))
+            .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+            .unwrap();
+        assert_eq!(mock.header(0, CHECKSUM), None);
+
+        // Every part of a multipart upload carries its own checksum
+        let mock = MockS3::default();
+        sync(mock.writer())
+            .write_bytes(
+                "large",
+                &vec![0; 60 * MB],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .unwrap();
+        let requests = mock.requests();
+        for (i, request) in requests.iter().enumerate() {
+            if request.contains("partNumber=") {
+                assert!(mock.header(i, CHECKSUM).is_some());
+            }
+        }
+        let completed = mock.completed_upload.lock().unwrap().clone().unwrap();
+        assert_eq!(completed.matches("<ChecksumCRC32>").count(), 3);
+    }
+
+    #[test]
+    fn test_shared_runtime() {
+        let runtime = ReportRuntime::new().unwrap();
+        let mock = MockS3::default();
+        let writers = ["first", "second"].map(|prefix| {
+            let writer = S3Writer::create(
+                mock.client(),
+                "bucket".into(),
+                prefix.into(),
+                MultipartConfig::default(),
+            )
+            .unwrap();
+            BlockingWriter::new(writer, runtime.clone())
+        });
+
+        for writer in &writers {
+            writer
+                .write_bytes("file", b"data", &mime::TEXT_PLAIN, EncodingType::Plain)
+                .unwrap();
+        }
+        assert_eq!(mock.requests().len(), 2);
+    }
+
+    #[test]
+    fn test_async_writer() {
+        let mock = MockS3::default();
+        let writer = mock.writer();
+        ReportRuntime::new().unwrap().block_on(async {
+            AsyncReportWriter::write_string(
+                &writer,
+                "index.html",
+                "<html>".into(),
+                &mime::TEXT_HTML,
+            )
+            .await
+            .unwrap();
+            AsyncReportWriter::write_bytes(
+                &writer,
+                "large",
+                &vec![0; 60 * MB],
+                &mime::TEXT_PLAIN,
+                EncodingType::Plain,
+            )
+            .await
+            .unwrap();
+        });
+        // One put, then the create, 3 parts and the complete of the multipart upload
+        assert_eq!(mock.requests().len(), 6);
+    }
+
+    #[test]
+    fn test_multipart_part_size() {
+        const GB: usize = 1024 * MB;
+
+        let config = MultipartConfig {
+            threshold: 6 * MB,
+            chunk_size: 5 * MB,
+        };
+
+        // Moderately large bodies keep the configured part size
+        assert_eq!(config.part_size(60 * MB), 5 * MB);
+        assert_eq!(config.part_size(5 * MB * MAX_PARTS), 5 * MB);
+
+        // Huge bodies get bigger parts so they fit in the maximum number of parts
+        for len in [5 * MB * MAX_PARTS + 1, 1024 * GB] {
+            let part_size = config.part_size(len);
+            assert!(part_size >= config.chunk_size);
+            assert!(len.div_ceil(part_size) <= MAX_PARTS);
+        }
+    }
+
+    #[test]
+    fn test_multipart_chunk_size_validation() {
+        let mock = MockS3::default();
+        assert!(S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig {
+                threshold: 6 * MB,
+                chunk_size: 4 * MB,
+            }
+        )
+        .is_err());
+        assert!(S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "prefix".into(),
+            MultipartConfig::default()
+        )
+        .is_ok());
+    }
+
+    #[test]
+    fn test_expand_prefix() {
+        let now = Utc.with_ymd_and_hms(2024, 3, 7, 23, 59, 0).unwrap();
+        assert_eq!(
+            expand_prefix("reports/{yyyy}/{mm}/{experiment}", "pr-1234", now).unwrap(),
+            "reports/2024/03/pr-1234"
+        );
+        assert_eq!(
+            expand_prefix("{date}/{dd}-{experiment}", "beta", now).unwrap(),
+            "2024-03-07/07-beta"
+        );
+        assert_eq!(
+            expand_prefix("static/prefix", "beta", now).unwrap(),
+            "static/prefix"
+        );
+
+        for bad in ["reports/{year}", "reports/{experiment", "{}"] {
+            assert!(
+                expand_prefix(bad, "beta", now).is_err(),
+                "valid bad prefix: {bad}"
+            );
+        }
+
+        let mock = MockS3::default();
+        let writer = S3Writer::create(
+            mock.client(),
+            "bucket".into(),
+            "reports/{yyyy}/{mm}/{experiment}".into(),
+            MultipartConfig::default(),
+        )
+        .unwrap()
+        .with_prefix_placeholders("pr-1234", now)
+        .unwrap();
+        assert_eq!(
+            writer.key("index.html".as_ref()),
+            "reports/2024/03/pr-1234/index.html"
+        );
+    }
+
+    #[test]
+    fn test_expired_report_keys() {
+        let now = Utc.with_ymd_and_hms(2024, 6, 1, 0, 0, 0).unwrap();
+        let cutoff = now - chrono::Duration::days(90);
+        let old = now - chrono::Duration::days(200);
+        let recent = now - chrono::Duration::days(10);
+        let object = |key: &str, last_modified| ReportObject {
+            key: key.into(),
+            last_modified,
+        };
+
+        let objects = [
+            // Reports without a date in their keys are aged by their newest object
+            object("reports/pr-1/index.html", old),
+            object("reports/pr-1/stable/reg/foo-2024-05-30/log.txt", old),
+            object("reports/pr-2/index.html", old),
+            object("reports/pr-2/results.json", recent),
+            // Dates embedded in the keys win over the last modification time
+            object("reports/2023-12-01/pr-3/index.html", recent),
+            object("reports/2024/05/20/pr-4/index.html", old),
+            object("reports/pr-5-2023-11-02/index.html", recent),
+            // Keys outside of the prefix are never selected
+            object("reports-old/pr-6/index.html", old),
+            object("other/reports/pr-7/index.html", old),
+            object("reports", old),
+        ];
+
+        let mut expired = expired_report_keys("reports", &objects, cutoff);
+        expired.sort();
+        assert_eq!(
+            expired,
+            vec![
+                "reports/2023-12-01/pr-3/index.html",
+                "reports/pr-1/index.html",
+                "reports/pr-1/stable/reg/foo-2024-05-30/log.txt",
+                "reports/pr-5-2023-11-02/index.html",
+            ]
+        );
+
+        // Without a prefix the whole bucket is considered, each directory being a report
+        let mut expired = expired_report_keys("", &objects, cutoff);
+        expired.sort();
+        assert_eq!(
+            expired,
+            vec![
+                "other/reports/pr-7/index.html",
+                "reports-old/pr-6/index.html"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_parse_s3prefix() {
+        assert_eq!(
+            S3Prefix::from_str("s3://bucket-name").unwrap(),
+            S3Prefix {
+                bucket: "bucket-name".into(),
+                prefix: "".into(),
+                region: None,
+                endpoint: None,
+            }
+        );
+        assert_eq!(
+            S3Prefix::from_str("s3://bucket-name/path/prefix").unwrap(),
+            S3Prefix {
+                bucket: "bucket-name".into(),
+                prefix: "path/prefix".into(),
+                region: None,
+                endpoint: None,
+            }
+        );
+        assert_eq!(
+            S3Prefix::from_str(
+                "s3://bucket-name/path/prefix?region=us-west-2&endpoint=https://minio.local:9000"
+            )
+            .unwrap(),
+            S3Prefix {
+                bucket: "bucket-name".into(),
+                prefix: "path/prefix".into(),
+                region: Some("us-west-2".into()),
+                endpoint: Some("https://minio.local:9000".into()),
+            }
+        );
+        assert_eq!(
+            S3Prefix::from_str("s3://bucket-name?region=eu-central-1").unwrap(),
+            S3Prefix {
+                bucket: "bucket-name".into(),
+                prefix: "".into(),
+                region: Some("eu-central-1".into()),
+                endpoint: None,
+            }
+        );
+
+        for bad in &[
+            "https://example.com",
+            "s3://user:pass@bucket/path/prefix",
+            "s3://bucket:80",
+            "s3://bucket/path/prefix?query#fragment",
+            "s3://bucket/path/prefix?region=us-west-2#fragment",
+            "s3://bucket/path/prefix?unknown=value",
+        ] {
+            assert!(S3Prefix::from_str(bad).is_err(), "valid bad url: {bad}");
+        }
+    }
+
+    #[test]
+    fn test_display_s3prefix() {
+        for url in &[
+            "s3://bucket-name/path/prefix",
+            "s3://bucket-name/path/prefix?region=us-west-2",
+            "s3://bucket-name/path/prefix?region=us-west-2&endpoint=https://minio.local:9000",
+        ] {
+            let parsed = S3Prefix::from_str(url).unwrap();
+            assert_eq!(S3Prefix::from_str(&parsed.to_string()).unwrap(), parsed);
+        }
+        assert_eq!(
+            S3Prefix::from_str("s3://bucket-name/path?region=us-west-2")
+                .unwrap()
+                .to_string(),
+            "s3://bucket-name/path?region=us-west-2"
+        );
     }
 }
@@ -0,0 +1,338 @@
+use crate::prelude::*;
+use crate::report::ReportWriter;
+use crate::results::EncodingType;
+use mime::Mime;
+use std::borrow::Cow;
+use std::collections::HashSet;
+use std::fmt::{self, Display};
+use std::fs::File;
+use std::io::{BufRead, BufReader, Read, Seek, SeekFrom, Write};
+use std::path::{Path, PathBuf};
+use std::process::{Child, ChildStdin, ChildStdout, Command, Stdio};
+use std::sync::Mutex;
+use tempfile::NamedTempFile;
+
+/// Operations `SftpWriter` needs from the remote server.
+pub trait SftpTransport: Send + Sync {
+    /// Create a directory, succeeding if it already exists.
+    fn create_dir(&self, path: &Path) -> Fallible<()>;
+    /// Create or replace a file with the provided content.
+    fn write_file(&self, path: &Path, data: &[u8]) -> Fallible<()>;
+}
+
+/// Transport running the system's `sftp` client in batch mode. A single session is kept open and
+/// fed the commands as they come, so the connection isn't set up again for every file.
+pub struct SftpCommand {
+    host: String,
+    port: u16,
+    user: String,
+    key_path: PathBuf,
+    session: Mutex<Option<SftpSession>>,
+}
+
+impl SftpCommand {
+    fn spawn(&self) -> Fallible<SftpSession> {
+        // Written to a file, as a pipe nobody reads would block sftp once full
+        let stderr = tempfile::tempfile()?;
+        let mut child = Command::new("sftp")
+            .arg("-b")
+            .arg("-")
+            .arg("-P")
+            .arg(self.port.to_string())
+            .arg("-i")
+            .arg(&self.key_path)
+            .arg("-o")
+            .arg("BatchMode=yes")
+            .arg(format!("{}@{}", self.user, self.host))
+            .stdin(Stdio::piped())
+            .stdout(Stdio::piped())
+            .stderr(stderr.try_clone()?)
+            .spawn()
+            .with_context(|_| "failed to spawn sftp")?;
+
+        Ok(SftpSession {
+            stdin: child.stdin.take(),
+            stdout: BufReader::new(child.stdout.take().unwrap()),
+            stderr,
+            child,
+            commands: 0,
+        })
+    }
+
+    fn run(&self, batch: &str) -> Fallible<()> {
+        let mut session = self.session.lock().unwrap();
+        if session.is_none() {
+            *session = Some(self.spawn()?);
+        }
+        match session.as_mut().unwrap().run(batch) {
+            Ok(true) => Ok(()),
+            // sftp exits in batch mode as soon as a command fails, so the next command needs a
+            // new session
+            Ok(false) => {
+                let stderr = session.take().unwrap().finish()?;
+                bail!("sftp to {} failed: {}", self.host, stderr);
+            }
+            Err(err) => {
+                session.take();
+                Err(err)
+            }
+        }
+    }
+}
+
+/// Running `sftp` process, reading its commands from the standard input.
+struct SftpSession {
+    child: Child,
+    stdin: Option<ChildStdin>,
+    stdout: BufReader<ChildStdout>,
+    stderr: File,
+    commands: u64,
+}
+
+impl SftpSession {
+    /// Run the commands of `batch`, waiting for them to complete. Returns `false` if one of them
+    /// failed, making sftp exit.
+    fn run(&mut self, batch: &str) -> Fallible<bool> {
+        // The output of a local command marks the end of the batch, as the commands run in order
+        self.commands += 1;
+        let marker = format!("crater-sftp-done-{}", self.commands);
+        let stdin = self.stdin.as_mut().unwrap();
+        if write!(stdin, "{batch}!echo {marker}\n")
+            .and_then(|()| stdin.flush())
+            .is_err()
+        {
+            // The process already exited
+            return Ok(false);
+        }
+
+        let mut line = String::new();
+        loop {
+            line.clear();
+            if self.stdout.read_line(&mut line)? == 0 {
+                return Ok(false);
+            }
+            if line.trim_end() == marker {
+                return Ok(true);
+            }
+        }
+    }
+
+    /// Wait for sftp to exit, returning what it wrote to the standard error.
+    fn finish(mut self) -> Fallible<String> {
+        self.stdin.take();
+        self.child.wait()?;
+        let mut stderr = String::new();
+        self.stderr.seek(SeekFrom::Start(0))?;
+        self.stderr.read_to_string(&mut stderr)?;
+        Ok(stderr)
+    }
+}
+
+impl Drop for SftpSession {
+    fn drop(&mut self) {
+        // Closing the standard input ends the session
+        self.stdin.take();
+        let _ = self.child.wait();
+    }
+}
+
+/// Quote a path for an sftp batch file.
+fn quote(path: &Path) -> String {
+    format!(
+        "\"{}\"",
+        path.to_string_lossy()
+            .replace('\\', "\\\\")
+            .replace('"', "\\\"")
+    )
+}
+
+impl SftpTransport for SftpCommand {
+    fn create_dir(&self, path: &Path) -> Fallible<()> {
+        // The leading `-` makes sftp ignore the error if the directory already exists
+        self.run(&format!("-mkdir {}\n", quote(path)))
+    }
+
+    fn write_file(&self, path: &Path, data: &[u8]) -> Fallible<()> {
+        let mut local = NamedTempFile::new()?;
+        local.write_all(data)?;
+        local.flush()?;
+        self.run(&format!("put {} {}\n", quote(local.path()), quote(path)))
+    }
+}
+
+pub struct SftpWriter<T: SftpTransport = SftpCommand> {
+    transport: T,
+    base: PathBuf,
+    /// Directories already created, which don't need to be created again for the next files.
+    created_dirs: Mutex<HashSet<PathBuf>>,
+}
+
+impl SftpWriter<SftpCommand> {
+    pub fn create(
+        host: String,
+        port: u16,
+        user: String,
+        key_path: PathBuf,
+        base: PathBuf,
+    ) -> SftpWriter<SftpCommand> {
+        SftpWriter::with_transport(
+            SftpCommand {
+                host,
+                port,
+                user,
+                key_path,
+                session: Mutex::new(None),
+            },
+            base,
+        )
+    }
+}
+
+impl<T: SftpTransport> SftpWriter<T> {
+    pub fn with_transport(transport: T, base: PathBuf) -> SftpWriter<T> {
+        SftpWriter {
+            transport,
+            base,
+            created_dirs: Mutex::new(HashSet::new()),
+        }
+    }
+
+    fn create_parents(&self, path: &Path) -> Fallible<()> {
+        let mut dirs = vec![self.base.clone()];
+        if let Some(parent) = path.parent() {
+            for component in parent.components() {
+                let dir = dirs.last().unwrap().join(component);
+                dirs.push(dir);
+            }
+        }
+
+        let mut created_dirs = self.created_dirs.lock().unwrap();
+        for dir in dirs {
+            if !created_dirs.contains(&dir) {
+                self.transport.create_dir(&dir)?;
+                created_dirs.insert(dir);
+            }
+        }
+        Ok(())
+    }
+}
+
+impl<T: SftpTransport> ReportWriter for SftpWriter<T> {
+    fn write_bytes<P: AsRef<Path>>(
+        &self,
+        path: P,
+        b: &[u8],
+        _: &Mime,
+        encoding_type: EncodingType,
+    ) -> Fallible<()> {
+        let path = path.as_ref();
+        self.create_parents(path)?;
+
+        let remote = self.base.join(path);
+        self.transport
+            .write_file(&remote, b)
+            .with_context(|_| format!("failed to upload to {path:?}"))?;
+
+        // SFTP has no notion of content encoding, so record it next to the file
+        if encoding_type != EncodingType::Plain {
+            let mut sidecar = remote.into_os_string();
+            sidecar.push(".encoding");
+            self.transport
+                .write_file(Path::new(&sidecar), encoding_type.to_str().as_bytes())?;
+        }
+
+        Ok(())
+    }
+
+    fn write_string<P: AsRef<Path>>(&self, path: P, s: Cow<str>, mime: &Mime) -> Fallible<()> {
+        self.write_bytes(path, s.as_bytes(), mime, EncodingType::Plain)
+    }
+}
+
+impl<T: SftpTransport> Display for SftpWriter<T> {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        self.base.display().fmt(f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{SftpTransport, SftpWriter};
+    use crate::prelude::*;
+    use crate::report::ReportWriter;
+    use crate::results::EncodingType;
+    use std::collections::{BTreeSet, HashMap};
+    use std::path::{Path, PathBuf};
+    use std::sync::Mutex;
+
+    #[derive(Default)]
+    struct MockTransport {
+        dirs: Mutex<BTreeSet<PathBuf>>,
+        files: Mutex<HashMap<PathBuf, Vec<u8>>>,
+    }
+
+    impl SftpTransport for MockTransport {
+        fn create_dir(&self, path: &Path) -> Fallible<()> {
+            if !self.dirs.lock().unwrap().insert(path.into()) {
+                bail!("directory {:?} created twice", path);
+            }
+            Ok(())
+        }
+
+        fn write_file(&self, path: &Path, data: &[u8]) -> Fallible<()> {
+            if !self.dirs.lock().unwrap().contains(path.parent().unwrap()) {
+                bail!("missing parent directory of {:?}", path);
+            }
+            self.files.lock().unwrap().insert(path.into(), data.into());
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_sftp_writer() {
+        let writer = SftpWriter::with_transport(MockTransport::default(), "/reports/ex".into());
+        let log = (0..=255).collect::<Vec<u8>>();
+
+        writer
+            .write_string("index.html", "<html>".into(), &mime::TEXT_HTML)
+            .unwrap();
+        writer
+            .write_bytes(
+                "try#deadbeef/reg/foo-1.0.0/log.txt",
+                &log,
+                &mime::TEXT_PLAIN,
+                EncodingType::Gzip,
+            )
+            .unwrap();
+        // The directories created for the previous files aren't created again
+        writer
+            .write_string(
+                "try#deadbeef/reg/foo-1.0.0/summary.txt",
+                "regressed".into(),
+                &mime::TEXT_PLAIN,
+            )
+            .unwrap();
+
+        let dirs = writer.transport.dirs.lock().unwrap();
+        for dir in &[
+            "/reports/ex",
+            "/reports/ex/try#deadbeef",
+            "/reports/ex/try#deadbeef/reg",
+            "/reports/ex/try#deadbeef/reg/foo-1.0.0",
+        ] {
+            assert!(dirs.contains(Path::new(dir)), "missing directory {dir}");
+        }
+
+        let files = writer.transport.files.lock().unwrap();
+        assert_eq!(files.len(), 4);
+        assert_eq!(files[Path::new("/reports/ex/index.html")], b"<html>");
+        assert_eq!(
+            files[Path::new("/reports/ex/try#deadbeef/reg/foo-1.0.0/log.txt")],
+            log
+        );
+        assert_eq!(
+            files[Path::new("/reports/ex/try#deadbeef/reg/foo-1.0.0/log.txt.encoding")],
+            b"gzip"
+        );
+    }
+}
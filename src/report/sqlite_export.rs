@@ -0,0 +1,181 @@
+use crate::prelude::*;
+use crate::report::summary::{crate_name_and_version, SummaryCategory};
+use crate::report::{RawTestResults, ReportWriter};
+use crate::results::EncodingType;
+use rusqlite::Connection;
+use std::collections::BTreeMap;
+use std::path::Path;
+
+pub const SQLITE_PATH: &str = "results.db";
+
+const SCHEMA: &str = "
+    CREATE TABLE crates (
+        id INTEGER PRIMARY KEY,
+        name TEXT NOT NULL,
+        version TEXT,
+        comparison TEXT NOT NULL,
+        category TEXT NOT NULL
+    );
+    CREATE INDEX crates_name ON crates (name);
+    CREATE INDEX crates_category ON crates (category);
+
+    CREATE TABLE results (
+        crate_id INTEGER NOT NULL REFERENCES crates (id),
+        toolchain TEXT NOT NULL,
+        result TEXT NOT NULL,
+        log_url TEXT NOT NULL
+    );
+    CREATE INDEX results_crate_id ON results (crate_id);
+
+    CREATE TABLE categories (
+        name TEXT PRIMARY KEY,
+        crates INTEGER NOT NULL
+    );
+";
+
+fn build_db(raw: &RawTestResults, path: &Path) -> Fallible<()> {
+    let mut conn = Connection::open(path)?;
+    conn.execute_batch(SCHEMA)?;
+
+    let t = conn.transaction()?;
+    let mut categories = BTreeMap::new();
+    for (id, krate) in raw.crates.iter().enumerate() {
+        let (name, version) = crate_name_and_version(&krate.krate);
+        let category = SummaryCategory::for_crate(krate);
+        *categories.entry(category.to_string()).or_insert(0u32) += 1;
+
+        t.execute(
+            "INSERT INTO crates (id, name, version, comparison, category) \
+             VALUES (?1, ?2, ?3, ?4, ?5);",
+            rusqlite::params![
+                id as i64,
+                name,
+                version,
+                krate.res.to_string(),
+                category.to_string()
+            ],
+        )?;
+        for (toolchain, run) in &krate.runs {
+            if let Some(run) = run {
+                t.execute(
+                    "INSERT INTO results (crate_id, toolchain, result, log_url) \
+                     VALUES (?1, ?2, ?3, ?4);",
+                    rusqlite::params![
+                        id as i64,
+                        toolchain,
+                        run.res.to_string(),
+                        format!("{}/log.txt", run.log)
+                    ],
+                )?;
+            }
+        }
+    }
+    for (name, count) in categories {
+        t.execute(
+            "INSERT INTO categories (name, crates) VALUES (?1, ?2);",
+            rusqlite::params![name, count],
+        )?;
+    }
+    t.commit()?;
+
+    Ok(())
+}
+
+/// Export the results as a SQLite database, so they can be queried with SQL.
+pub fn write_sqlite<W: ReportWriter>(raw: &RawTestResults, dest: &W) -> Fallible<()> {
+    // SQLite needs a file to write the database to, which is then uploaded as is
+    let file = tempfile::NamedTempFile::new()?;
+    build_db(raw, file.path())?;
+    dest.write_bytes(
+        SQLITE_PATH,
+        &std::fs::read(file.path())?,
+        &"application/vnd.sqlite3".parse().unwrap(),
+        EncodingType::Plain,
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{write_sqlite, SQLITE_PATH};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::Crate;
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::report::{BuildTestResult, Comparison, CrateResult, MemoryWriter, RawTestResults};
+    use crate::results::{EncodingType, FailureReason, TestResult};
+    use rusqlite::Connection;
+
+    #[test]
+    fn test_sqlite_export() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let run = |res| {
+            Some(BuildTestResult {
+                res,
+                log: "stable/local/krate".into(),
+                log_url: None,
+                duration_ms: None,
+                repro: None,
+            })
+        };
+        let result = |name: &str, res, runs: [Option<BuildTestResult>; 2]| CrateResult {
+            name: name.into(),
+            url: String::new(),
+            krate: Crate::Local(name.into()),
+            status: None,
+            res,
+            runs: ex
+                .toolchains
+                .iter()
+                .map(|tc| tc.to_string())
+                .zip(runs)
+                .collect(),
+        };
+        let raw = RawTestResults {
+            crates: vec![
+                result(
+                    "regressed",
+                    Comparison::Regressed,
+                    [
+                        run(TestResult::TestPass),
+                        run(TestResult::BuildFail(FailureReason::Unknown)),
+                    ],
+                ),
+                result(
+                    "unchanged",
+                    Comparison::SameTestPass,
+                    [run(TestResult::TestPass), run(TestResult::TestPass)],
+                ),
+                result("missing", Comparison::Unknown, [None, None]),
+            ],
+        };
+
+        let writer = MemoryWriter::new();
+        write_sqlite(&raw, &writer).unwrap();
+        let stored = writer.get(SQLITE_PATH).unwrap();
+        assert_eq!(stored.mime.to_string(), "application/vnd.sqlite3");
+        assert_eq!(stored.encoding, EncodingType::Plain);
+
+        let file = tempfile::NamedTempFile::new().unwrap();
+        std::fs::write(file.path(), &stored.data).unwrap();
+        let conn = Connection::open(file.path()).unwrap();
+        let count = |sql: &str| -> u32 { conn.query_row(sql, [], |row| row.get(0)).unwrap() };
+
+        assert_eq!(count("SELECT COUNT(*) FROM crates;"), 3);
+        assert_eq!(count("SELECT COUNT(*) FROM results;"), 4);
+        assert_eq!(count("SELECT SUM(crates) FROM categories;"), 3);
+        assert_eq!(
+            count(
+                "SELECT COUNT(*) FROM results JOIN crates ON crates.id = results.crate_id \
+                 WHERE crates.name = 'regressed' AND results.result = 'build-fail:unknown';"
+            ),
+            1
+        );
+    }
+}
@@ -0,0 +1,627 @@
+use crate::crates::Crate;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{
+    crate_to_name, error_codes, BuildTimesV1, CodeClusterV1, Comparison, CrateResult,
+    DiagnosticsDiff, RawTestResults, ReportWriter,
+};
+use crate::results::{EncodedLog, EncodingType, FailureReason, TestResult};
+use std::collections::BTreeMap;
+
+/// Path of the summary in the report. `results.json` is already taken by the raw results the
+/// HTML report is generated from, whose format isn't meant to be stable.
+pub const SUMMARY_PATH: &str = "summary.json";
+
+string_enum!(pub enum SummaryCategory {
+    Regressed => "regressed",
+    Fixed => "fixed",
+    Unchanged => "unchanged",
+    Broken => "broken",
+    Timeout => "timeout",
+    OutOfMemory => "out-of-memory",
+    FetchError => "fetch-error",
+});
+
+impl SummaryCategory {
+    /// Category of a crate, setting apart the ones which only didn't change because they ran out
+    /// of memory: the comparison treats OOMs as spurious, but they still deserve a look.
+    pub(super) fn for_crate(krate: &CrateResult) -> Self {
+        let runs = krate.compared_runs();
+        let oom = runs.iter().flatten().any(|run| {
+            matches!(
+                run.res,
+                TestResult::BuildFail(FailureReason::OOM)
+                    | TestResult::TestFail(FailureReason::OOM)
+            )
+        });
+
+        let category = match runs {
+            [Some(start), Some(end)] => {
+                SummaryCategory::from_results(krate.res, &start.res, &end.res)
+            }
+            _ => SummaryCategory::from(krate.res),
+        };
+        match category {
+            SummaryCategory::Unchanged | SummaryCategory::Broken if oom => {
+                SummaryCategory::OutOfMemory
+            }
+            category => category,
+        }
+    }
+}
+
+/// Whether a crate worked with a toolchain.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub enum Outcome {
+    Pass,
+    Fail,
+}
+
+impl Outcome {
+    /// Outcome of a result, or `None` for the results telling nothing about the toolchain, like
+    /// errors, timeouts or spurious failures.
+    pub fn of(result: &TestResult) -> Option<Self> {
+        match result {
+            TestResult::TestPass | TestResult::TestSkipped | TestResult::Warnings(_) => {
+                Some(Outcome::Pass)
+            }
+            TestResult::BuildFail(reason)
+            | TestResult::TestFail(reason)
+            | TestResult::DocFail(reason)
+                if !reason.is_spurious() =>
+            {
+                Some(Outcome::Fail)
+            }
+            _ => None,
+        }
+    }
+}
+
+impl SummaryCategory {
+    /// Category of a crate given its outcome with the start and the end toolchain. A crate
+    /// failing with both toolchains is broken rather than regressed, as the new toolchain didn't
+    /// make it any worse.
+    pub fn from_outcomes(start: Outcome, end: Outcome) -> Self {
+        match (start, end) {
+            (Outcome::Pass, Outcome::Pass) => SummaryCategory::Unchanged,
+            (Outcome::Pass, Outcome::Fail) => SummaryCategory::Regressed,
+            (Outcome::Fail, Outcome::Pass) => SummaryCategory::Fixed,
+            (Outcome::Fail, Outcome::Fail) => SummaryCategory::Broken,
+        }
+    }
+
+    /// Category of a crate given its results with the start and the end toolchain, and their
+    /// comparison. Crates which failed with the start toolchain are broken or fixed, even when
+    /// the comparison found them to fail worse with the end one.
+    pub fn from_results(comparison: Comparison, start: &TestResult, end: &TestResult) -> Self {
+        match comparison {
+            Comparison::Regressed
+            | Comparison::DocRegressed
+            | Comparison::Fixed
+            | Comparison::SameBuildFail
+            | Comparison::SameTestFail
+            | Comparison::SameDocFail
+            | Comparison::SameTestPass
+            | Comparison::SameTestSkipped => match (Outcome::of(start), Outcome::of(end)) {
+                // Passing with both toolchains still regresses with new lints
+                (Some(Outcome::Pass), Some(Outcome::Pass)) => comparison.into(),
+                (Some(start), Some(end)) => SummaryCategory::from_outcomes(start, end),
+                _ => comparison.into(),
+            },
+            // Spurious, flaky or uncomparable results don't depend on the outcomes
+            _ => comparison.into(),
+        }
+    }
+}
+
+impl From<Comparison> for SummaryCategory {
+    fn from(comparison: Comparison) -> Self {
+        match comparison {
+            Comparison::Regressed | Comparison::DocRegressed => SummaryCategory::Regressed,
+            Comparison::Fixed => SummaryCategory::Fixed,
+            Comparison::SameBuildFail | Comparison::SameTestFail | Comparison::SameDocFail => {
+                SummaryCategory::Broken
+            }
+            Comparison::SameTestPass | Comparison::SameTestSkipped => SummaryCategory::Unchanged,
+            // Results we couldn't compare are grouped with the crates broken on both toolchains
+            Comparison::Broken | Comparison::Unknown | Comparison::Error => SummaryCategory::Broken,
+            Comparison::Timeout => SummaryCategory::Timeout,
+            // Kept apart so download hiccups don't show up in the regression counts
+            Comparison::FetchError => SummaryCategory::FetchError,
+            // Regressions the second run didn't reproduce
+            Comparison::Skipped
+            | Comparison::Yanked
+            | Comparison::SpuriousRegressed
+            | Comparison::SpuriousFixed
+            | Comparison::Flaky => SummaryCategory::Unchanged,
+        }
+    }
+}
+
+/// Machine-readable summary of an experiment's results, written next to the HTML report.
+///
+/// This is consumed by external tools: fields must not be renamed or removed. Breaking changes
+/// require adding a new `ReportSummaryV2` and bumping `version`.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ReportSummaryV1 {
+    pub version: u32,
+    pub experiment: String,
+    pub crates: Vec<CrateSummaryV1>,
+    /// Number of crates left out of the summary by the report filter.
+    #[serde(default)]
+    pub omitted: usize,
+    /// Missing from the summaries written before the counts were added.
+    #[serde(default)]
+    pub counts: CategoryCountsV1,
+    /// Labels of the experiment, left out when it has none.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    pub labels: BTreeMap<String, String>,
+    /// Slowest crates with each toolchain, left out when the build times weren't recorded.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub build_times: Option<BuildTimesV1>,
+    /// Regressed crates clustered by the error code they newly hit, left out when none regressed.
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    pub regressions_by_code: Vec<CodeClusterV1>,
+}
+
+/// Number of crates in each category. Skipped and yanked crates are counted apart from the
+/// unchanged ones, so the categories always add up to `total`.
+#[derive(Debug, Clone, Default, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CategoryCountsV1 {
+    pub regressed: usize,
+    pub fixed: usize,
+    pub unchanged: usize,
+    pub broken: usize,
+    pub skipped: usize,
+    pub timeout: usize,
+    pub out_of_memory: usize,
+    pub fetch_error: usize,
+    pub total: usize,
+}
+
+impl CategoryCountsV1 {
+    pub fn count(crates: impl IntoIterator<Item = (SummaryCategory, Comparison)>) -> Self {
+        let mut counts = CategoryCountsV1::default();
+        for (category, comparison) in crates {
+            let count = match category {
+                _ if matches!(comparison, Comparison::Skipped | Comparison::Yanked) => {
+                    &mut counts.skipped
+                }
+                SummaryCategory::Regressed => &mut counts.regressed,
+                SummaryCategory::Fixed => &mut counts.fixed,
+                SummaryCategory::Unchanged => &mut counts.unchanged,
+                SummaryCategory::Broken => &mut counts.broken,
+                SummaryCategory::Timeout => &mut counts.timeout,
+                SummaryCategory::OutOfMemory => &mut counts.out_of_memory,
+                SummaryCategory::FetchError => &mut counts.fetch_error,
+            };
+            *count += 1;
+            counts.total += 1;
+        }
+        counts
+    }
+
+    pub(super) fn for_results(raw: &RawTestResults) -> Self {
+        Self::count(
+            raw.crates
+                .iter()
+                .map(|krate| (SummaryCategory::for_crate(krate), krate.res)),
+        )
+    }
+
+    /// The counts along with the name of their category, in the order they're displayed.
+    pub fn entries(&self) -> [(&'static str, usize); 8] {
+        [
+            ("regressed", self.regressed),
+            ("fixed", self.fixed),
+            ("unchanged", self.unchanged),
+            ("broken", self.broken),
+            ("skipped", self.skipped),
+            ("timeout", self.timeout),
+            ("out-of-memory", self.out_of_memory),
+            ("fetch-error", self.fetch_error),
+        ]
+    }
+
+    /// Share of the crates `count` represents, from 0 to 100. Empty experiments have no share.
+    pub fn percentage(&self, count: usize) -> f64 {
+        if self.total == 0 {
+            0.0
+        } else {
+            count as f64 * 100.0 / self.total as f64
+        }
+    }
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct CrateSummaryV1 {
+    pub name: String,
+    pub version: Option<String>,
+    pub category: SummaryCategory,
+    pub comparison: Comparison,
+    pub results: Vec<ToolchainResultV1>,
+    /// Diagnostic codes introduced or removed by the end toolchain, for regressed and fixed
+    /// crates only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub diagnostics: Option<DiagnosticsDiff>,
+}
+
+#[derive(Debug, Clone, PartialEq, Eq, Serialize, Deserialize)]
+pub struct ToolchainResultV1 {
+    pub toolchain: String,
+    pub result: Option<TestResult>,
+    /// Shell command reproducing the build from the crate's source, for regressed crates only.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub repro: Option<String>,
+}
+
+/// Split a crate into the name and version shown in machine-readable exports. Only registry
+/// crates have a version, the other ones are identified by their full name.
+pub(crate) fn crate_name_and_version(krate: &Crate) -> (String, Option<String>) {
+    match krate {
+        Crate::Registry(details) => (details.name.clone(), Some(details.version.clone())),
+        Crate::AltRegistry(details) => (details.name.clone(), Some(details.version.clone())),
+        other => (crate_to_name(other), None),
+    }
+}
+
+impl ReportSummaryV1 {
+    pub const VERSION: u32 = 1;
+
+    pub fn new(ex: &Experiment, raw: &RawTestResults, omitted: usize) -> Self {
+        let crates = raw
+            .crates
+            .iter()
+            .map(|krate| {
+                let (name, version) = crate_name_and_version(&krate.krate);
+
+                CrateSummaryV1 {
+                    name,
+                    version,
+                    category: SummaryCategory::for_crate(krate),
+                    comparison: krate.res,
+                    results: krate
+                        .runs
+                        .iter()
+                        .map(|(tc, run)| ToolchainResultV1 {
+                            toolchain: tc.clone(),
+                            result: run.as_ref().map(|run| run.res.clone()),
+                            repro: run.as_ref().and_then(|run| run.repro.clone()),
+                        })
+                        .collect(),
+                    diagnostics: DiagnosticsDiff::for_crate(krate),
+                }
+            })
+            .collect();
+
+        ReportSummaryV1 {
+            version: Self::VERSION,
+            experiment: ex.name.clone(),
+            counts: CategoryCountsV1::for_results(raw),
+            crates,
+            omitted,
+            labels: ex.labels.clone(),
+            build_times: None,
+            regressions_by_code: error_codes::regressions_by_code(raw),
+        }
+    }
+}
+
+pub fn write_summary<W: ReportWriter>(
+    ex: &Experiment,
+    raw: &RawTestResults,
+    omitted: usize,
+    build_times: Option<&BuildTimesV1>,
+    dest: &W,
+) -> Fallible<()> {
+    let summary = ReportSummaryV1 {
+        build_times: build_times.cloned(),
+        ..ReportSummaryV1::new(ex, raw, omitted)
+    };
+    let encoded = EncodedLog::from_plain_slice(
+        serde_json::to_string(&summary)?.as_bytes(),
+        EncodingType::Gzip,
+    )?;
+    dest.write_bytes(
+        SUMMARY_PATH,
+        encoded.as_slice(),
+        &mime::APPLICATION_JSON,
+        encoded.get_encoding_type(),
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        CategoryCountsV1, CrateSummaryV1, Outcome, ReportSummaryV1, SummaryCategory,
+        ToolchainResultV1,
+    };
+    use crate::config::Config;
+    use crate::crates::Crate;
+    use crate::report::{
+        compare, BuildTestResult, ClusteredCrateV1, CodeClusterV1, Comparison, CrateResult,
+        DiagnosticsDiff,
+    };
+    use crate::results::{FailureReason, TestResult};
+    use serde_json::json;
+
+    #[test]
+    fn test_summary_serialization() {
+        let summary = ReportSummaryV1 {
+            version: ReportSummaryV1::VERSION,
+            experiment: "pr-1".into(),
+            crates: vec![CrateSummaryV1 {
+                name: "lazy_static".into(),
+                version: Some("1.0.0".into()),
+                category: SummaryCategory::Regressed,
+                comparison: Comparison::Regressed,
+                results: vec![
+                    ToolchainResultV1 {
+                        toolchain: "stable".into(),
+                        result: Some(TestResult::TestPass),
+                        repro: None,
+                    },
+                    ToolchainResultV1 {
+                        toolchain: "beta".into(),
+                        result: Some(TestResult::BuildFail(FailureReason::CompilerError(
+                            btreeset!["E0308".parse().unwrap()],
+                        ))),
+                        repro: Some("cargo +beta build --frozen".into()),
+                    },
+                ],
+                diagnostics: Some(DiagnosticsDiff {
+                    added: vec!["E0308".into()],
+                    removed: Vec::new(),
+                }),
+            }],
+            omitted: 0,
+            counts: CategoryCountsV1::count([(SummaryCategory::Regressed, Comparison::Regressed)]),
+            labels: [("pr".to_string(), "1".to_string())].into(),
+            build_times: None,
+            regressions_by_code: vec![CodeClusterV1 {
+                code: "E0308".into(),
+                count: 1,
+                crates: vec![ClusteredCrateV1 {
+                    name: "lazy_static-1.0.0".into(),
+                    url: "https://crates.io/crates/lazy_static/1.0.0".into(),
+                }],
+            }],
+        };
+
+        let expected = json!({
+            "version": 1,
+            "experiment": "pr-1",
+            "crates": [{
+                "name": "lazy_static",
+                "version": "1.0.0",
+                "category": "regressed",
+                "comparison": "regressed",
+                "results": [
+                    {"toolchain": "stable", "result": "test-pass"},
+                    {
+                        "toolchain": "beta",
+                        "result": "build-fail:compiler-error(E0308)",
+                        "repro": "cargo +beta build --frozen",
+                    },
+                ],
+                "diagnostics": {"added": ["E0308"], "removed": []},
+            }],
+            "omitted": 0,
+            "counts": {
+                "regressed": 1,
+                "fixed": 0,
+                "unchanged": 0,
+                "broken": 0,
+                "skipped": 0,
+                "timeout": 0,
+                "out_of_memory": 0,
+                "fetch_error": 0,
+                "total": 1,
+            },
+            "labels": {"pr": "1"},
+            "regressions_by_code": [{
+                "code": "E0308",
+                "count": 1,
+                "crates": [{
+                    "name": "lazy_static-1.0.0",
+                    "url": "https://crates.io/crates/lazy_static/1.0.0",
+                }],
+            }],
+        });
+
+        let serialized = serde_json::to_value(&summary).unwrap();
+        assert_eq!(serialized, expected);
+        assert_eq!(
+            serde_json::from_value::<ReportSummaryV1>(serialized).unwrap(),
+            summary
+        );
+    }
+
+    #[test]
+    fn test_category_counts() {
+        let counts = CategoryCountsV1::count([
+            (SummaryCategory::Regressed, Comparison::Regressed),
+            (SummaryCategory::Regressed, Comparison::DocRegressed),
+            (SummaryCategory::Fixed, Comparison::Fixed),
+            (SummaryCategory::Unchanged, Comparison::SameTestPass),
+            (SummaryCategory::Unchanged, Comparison::Skipped),
+            (SummaryCategory::Unchanged, Comparison::Yanked),
+            (SummaryCategory::Broken, Comparison::Broken),
+            (SummaryCategory::Timeout, Comparison::Timeout),
+            (SummaryCategory::FetchError, Comparison::FetchError),
+        ]);
+
+        assert_eq!(
+            counts,
+            CategoryCountsV1 {
+                regressed: 2,
+                fixed: 1,
+                unchanged: 1,
+                broken: 1,
+                skipped: 2,
+                timeout: 1,
+                out_of_memory: 0,
+                fetch_error: 1,
+                total: 9,
+            }
+        );
+        assert_eq!(
+            counts
+                .entries()
+                .iter()
+                .map(|(_, count)| count)
+                .sum::<usize>(),
+            counts.total
+        );
+        assert_eq!(counts.percentage(counts.regressed), 25.0);
+        assert_eq!(counts.percentage(counts.fixed), 12.5);
+        assert_eq!(counts.percentage(counts.out_of_memory), 0.0);
+
+        let empty = CategoryCountsV1::count(std::iter::empty());
+        assert_eq!(empty.total, 0);
+        assert_eq!(empty.percentage(empty.regressed), 0.0);
+    }
+
+    #[test]
+    fn test_category_from_outcomes() {
+        let table = [
+            (Outcome::Pass, Outcome::Pass, SummaryCategory::Unchanged),
+            (Outcome::Pass, Outcome::Fail, SummaryCategory::Regressed),
+            (Outcome::Fail, Outcome::Pass, SummaryCategory::Fixed),
+            (Outcome::Fail, Outcome::Fail, SummaryCategory::Broken),
+        ];
+        for (start, end, category) in table {
+            assert_eq!(
+                SummaryCategory::from_outcomes(start, end),
+                category,
+                "{start:?} -> {end:?}"
+            );
+        }
+    }
+
+    #[test]
+    fn test_category_from_results() {
+        let config = Config::default();
+        let krate = Crate::Local("foo".into());
+        let build_fail = TestResult::BuildFail(FailureReason::Unknown);
+        let test_fail = TestResult::TestFail(FailureReason::Unknown);
+        let doc_fail = TestResult::DocFail(FailureReason::Unknown);
+        let ice = TestResult::BuildFail(FailureReason::ICE);
+        let oom = TestResult::BuildFail(FailureReason::OOM);
+
+        let table = [
+            (
+                &TestResult::TestPass,
+                &TestResult::TestPass,
+                SummaryCategory::Unchanged,
+            ),
+            (
+                &TestResult::TestPass,
+                &build_fail,
+                SummaryCategory::Regressed,
+            ),
+            (&TestResult::TestPass, &doc_fail, SummaryCategory::Regressed),
+            (&build_fail, &TestResult::TestPass, SummaryCategory::Fixed),
+            (&build_fail, &build_fail, SummaryCategory::Broken),
+            // Compared as regressions, but the crates already failed with the start toolchain
+            (&test_fail, &build_fail, SummaryCategory::Broken),
+            (&build_fail, &ice, SummaryCategory::Broken),
+            // Compared as fixed, but still failing with the end toolchain
+            (&build_fail, &test_fail, SummaryCategory::Broken),
+            // Spurious failures and uncomparable results keep the category of the comparison
+            (&TestResult::TestPass, &oom, SummaryCategory::Unchanged),
+            (
+                &TestResult::TestPass,
+                &TestResult::Timeout,
+                SummaryCategory::Timeout,
+            ),
+            (&build_fail, &TestResult::Error, SummaryCategory::Broken),
+        ];
+        for (start, end, category) in table {
+            let comparison = compare(&config, &krate, Some(start), Some(end));
+            assert_eq!(
+                SummaryCategory::from_results(comparison, start, end),
+                category,
+                "{start} -> {end} ({comparison})"
+            );
+        }
+    }
+
+    #[test]
+    fn test_summary_category() {
+        assert_eq!(
+            SummaryCategory::from(Comparison::SpuriousRegressed),
+            SummaryCategory::Unchanged
+        );
+        assert_eq!(
+            SummaryCategory::from(Comparison::Error),
+            SummaryCategory::Broken
+        );
+        assert_eq!(
+            SummaryCategory::from(Comparison::Timeout),
+            SummaryCategory::Timeout
+        );
+        assert_eq!(
+            SummaryCategory::from(Comparison::FetchError),
+            SummaryCategory::FetchError
+        );
+        assert_eq!(
+            SummaryCategory::from(Comparison::Flaky),
+            SummaryCategory::Unchanged
+        );
+        // Yanked versions weren't built, so they can't be regressions
+        assert_eq!(
+            SummaryCategory::from(Comparison::Yanked),
+            SummaryCategory::Unchanged
+        );
+    }
+
+    #[test]
+    fn test_out_of_memory_category() {
+        let krate = |res, start, end| CrateResult {
+            name: "memory-hungry (local)".into(),
+            url: String::new(),
+            krate: Crate::Local("memory-hungry".into()),
+            status: None,
+            res,
+            runs: ["stable", "beta"]
+                .map(String::from)
+                .into_iter()
+                .zip([start, end].map(|res| {
+                    Some(BuildTestResult {
+                        res,
+                        log: String::new(),
+                        log_url: None,
+                        duration_ms: None,
+                        repro: None,
+                    })
+                }))
+                .collect(),
+        };
+
+        assert_eq!(
+            SummaryCategory::for_crate(&krate(
+                Comparison::SpuriousFixed,
+                TestResult::BuildFail(FailureReason::OOM),
+                TestResult::TestFail(FailureReason::OOM),
+            )),
+            SummaryCategory::OutOfMemory
+        );
+        assert_eq!(
+            SummaryCategory::for_crate(&krate(
+                Comparison::Regressed,
+                TestResult::BuildFail(FailureReason::OOM),
+                TestResult::BuildFail(FailureReason::ICE),
+            )),
+            SummaryCategory::Regressed
+        );
+        assert_eq!(
+            SummaryCategory::for_crate(&krate(
+                Comparison::SameTestPass,
+                TestResult::TestPass,
+                TestResult::TestPass,
+            )),
+            SummaryCategory::Unchanged
+        );
+    }
+}
@@ -1,13 +1,19 @@
+use crate::config::Config;
 use crate::crates::Crate;
 use crate::db::{Database, QueryUtils};
-use crate::experiments::{Experiment, Status};
+use crate::experiments::{Confirmation, Experiment, Status};
 use crate::prelude::*;
 use crate::results::{
-    DeleteResults, EncodedLog, EncodingType, ReadResults, TestResult, WriteResults,
+    CompressionOptions, DeleteResults, EncodedLog, EncodingType, ReadResults, ResultKinds,
+    TestResult, WriteResults,
 };
 use crate::toolchain::Toolchain;
 use base64::Engine;
+use chrono::{DateTime, Utc};
 use rustwide::logging::{self, LogStorage};
+use std::borrow::Cow;
+use std::collections::BTreeSet;
+use std::time::{Duration, Instant};
 
 #[derive(Deserialize)]
 pub struct TaskResult {
@@ -16,6 +22,9 @@ pub struct TaskResult {
     pub toolchain: Toolchain,
     pub result: TestResult,
     pub log: String,
+    /// Wall-clock time of the build, not sent by older agents.
+    #[serde(default)]
+    pub duration_ms: Option<u64>,
 }
 
 #[derive(Deserialize)]
@@ -24,13 +33,73 @@ pub struct ProgressData {
     pub version: Option<(Crate, Crate)>,
 }
 
-pub struct DatabaseDB<'a> {
-    db: &'a Database,
+/// A result stored in the database, along with its position in the results table.
+pub struct StoredResult {
+    /// Opaque position of the result, increasing as results are stored or overwritten.
+    pub cursor: i64,
+    pub krate: Crate,
+    pub toolchain: Toolchain,
+    pub result: TestResult,
+    /// Result of the same crate on the toolchain it's compared with, if it was stored before this
+    /// one. Never set for the results on the extra toolchains.
+    pub previous: Option<(Toolchain, TestResult)>,
+    /// Missing for the results stored before the timestamp was recorded.
+    pub completed_at: Option<DateTime<Utc>>,
+}
+
+/// Result of a crate in an experiment.
+pub struct ExperimentResult {
+    pub experiment: String,
+    pub krate: Crate,
+    pub toolchain: Toolchain,
+    pub result: TestResult,
+}
+
+/// Results stored in the database, or written in a transaction of it.
+pub struct DatabaseDB<'a, DB = Database> {
+    db: &'a DB,
+    compression: CompressionOptions,
+    max_log_size: Option<usize>,
+}
+
+/// Log cut down to `max_size` bytes by dropping its middle, which is replaced by a marker, as
+/// the beginning and the end of a build are the most useful parts. Smaller logs are unchanged.
+fn truncate_log(log: &[u8], max_size: usize) -> Cow<'_, [u8]> {
+    if log.len() <= max_size {
+        return Cow::Borrowed(log);
+    }
+
+    let marker = |truncated: usize| format!("\n[... {truncated} bytes truncated ...]\n");
+    // The marker can't be longer than the one of the whole log
+    let kept = max_size.saturating_sub(marker(log.len()).len());
+    let head = kept / 2;
+    let tail = kept - head;
+
+    let mut truncated = Vec::with_capacity(max_size);
+    truncated.extend_from_slice(&log[..head]);
+    truncated.extend_from_slice(marker(log.len() - head - tail).as_bytes());
+    truncated.extend_from_slice(&log[log.len() - tail..]);
+    Cow::Owned(truncated)
 }
 
 impl<'a> DatabaseDB<'a> {
     pub fn new(db: &'a Database) -> Self {
-        DatabaseDB { db }
+        DatabaseDB {
+            db,
+            compression: CompressionOptions::default(),
+            max_log_size: None,
+        }
+    }
+
+    pub fn with_compression(mut self, compression: CompressionOptions) -> Self {
+        self.compression = compression;
+        self
+    }
+
+    /// Truncate the stored logs larger than `max_size` bytes, recording their original size.
+    pub fn with_max_log_size(mut self, max_size: usize) -> Self {
+        self.max_log_size = Some(max_size);
+        self
     }
 
     pub fn clear_stale_records(&self) -> Fallible<()> {
@@ -77,13 +146,234 @@ impl<'a> DatabaseDB<'a> {
         Ok(())
     }
 
+    /// Store results sent by the agents in a single transaction, returning whether each of them
+    /// was new or changed the stored one. Either all of them are stored or none is.
+    pub fn store_batch(
+        &self,
+        ex: &Experiment,
+        batch: &[&ProgressData],
+        encoding_type: EncodingType,
+    ) -> Fallible<Vec<bool>> {
+        self.db.transaction(|transaction| {
+            let results = DatabaseDB {
+                db: transaction,
+                compression: self.compression,
+                max_log_size: self.max_log_size,
+            };
+            batch
+                .iter()
+                .map(|data| results.store(ex, data, encoding_type))
+                .collect()
+        })
+    }
+
+    /// Delete the results of the crates with any result of the given kinds and queue them again,
+    /// returning how many crates were requeued. The other results are left untouched.
+    pub fn requeue_results(&self, ex: &Experiment, kinds: &ResultKinds) -> Fallible<usize> {
+        let mut crates = BTreeSet::new();
+        for (krate, result) in self.db.query(
+            "SELECT crate, result FROM results WHERE experiment = ?1;",
+            [&ex.name],
+            |row| {
+                Ok((
+                    row.get::<_, String>("crate")?,
+                    row.get::<_, String>("result")?,
+                ))
+            },
+        )? {
+            if kinds.contains(&result.parse()?) {
+                crates.insert(krate);
+            }
+        }
+
+        self.db.transaction(|transaction| {
+            for krate in &crates {
+                transaction.execute(
+                    "UPDATE experiment_crates SET status = ?1, started_at = NULL \
+                     WHERE experiment = ?2 AND crate = ?3;",
+                    &[&Status::Queued.to_string(), &ex.name, krate],
+                )?;
+                transaction.execute(
+                    "DELETE FROM results WHERE experiment = ?1 AND crate = ?2;",
+                    &[&ex.name, krate],
+                )?;
+            }
+            Ok(())
+        })?;
+
+        Ok(crates.len())
+    }
+
+    /// Once `agent` completed a crate of an experiment confirming its regressions, move the
+    /// confirmation of the crate's regression forward. Regressed crates are queued again, with
+    /// their results deleted, until another agent confirms the regression or finds it flaky.
+    pub fn confirm_regression(
+        &self,
+        ex: &Experiment,
+        config: &Config,
+        krate: &Crate,
+        agent: &str,
+    ) -> Fallible<Option<Confirmation>> {
+        if !ex.confirm_regressions {
+            return Ok(None);
+        }
+
+        let Some((status, current, first_agent)) = self.db.get_row(
+            "SELECT status, confirmation, first_agent FROM experiment_crates \
+             WHERE experiment = ?1 AND crate = ?2;",
+            [&ex.name, &krate.id()],
+            |row| {
+                Ok((
+                    row.get::<_, String>("status")?,
+                    row.get::<_, Option<String>>("confirmation")?,
+                    row.get::<_, Option<String>>("first_agent")?,
+                ))
+            },
+        )?
+        else {
+            return Ok(None);
+        };
+        let current = current.map(|c| c.parse::<Confirmation>()).transpose()?;
+
+        // Wait for the results with every toolchain
+        if status != Status::Completed.to_str() {
+            return Ok(current);
+        }
+
+        let regressed =
+            crate::report::count_regressions(self, config, ex, std::slice::from_ref(krate))? > 0;
+        let next = Confirmation::next(current.zip(first_agent.as_deref()), agent, regressed);
+        match next {
+            Some(Confirmation::Pending) => self.db.transaction(|transaction| {
+                transaction.execute(
+                    "UPDATE experiment_crates SET status = ?1, started_at = NULL, \
+                     confirmation = ?2, first_agent = IFNULL(first_agent, ?3) \
+                     WHERE experiment = ?4 AND crate = ?5;",
+                    &[
+                        &Status::Queued.to_str(),
+                        &Confirmation::Pending.to_str(),
+                        &agent,
+                        &ex.name,
+                        &krate.id(),
+                    ],
+                )?;
+                transaction.execute(
+                    "DELETE FROM results WHERE experiment = ?1 AND crate = ?2;",
+                    &[&ex.name, &krate.id()],
+                )?;
+                Ok(())
+            })?,
+            Some(confirmation) if next != current => {
+                self.db.execute(
+                    "UPDATE experiment_crates SET confirmation = ?1 \
+                     WHERE experiment = ?2 AND crate = ?3;",
+                    &[&confirmation.to_str(), &ex.name, &krate.id()],
+                )?;
+            }
+            _ => {}
+        }
+
+        Ok(next)
+    }
+
+    /// Load at most `limit` results of the experiment stored after `cursor`, in the order they
+    /// were stored. Pass the cursor of the last returned result to resume from there. Overwritten
+    /// results are returned again after the cursor, as if they were stored anew.
+    pub fn load_results_since(
+        &self,
+        ex: &Experiment,
+        cursor: i64,
+        limit: u32,
+    ) -> Fallible<Vec<StoredResult>> {
+        self.db
+            .query(
+                "SELECT r.seq, r.crate, r.toolchain, r.result, r.completed_at, \
+                        o.toolchain, o.result \
+                 FROM results r LEFT JOIN results o \
+                 ON o.experiment = r.experiment AND o.crate = r.crate AND o.seq < r.seq \
+                    AND o.toolchain = CASE r.toolchain WHEN ?4 THEN ?5 WHEN ?5 THEN ?4 END \
+                 WHERE r.experiment = ?1 AND r.seq > ?2 \
+                 ORDER BY r.seq \
+                 LIMIT ?3;",
+                rusqlite::params![
+                    ex.name,
+                    cursor,
+                    limit,
+                    ex.toolchains[0].to_string(),
+                    ex.toolchains[1].to_string()
+                ],
+                |row| {
+                    Ok((
+                        row.get::<_, i64>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                        row.get::<_, Option<DateTime<Utc>>>(4)?,
+                        row.get::<_, Option<String>>(5)?,
+                        row.get::<_, Option<String>>(6)?,
+                    ))
+                },
+            )?
+            .into_iter()
+            .map(
+                |(cursor, krate, toolchain, result, completed_at, prev_tc, prev_result)| {
+                    Ok(StoredResult {
+                        cursor,
+                        krate: krate.parse()?,
+                        toolchain: toolchain.parse()?,
+                        result: result.parse()?,
+                        previous: match (prev_tc, prev_result) {
+                            (Some(tc), Some(result)) => Some((tc.parse()?, result.parse()?)),
+                            _ => None,
+                        },
+                        completed_at,
+                    })
+                },
+            )
+            .collect()
+    }
+
+    /// Load the results of every version of the registry crate `name`, across all the
+    /// experiments.
+    pub fn load_registry_crate_results(&self, name: &str) -> Fallible<Vec<ExperimentResult>> {
+        // The ids of all the versions sort between `reg/{name}/` and `reg/{name}0`, as `0`
+        // follows `/`, which lets the range be looked up in the index on the crate column
+        self.db
+            .query(
+                "SELECT experiment, crate, toolchain, result FROM results \
+                 WHERE crate >= ?1 AND crate < ?2;",
+                [format!("reg/{name}/"), format!("reg/{name}0")],
+                |row| {
+                    Ok((
+                        row.get::<_, String>(0)?,
+                        row.get::<_, String>(1)?,
+                        row.get::<_, String>(2)?,
+                        row.get::<_, String>(3)?,
+                    ))
+                },
+            )?
+            .into_iter()
+            .map(|(experiment, krate, toolchain, result)| {
+                Ok(ExperimentResult {
+                    experiment,
+                    krate: krate.parse()?,
+                    toolchain: toolchain.parse()?,
+                    result: result.parse()?,
+                })
+            })
+            .collect()
+    }
+}
+
+impl<'a, DB: QueryUtils> DatabaseDB<'a, DB> {
+    /// Store a result sent by an agent, returning whether it was new or changed the stored one.
     pub fn store(
         &self,
         ex: &Experiment,
         data: &ProgressData,
         encoding_type: EncodingType,
-    ) -> Fallible<()> {
-        self.store_result(
+    ) -> Fallible<bool> {
+        let stored = self.store_result(
             ex,
             &data.result.krate,
             &data.result.toolchain,
@@ -92,6 +382,7 @@ impl<'a> DatabaseDB<'a> {
                 .decode(&data.result.log)
                 .with_context(|_| "invalid base64 log provided")?,
             encoding_type,
+            data.result.duration_ms.map(Duration::from_millis),
         )?;
 
         if let Some((old, new)) = &data.version {
@@ -100,14 +391,21 @@ impl<'a> DatabaseDB<'a> {
 
         self.mark_crate_as_completed(ex, &data.result.krate)?;
 
-        Ok(())
+        Ok(stored)
     }
 
     fn mark_crate_as_completed(&self, ex: &Experiment, krate: &Crate) -> Fallible<usize> {
+        // The crate is only done once every toolchain, extra ones included, has a result
+        let toolchains = ex.all_toolchains().count() as i64;
         self.db.execute(
             "UPDATE experiment_crates SET status = ?1 WHERE experiment = ?2 AND crate = ?3 \
-             AND ( (SELECT COUNT(*) FROM results WHERE experiment = ?2 AND crate = ?3) > 1 )",
-            &[&Status::Completed.to_string(), &ex.name, &krate.id()],
+             AND ( (SELECT COUNT(*) FROM results WHERE experiment = ?2 AND crate = ?3) >= ?4 )",
+            &[
+                &Status::Completed.to_string(),
+                &ex.name,
+                &krate.id(),
+                &toolchains,
+            ],
         )
     }
 
@@ -119,12 +417,40 @@ impl<'a> DatabaseDB<'a> {
         res: &TestResult,
         log: &[u8],
         desired_encoding_type: EncodingType,
-    ) -> Fallible<()> {
-        let encoded_log = EncodedLog::from_plain_slice(log, desired_encoding_type)?;
-        self.insert_into_results(ex, krate, toolchain, res, encoded_log)?;
-        Ok(())
+        duration: Option<Duration>,
+    ) -> Fallible<bool> {
+        let size = log.len();
+        let log = match self.max_log_size {
+            Some(max_size) => truncate_log(log, max_size),
+            None => Cow::Borrowed(log),
+        };
+        // Only recorded for the truncated logs
+        let original_log_size = matches!(log, Cow::Owned(_)).then_some(size);
+
+        // The stored log records its encoding, so readers decode the plain ones as such
+        let encoding_type = if log.len() > self.compression.min_compressed_size {
+            desired_encoding_type
+        } else {
+            EncodingType::Plain
+        };
+        let encoded_log =
+            EncodedLog::from_plain_slice_with(&log, encoding_type, &self.compression)?;
+        self.insert_into_results(
+            ex,
+            krate,
+            toolchain,
+            res,
+            encoded_log,
+            duration,
+            original_log_size,
+        )
     }
 
+    /// Insert a result, or update the stored one for the same crate and toolchain.
+    ///
+    /// Agents submit results again when they don't know whether the server received them, so
+    /// submitting the same result twice is a no-op, and a different one overwrites the stored
+    /// one. Returns whether anything was written.
     fn insert_into_results(
         &self,
         ex: &Experiment,
@@ -132,10 +458,42 @@ impl<'a> DatabaseDB<'a> {
         toolchain: &Toolchain,
         res: &TestResult,
         log: EncodedLog,
-    ) -> Fallible<usize> {
+        duration: Option<Duration>,
+        original_log_size: Option<usize>,
+    ) -> Fallible<bool> {
+        if let (Some(stored), Some(stored_log)) = (
+            self.load_test_result(ex, toolchain, krate)?,
+            self.load_log(ex, toolchain, krate)?,
+        ) {
+            // Decoding both logs is only needed when they're stored differently
+            let same_log = (stored_log.get_encoding_type() == log.get_encoding_type()
+                && stored_log.as_slice() == log.as_slice())
+                || stored_log.to_plain()? == log.to_plain()?;
+            if stored == *res && same_log {
+                return Ok(false);
+            }
+            warn!(
+                "overwriting the result of {} on {} in {} ({} -> {})",
+                krate.id(),
+                toolchain,
+                ex.name,
+                stored,
+                res
+            );
+        }
+
+        // Overwritten results keep their rowid, so they're moved to the end of the sequence
+        // instead, for `load_results_since` to return them again
         self.db.execute(
-            "INSERT INTO results (experiment, crate, toolchain, result, log, encoding) \
-             VALUES (?1, ?2, ?3, ?4, ?5, ?6);",
+            "INSERT INTO results \
+             (experiment, crate, toolchain, result, log, encoding, completed_at, duration_ms, \
+             original_log_size, seq) \
+             VALUES (?1, ?2, ?3, ?4, ?5, ?6, ?7, ?8, ?9, \
+             (SELECT COALESCE(MAX(seq), 0) + 1 FROM results WHERE experiment = ?1)) \
+             ON CONFLICT (experiment, crate, toolchain) DO UPDATE SET \
+             result = excluded.result, log = excluded.log, encoding = excluded.encoding, \
+             completed_at = excluded.completed_at, duration_ms = excluded.duration_ms, \
+             original_log_size = excluded.original_log_size, seq = excluded.seq;",
             &[
                 &ex.name,
                 &krate.id(),
@@ -143,12 +501,16 @@ impl<'a> DatabaseDB<'a> {
                 &res.to_string(),
                 &log.as_slice(),
                 &log.get_encoding_type().to_str(),
+                &Utc::now(),
+                &duration.map(|duration| duration.as_millis() as i64),
+                &original_log_size.map(|size| size as i64),
             ],
-        )
+        )?;
+        Ok(true)
     }
 }
 
-impl<'a> ReadResults for DatabaseDB<'a> {
+impl<'a, DB: QueryUtils> ReadResults for DatabaseDB<'a, DB> {
     fn load_log(
         &self,
         ex: &Experiment,
@@ -168,6 +530,8 @@ impl<'a> ReadResults for DatabaseDB<'a> {
                 Ok(match encoding {
                     EncodingType::Plain => EncodedLog::Plain(log),
                     EncodingType::Gzip => EncodedLog::Gzip(log),
+                    EncodingType::Zstd => EncodedLog::Zstd(log),
+                    EncodingType::Brotli => EncodedLog::Brotli(log),
                 })
             },
         )
@@ -196,9 +560,36 @@ impl<'a> ReadResults for DatabaseDB<'a> {
             Ok(None)
         }
     }
+
+    fn load_duration(
+        &self,
+        ex: &Experiment,
+        toolchain: &Toolchain,
+        krate: &Crate,
+    ) -> Fallible<Option<Duration>> {
+        let duration: Option<Option<i64>> = self.db.get_row(
+            "SELECT duration_ms FROM results \
+             WHERE experiment = ?1 AND toolchain = ?2 AND crate = ?3 \
+             LIMIT 1;",
+            [&ex.name, &toolchain.to_string(), &krate.id()],
+            |row| row.get("duration_ms"),
+        )?;
+        Ok(duration
+            .flatten()
+            .map(|ms| Duration::from_millis(ms as u64)))
+    }
+
+    fn load_confirmation(&self, ex: &Experiment, krate: &Crate) -> Fallible<Option<Confirmation>> {
+        let confirmation: Option<Option<String>> = self.db.get_row(
+            "SELECT confirmation FROM experiment_crates WHERE experiment = ?1 AND crate = ?2;",
+            [&ex.name, &krate.id()],
+            |row| row.get("confirmation"),
+        )?;
+        Ok(confirmation.flatten().map(|c| c.parse()).transpose()?)
+    }
 }
 
-impl<'a> WriteResults for DatabaseDB<'a> {
+impl<'a, DB: QueryUtils> WriteResults for DatabaseDB<'a, DB> {
     fn get_result(
         &self,
         ex: &Experiment,
@@ -228,7 +619,9 @@ impl<'a> WriteResults for DatabaseDB<'a> {
     where
         F: FnOnce() -> Fallible<TestResult>,
     {
+        let start = Instant::now();
         let result = logging::capture(storage, f)?;
+        let duration = start.elapsed();
         let output = storage.to_string();
         self.store_result(
             ex,
@@ -237,6 +630,7 @@ impl<'a> WriteResults for DatabaseDB<'a> {
             &result,
             output.as_bytes(),
             encoding_type,
+            Some(duration),
         )?;
         Ok(result)
     }
@@ -263,18 +657,19 @@ mod tests {
     use base64::Engine;
     use rustwide::logging::LogStorage;
 
-    use super::{DatabaseDB, ProgressData, TaskResult};
+    use super::{truncate_log, DatabaseDB, ProgressData, TaskResult};
     use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::agent::Capabilities;
     use crate::config::Config;
     use crate::crates::{Crate, RegistryCrate};
-    use crate::db::Database;
-    use crate::experiments::Experiment;
+    use crate::db::{Database, QueryUtils};
+    use crate::experiments::{Confirmation, Experiment, Status};
     use crate::prelude::*;
     use crate::results::{
         DeleteResults, EncodedLog, EncodingType, FailureReason, ReadResults, TestResult,
         WriteResults,
     };
-    use crate::toolchain::{MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
 
     use std::collections::BTreeSet;
 
@@ -379,7 +774,9 @@ mod tests {
             .unwrap();
         assert!(String::from_utf8_lossy(match result_var {
             EncodedLog::Plain(ref data) => data,
-            EncodedLog::Gzip(_) => panic!("The encoded log should not be Gzipped."),
+            EncodedLog::Gzip(_) | EncodedLog::Zstd(_) | EncodedLog::Brotli(_) => {
+                panic!("The encoded log should not be compressed.")
+            }
         })
         .contains("hello world"));
 
@@ -468,6 +865,7 @@ mod tests {
                         toolchain: MAIN_TOOLCHAIN.clone(),
                         result: TestResult::TestPass,
                         log: base64::engine::general_purpose::STANDARD.encode("foo"),
+                        duration_ms: None,
                     },
                     version: Some((krate.clone(), updated.clone())),
                 },
@@ -497,4 +895,605 @@ mod tests {
             None
         );
     }
+
+    #[test]
+    fn test_store_twice() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let krate = Crate::Local("build-pass".into());
+        let progress = |result, log: &str| ProgressData {
+            result: TaskResult {
+                krate: krate.clone(),
+                toolchain: MAIN_TOOLCHAIN.clone(),
+                result,
+                log: base64::engine::general_purpose::STANDARD.encode(log),
+                duration_ms: None,
+            },
+            version: None,
+        };
+        let count = || -> u32 {
+            db.get_row(
+                "SELECT COUNT(*) FROM results WHERE experiment = ?1;",
+                [&ex.name],
+                |row| row.get(0),
+            )
+            .unwrap()
+            .unwrap()
+        };
+
+        // Submitting the same result again is a no-op
+        let first = progress(TestResult::TestPass, "foo");
+        assert!(results.store(&ex, &first, EncodingType::Plain).unwrap());
+        assert!(!results.store(&ex, &first, EncodingType::Plain).unwrap());
+        assert_eq!(count(), 1);
+
+        // While a different one replaces the stored result
+        let changed = progress(TestResult::TestSkipped, "bar");
+        assert!(results.store(&ex, &changed, EncodingType::Plain).unwrap());
+        assert_eq!(count(), 1);
+        assert_eq!(
+            results
+                .load_test_result(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap(),
+            Some(TestResult::TestSkipped)
+        );
+        assert_eq!(
+            results.load_log(&ex, &MAIN_TOOLCHAIN, &krate).unwrap(),
+            Some(EncodedLog::Plain(b"bar".to_vec()))
+        );
+    }
+
+    #[test]
+    fn test_compress_above_threshold() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let small = "x".repeat(200);
+        let large = "error: could not compile\n".repeat(1024 * 1024 / 25);
+        for (name, log, encoding) in [
+            ("small", &small, EncodingType::Plain),
+            ("large", &large, EncodingType::Gzip),
+        ] {
+            let krate = Crate::Local(name.into());
+            let progress = ProgressData {
+                result: TaskResult {
+                    krate: krate.clone(),
+                    toolchain: MAIN_TOOLCHAIN.clone(),
+                    result: TestResult::TestPass,
+                    log: base64::engine::general_purpose::STANDARD.encode(log),
+                    duration_ms: None,
+                },
+                version: None,
+            };
+            results.store(&ex, &progress, EncodingType::Gzip).unwrap();
+
+            let stored = results
+                .load_log(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored.get_encoding_type(), encoding);
+            assert_eq!(stored.to_plain().unwrap(), log.as_bytes());
+        }
+    }
+
+    #[test]
+    fn test_truncate_log() {
+        const MAX_SIZE: usize = 10 * 1024 * 1024;
+
+        let small = b"error: could not compile".as_slice();
+        assert_eq!(truncate_log(small, MAX_SIZE).as_ref(), small);
+
+        let mut log = b"HEAD".to_vec();
+        log.resize(30 * 1024 * 1024 - 4, b'x');
+        log.extend_from_slice(b"TAIL");
+        let truncated = String::from_utf8(truncate_log(&log, MAX_SIZE).into_owned()).unwrap();
+        assert!(truncated.len() <= MAX_SIZE);
+        assert!(truncated.starts_with("HEAD"));
+        assert!(truncated.ends_with("TAIL"));
+
+        // The marker accounts for every byte left out
+        let (head, rest) = truncated.split_once("\n[... ").unwrap();
+        let (removed, tail) = rest.split_once(" bytes truncated ...]\n").unwrap();
+        let removed: usize = removed.parse().unwrap();
+        assert_eq!(head.len() + removed + tail.len(), log.len());
+        assert!(head.len() + tail.len() > MAX_SIZE - 64);
+    }
+
+    #[test]
+    fn test_store_truncated_log() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db).with_max_log_size(1024);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        for (name, size, original_size) in [("small", 1024, None), ("large", 4096, Some(4096))] {
+            let krate = Crate::Local(name.into());
+            let progress = ProgressData {
+                result: TaskResult {
+                    krate: krate.clone(),
+                    toolchain: MAIN_TOOLCHAIN.clone(),
+                    result: TestResult::TestPass,
+                    log: base64::engine::general_purpose::STANDARD.encode("x".repeat(size)),
+                    duration_ms: None,
+                },
+                version: None,
+            };
+            results.store(&ex, &progress, EncodingType::Plain).unwrap();
+
+            let stored = results
+                .load_log(&ex, &MAIN_TOOLCHAIN, &krate)
+                .unwrap()
+                .unwrap()
+                .to_plain()
+                .unwrap();
+            assert!(stored.len() <= 1024);
+            let stored_size: Option<i64> = db
+                .get_row(
+                    "SELECT original_log_size FROM results WHERE crate = ?1;",
+                    [&krate.id()],
+                    |row| row.get(0),
+                )
+                .unwrap()
+                .unwrap();
+            assert_eq!(stored_size, original_size);
+        }
+    }
+
+    #[test]
+    fn test_store_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("crater.db");
+        let config = Config::default();
+
+        let db = Database::open_at(&path).unwrap();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let crates = ex.get_crates(&db).unwrap();
+
+        let progress = |krate: &Crate, toolchain: &Toolchain, log: &str| ProgressData {
+            result: TaskResult {
+                krate: krate.clone(),
+                toolchain: toolchain.clone(),
+                result: TestResult::TestPass,
+                log: log.into(),
+                duration_ms: None,
+            },
+            version: None,
+        };
+        let log = base64::engine::general_purpose::STANDARD.encode("log");
+
+        // Both results of the first crate, and one of the second
+        let batch = [
+            progress(&crates[0], &ex.toolchains[0], &log),
+            progress(&crates[0], &ex.toolchains[1], &log),
+            progress(&crates[1], &ex.toolchains[0], &log),
+        ];
+        let results = DatabaseDB::new(&db);
+        assert_eq!(
+            results
+                .store_batch(&ex, &batch.iter().collect::<Vec<_>>(), EncodingType::Plain)
+                .unwrap(),
+            vec![true, true, true]
+        );
+
+        // A batch with an invalid result is stored not at all
+        let batch = [
+            progress(&crates[1], &ex.toolchains[1], &log),
+            progress(&crates[2], &ex.toolchains[0], "not base64!"),
+        ];
+        assert!(results
+            .store_batch(&ex, &batch.iter().collect::<Vec<_>>(), EncodingType::Plain)
+            .is_err());
+
+        // Nothing is held in memory, so the stored results survive a crash
+        drop(results);
+        drop(ctx);
+        drop(db);
+        let db = Database::open_at(&path).unwrap();
+        let results = DatabaseDB::new(&db);
+        for (krate, toolchain, expected) in [
+            (&crates[0], &ex.toolchains[0], Some(TestResult::TestPass)),
+            (&crates[0], &ex.toolchains[1], Some(TestResult::TestPass)),
+            (&crates[1], &ex.toolchains[0], Some(TestResult::TestPass)),
+            (&crates[1], &ex.toolchains[1], None),
+            (&crates[2], &ex.toolchains[0], None),
+        ] {
+            assert_eq!(
+                results.load_test_result(&ex, toolchain, krate).unwrap(),
+                expected
+            );
+        }
+
+        // Only the crate with both results is completed, the others are dispatched again
+        assert_eq!(
+            ex.get_completed_crates(&db).unwrap(),
+            vec![crates[0].clone()]
+        );
+    }
+
+    #[test]
+    fn test_load_results_since() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let foo = Crate::Local("foo".into());
+        let bar = Crate::Local("bar".into());
+        let stored = [
+            (&foo, &*MAIN_TOOLCHAIN, TestResult::TestPass),
+            (
+                &bar,
+                &*TEST_TOOLCHAIN,
+                TestResult::TestFail(FailureReason::Unknown),
+            ),
+            (
+                &foo,
+                &*TEST_TOOLCHAIN,
+                TestResult::BuildFail(FailureReason::Unknown),
+            ),
+        ];
+        for (krate, toolchain, result) in &stored {
+            results
+                .store_result(
+                    &ex,
+                    krate,
+                    toolchain,
+                    result,
+                    b"",
+                    EncodingType::Plain,
+                    None,
+                )
+                .unwrap();
+        }
+
+        // Results are returned in the order they were stored
+        let all = results.load_results_since(&ex, 0, 10).unwrap();
+        assert_eq!(
+            all.iter()
+                .map(|r| (&r.krate, &r.toolchain, &r.result))
+                .collect::<Vec<_>>(),
+            stored
+                .iter()
+                .map(|(krate, toolchain, result)| (*krate, *toolchain, result))
+                .collect::<Vec<_>>()
+        );
+        assert!(all.windows(2).all(|w| w[0].cursor < w[1].cursor));
+        assert!(all.iter().all(|r| r.completed_at.is_some()));
+
+        // The previous result is only known for the second result of a crate
+        assert!(all[0].previous.is_none());
+        assert!(all[1].previous.is_none());
+        assert_eq!(
+            all[2].previous,
+            Some((MAIN_TOOLCHAIN.clone(), TestResult::TestPass))
+        );
+
+        // Resuming from a cursor neither skips nor repeats results
+        let first = results.load_results_since(&ex, 0, 1).unwrap();
+        assert_eq!(first.len(), 1);
+        let rest = results
+            .load_results_since(&ex, first[0].cursor, 10)
+            .unwrap();
+        assert_eq!(
+            first
+                .iter()
+                .chain(&rest)
+                .map(|r| r.cursor)
+                .collect::<Vec<_>>(),
+            all.iter().map(|r| r.cursor).collect::<Vec<_>>()
+        );
+        assert!(results
+            .load_results_since(&ex, all[2].cursor, 10)
+            .unwrap()
+            .is_empty());
+
+        // Overwritten results come again after the cursor, unlike the resubmitted ones
+        let store = |result: &TestResult| {
+            results
+                .store_result(
+                    &ex,
+                    &foo,
+                    &MAIN_TOOLCHAIN,
+                    result,
+                    b"",
+                    EncodingType::Plain,
+                    None,
+                )
+                .unwrap()
+        };
+        assert!(!store(&TestResult::TestPass));
+        assert!(results
+            .load_results_since(&ex, all[2].cursor, 10)
+            .unwrap()
+            .is_empty());
+        assert!(store(&TestResult::TestFail(FailureReason::Unknown)));
+        let overwritten = results.load_results_since(&ex, all[2].cursor, 10).unwrap();
+        assert_eq!(overwritten.len(), 1);
+        assert_eq!(overwritten[0].krate, foo);
+        assert_eq!(overwritten[0].toolchain, *MAIN_TOOLCHAIN);
+        assert_eq!(
+            overwritten[0].result,
+            TestResult::TestFail(FailureReason::Unknown)
+        );
+        assert_eq!(
+            overwritten[0].previous,
+            Some((
+                TEST_TOOLCHAIN.clone(),
+                TestResult::BuildFail(FailureReason::Unknown)
+            ))
+        );
+        assert!(overwritten[0].cursor > all[2].cursor);
+    }
+
+    #[test]
+    fn test_requeue_results() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        // Create a dummy experiment to attach the results to
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+
+        let local = |name: &str| Crate::Local(name.to_string());
+        let mixed = [
+            (
+                local("build-pass"),
+                TestResult::FetchError("timed out".into()),
+                TestResult::FetchError("timed out".into()),
+            ),
+            (
+                local("memory-hungry"),
+                TestResult::TestPass,
+                TestResult::Timeout,
+            ),
+            (
+                local("build-fail"),
+                TestResult::TestPass,
+                TestResult::BuildFail(FailureReason::Unknown),
+            ),
+            (
+                local("test-fail"),
+                TestResult::TestPass,
+                TestResult::TestPass,
+            ),
+        ];
+        for (krate, start, end) in &mixed {
+            for (toolchain, result) in [(&*MAIN_TOOLCHAIN, start), (&*TEST_TOOLCHAIN, end)] {
+                results
+                    .store(
+                        &ex,
+                        &ProgressData {
+                            result: TaskResult {
+                                krate: krate.clone(),
+                                toolchain: toolchain.clone(),
+                                result: result.clone(),
+                                log: base64::engine::general_purpose::STANDARD.encode("foo"),
+                                duration_ms: None,
+                            },
+                            version: None,
+                        },
+                        EncodingType::Plain,
+                    )
+                    .unwrap();
+            }
+        }
+        ex.set_status(&db, Status::Running).unwrap();
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
+        ex.set_status(&db, Status::Completed).unwrap();
+
+        let kinds = "fetch-error,timeout".parse().unwrap();
+        assert_eq!(results.requeue_results(&ex, &kinds).unwrap(), 2);
+        ex.requeue(&db).unwrap();
+
+        // Both runs of the requeued crates are deleted, even the good start run of memory-hungry
+        for krate in [local("build-pass"), local("memory-hungry")] {
+            for toolchain in [&*MAIN_TOOLCHAIN, &*TEST_TOOLCHAIN] {
+                assert!(results
+                    .get_result(&ex, toolchain, &krate)
+                    .unwrap()
+                    .is_none());
+            }
+        }
+        assert_eq!(
+            results
+                .get_result(&ex, &TEST_TOOLCHAIN, &local("build-fail"))
+                .unwrap(),
+            Some(TestResult::BuildFail(FailureReason::Unknown))
+        );
+        assert_eq!(
+            results
+                .get_result(&ex, &TEST_TOOLCHAIN, &local("test-fail"))
+                .unwrap(),
+            Some(TestResult::TestPass)
+        );
+
+        // The experiment runs again, and is only complete once the requeued crates are done
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        assert_eq!(ex.status, Status::Queued);
+        assert!(ex.completed_at.is_none());
+        let uncompleted = ex.get_uncompleted_crates(&db, None).unwrap();
+        assert!(uncompleted.contains(&local("build-pass")));
+        assert!(uncompleted.contains(&local("memory-hungry")));
+        assert!(!uncompleted.contains(&local("build-fail")));
+        assert!(!uncompleted.contains(&local("test-fail")));
+    }
+
+    #[test]
+    fn test_confirm_regressions() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment {
+            confirm_regressions: true,
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let caps = Capabilities::new(&["linux"]);
+
+        // Store the results of a crate run by an agent, passing with the start toolchain
+        let run = |agent: &str, krate: &Crate, end: &TestResult| {
+            for (toolchain, result) in [
+                (&*MAIN_TOOLCHAIN, &TestResult::TestPass),
+                (&*TEST_TOOLCHAIN, end),
+            ] {
+                let data = ProgressData {
+                    result: TaskResult {
+                        krate: krate.clone(),
+                        toolchain: toolchain.clone(),
+                        result: result.clone(),
+                        log: base64::engine::general_purpose::STANDARD.encode("foo"),
+                        duration_ms: None,
+                    },
+                    version: None,
+                };
+                results.store(&ex, &data, EncodingType::Plain).unwrap();
+            }
+            results
+                .confirm_regression(&ex, &config, krate, agent)
+                .unwrap()
+        };
+        let regressed = TestResult::BuildFail(FailureReason::Unknown);
+        let confirmed = Crate::Local("beta-regression".into());
+        let flaky = Crate::Local("ice-regression".into());
+        let passing = Crate::Local("build-pass".into());
+
+        // The first agent gets every crate, and two of them regress
+        let crates = ex
+            .get_uncompleted_crates_for(&db, &config, "agent-1", &caps, None)
+            .unwrap();
+        assert_eq!(crates.len(), ex.get_crates(&db).unwrap().len());
+        assert_eq!(run("agent-1", &passing, &TestResult::TestPass), None);
+        assert_eq!(
+            run("agent-1", &confirmed, &regressed),
+            Some(Confirmation::Pending)
+        );
+        assert_eq!(
+            run("agent-1", &flaky, &regressed),
+            Some(Confirmation::Pending)
+        );
+
+        // The regressed crates are queued again without their results, for another agent only
+        assert!(results
+            .load_test_result(&ex, &MAIN_TOOLCHAIN, &confirmed)
+            .unwrap()
+            .is_none());
+        assert!(ex
+            .get_uncompleted_crates_for(&db, &config, "agent-1", &caps, None)
+            .unwrap()
+            .is_empty());
+        let rerun = ex
+            .get_uncompleted_crates_for(&db, &config, "agent-2", &caps, None)
+            .unwrap();
+        assert_eq!(rerun.len(), 2);
+        assert!(rerun.contains(&confirmed) && rerun.contains(&flaky));
+
+        // An agreeing rerun confirms the regression, a disagreeing one marks the crate as flaky
+        assert_eq!(
+            run("agent-2", &confirmed, &regressed),
+            Some(Confirmation::Confirmed)
+        );
+        assert_eq!(
+            run("agent-2", &flaky, &TestResult::TestPass),
+            Some(Confirmation::Flaky)
+        );
+        assert_eq!(
+            results.load_confirmation(&ex, &confirmed).unwrap(),
+            Some(Confirmation::Confirmed)
+        );
+        assert_eq!(
+            results.load_confirmation(&ex, &flaky).unwrap(),
+            Some(Confirmation::Flaky)
+        );
+        assert_eq!(results.load_confirmation(&ex, &passing).unwrap(), None);
+        assert!(ex
+            .get_uncompleted_crates_for(&db, &config, "agent-2", &caps, None)
+            .unwrap()
+            .is_empty());
+    }
+
+    #[test]
+    fn test_complete_with_extra_toolchains() {
+        let db = Database::temp().unwrap();
+        let results = DatabaseDB::new(&db);
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        let nightly: Toolchain = "nightly".parse().unwrap();
+        CreateExperiment {
+            extra_toolchains: vec![nightly.clone()],
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        ex.get_uncompleted_crates(&db, None).unwrap();
+
+        let krate = Crate::Local("build-pass".into());
+        let status = || -> String {
+            db.get_row(
+                "SELECT status FROM experiment_crates WHERE experiment = ?1 AND crate = ?2;",
+                [&ex.name, &krate.id()],
+                |r| r.get(0),
+            )
+            .unwrap()
+            .unwrap()
+        };
+        let store = |toolchain: &Toolchain| {
+            let data = ProgressData {
+                result: TaskResult {
+                    krate: krate.clone(),
+                    toolchain: toolchain.clone(),
+                    result: TestResult::TestPass,
+                    log: base64::engine::general_purpose::STANDARD.encode("foo"),
+                    duration_ms: None,
+                },
+                version: None,
+            };
+            results.store(&ex, &data, EncodingType::Plain).unwrap();
+        };
+
+        // The crate isn't completed until the extra toolchain has a result too
+        store(&MAIN_TOOLCHAIN);
+        store(&TEST_TOOLCHAIN);
+        assert_ne!(status(), Status::Completed.to_str());
+        assert!(!ex.get_completed_crates(&db).unwrap().contains(&krate));
+
+        store(&nightly);
+        assert_eq!(status(), Status::Completed.to_str());
+        assert!(ex.get_completed_crates(&db).unwrap().contains(&krate));
+    }
 }
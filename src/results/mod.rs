@@ -2,10 +2,12 @@ mod db;
 #[cfg(test)]
 mod dummy;
 use crate::crates::Crate;
-use crate::experiments::Experiment;
+use crate::experiments::{Confirmation, Experiment};
 use crate::prelude::*;
 
-pub use crate::results::db::{DatabaseDB, ProgressData};
+pub use crate::results::db::{
+    DatabaseDB, ExperimentResult, ProgressData, StoredResult, TaskResult,
+};
 #[cfg(test)]
 pub use crate::results::dummy::DummyDB;
 use crate::toolchain::Toolchain;
@@ -14,6 +16,7 @@ use flate2::write::GzEncoder;
 use flate2::Compression;
 use rustwide::logging::LogStorage;
 use std::collections::BTreeSet;
+use std::time::Duration;
 use std::{fmt, io::Read, io::Write, str::FromStr};
 
 pub trait ReadResults {
@@ -29,6 +32,24 @@ pub trait ReadResults {
         toolchain: &Toolchain,
         krate: &Crate,
     ) -> Fallible<Option<TestResult>>;
+    /// Wall-clock time of the crate's build, not recorded for the results of older versions.
+    fn load_duration(
+        &self,
+        _ex: &Experiment,
+        _toolchain: &Toolchain,
+        _krate: &Crate,
+    ) -> Fallible<Option<Duration>> {
+        Ok(None)
+    }
+    /// State of the confirmation of the crate's regression on another agent, only available for
+    /// the experiments confirming their regressions.
+    fn load_confirmation(
+        &self,
+        _ex: &Experiment,
+        _krate: &Crate,
+    ) -> Fallible<Option<Confirmation>> {
+        Ok(None)
+    }
 }
 
 pub trait WriteResults {
@@ -60,31 +81,97 @@ pub trait DeleteResults {
 string_enum!(pub enum EncodingType {
     Plain => "plain",
     Gzip => "gzip",
+    Zstd => "zstd",
+    Brotli => "brotli",
 });
 
+const BROTLI_MAX_QUALITY: u32 = 11;
+const BROTLI_BUFFER_SIZE: usize = 4096;
+/// Base 2 logarithm of the brotli window size, 22 being the default of the reference encoder.
+const BROTLI_WINDOW_SIZE: u32 = 22;
+
+/// Settings of the compressors used to encode logs.
+#[derive(Clone, Copy, Debug, Serialize, Deserialize)]
+#[serde(rename_all = "kebab-case")]
+pub struct CompressionOptions {
+    /// Gzip compression level, from 0 (no compression) to 9 (best compression).
+    #[serde(default = "default_gzip_level")]
+    pub gzip_level: u32,
+    /// Brotli compression quality, from 0 (fastest) to 11 (best compression).
+    #[serde(default = "default_brotli_quality")]
+    pub brotli_quality: u32,
+    /// Logs up to this many bytes are stored uncompressed, as the compression headers would make
+    /// them larger.
+    #[serde(default = "default_min_compressed_size")]
+    pub min_compressed_size: usize,
+}
+
+fn default_gzip_level() -> u32 {
+    Compression::default().level()
+}
+
+fn default_brotli_quality() -> u32 {
+    BROTLI_MAX_QUALITY
+}
+
+fn default_min_compressed_size() -> usize {
+    1024
+}
+
+impl Default for CompressionOptions {
+    fn default() -> Self {
+        CompressionOptions {
+            gzip_level: default_gzip_level(),
+            brotli_quality: default_brotli_quality(),
+            min_compressed_size: default_min_compressed_size(),
+        }
+    }
+}
+
+#[derive(Debug, thiserror::Error)]
+#[error("failed to decode {encoding} object: {source}")]
+pub struct DecodeError {
+    encoding: EncodingType,
+    source: std::io::Error,
+}
+
+/// Read a stored object and decode it according to the encoding it was stored with. Compressed
+/// objects are decompressed as they're read, without buffering the compressed input first.
+pub fn read_object<R: Read>(mut reader: R, encoding: EncodingType) -> Fallible<Vec<u8>> {
+    let mut decoded = Vec::new();
+    match encoding {
+        EncodingType::Plain => reader.read_to_end(&mut decoded),
+        EncodingType::Gzip => GzDecoder::new(reader).read_to_end(&mut decoded),
+        EncodingType::Zstd => {
+            zstd::stream::Decoder::new(reader).and_then(|mut d| d.read_to_end(&mut decoded))
+        }
+        EncodingType::Brotli => {
+            brotli::Decompressor::new(reader, BROTLI_BUFFER_SIZE).read_to_end(&mut decoded)
+        }
+    }
+    .map_err(|source| DecodeError { encoding, source })?;
+    Ok(decoded)
+}
+
 #[derive(Clone, PartialEq, Eq, Debug)]
 pub enum EncodedLog {
     Plain(Vec<u8>),
     Gzip(Vec<u8>),
+    Zstd(Vec<u8>),
+    Brotli(Vec<u8>),
 }
 
 impl EncodedLog {
     pub fn to_plain(&self) -> Fallible<Vec<u8>> {
-        match self {
-            EncodedLog::Plain(data) => Ok(data.to_vec()),
-            EncodedLog::Gzip(data) => {
-                let mut decoded_log = GzDecoder::new(data.as_slice());
-                let mut new_log = Vec::new();
-                decoded_log.read_to_end(&mut new_log)?;
-                Ok(new_log)
-            }
-        }
+        read_object(self.as_slice(), self.get_encoding_type())
     }
 
     pub fn get_encoding_type(&self) -> EncodingType {
         match self {
             EncodedLog::Plain(_) => EncodingType::Plain,
             EncodedLog::Gzip(_) => EncodingType::Gzip,
+            EncodedLog::Zstd(_) => EncodingType::Zstd,
+            EncodedLog::Brotli(_) => EncodingType::Brotli,
         }
     }
 
@@ -92,17 +179,40 @@ impl EncodedLog {
         match self {
             EncodedLog::Plain(data) => data,
             EncodedLog::Gzip(data) => data,
+            EncodedLog::Zstd(data) => data,
+            EncodedLog::Brotli(data) => data,
         }
     }
 
     pub fn from_plain_slice(data: &[u8], desired_encoding: EncodingType) -> Fallible<EncodedLog> {
+        EncodedLog::from_plain_slice_with(data, desired_encoding, &CompressionOptions::default())
+    }
+
+    pub fn from_plain_slice_with(
+        data: &[u8],
+        desired_encoding: EncodingType,
+        options: &CompressionOptions,
+    ) -> Fallible<EncodedLog> {
         match desired_encoding {
             EncodingType::Gzip => {
-                let mut encoded_log = GzEncoder::new(Vec::new(), Compression::default());
+                let level = Compression::new(options.gzip_level.min(9));
+                let mut encoded_log = GzEncoder::new(Vec::new(), level);
                 encoded_log.write_all(data)?;
                 let encoded_log = encoded_log.finish()?;
                 Ok(EncodedLog::Gzip(encoded_log))
             }
+            EncodingType::Zstd => Ok(EncodedLog::Zstd(zstd::stream::encode_all(data, 0)?)),
+            EncodingType::Brotli => {
+                let mut encoded_log = brotli::CompressorWriter::new(
+                    Vec::new(),
+                    BROTLI_BUFFER_SIZE,
+                    options.brotli_quality.min(BROTLI_MAX_QUALITY),
+                    BROTLI_WINDOW_SIZE,
+                );
+                encoded_log.write_all(data)?;
+                encoded_log.flush()?;
+                Ok(EncodedLog::Brotli(encoded_log.into_inner()))
+            }
             EncodingType::Plain => Ok(EncodedLog::Plain(data.to_vec())),
         }
     }
@@ -131,7 +241,7 @@ macro_rules! test_result_enum {
 
                 if part2.is_none() {
                     match part1 {
-                        $($with_reason_repr => Ok($name::$with_reason_name($reason::Unknown)),)*
+                        $($with_reason_repr => Ok($name::$with_reason_name($reason::default())),)*
                         $($reasonless_repr => Ok($name::$reasonless_name),)*
                         other => Err(TestResultParseError::UnknownResult(other.into()).into()),
                     }
@@ -192,8 +302,9 @@ impl ::std::str::FromStr for DiagnosticCode {
     }
 }
 
-#[derive(Debug, PartialEq, Eq, Clone, Hash, Serialize, Deserialize)]
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
 pub enum FailureReason {
+    #[default]
     Unknown,
     OOM,
     NoSpace,
@@ -203,6 +314,7 @@ pub enum FailureReason {
     CompilerDiagnosticChange,
     CompilerError(BTreeSet<DiagnosticCode>),
     DependsOn(BTreeSet<Crate>),
+    UndefinedBehavior(BTreeSet<DiagnosticCode>),
 }
 
 impl Fail for FailureReason {}
@@ -234,6 +346,15 @@ impl ::std::fmt::Display for FailureReason {
                     .join(", "),
             ),
             FailureReason::CompilerDiagnosticChange => write!(f, "compiler-diagnostic-change"),
+            FailureReason::UndefinedBehavior(kinds) => write!(
+                f,
+                "undefined-behavior({})",
+                kinds
+                    .iter()
+                    .map(|kind| kind.code.clone())
+                    .collect::<Vec<String>>()
+                    .join(", "),
+            ),
         }
     }
 }
@@ -260,6 +381,13 @@ impl ::std::str::FromStr for FailureReason {
                     }
                     Ok(FailureReason::DependsOn(krates))
                 }
+                "undefined-behavior" => Ok(FailureReason::UndefinedBehavior(
+                    contents
+                        .map(|st| DiagnosticCode {
+                            code: st.to_string(),
+                        })
+                        .collect(),
+                )),
                 _ => bail!("unexpected prefix: {}", prefix),
             }
         } else {
@@ -287,7 +415,8 @@ impl FailureReason {
             FailureReason::CompilerError(_)
             | FailureReason::DependsOn(_)
             | FailureReason::Unknown
-            | FailureReason::ICE => false,
+            | FailureReason::ICE
+            | FailureReason::UndefinedBehavior(_) => false,
         }
     }
 }
@@ -300,32 +429,192 @@ string_enum!(pub enum BrokenReason {
     MissingGitRepository => "missing-git-repository",
 });
 
+impl Default for BrokenReason {
+    fn default() -> Self {
+        BrokenReason::Unknown
+    }
+}
+
+/// Clippy lints a crate triggered as warnings.
+#[derive(Debug, PartialEq, Eq, Clone, Hash, Default, Serialize, Deserialize)]
+pub struct Lints(pub BTreeSet<DiagnosticCode>);
+
+impl ::std::fmt::Display for Lints {
+    fn fmt(&self, f: &mut ::std::fmt::Formatter) -> ::std::fmt::Result {
+        let lints = self
+            .0
+            .iter()
+            .map(|lint| lint.code.as_str())
+            .collect::<Vec<_>>();
+        write!(f, "{}", lints.join(", "))
+    }
+}
+
+impl ::std::str::FromStr for Lints {
+    type Err = ::failure::Error;
+
+    fn from_str(s: &str) -> ::failure::Fallible<Lints> {
+        Ok(Lints(
+            s.split(", ")
+                .filter(|lint| !lint.is_empty())
+                .map(|lint| DiagnosticCode::from(lint.to_string()))
+                .collect(),
+        ))
+    }
+}
+
 test_result_enum!(pub enum TestResult {
     with_reason {
         BrokenCrate(BrokenReason) => "broken",
         BuildFail(FailureReason) => "build-fail",
         TestFail(FailureReason) => "test-fail",
+        DocFail(FailureReason) => "doc-fail",
+        Warnings(Lints) => "warnings",
+        FetchError(String) => "fetch-error",
     }
     without_reason {
         TestSkipped => "test-skipped",
         TestPass => "test-pass",
         Skipped => "skipped",
+        SkippedMsrv => "skipped-msrv",
+        Yanked => "yanked",
         Error => "error",
+        Timeout => "timeout",
+        Unavailable => "unavailable",
     }
 });
 
 from_into_string!(TestResult);
 
+impl TestResult {
+    /// Error codes of the compiler errors, or the lints of the warnings, reported in the result.
+    pub fn diagnostic_codes(&self) -> BTreeSet<DiagnosticCode> {
+        match self {
+            TestResult::BuildFail(FailureReason::CompilerError(codes))
+            | TestResult::TestFail(FailureReason::CompilerError(codes))
+            | TestResult::DocFail(FailureReason::CompilerError(codes)) => codes.clone(),
+            TestResult::Warnings(lints) => lints.0.clone(),
+            _ => BTreeSet::new(),
+        }
+    }
+}
+
+/// Kinds of results, matched regardless of their failure reason. Parsed from comma-separated
+/// names like `fetch-error,timeout`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct ResultKinds(Vec<String>);
+
+impl ResultKinds {
+    pub fn contains(&self, result: &TestResult) -> bool {
+        let repr = result.to_string();
+        let kind = repr.split(':').next().unwrap();
+        self.0.iter().any(|k| k == kind)
+    }
+}
+
+impl FromStr for ResultKinds {
+    type Err = ::failure::Error;
+
+    fn from_str(input: &str) -> Fallible<Self> {
+        let kinds = input
+            .split(',')
+            .map(str::trim)
+            .filter(|kind| !kind.is_empty())
+            .map(|kind| {
+                // Only bare names are accepted, so reject the ones with a reason
+                if kind.contains(':') {
+                    bail!("unexpected failure reason in result kind {}", kind);
+                }
+                TestResult::from_str(kind)?;
+                Ok(kind.to_string())
+            })
+            .collect::<Fallible<Vec<_>>>()?;
+
+        if kinds.is_empty() {
+            bail!("no result kinds provided");
+        }
+        Ok(ResultKinds(kinds))
+    }
+}
+
+impl fmt::Display for ResultKinds {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        write!(f, "{}", self.0.join(","))
+    }
+}
+
 #[cfg(test)]
 mod tests {
+    use super::{read_object, CompressionOptions, EncodedLog, EncodingType};
     use crate::crates::*;
     use std::collections::BTreeSet;
     use std::str::FromStr;
 
+    #[test]
+    fn test_read_object() {
+        let payload = b"error[E0308]: mismatched types\n".repeat(128);
+
+        for encoding in [
+            EncodingType::Plain,
+            EncodingType::Gzip,
+            EncodingType::Zstd,
+            EncodingType::Brotli,
+        ] {
+            let encoded = EncodedLog::from_plain_slice(&payload, encoding).unwrap();
+            assert_eq!(read_object(encoded.as_slice(), encoding).unwrap(), payload);
+        }
+
+        // A truncated stream is reported as a decoding error
+        let encoded = EncodedLog::from_plain_slice(&payload, EncodingType::Gzip).unwrap();
+        let truncated = &encoded.as_slice()[..encoded.as_slice().len() / 2];
+        let err = read_object(truncated, EncodingType::Gzip).unwrap_err();
+        assert!(err.to_string().starts_with("failed to decode gzip object"));
+    }
+
+    #[test]
+    fn test_encoded_log_round_trip() {
+        let payload = br#"{"crates":[{"name":"lazy_static-1.0.0","res":"regressed"}]}"#.repeat(64);
+
+        for encoding in [
+            EncodingType::Plain,
+            EncodingType::Gzip,
+            EncodingType::Zstd,
+            EncodingType::Brotli,
+        ] {
+            let encoded = EncodedLog::from_plain_slice(&payload, encoding).unwrap();
+            assert_eq!(encoded.get_encoding_type(), encoding);
+            if encoding != EncodingType::Plain {
+                assert!(encoded.as_slice().len() < payload.len());
+            }
+            assert_eq!(encoded.to_plain().unwrap(), payload);
+        }
+    }
+
+    #[test]
+    fn test_gzip_level() {
+        let payload = (0..10_000)
+            .map(|i| format!("line {i}: compiling crate-{}\n", i % 37))
+            .collect::<String>();
+
+        let encode = |gzip_level| {
+            let options = CompressionOptions {
+                gzip_level,
+                ..CompressionOptions::default()
+            };
+            let encoded =
+                EncodedLog::from_plain_slice_with(payload.as_bytes(), EncodingType::Gzip, &options)
+                    .unwrap();
+            assert_eq!(encoded.to_plain().unwrap(), payload.as_bytes());
+            encoded.as_slice().len()
+        };
+        assert!(encode(9) <= encode(1));
+    }
+
     #[test]
     fn test_test_result_parsing() {
         use super::{
             FailureReason::*,
+            Lints,
             TestResult::{self, *},
         };
 
@@ -359,6 +648,14 @@ mod tests {
             "build-fail:ice" => BuildFail(ICE),
             "build-fail:no-space" => BuildFail(NoSpace),
             "test-fail:timeout" => TestFail(Timeout),
+            "test-fail:undefined-behavior(memory leaked, out-of-bounds pointer use)" => TestFail(UndefinedBehavior(btreeset!["memory leaked".parse().unwrap(), "out-of-bounds pointer use".parse().unwrap()])),
+            "doc-fail:compiler-error(E0425)" => DocFail(CompilerError(btreeset!["E0425".parse().unwrap()])),
+            "timeout" => TestResult::Timeout,
+            "unavailable" => Unavailable,
+            "skipped-msrv" => SkippedMsrv,
+            "yanked" => Yanked,
+            "fetch-error:failed to download lazy_static-1.0.0: timed out" => FetchError("failed to download lazy_static-1.0.0: timed out".into()),
+            "warnings:clippy::print_with_newline, clippy::redundant_clone" => Warnings(Lints(btreeset!["clippy::print_with_newline".parse().unwrap(), "clippy::redundant_clone".parse().unwrap()])),
             "test-pass" => TestPass,
             "error" => Error,
             "build-fail:depends-on(reg/clint/0.2.1)" => BuildFail(DependsOn(btreeset![Crate::Registry(RegistryCrate{name: "clint".to_string(), version: "0.2.1".to_string()})])),
@@ -372,4 +669,20 @@ mod tests {
         assert!(TestResult::from_str("error:oom").is_err());
         assert!(TestResult::from_str("build-fail:pleasedonotaddthis").is_err());
     }
+
+    #[test]
+    fn test_result_kinds() {
+        use super::{FailureReason, ResultKinds, TestResult};
+
+        let kinds: ResultKinds = "fetch-error, timeout,build-fail".parse().unwrap();
+        assert_eq!(kinds.to_string(), "fetch-error,timeout,build-fail");
+        assert!(kinds.contains(&TestResult::FetchError("timed out".into())));
+        assert!(kinds.contains(&TestResult::Timeout));
+        assert!(kinds.contains(&TestResult::BuildFail(FailureReason::OOM)));
+        assert!(!kinds.contains(&TestResult::TestFail(FailureReason::Timeout)));
+
+        assert!(ResultKinds::from_str("").is_err());
+        assert!(ResultKinds::from_str("regressed").is_err());
+        assert!(ResultKinds::from_str("build-fail:oom").is_err());
+    }
 }
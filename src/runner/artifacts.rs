@@ -0,0 +1,223 @@
+use crate::config::{ArtifactsOptions, Config};
+use crate::crates::Crate;
+use crate::dirs::PRESERVED_ARTIFACTS_DIR;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{compare, Comparison, FileWriter, ReportWriter};
+use crate::results::{EncodingType, TestResult};
+use crate::toolchain::Toolchain;
+use crate::utils;
+use flate2::write::GzEncoder;
+use flate2::Compression;
+use percent_encoding::utf8_percent_encode;
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+use std::sync::Mutex;
+use tar::{Builder as TarBuilder, Header as TarHeader};
+
+/// Archives of the target directories of the regressed crates, written to
+/// `artifacts/{crate}.tar.gz` in the experiment's directory of `PRESERVED_ARTIFACTS_DIR` along
+/// with a script running the same cargo commands, to debug the regressions locally.
+pub(super) struct ArtifactsStore {
+    writer: FileWriter,
+    max_crates: usize,
+    max_archive_size: usize,
+    preserved: Mutex<usize>,
+}
+
+impl ArtifactsStore {
+    /// Store of the experiment's artifacts, or `None` if they're not preserved.
+    pub(super) fn new(ex: &Experiment, options: &ArtifactsOptions) -> Fallible<Option<Self>> {
+        if !options.preserve_failed_artifacts {
+            return Ok(None);
+        }
+
+        Ok(Some(ArtifactsStore {
+            writer: FileWriter::create(PRESERVED_ARTIFACTS_DIR.join(&ex.name))?,
+            max_crates: options.max_crates,
+            max_archive_size: options.max_archive_size.to_bytes(),
+            preserved: Mutex::new(0),
+        }))
+    }
+
+    /// Archive the target directory of the crate if its results are a regression and fewer than
+    /// `max_crates` crates were preserved, returning whether it was.
+    pub(super) fn preserve(
+        &self,
+        config: &Config,
+        krate: &Crate,
+        toolchain: &Toolchain,
+        start: &TestResult,
+        end: &TestResult,
+        target_dir: &Path,
+        commands: &[String],
+    ) -> Fallible<bool> {
+        {
+            let mut preserved = self.preserved.lock().unwrap();
+            if !should_preserve(config, krate, start, end, *preserved, self.max_crates) {
+                return Ok(false);
+            }
+            *preserved += 1;
+        }
+
+        let script = repro_script(krate, toolchain, commands);
+        let res = archive(target_dir, &script, self.max_archive_size).and_then(|archive| {
+            self.writer.write_bytes(
+                artifact_path(krate),
+                &archive,
+                &"application/gzip".parse().unwrap(),
+                EncodingType::Plain,
+            )
+        });
+        if res.is_err() {
+            // Let another crate take the slot
+            *self.preserved.lock().unwrap() -= 1;
+        }
+        res.map(|()| true)
+    }
+}
+
+/// Whether the artifacts of a crate with the given results should be preserved, after `preserved`
+/// crates already were.
+fn should_preserve(
+    config: &Config,
+    krate: &Crate,
+    start: &TestResult,
+    end: &TestResult,
+    preserved: usize,
+    max_crates: usize,
+) -> bool {
+    preserved < max_crates
+        && matches!(
+            compare(config, krate, Some(start), Some(end)),
+            Comparison::Regressed | Comparison::DocRegressed
+        )
+}
+
+fn artifact_path(krate: &Crate) -> PathBuf {
+    let name = utf8_percent_encode(&krate.id(), &utils::FILENAME_ENCODE_SET).to_string();
+    PathBuf::from("artifacts").join(format!("{name}.tar.gz"))
+}
+
+/// Shell script running the cargo commands of the build, from the crate's source directory.
+fn repro_script(krate: &Crate, toolchain: &Toolchain, commands: &[String]) -> String {
+    let mut script = format!(
+        "#!/bin/sh\n# Commands run on {krate} with toolchain {toolchain}, to run from the \
+         crate's source with that toolchain\n# The target directory of the build is in \
+         `target/`\nset -e\n"
+    );
+    for command in commands {
+        script.push_str(command);
+        script.push('\n');
+    }
+    script
+}
+
+/// Writer failing once more than `limit` bytes are written to it.
+struct LimitedWriter {
+    buffer: Vec<u8>,
+    limit: usize,
+}
+
+impl Write for LimitedWriter {
+    fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+        if self.buffer.len() + buf.len() > self.limit {
+            return Err(io::Error::new(
+                io::ErrorKind::Other,
+                format!("the archive is larger than {} bytes", self.limit),
+            ));
+        }
+        self.buffer.extend_from_slice(buf);
+        Ok(buf.len())
+    }
+
+    fn flush(&mut self) -> io::Result<()> {
+        Ok(())
+    }
+}
+
+/// Gzipped tarball of the script and of the target directory, failing if it's larger than
+/// `max_size` bytes.
+fn archive(target_dir: &Path, script: &str, max_size: usize) -> Fallible<Vec<u8>> {
+    let writer = LimitedWriter {
+        buffer: Vec::new(),
+        limit: max_size,
+    };
+    let mut builder = TarBuilder::new(GzEncoder::new(writer, Compression::default()));
+
+    let mut header = TarHeader::new_gnu();
+    header.set_size(script.len() as u64);
+    header.set_mode(0o755);
+    header.set_cksum();
+    builder.append_data(&mut header, "repro.sh", script.as_bytes())?;
+    if target_dir.is_dir() {
+        builder.append_dir_all("target", target_dir)?;
+    }
+
+    Ok(builder.into_inner()?.finish()?.buffer)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{archive, should_preserve};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::results::{FailureReason, TestResult};
+    use flate2::read::GzDecoder;
+    use std::fs;
+    use std::path::PathBuf;
+    use tar::Archive;
+
+    #[test]
+    fn test_should_preserve() {
+        let config = Config::default();
+        let krate = Crate::Registry(RegistryCrate {
+            name: "foo".into(),
+            version: "1.0.0".into(),
+        });
+        let preserve =
+            |start, end, preserved| should_preserve(&config, &krate, &start, &end, preserved, 2);
+        let build_fail = || TestResult::BuildFail(FailureReason::Unknown);
+
+        // Only regressions are preserved
+        assert!(preserve(TestResult::TestPass, build_fail(), 0));
+        assert!(preserve(
+            TestResult::TestPass,
+            TestResult::DocFail(FailureReason::Unknown),
+            0
+        ));
+        assert!(!preserve(TestResult::TestPass, TestResult::TestPass, 0));
+        assert!(!preserve(build_fail(), build_fail(), 0));
+        assert!(!preserve(build_fail(), TestResult::TestPass, 0));
+        assert!(!preserve(TestResult::TestPass, TestResult::Error, 0));
+        assert!(!preserve(
+            TestResult::TestPass,
+            TestResult::BuildFail(FailureReason::OOM),
+            0
+        ));
+
+        // Up to the cap
+        assert!(preserve(TestResult::TestPass, build_fail(), 1));
+        assert!(!preserve(TestResult::TestPass, build_fail(), 2));
+        assert!(!preserve(TestResult::TestPass, build_fail(), 3));
+    }
+
+    #[test]
+    fn test_archive() {
+        let target = tempfile::tempdir().unwrap();
+        fs::create_dir_all(target.path().join("debug")).unwrap();
+        fs::write(target.path().join("debug").join("foo"), vec![0xAB; 4096]).unwrap();
+
+        let bytes = archive(target.path(), "cargo build\n", 1024 * 1024).unwrap();
+        let paths = Archive::new(GzDecoder::new(bytes.as_slice()))
+            .entries()
+            .unwrap()
+            .map(|entry| entry.unwrap().path().unwrap().into_owned())
+            .collect::<Vec<_>>();
+        assert!(paths.contains(&PathBuf::from("repro.sh")));
+        assert!(paths.contains(&PathBuf::from("target/debug/foo")));
+
+        // Archives over the size limit are refused
+        assert!(archive(target.path(), "cargo build\n", 16).is_err());
+    }
+}
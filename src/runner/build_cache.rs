@@ -0,0 +1,162 @@
+use crate::config::BuildCacheOptions;
+use crate::dirs::BUILD_CACHE_DIR;
+use crate::experiments::{Experiment, Mode};
+use crate::prelude::*;
+use crate::toolchain::Toolchain;
+use crate::utils;
+use percent_encoding::utf8_percent_encode;
+use rustwide::cmd::{MountKind, SandboxBuilder};
+use sha1::{Digest, Sha1};
+use std::collections::HashMap;
+use std::path::{Path, PathBuf};
+
+/// Where the sccache binary of the agent is mounted in the sandboxes.
+const SANDBOX_SCCACHE: &str = "/opt/crater/sccache";
+/// Where the cache of the toolchain is mounted in the sandboxes.
+const SANDBOX_CACHE_DIR: &str = "/opt/crater/build-cache";
+
+/// Compiler cache shared by all the workers of an agent, stored in `work/cache/builds` with a
+/// directory for each toolchain, target and rustflags, so builds with different configurations
+/// never share artifacts. The registry doesn't need one, as rustwide already mounts the cargo
+/// home of the workspace in every sandbox.
+pub(super) struct BuildCache {
+    sccache: PathBuf,
+    max_size: String,
+    dirs: HashMap<String, PathBuf>,
+}
+
+impl BuildCache {
+    /// Cache of the experiment's toolchains, or `None` if it's disabled or unusable. Toolchains
+    /// whose directory can't be created are built without it.
+    pub(super) fn new(ex: &Experiment, options: &BuildCacheOptions) -> Option<Self> {
+        if !options.enabled {
+            return None;
+        }
+        // cargo miri replaces the compiler with its own driver, which sccache can't wrap
+        if ex.mode == Mode::Miri {
+            return None;
+        }
+        if !options.sccache.is_file() {
+            warn!(
+                "sccache not found at {}, building without the build cache",
+                options.sccache.display()
+            );
+            return None;
+        }
+
+        let mut dirs = HashMap::new();
+        for tc in ex.all_toolchains() {
+            let key = cache_key(tc);
+            let dir = BUILD_CACHE_DIR.join(&key);
+            match std::fs::create_dir_all(&dir) {
+                Ok(()) => {
+                    dirs.insert(key, dir);
+                }
+                Err(err) => warn!("building {} without the build cache: {}", tc, err),
+            }
+        }
+
+        Some(BuildCache {
+            sccache: options.sccache.clone(),
+            max_size: options.max_size.to_string(),
+            dirs,
+        })
+    }
+
+    fn dir(&self, toolchain: &Toolchain) -> Option<&Path> {
+        self.dirs.get(&cache_key(toolchain)).map(PathBuf::as_path)
+    }
+
+    /// Mount sccache and the cache of the toolchain in the sandbox, if it has one.
+    pub(super) fn mount(&self, toolchain: &Toolchain, sandbox: SandboxBuilder) -> SandboxBuilder {
+        match self.dir(toolchain) {
+            Some(dir) => sandbox
+                .mount(
+                    &self.sccache,
+                    Path::new(SANDBOX_SCCACHE),
+                    MountKind::ReadOnly,
+                )
+                .mount(dir, Path::new(SANDBOX_CACHE_DIR), MountKind::ReadWrite),
+            None => sandbox,
+        }
+    }
+
+    /// Environment making cargo compile through sccache, empty if the toolchain has no cache.
+    /// sccache runs the compiler itself when the cache can't be used, so a broken cache only
+    /// costs a rebuild.
+    pub(super) fn env(&self, toolchain: &Toolchain) -> Vec<(&'static str, String)> {
+        if self.dir(toolchain).is_none() {
+            return Vec::new();
+        }
+        vec![
+            ("RUSTC_WRAPPER", SANDBOX_SCCACHE.into()),
+            ("SCCACHE_DIR", SANDBOX_CACHE_DIR.into()),
+            ("SCCACHE_CACHE_SIZE", self.max_size.clone()),
+            ("SCCACHE_IGNORE_SERVER_IO_ERROR", "1".into()),
+        ]
+    }
+}
+
+/// Name of the cache directory of the toolchain, which must differ whenever the toolchain, the
+/// target or the rustflags do.
+fn cache_key(toolchain: &Toolchain) -> String {
+    let name = toolchain.rustup_name();
+    let mut hasher = Sha1::new();
+    // Debug formatting tells a missing value apart from an empty one
+    hasher.update(format!(
+        "{:?}\0{:?}\0{:?}",
+        name, toolchain.target, toolchain.rustflags
+    ));
+    let hash = hasher
+        .finalize()
+        .iter()
+        .map(|byte| format!("{byte:02x}"))
+        .collect::<String>();
+    format!(
+        "{}-{}",
+        utf8_percent_encode(&name, &utils::FILENAME_ENCODE_SET),
+        &hash[..16]
+    )
+}
+
+#[cfg(test)]
+mod tests {
+    use super::cache_key;
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+
+    #[test]
+    fn test_cache_key() {
+        let with_flags = |rustflags: Option<&str>| Toolchain {
+            rustflags: rustflags.map(Into::into),
+            ..MAIN_TOOLCHAIN.clone()
+        };
+
+        let opt = cache_key(&with_flags(Some("-Copt-level=3")));
+        let debug = cache_key(&with_flags(Some("-Cdebug-assertions=on")));
+        assert_ne!(opt, debug);
+        assert_eq!(opt, cache_key(&with_flags(Some("-Copt-level=3"))));
+        assert!(opt.starts_with("stable-"));
+
+        let keys = [
+            cache_key(&MAIN_TOOLCHAIN),
+            cache_key(&TEST_TOOLCHAIN),
+            cache_key(&with_flags(Some(""))),
+            cache_key(&Toolchain {
+                target: Some("x86_64-unknown-linux-musl".into()),
+                ..MAIN_TOOLCHAIN.clone()
+            }),
+            opt,
+            debug,
+        ];
+        for (i, key) in keys.iter().enumerate() {
+            assert!(!keys[i + 1..].contains(key), "{key}");
+        }
+
+        // Only the settings in the key split the cache
+        let locked = Toolchain {
+            cargoflags: Some("--locked".into()),
+            ..MAIN_TOOLCHAIN.clone()
+        };
+        assert_eq!(cache_key(&locked), cache_key(&MAIN_TOOLCHAIN));
+    }
+}
@@ -0,0 +1,144 @@
+use crate::config::FetchOptions;
+use std::sync::{Condvar, Mutex};
+use std::thread;
+use std::time::{Duration, Instant};
+
+/// Token bucket refilled at `rate` tokens per second, holding at most one second worth of them.
+struct TokenBucket {
+    rate: f64,
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TokenBucket {
+    fn new(rate: f64) -> Self {
+        TokenBucket {
+            rate,
+            tokens: rate.max(1.0),
+            last_refill: Instant::now(),
+        }
+    }
+
+    /// Take a token, or return how long to wait before one is available.
+    fn take(&mut self) -> Result<(), Duration> {
+        let now = Instant::now();
+        let elapsed = now.duration_since(self.last_refill).as_secs_f64();
+        self.tokens = (self.tokens + elapsed * self.rate).min(self.rate.max(1.0));
+        self.last_refill = now;
+
+        if self.tokens >= 1.0 {
+            self.tokens -= 1.0;
+            Ok(())
+        } else {
+            Err(Duration::from_secs_f64((1.0 - self.tokens) / self.rate))
+        }
+    }
+}
+
+/// Limits shared by all the workers on the downloads of the crates' sources, so starting an
+/// experiment doesn't flood the registries.
+pub(super) struct FetchLimiter {
+    max_concurrent: usize,
+    in_flight: Mutex<usize>,
+    released: Condvar,
+    bucket: Option<Mutex<TokenBucket>>,
+}
+
+/// Slot of a download in progress, released when dropped.
+pub(super) struct FetchPermit<'a> {
+    limiter: &'a FetchLimiter,
+}
+
+impl FetchLimiter {
+    pub(super) fn new(options: &FetchOptions) -> Self {
+        FetchLimiter {
+            max_concurrent: options.max_concurrent.max(1),
+            in_flight: Mutex::new(0),
+            released: Condvar::new(),
+            bucket: options
+                .requests_per_second
+                .filter(|rate| *rate > 0.0)
+                .map(|rate| Mutex::new(TokenBucket::new(rate))),
+        }
+    }
+
+    /// Wait until a download can start without exceeding the limits.
+    pub(super) fn acquire(&self) -> FetchPermit<'_> {
+        let mut in_flight = self
+            .released
+            .wait_while(self.in_flight.lock().unwrap(), |in_flight| {
+                *in_flight >= self.max_concurrent
+            })
+            .unwrap();
+        *in_flight += 1;
+        drop(in_flight);
+        let permit = FetchPermit { limiter: self };
+
+        if let Some(bucket) = &self.bucket {
+            loop {
+                let wait = bucket.lock().unwrap().take();
+                match wait {
+                    Ok(()) => break,
+                    Err(wait) => thread::sleep(wait),
+                }
+            }
+        }
+
+        permit
+    }
+}
+
+impl Drop for FetchPermit<'_> {
+    fn drop(&mut self) {
+        *self.limiter.in_flight.lock().unwrap() -= 1;
+        self.limiter.released.notify_one();
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::FetchLimiter;
+    use crate::config::FetchOptions;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_concurrency_cap() {
+        let limiter = FetchLimiter::new(&FetchOptions {
+            max_concurrent: 2,
+            requests_per_second: None,
+        });
+        let in_flight = AtomicUsize::new(0);
+        let max_in_flight = AtomicUsize::new(0);
+
+        thread::scope(|s| {
+            for _ in 0..8 {
+                s.spawn(|| {
+                    let _permit = limiter.acquire();
+                    let current = in_flight.fetch_add(1, Ordering::SeqCst) + 1;
+                    max_in_flight.fetch_max(current, Ordering::SeqCst);
+                    thread::sleep(Duration::from_millis(20));
+                    in_flight.fetch_sub(1, Ordering::SeqCst);
+                });
+            }
+        });
+
+        assert_eq!(max_in_flight.load(Ordering::SeqCst), 2);
+    }
+
+    #[test]
+    fn test_rate_limit() {
+        let limiter = FetchLimiter::new(&FetchOptions {
+            max_concurrent: 16,
+            requests_per_second: Some(20.0),
+        });
+
+        // The bucket starts full, the next requests have to wait for it to refill
+        let start = Instant::now();
+        for _ in 0..30 {
+            drop(limiter.acquire());
+        }
+        assert!(start.elapsed() >= Duration::from_millis(400));
+    }
+}
@@ -1,3 +1,6 @@
+mod artifacts;
+mod build_cache;
+mod fetch;
 mod tasks;
 mod test;
 mod unstable_features;
@@ -5,9 +8,13 @@ mod worker;
 
 use crate::config::Config;
 use crate::crates::Crate;
+use crate::dirs::RUSTUP_TOOLCHAINS_DIR;
 use crate::experiments::{Experiment, Mode};
 use crate::prelude::*;
 use crate::results::{TestResult, WriteResults};
+use crate::runner::artifacts::ArtifactsStore;
+use crate::runner::build_cache::BuildCache;
+use crate::runner::fetch::FetchLimiter;
 use crate::runner::worker::{DiskSpaceWatcher, Worker};
 use rustwide::Workspace;
 use std::thread::scope;
@@ -44,6 +51,13 @@ pub fn run_ex<DB: WriteResults + Sync>(
     // given low human resources. Additionally, it'll be indirectly alerted
     // through the worker being "down" according to our progress metrics, since
     // jobs won't be completed.
+    // Fail fast instead of discovering it once the crates start building
+    for tc in ex.all_toolchains() {
+        if let Some(local) = &tc.local {
+            local.validate()?;
+        }
+    }
+
     let mut i = 0;
     while !rustwide::cmd::docker_running(workspace) {
         log::error!(
@@ -63,26 +77,60 @@ pub fn run_ex<DB: WriteResults + Sync>(
         // But don't uninstall it if we're going to reinstall in a couple lines.
         // And don't uninstall stable, since that is mainly used for
         // installing tools.
-        if !tc.is_needed_by_rustwide() && !ex.toolchains.iter().any(|t| tc == t.source) {
+        if !tc.is_needed_by_rustwide() && !ex.all_toolchains().any(|t| tc == t.source) {
             tc.uninstall(workspace)?;
         }
     }
 
     info!("preparing the execution...");
-    for tc in &ex.toolchains {
-        tc.install(workspace)?;
-        if ex.mode == Mode::Clippy {
-            tc.add_component(workspace, "clippy")?;
+    for tc in ex.all_toolchains() {
+        if let Some(local) = &tc.local {
+            local.install(&RUSTUP_TOOLCHAINS_DIR)?;
+        } else {
+            tc.install(workspace)?;
+        }
+        let components: &[&str] = match ex.mode {
+            Mode::Clippy => &["clippy"],
+            Mode::Miri => &["miri", "rust-src"],
+            _ => &[],
+        };
+        for component in components {
+            // Crates will be reported as unavailable instead of failing the whole run
+            if let Err(err) = tc.add_component(workspace, component) {
+                warn!("{} is not available for {}: {}", component, tc, err);
+            }
         }
         if let Some(requested_target) = &tc.target {
-            tc.add_target(workspace, requested_target)?;
+            if let Err(err) = tc.add_target(workspace, requested_target) {
+                bail!(
+                    "failed to install the {} target for {}: {}",
+                    requested_target,
+                    tc,
+                    err
+                );
+            }
         }
     }
 
     info!("running tasks in {} threads...", threads_count);
 
+    let fetch_limiter = FetchLimiter::new(&config.fetch);
+    let artifacts = ArtifactsStore::new(ex, &config.artifacts)?;
+    let build_cache = BuildCache::new(ex, &config.build_cache);
     let workers = (0..threads_count)
-        .map(|i| Worker::new(format!("worker-{i}"), workspace, ex, config, db, next_crate))
+        .map(|i| {
+            Worker::new(
+                format!("worker-{i}"),
+                workspace,
+                ex,
+                config,
+                db,
+                &fetch_limiter,
+                artifacts.as_ref(),
+                build_cache.as_ref(),
+                next_crate,
+            )
+        })
         .collect::<Vec<_>>();
 
     let disk_watcher = DiskSpaceWatcher::new(
@@ -3,13 +3,18 @@ use crate::crates::{Crate, GitHubRepo};
 use crate::experiments::Experiment;
 use crate::prelude::*;
 use crate::results::{EncodingType, TestResult, WriteResults};
+use crate::runner::artifacts::ArtifactsStore;
+use crate::runner::build_cache::BuildCache;
+use crate::runner::fetch::FetchLimiter;
 use crate::runner::test;
-use crate::runner::test::detect_broken;
+use crate::runner::test::{detect_broken, fetch_error};
+use crate::runner::OverrideResult;
 use crate::toolchain::Toolchain;
 use crate::utils;
 use rustwide::{Build, BuildDirectory, Workspace};
 use std::collections::HashMap;
 use std::sync::Mutex;
+use std::time::{Duration, Instant};
 
 use rustwide::logging::{self, LogStorage};
 use std::fmt;
@@ -22,6 +27,13 @@ pub(super) struct TaskCtx<'ctx, DB: WriteResults + 'ctx> {
     pub(super) toolchain: &'ctx Toolchain,
     pub(super) krate: &'ctx Crate,
     pub(super) quiet: bool,
+    pub(super) artifacts: Option<&'ctx ArtifactsStore>,
+    pub(super) build_cache: Option<&'ctx BuildCache>,
+    /// Result of the start toolchain when building with the end toolchain.
+    pub(super) start_result: Option<&'ctx TestResult>,
+    /// Cargo commands run so far, to reproduce the build.
+    pub(super) commands: Mutex<Vec<String>>,
+    started: Instant,
 }
 
 impl<'ctx, DB: WriteResults + 'ctx> TaskCtx<'ctx, DB> {
@@ -33,6 +45,9 @@ impl<'ctx, DB: WriteResults + 'ctx> TaskCtx<'ctx, DB> {
         toolchain: &'ctx Toolchain,
         krate: &'ctx Crate,
         quiet: bool,
+        artifacts: Option<&'ctx ArtifactsStore>,
+        build_cache: Option<&'ctx BuildCache>,
+        start_result: Option<&'ctx TestResult>,
     ) -> Self {
         TaskCtx {
             build_dir,
@@ -42,8 +57,19 @@ impl<'ctx, DB: WriteResults + 'ctx> TaskCtx<'ctx, DB> {
             toolchain,
             krate,
             quiet,
+            artifacts,
+            build_cache,
+            start_result,
+            commands: Mutex::new(Vec::new()),
+            started: Instant::now(),
         }
     }
+
+    /// Time left before the crate exceeds the build timeout, shared by all its cargo commands.
+    pub(super) fn remaining_time(&self) -> Duration {
+        Duration::from_secs(self.config.sandbox.build_timeout_secs)
+            .saturating_sub(self.started.elapsed())
+    }
 }
 
 pub(super) enum TaskStep {
@@ -53,7 +79,9 @@ pub(super) enum TaskStep {
     CheckOnly { tc: Toolchain, quiet: bool },
     Clippy { tc: Toolchain, quiet: bool },
     Rustdoc { tc: Toolchain, quiet: bool },
+    Miri { tc: Toolchain, quiet: bool },
     UnstableFeatures { tc: Toolchain },
+    TestOnly { tc: Toolchain, quiet: bool },
 }
 
 impl fmt::Debug for TaskStep {
@@ -65,7 +93,9 @@ impl fmt::Debug for TaskStep {
             TaskStep::CheckOnly { ref tc, quiet } => ("check", quiet, Some(tc)),
             TaskStep::Clippy { ref tc, quiet } => ("clippy", quiet, Some(tc)),
             TaskStep::Rustdoc { ref tc, quiet } => ("doc", quiet, Some(tc)),
+            TaskStep::Miri { ref tc, quiet } => ("miri", quiet, Some(tc)),
             TaskStep::UnstableFeatures { ref tc } => ("find unstable features on", false, Some(tc)),
+            TaskStep::TestOnly { ref tc, quiet } => ("test", quiet, Some(tc)),
         };
 
         write!(f, "{name}")?;
@@ -106,7 +136,9 @@ impl Task {
             | TaskStep::CheckOnly { ref tc, .. }
             | TaskStep::Clippy { ref tc, .. }
             | TaskStep::Rustdoc { ref tc, .. }
-            | TaskStep::UnstableFeatures { ref tc } => {
+            | TaskStep::Miri { ref tc, .. }
+            | TaskStep::UnstableFeatures { ref tc }
+            | TaskStep::TestOnly { ref tc, .. } => {
                 db.record_result(ex, tc, &self.krate, storage, EncodingType::Plain, || {
                     error!("this task or one of its parent failed!");
                     utils::report_failure(err);
@@ -125,8 +157,12 @@ impl Task {
         build_dir: &'ctx HashMap<&'ctx crate::toolchain::Toolchain, Mutex<BuildDirectory>>,
         ex: &'ctx Experiment,
         db: &'ctx DB,
+        fetch_limiter: &FetchLimiter,
+        artifacts: Option<&'ctx ArtifactsStore>,
+        build_cache: Option<&'ctx BuildCache>,
+        start_result: Option<&'ctx TestResult>,
         logs: &LogStorage,
-    ) -> Fallible<()> {
+    ) -> Fallible<Option<TestResult>> {
         let (build_dir, action, test, toolchain, quiet): (
             _,
             _,
@@ -153,6 +189,9 @@ impl Task {
             TaskStep::Rustdoc { ref tc, quiet } => {
                 (&build_dir[tc], "documenting", test::test_rustdoc, tc, quiet)
             }
+            TaskStep::Miri { ref tc, quiet } => {
+                (&build_dir[tc], "running miri", test::test_miri, tc, quiet)
+            }
             TaskStep::UnstableFeatures { ref tc } => (
                 &build_dir[tc],
                 "checking unstable",
@@ -160,8 +199,32 @@ impl Task {
                 tc,
                 false,
             ),
+            TaskStep::TestOnly { ref tc, quiet } => {
+                (&build_dir[tc], "testing", test::test_test_only, tc, quiet)
+            }
             TaskStep::Prepare => {
                 logging::capture(logs, || {
+                    let _permit = fetch_limiter.acquire();
+                    if let Crate::Registry(krate) = &self.krate {
+                        // Building a yanked version would only produce misleading failures
+                        match krate.is_yanked() {
+                            Ok(true) => {
+                                return Err(err_msg(format!("{} is yanked", self.krate))
+                                    .context(OverrideResult(TestResult::Yanked))
+                                    .into());
+                            }
+                            Ok(false) => {}
+                            Err(err) => {
+                                warn!("failed to check if {} is yanked: {}", self.krate, err)
+                            }
+                        }
+                    }
+                    match &self.krate {
+                        Crate::AltRegistry(krate) => krate.download().map_err(fetch_error)?,
+                        Crate::Git(repo) if repo.is_ssh() => repo.fetch().map_err(fetch_error)?,
+                        Crate::Workspace(member) => member.stage()?,
+                        _ => {}
+                    }
                     let rustwide_crate = self.krate.to_rustwide();
                     for attempt in 1..=15 {
                         match detect_broken(rustwide_crate.fetch(workspace)) {
@@ -183,7 +246,7 @@ impl Task {
                                         // this too often we'd replace the
                                         // machine, but it's not very clear
                                         // what "too often" means here.
-                                        return Err(e);
+                                        return Err(fetch_error(e));
                                     } else {
                                         log::warn!(
                                             "Retrying crate fetch in 3 seconds (attempt {})",
@@ -192,7 +255,7 @@ impl Task {
                                         std::thread::sleep(std::time::Duration::from_secs(3));
                                     }
                                 } else {
-                                    return Err(e);
+                                    return Err(fetch_error(e));
                                 }
                             }
                         }
@@ -218,13 +281,22 @@ impl Task {
                     }
                     Ok(())
                 })?;
-                return Ok(());
+                return Ok(None);
             }
         };
 
-        let ctx = TaskCtx::new(build_dir, config, db, ex, toolchain, &self.krate, quiet);
-        test::run_test(action, &ctx, test, logs)?;
-
-        Ok(())
+        let ctx = TaskCtx::new(
+            build_dir,
+            config,
+            db,
+            ex,
+            toolchain,
+            &self.krate,
+            quiet,
+            artifacts,
+            build_cache,
+            start_result,
+        );
+        Ok(Some(test::run_test(action, &ctx, test, logs)?))
     }
 }
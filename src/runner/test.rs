@@ -1,11 +1,16 @@
+use crate::config::CheckOptions;
 use crate::crates::Crate;
+use crate::experiments::{CapLints, Mode};
 use crate::prelude::*;
+use crate::report::COMMAND_LOG_PREFIX;
 use crate::results::DiagnosticCode;
-use crate::results::{BrokenReason, EncodingType, FailureReason, TestResult, WriteResults};
+use crate::results::{BrokenReason, EncodingType, FailureReason, Lints, TestResult, WriteResults};
 use crate::runner::tasks::TaskCtx;
 use crate::runner::OverrideResult;
-use cargo_metadata::diagnostic::DiagnosticLevel;
-use cargo_metadata::{Message, Metadata, Package, Target};
+use crate::toolchain::{redact_env, Toolchain};
+use cargo_metadata::diagnostic::{Diagnostic, DiagnosticLevel};
+use cargo_metadata::semver::Version;
+use cargo_metadata::{Edition, Message, Metadata, Package, Target};
 use docsrs_metadata::Metadata as DocsrsMetadata;
 use failure::Error;
 use remove_dir_all::remove_dir_all;
@@ -13,6 +18,14 @@ use rustwide::cmd::{CommandError, ProcessLinesActions, SandboxBuilder};
 use rustwide::logging::LogStorage;
 use rustwide::{Build, PrepareError};
 use std::collections::{BTreeSet, HashMap, HashSet};
+use std::path::Path;
+use std::sync::Mutex;
+
+lazy_static! {
+    // Version of each toolchain, so it's only asked to cargo once
+    static ref TOOLCHAIN_VERSIONS: Mutex<HashMap<String, Option<Version>>> =
+        Mutex::new(HashMap::new());
+}
 
 fn failure_reason(err: &Error) -> FailureReason {
     for cause in err.iter_chain() {
@@ -30,6 +43,40 @@ fn failure_reason(err: &Error) -> FailureReason {
     FailureReason::Unknown
 }
 
+/// Result of a failed step: `fail` with the detected reason, unless the crate ran out of time or
+/// the error overrides the result.
+fn failure_result(err: &Error, fail: fn(FailureReason) -> TestResult) -> TestResult {
+    for cause in err.iter_chain() {
+        if let Some(&CommandError::Timeout(_)) = cause.downcast_ctx() {
+            return TestResult::Timeout;
+        } else if let Some(OverrideResult(res)) = cause.downcast_ctx() {
+            return res.clone();
+        }
+    }
+
+    fail(failure_reason(err))
+}
+
+/// Record a failure to download the crate as a fetch error, unless it was already detected as a
+/// broken crate, so it's not mistaken for a problem with the toolchains.
+pub(super) fn fetch_error(err: Error) -> Error {
+    if err
+        .iter_chain()
+        .any(|cause| cause.downcast_ctx::<OverrideResult>().is_some())
+    {
+        return err;
+    }
+
+    let message = err
+        .iter_chain()
+        .map(|cause| cause.to_string())
+        .collect::<Vec<_>>()
+        .join(": ")
+        .replace('\n', " ");
+    err.context(OverrideResult(TestResult::FetchError(message)))
+        .into()
+}
+
 pub(super) fn detect_broken<T>(res: Result<T, Error>) -> Result<T, Error> {
     match res {
         Ok(ok) => Ok(ok),
@@ -65,6 +112,40 @@ pub(super) fn detect_broken<T>(res: Result<T, Error>) -> Result<T, Error> {
     }
 }
 
+/// Name of the clippy lint which emitted the diagnostic, if it's a clippy warning.
+fn clippy_lint(diagnostic: &Diagnostic) -> Option<DiagnosticCode> {
+    match (&diagnostic.level, &diagnostic.code) {
+        (DiagnosticLevel::Warning, Some(code)) if code.code.starts_with("clippy::") => {
+            Some(DiagnosticCode::from(code.code.clone()))
+        }
+        _ => None,
+    }
+}
+
+/// Kind of undefined behavior or leak reported by a Miri error line. The details which change
+/// between runs, like allocation ids and offsets, are masked so reports can be compared.
+fn miri_diagnostic(line: &str) -> Option<DiagnosticCode> {
+    let message = line.trim_start().strip_prefix("error: ")?;
+    let kind = if let Some(ub) = message.strip_prefix("Undefined Behavior: ") {
+        ub.split(": ").next().unwrap_or(ub)
+    } else if message.starts_with("memory leaked") {
+        "memory leaked"
+    } else {
+        return None;
+    };
+
+    let mut masked = String::new();
+    for c in kind.trim().chars() {
+        if !c.is_ascii_digit() {
+            masked.push(c);
+        } else if !masked.ends_with('N') {
+            masked.push('N');
+        }
+    }
+    // Commas separate the kinds once serialized in the failure reason
+    Some(DiagnosticCode::from(masked.replace(',', ";")))
+}
+
 fn get_local_packages(build_env: &Build) -> Fallible<Vec<Package>> {
     Ok(build_env
         .cargo()
@@ -78,41 +159,179 @@ fn get_local_packages(build_env: &Build) -> Fallible<Vec<Package>> {
         .collect())
 }
 
-fn run_cargo<DB: WriteResults>(
+/// Version of the toolchain used by `build_env`, as reported by its cargo.
+fn toolchain_version(toolchain: &Toolchain, build_env: &Build) -> Option<Version> {
+    let key = toolchain.to_string();
+    if let Some(version) = TOOLCHAIN_VERSIONS.lock().unwrap().get(&key) {
+        return version.clone();
+    }
+
+    let version = match build_env
+        .cargo()
+        .args(&["--version"])
+        .log_output(false)
+        .run_capture()
+    {
+        Ok(output) => output
+            .stdout_lines()
+            .first()
+            .and_then(|line| parse_cargo_version(line)),
+        Err(err) => {
+            warn!("failed to get the version of {}: {}", toolchain, err);
+            None
+        }
+    };
+    TOOLCHAIN_VERSIONS
+        .lock()
+        .unwrap()
+        .insert(key, version.clone());
+    version
+}
+
+/// Parse the output of `cargo --version`, like `cargo 1.76.0 (c84b36747 2024-01-18)`.
+fn parse_cargo_version(line: &str) -> Option<Version> {
+    Version::parse(line.split_whitespace().nth(1)?).ok()
+}
+
+/// Oldest Rust version supporting `edition`.
+fn edition_min_version(edition: &Edition) -> Option<Version> {
+    match edition {
+        Edition::E2018 => Some(Version::new(1, 31, 0)),
+        Edition::E2021 => Some(Version::new(1, 56, 0)),
+        _ => None,
+    }
+}
+
+/// Whether a toolchain of the given version can build a package declaring `rust_version` and
+/// using `edition`. Pre-releases of a version, like its nightlies, are considered to support it.
+fn supports_package(
+    toolchain: &Version,
+    rust_version: Option<&Version>,
+    edition: &Edition,
+) -> bool {
+    let release = |v: &Version| (v.major, v.minor, v.patch);
+    rust_version
+        .into_iter()
+        .cloned()
+        .chain(edition_min_version(edition))
+        .all(|required| release(toolchain) >= release(&required))
+}
+
+/// Whether the toolchain is too old to build any of the packages, as declared by their
+/// `rust-version` or implied by their edition.
+fn toolchain_too_old<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     build_env: &Build,
-    args: &[&str],
-    check_errors: bool,
     local_packages: &[Package],
-    env: HashMap<&'static str, String>,
-) -> Fallible<()> {
-    let local_packages_id: HashSet<_> = local_packages.iter().map(|p| &p.id).collect();
+) -> bool {
+    let declares_version = |p: &Package| p.rust_version.is_some() || p.edition != Edition::E2015;
+    if !local_packages.iter().any(declares_version) {
+        return false;
+    }
+    let Some(version) = toolchain_version(ctx.toolchain, build_env) else {
+        return false;
+    };
+    local_packages
+        .iter()
+        .any(|p| !supports_package(&version, p.rust_version.as_ref(), &p.edition))
+}
 
+/// Arguments of a cargo invocation, with the target and the extra flags of the toolchain.
+fn cargo_args<'a>(args: &[&'a str], toolchain: &'a Toolchain) -> Vec<&'a str> {
     let mut args = args.to_vec();
-    if let Some(ref target) = ctx.toolchain.target {
+    if let Some(ref target) = toolchain.target {
         args.extend(["--target", target]);
     }
-    if let Some(ref tc_cargoflags) = ctx.toolchain.cargoflags {
+    if let Some(ref tc_cargoflags) = toolchain.cargoflags {
         args.extend(tc_cargoflags.split(' '));
     }
+    args
+}
 
-    let mut rustflags = format!("--cap-lints={}", ctx.experiment.cap_lints.to_str());
-    if let Some(ref tc_rustflags) = ctx.toolchain.rustflags {
-        rustflags.push(' ');
-        rustflags.push_str(tc_rustflags);
-    }
+/// Compiler flags passed to every build with the toolchain, on top of the experiment's lint cap.
+fn flags_env(cap_lints: &CapLints, toolchain: &Toolchain) -> [(&'static str, String); 2] {
+    let flags = |extra: &Option<String>| {
+        let mut flags = format!("--cap-lints={}", cap_lints.to_str());
+        if let Some(extra) = extra {
+            flags.push(' ');
+            flags.push_str(extra);
+        }
+        flags
+    };
 
-    let mut rustdocflags = format!("--cap-lints={}", ctx.experiment.cap_lints.to_str());
-    if let Some(ref tc_rustdocflags) = ctx.toolchain.rustdocflags {
-        rustdocflags.push(' ');
-        rustdocflags.push_str(tc_rustdocflags);
+    [
+        ("RUSTFLAGS", flags(&toolchain.rustflags)),
+        ("RUSTDOCFLAGS", flags(&toolchain.rustdocflags)),
+    ]
+}
+
+/// Environment of a cargo invocation: the flags and the variables set by crater for the command,
+/// overridden by the variables configured on the toolchain.
+fn command_env(
+    cap_lints: &CapLints,
+    toolchain: &Toolchain,
+    env: HashMap<&'static str, String>,
+) -> Vec<(String, String)> {
+    let mut merged = flags_env(cap_lints, toolchain)
+        .into_iter()
+        .chain(env)
+        .map(|(var, value)| (var.to_string(), value))
+        .collect::<Vec<_>>();
+    for (var, value) in &toolchain.env {
+        match merged.iter_mut().find(|(existing, _)| existing == var) {
+            Some((_, existing)) => *existing = value.clone(),
+            None => merged.push((var.clone(), value.clone())),
+        }
     }
+    merged
+}
+
+/// Cargo invocation with the flags passed in its environment, as it would be typed in a shell.
+/// The values of the secret variables are redacted, as the command ends up in the logs.
+fn shell_command(env: &[(String, String)], toolchain: &str, args: &[&str]) -> String {
+    let quote = |s: &str| {
+        if !s.is_empty()
+            && s.chars()
+                .all(|c| c.is_ascii_alphanumeric() || "-_=.,:/+".contains(c))
+        {
+            s.to_string()
+        } else {
+            format!("'{}'", s.replace('\'', "'\\''"))
+        }
+    };
+    env.iter()
+        .map(|(var, value)| format!("{var}={}", quote(redact_env(var, value))))
+        .chain(std::iter::once(format!("cargo +{}", quote(toolchain))))
+        .chain(args.iter().map(|arg| quote(arg)))
+        .collect::<Vec<_>>()
+        .join(" ")
+}
+
+fn run_cargo<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    build_env: &Build,
+    args: &[&str],
+    check_errors: bool,
+    local_packages: &[Package],
+    env: HashMap<&'static str, String>,
+) -> Fallible<Lints> {
+    let local_packages_id: HashSet<_> = local_packages.iter().map(|p| &p.id).collect();
+
+    let args = cargo_args(args, ctx.toolchain);
+    let command_env = command_env(&ctx.experiment.cap_lints, ctx.toolchain, env);
+    let command = shell_command(&command_env, &ctx.toolchain.rustup_name(), &args);
+    // Recorded in the log, for the report to show how to reproduce the build
+    info!("{}{}", COMMAND_LOG_PREFIX, command);
+    ctx.commands.lock().unwrap().push(command);
 
     let mut did_ice = false;
     let mut did_network = false;
     let mut did_trybuild = false;
     let mut ran_out_of_space = false;
+    let mut tool_missing = false;
     let mut error_codes = BTreeSet::new();
+    let mut lints = BTreeSet::new();
+    let mut undefined_behavior = BTreeSet::new();
     let mut deps = BTreeSet::new();
 
     let mut detect_error = |line: &str, actions: &mut ProcessLinesActions| {
@@ -131,6 +350,13 @@ fn run_cargo<DB: WriteResults>(
         if line.contains("the environment variable TRYBUILD=overwrite") {
             did_trybuild = true;
         }
+        // Cargo subcommands provided by rustup components, like clippy or miri
+        if line.contains("error: no such command: `")
+            || (line.contains("'cargo-") && line.contains("' is not installed"))
+        {
+            tool_missing = true;
+        }
+        undefined_behavior.extend(miri_diagnostic(line));
 
         // Avoid trying to deserialize non JSON output
         if !line.starts_with('{') {
@@ -155,6 +381,9 @@ fn run_cargo<DB: WriteResults>(
                     (DiagnosticLevel::Ice, pkgid) if local_packages_id.contains(pkgid) => {
                         did_ice = true
                     }
+                    (DiagnosticLevel::Warning, pkgid) if local_packages_id.contains(pkgid) => {
+                        lints.extend(clippy_lint(&inner_message));
+                    }
                     // If the error is in a crate that is not local then it's referred to a dependency
                     // of the current crate
                     (DiagnosticLevel::Error, pkgid) => {
@@ -180,10 +409,14 @@ fn run_cargo<DB: WriteResults>(
         .cargo()
         .args(&args)
         .env("CARGO_INCREMENTAL", "0")
-        .env("RUST_BACKTRACE", "full")
-        .env("RUSTFLAGS", rustflags)
-        .env("RUSTDOCFLAGS", rustdocflags);
-    for (var, data) in env {
+        .env("RUST_BACKTRACE", "full");
+    // Set first, so the toolchain's environment can still override it
+    if let Some(build_cache) = ctx.build_cache {
+        for (var, data) in build_cache.env(ctx.toolchain) {
+            command = command.env(var, data);
+        }
+    }
+    for (var, data) in &command_env {
         command = command.env(var, data);
     }
 
@@ -194,12 +427,19 @@ fn run_cargo<DB: WriteResults>(
     if ctx.quiet {
         command = command.no_output_timeout(None);
     }
+    command = command.timeout(Some(ctx.remaining_time()));
 
     match command.run() {
-        Ok(()) => Ok(()),
+        Ok(()) => Ok(Lints(lints)),
         Err(e) => {
-            if did_ice {
+            if tool_missing {
+                Err(e.context(OverrideResult(TestResult::Unavailable)).into())
+            } else if did_ice {
                 Err(e.context(FailureReason::ICE).into())
+            } else if !undefined_behavior.is_empty() {
+                Err(e
+                    .context(FailureReason::UndefinedBehavior(undefined_behavior))
+                    .into())
             } else if !deps.is_empty() {
                 Err(e.context(FailureReason::DependsOn(deps)).into())
             } else if !error_codes.is_empty() {
@@ -222,12 +462,13 @@ pub(super) fn run_test<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     test_fn: fn(&TaskCtx<DB>, &Build, &[Package]) -> Fallible<TestResult>,
     logs: &LogStorage,
-) -> Fallible<()> {
+) -> Fallible<TestResult> {
     if let Some(res) = ctx
         .db
         .get_result(ctx.experiment, ctx.toolchain, ctx.krate)?
     {
         info!("skipping crate {}. existing result: {}", ctx.krate, res);
+        Ok(res)
     } else {
         ctx.db.record_result(
             ctx.experiment,
@@ -240,12 +481,15 @@ pub(super) fn run_test<DB: WriteResults>(
                     "{} {} against {} for {}",
                     action,
                     ctx.krate,
-                    ctx.toolchain.to_string(),
+                    ctx.toolchain.redacted(),
                     ctx.experiment.name
                 );
-                let sandbox = SandboxBuilder::new()
-                    .memory_limit(Some(ctx.config.sandbox.memory_limit.to_bytes()))
+                let mut sandbox = SandboxBuilder::new()
+                    .memory_limit(Some(ctx.config.memory_limit(ctx.krate).to_bytes()))
                     .enable_networking(false);
+                if let Some(build_cache) = ctx.build_cache {
+                    sandbox = build_cache.mount(ctx.toolchain, sandbox);
+                }
 
                 let krate = &ctx.krate.to_rustwide();
                 let mut build_dir = ctx.build_dir.lock().unwrap();
@@ -257,12 +501,52 @@ pub(super) fn run_test<DB: WriteResults>(
 
                 detect_broken(build.run(|build| {
                     let local_packages = get_local_packages(build)?;
-                    test_fn(ctx, build, &local_packages)
+                    // Failures of toolchains older than the crate supports are expected
+                    if toolchain_too_old(ctx, build, &local_packages) {
+                        return Ok(TestResult::SkippedMsrv);
+                    }
+                    let res = test_fn(ctx, build, &local_packages)?;
+                    preserve_artifacts(ctx, build, &res);
+                    Ok(res)
                 }))
             },
-        )?;
+        )
+    }
+}
+
+/// Keep the target directory of the crate if it regressed, while the build is still around.
+fn preserve_artifacts<DB: WriteResults>(ctx: &TaskCtx<DB>, build_env: &Build, end: &TestResult) {
+    let (Some(artifacts), Some(start)) = (ctx.artifacts, ctx.start_result) else {
+        return;
+    };
+    let commands = ctx.commands.lock().unwrap();
+    match artifacts.preserve(
+        ctx.config,
+        ctx.krate,
+        ctx.toolchain,
+        start,
+        end,
+        &build_env.host_target_dir(),
+        &commands,
+    ) {
+        Ok(true) => info!("preserved the build artifacts of {}", ctx.krate),
+        Ok(false) => {}
+        Err(err) => warn!(
+            "failed to preserve the build artifacts of {}: {}",
+            ctx.krate, err
+        ),
     }
-    Ok(())
+}
+
+/// Arguments of the cargo invocations building a crate. Building the tests is what pulls the
+/// dev-dependencies, so it's skipped when asked to, unless the tests are going to run.
+fn build_args(mode: Mode, skip_dev_deps: bool) -> Vec<&'static [&'static str]> {
+    let mut args: Vec<&'static [&'static str]> =
+        vec![&["build", "--frozen", "--message-format=json"]];
+    if !skip_dev_deps || mode.runs_tests() {
+        args.push(&["test", "--frozen", "--no-run", "--message-format=json"]);
+    }
+    args
 }
 
 fn build<DB: WriteResults>(
@@ -270,22 +554,16 @@ fn build<DB: WriteResults>(
     build_env: &Build,
     local_packages: &[Package],
 ) -> Fallible<()> {
-    run_cargo(
-        ctx,
-        build_env,
-        &["build", "--frozen", "--message-format=json"],
-        true,
-        local_packages,
-        HashMap::default(),
-    )?;
-    run_cargo(
-        ctx,
-        build_env,
-        &["test", "--frozen", "--no-run", "--message-format=json"],
-        true,
-        local_packages,
-        HashMap::default(),
-    )?;
+    for args in build_args(ctx.experiment.mode, ctx.experiment.skip_dev_deps) {
+        run_cargo(
+            ctx,
+            build_env,
+            args,
+            true,
+            local_packages,
+            HashMap::default(),
+        )?;
+    }
     Ok(())
 }
 
@@ -297,7 +575,8 @@ fn test<DB: WriteResults>(ctx: &TaskCtx<DB>, build_env: &Build) -> Fallible<()>
         false,
         &[],
         HashMap::default(),
-    )
+    )?;
+    Ok(())
 }
 
 pub(super) fn test_build_and_test<DB: WriteResults>(
@@ -313,25 +592,114 @@ pub(super) fn test_build_and_test<DB: WriteResults>(
     };
 
     Ok(match (build_r, test_r) {
-        (Err(err), None) => TestResult::BuildFail(failure_reason(&err)),
-        (Ok(_), Some(Err(err))) => TestResult::TestFail(failure_reason(&err)),
+        (Err(err), None) => failure_result(&err, TestResult::BuildFail),
+        (Ok(_), Some(Err(err))) => failure_result(&err, TestResult::TestFail),
         (Ok(_), Some(Ok(_))) => TestResult::TestPass,
         (_, _) => unreachable!(),
     })
 }
 
+/// Whether the target directory still holds a build of every local package of the crate, left
+/// there by an earlier run with the same toolchain.
+fn has_cached_build(target_dir: &Path, target: Option<&str>, local_packages: &[&str]) -> bool {
+    let fingerprints = match target {
+        Some(target) => target_dir.join(target).join("debug").join(".fingerprint"),
+        None => target_dir.join("debug").join(".fingerprint"),
+    };
+    let Ok(entries) = std::fs::read_dir(fingerprints) else {
+        return false;
+    };
+    let built = entries
+        .filter_map(|entry| entry.ok())
+        .filter_map(|entry| entry.file_name().into_string().ok())
+        .collect::<Vec<_>>();
+
+    !local_packages.is_empty()
+        && local_packages.iter().all(|package| {
+            // Fingerprints are named after the package, followed by a hash
+            built.iter().any(|name| {
+                name.strip_prefix(*package)
+                    .and_then(|rest| rest.strip_prefix('-'))
+                    .is_some_and(|hash| hash.chars().all(|c| c.is_ascii_hexdigit()))
+            })
+        })
+}
+
+/// Arguments of the cargo invocations of the `test-only` mode: the build commands, only run when
+/// there's no cached build to test, and the test command.
+fn test_only_args(cached: bool) -> (Vec<&'static [&'static str]>, &'static [&'static str]) {
+    let build = if cached {
+        Vec::new()
+    } else {
+        build_args(Mode::TestOnly, false)
+    };
+    (build, &["test", "--frozen", "--no-fail-fast"])
+}
+
+pub(super) fn test_test_only<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    build_env: &Build,
+    local_packages: &[Package],
+) -> Fallible<TestResult> {
+    let names = local_packages
+        .iter()
+        .map(|p| p.name.as_str())
+        .collect::<Vec<_>>();
+    let cached = has_cached_build(
+        &build_env.host_target_dir(),
+        ctx.toolchain.target.as_deref(),
+        &names,
+    );
+    if !cached {
+        warn!(
+            "no cached build of {} with {}, falling back to building and testing it",
+            ctx.krate,
+            ctx.toolchain.redacted()
+        );
+    }
+
+    let (build, test) = test_only_args(cached);
+    for args in build {
+        if let Err(err) = run_cargo(
+            ctx,
+            build_env,
+            args,
+            true,
+            local_packages,
+            HashMap::default(),
+        ) {
+            return Ok(failure_result(&err, TestResult::BuildFail));
+        }
+    }
+    if let Err(err) = run_cargo(ctx, build_env, test, false, &[], HashMap::default()) {
+        Ok(failure_result(&err, TestResult::TestFail))
+    } else {
+        Ok(TestResult::TestPass)
+    }
+}
+
 pub(super) fn test_build_only<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     build_env: &Build,
     local_packages_id: &[Package],
 ) -> Fallible<TestResult> {
     if let Err(err) = build(ctx, build_env, local_packages_id) {
-        Ok(TestResult::BuildFail(failure_reason(&err)))
+        Ok(failure_result(&err, TestResult::BuildFail))
     } else {
         Ok(TestResult::TestSkipped)
     }
 }
 
+/// Arguments of the `cargo check` invocation of the `check-only` mode.
+fn check_args(options: &CheckOptions) -> Vec<&'static str> {
+    let mut args = vec!["check", "--frozen", "--all"];
+    if options.all_targets {
+        args.push("--all-targets");
+    }
+    args.push("--message-format=json");
+    args
+}
+
 pub(super) fn test_check_only<DB: WriteResults>(
     ctx: &TaskCtx<DB>,
     build_env: &Build,
@@ -340,18 +708,12 @@ pub(super) fn test_check_only<DB: WriteResults>(
     if let Err(err) = run_cargo(
         ctx,
         build_env,
-        &[
-            "check",
-            "--frozen",
-            "--all",
-            "--all-targets",
-            "--message-format=json",
-        ],
+        &check_args(&ctx.config.check),
         true,
         local_packages_id,
         HashMap::default(),
     ) {
-        Ok(TestResult::BuildFail(failure_reason(&err)))
+        Ok(failure_result(&err, TestResult::BuildFail))
     } else {
         Ok(TestResult::TestPass)
     }
@@ -362,7 +724,7 @@ pub(super) fn test_clippy_only<DB: WriteResults>(
     build_env: &Build,
     local_packages: &[Package],
 ) -> Fallible<TestResult> {
-    if let Err(err) = run_cargo(
+    match run_cargo(
         ctx,
         build_env,
         &[
@@ -376,9 +738,9 @@ pub(super) fn test_clippy_only<DB: WriteResults>(
         local_packages,
         HashMap::default(),
     ) {
-        Ok(TestResult::BuildFail(failure_reason(&err)))
-    } else {
-        Ok(TestResult::TestPass)
+        Err(err) => Ok(failure_result(&err, TestResult::BuildFail)),
+        Ok(lints) if lints.0.is_empty() => Ok(TestResult::TestPass),
+        Ok(lints) => Ok(TestResult::Warnings(lints)),
     }
 }
 
@@ -397,19 +759,29 @@ pub(super) fn test_rustdoc<DB: WriteResults>(
         res
     };
 
+    let options = &ctx.config.rustdoc;
+    if options.build_first {
+        if let Err(err) = run_cargo(
+            ctx,
+            build_env,
+            &["build", "--frozen", "--message-format=json"],
+            true,
+            local_packages,
+            HashMap::default(),
+        ) {
+            return Ok(failure_result(&err, TestResult::BuildFail));
+        }
+    }
+
     // first, run a normal `cargo doc`
-    let res = run(
-        &[
-            "doc",
-            "--frozen",
-            "--no-deps",
-            "--document-private-items",
-            "--message-format=json",
-        ],
-        HashMap::default(),
-    );
+    let mut args = vec!["doc", "--frozen"];
+    if !options.include_deps {
+        args.push("--no-deps");
+    }
+    args.extend(["--document-private-items", "--message-format=json"]);
+    let res = run(args.as_slice(), HashMap::default());
     if let Err(err) = res {
-        return Ok(TestResult::BuildFail(failure_reason(&err)));
+        return Ok(failure_result(&err, TestResult::DocFail));
     }
 
     // next, if this is a library, run it with docs.rs metadata applied.
@@ -431,13 +803,37 @@ pub(super) fn test_rustdoc<DB: WriteResults>(
         env.insert("RUSTC_BOOTSTRAP", "1".to_string());
 
         if let Err(err) = run(&cargo_args, env) {
-            return Ok(TestResult::BuildFail(failure_reason(&err)));
+            return Ok(failure_result(&err, TestResult::DocFail));
         }
     }
 
     Ok(TestResult::TestPass)
 }
 
+pub(super) fn test_miri<DB: WriteResults>(
+    ctx: &TaskCtx<DB>,
+    build_env: &Build,
+    local_packages: &[Package],
+) -> Fallible<TestResult> {
+    // Miri is too slow to run on every crate, only the ones opting in are tested
+    if !ctx.config.runs_miri(ctx.krate) {
+        return Ok(TestResult::Skipped);
+    }
+
+    if let Err(err) = run_cargo(
+        ctx,
+        build_env,
+        &["miri", "test", "--frozen", "--message-format=json"],
+        true,
+        local_packages,
+        HashMap::default(),
+    ) {
+        Ok(failure_result(&err, TestResult::TestFail))
+    } else {
+        Ok(TestResult::TestPass)
+    }
+}
+
 fn is_library(target: &Target) -> bool {
     // Some examples and tests can be libraries (e.g. if they use `cdylib`).
     target.crate_types.iter().any(|ty| ty != "bin")
@@ -446,3 +842,436 @@ fn is_library(target: &Target) -> bool {
             .iter()
             .all(|k| !["example", "test", "bench"].contains(&k.as_str()))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::{
+        build_args, cargo_args, check_args, clippy_lint, command_env, failure_result, fetch_error,
+        flags_env, has_cached_build, miri_diagnostic, parse_cargo_version, shell_command,
+        supports_package, test_only_args,
+    };
+    use crate::config::CheckOptions;
+    use crate::experiments::{CapLints, Mode};
+    use crate::results::{BrokenReason, DiagnosticCode, FailureReason, TestResult};
+    use crate::runner::OverrideResult;
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use cargo_metadata::semver::Version;
+    use cargo_metadata::{Edition, Message};
+    use failure::Error;
+    use rustwide::cmd::CommandError;
+    use std::collections::{BTreeSet, HashMap};
+
+    #[test]
+    fn test_failure_result() {
+        let timeout: Error = Error::from(CommandError::Timeout(1))
+            .context("running cargo")
+            .into();
+        assert_eq!(
+            failure_result(&timeout, TestResult::BuildFail),
+            TestResult::Timeout
+        );
+
+        // Commands not producing output for too long are still spurious failures
+        let no_output = Error::from(CommandError::NoOutputFor(1));
+        assert_eq!(
+            failure_result(&no_output, TestResult::TestFail),
+            TestResult::TestFail(FailureReason::Timeout)
+        );
+
+        let oom = Error::from(CommandError::SandboxOOM);
+        assert_eq!(
+            failure_result(&oom, TestResult::BuildFail),
+            TestResult::BuildFail(FailureReason::OOM)
+        );
+    }
+
+    #[test]
+    fn test_clippy_unavailable() {
+        let err: Error = Error::from(CommandError::SandboxOOM)
+            .context(OverrideResult(TestResult::Unavailable))
+            .into();
+        assert_eq!(
+            failure_result(&err, TestResult::BuildFail),
+            TestResult::Unavailable
+        );
+    }
+
+    #[test]
+    fn test_clippy_lint() {
+        let output = concat!(
+            r#"{"reason":"compiler-message","package_id":"clippy-warn 0.1.0 (path+file:///opt/rustwide/workdir)","#,
+            r#""manifest_path":"/opt/rustwide/workdir/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"#,
+            r#""name":"clippy-warn","src_path":"/opt/rustwide/workdir/src/main.rs","edition":"2015","doc":true,"#,
+            r#""doctest":false,"test":true},"message":{"rendered":"warning: using `print!()` with a format string "#,
+            r#"that ends in a single newline\n","children":[],"code":{"code":"clippy::print_with_newline","#,
+            r#""explanation":null},"level":"warning","message":"using `print!()` with a format string that ends in "#,
+            r#"a single newline","spans":[]}}"#,
+            "\n",
+            r#"{"reason":"compiler-message","package_id":"clippy-warn 0.1.0 (path+file:///opt/rustwide/workdir)","#,
+            r#""manifest_path":"/opt/rustwide/workdir/Cargo.toml","target":{"kind":["bin"],"crate_types":["bin"],"#,
+            r#""name":"clippy-warn","src_path":"/opt/rustwide/workdir/src/main.rs","edition":"2015","doc":true,"#,
+            r#""doctest":false,"test":true},"message":{"rendered":"warning: unused variable: `x`\n","children":[],"#,
+            r#""code":{"code":"unused_variables","explanation":null},"level":"warning","#,
+            r#""message":"unused variable: `x`","spans":[]}}"#,
+            "\n",
+            r#"{"reason":"build-finished","success":true}"#,
+        );
+
+        let lints = output
+            .lines()
+            .filter_map(|line| match serde_json::from_str(line).unwrap() {
+                Message::CompilerMessage(message) => clippy_lint(&message.message),
+                _ => None,
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            lints,
+            vec![DiagnosticCode::from("clippy::print_with_newline".into())]
+        );
+    }
+
+    #[test]
+    fn test_miri_diagnostics() {
+        let start = concat!(
+            "test tests::leak ... error: memory leaked: alloc1442 (Rust heap, size: 4, align: 4), allocated here:\n",
+            "   --> /rustc/library/alloc/src/alloc.rs:98:9\n",
+            "error: aborting due to 1 previous error\n",
+        );
+        let end = concat!(
+            "test tests::leak ... error: memory leaked: alloc1517 (Rust heap, size: 4, align: 4), allocated here:\n",
+            "test tests::oob ... error: Undefined Behavior: out-of-bounds pointer use: alloc1623 has size 4, ",
+            "so pointer to 4 bytes starting at offset 4 is out-of-bounds\n",
+            "error: Undefined Behavior: trying to retag from <2113> for Unique permission at alloc985[0x0], ",
+            "but that tag does not exist in the borrow stack for this location\n",
+            "error: aborting due to 3 previous errors\n",
+        );
+
+        let kinds = |output: &str| {
+            output
+                .lines()
+                .filter_map(miri_diagnostic)
+                .collect::<BTreeSet<_>>()
+        };
+        let (start, end) = (kinds(start), kinds(end));
+
+        // Allocation ids differ between runs without making the leak a new report
+        assert_eq!(start.len(), 1);
+        assert!(start.is_subset(&end));
+        assert_eq!(
+            end.difference(&start)
+                .map(|kind| kind.to_string())
+                .collect::<Vec<_>>(),
+            vec![
+                "out-of-bounds pointer use",
+                "trying to retag from <N> for Unique permission at allocN[NxN]; but that tag does \
+                 not exist in the borrow stack for this location",
+            ]
+        );
+    }
+
+    #[test]
+    fn test_flags_env() {
+        let tuned = Toolchain {
+            rustflags: Some("-Copt-level=2 -Cdebug-assertions=on".into()),
+            ..TEST_TOOLCHAIN.clone()
+        };
+
+        assert_eq!(
+            flags_env(&CapLints::Forbid, &tuned),
+            [
+                (
+                    "RUSTFLAGS",
+                    "--cap-lints=forbid -Copt-level=2 -Cdebug-assertions=on".into()
+                ),
+                ("RUSTDOCFLAGS", "--cap-lints=forbid".into()),
+            ]
+        );
+        // The flags only apply to the toolchain they're configured on
+        assert_eq!(
+            flags_env(&CapLints::Forbid, &MAIN_TOOLCHAIN),
+            [
+                ("RUSTFLAGS", "--cap-lints=forbid".into()),
+                ("RUSTDOCFLAGS", "--cap-lints=forbid".into()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_command_env() {
+        let offline = Toolchain {
+            rustflags: Some("-Zfoo".into()),
+            env: [
+                ("CARGO_NET_OFFLINE", "true"),
+                ("RUSTDOCFLAGS", "--cap-lints=warn"),
+                ("DOCS_RS", "0"),
+            ]
+            .into_iter()
+            .map(|(var, value)| (var.to_string(), value.to_string()))
+            .collect(),
+            ..TEST_TOOLCHAIN.clone()
+        };
+        let env = HashMap::from([("DOCS_RS", "1".to_string())]);
+
+        // The toolchain's variables are added, and win over the ones set by crater
+        assert_eq!(
+            command_env(&CapLints::Forbid, &offline, env.clone()),
+            vec![
+                (
+                    "RUSTFLAGS".to_string(),
+                    "--cap-lints=forbid -Zfoo".to_string()
+                ),
+                ("RUSTDOCFLAGS".to_string(), "--cap-lints=warn".to_string()),
+                ("DOCS_RS".to_string(), "0".to_string()),
+                ("CARGO_NET_OFFLINE".to_string(), "true".to_string()),
+            ]
+        );
+        // Only for the toolchain they're configured on
+        assert_eq!(
+            command_env(&CapLints::Forbid, &MAIN_TOOLCHAIN, env),
+            vec![
+                ("RUSTFLAGS".to_string(), "--cap-lints=forbid".to_string()),
+                ("RUSTDOCFLAGS".to_string(), "--cap-lints=forbid".to_string()),
+                ("DOCS_RS".to_string(), "1".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_cargo_args() {
+        let wasm = Toolchain {
+            target: Some("wasm32-unknown-unknown".into()),
+            cargoflags: Some("-Zbuild-std".into()),
+            ..TEST_TOOLCHAIN.clone()
+        };
+
+        assert_eq!(
+            cargo_args(&["build", "--frozen"], &wasm),
+            vec![
+                "build",
+                "--frozen",
+                "--target",
+                "wasm32-unknown-unknown",
+                "-Zbuild-std"
+            ]
+        );
+        assert_eq!(
+            cargo_args(&["build", "--frozen"], &MAIN_TOOLCHAIN),
+            vec!["build", "--frozen"]
+        );
+    }
+
+    #[test]
+    fn test_build_args() {
+        let build: &[&str] = &["build", "--frozen", "--message-format=json"];
+        let build_tests: &[&str] = &["test", "--frozen", "--no-run", "--message-format=json"];
+
+        assert_eq!(build_args(Mode::BuildOnly, false), vec![build, build_tests]);
+        assert_eq!(build_args(Mode::BuildOnly, true), vec![build]);
+
+        // Tests need the dev-dependencies, so the flag is ignored
+        assert_eq!(
+            build_args(Mode::BuildAndTest, false),
+            vec![build, build_tests]
+        );
+        assert_eq!(
+            build_args(Mode::BuildAndTest, true),
+            vec![build, build_tests]
+        );
+    }
+
+    #[test]
+    fn test_test_only() {
+        let target_dir = tempfile::tempdir().unwrap();
+        let target_dir = target_dir.path();
+        let musl = "x86_64-unknown-linux-musl";
+
+        // Nothing was built yet
+        assert!(!has_cached_build(target_dir, None, &["foo"]));
+
+        let fingerprints = target_dir.join("debug").join(".fingerprint");
+        std::fs::create_dir_all(fingerprints.join("foo-bar-fedcba9876543210")).unwrap();
+        assert!(has_cached_build(target_dir, None, &["foo-bar"]));
+        assert!(!has_cached_build(target_dir, None, &["foo"]));
+        assert!(!has_cached_build(target_dir, None, &["foo", "foo-bar"]));
+
+        std::fs::create_dir_all(fingerprints.join("foo-0123456789abcdef")).unwrap();
+        assert!(has_cached_build(target_dir, None, &["foo", "foo-bar"]));
+        assert!(!has_cached_build(target_dir, None, &["fo"]));
+        assert!(!has_cached_build(target_dir, None, &[]));
+
+        // Builds for another target are stored apart
+        assert!(!has_cached_build(target_dir, Some(musl), &["foo"]));
+        let fingerprints = target_dir.join(musl).join("debug").join(".fingerprint");
+        std::fs::create_dir_all(fingerprints.join("foo-0123456789abcdef")).unwrap();
+        assert!(has_cached_build(target_dir, Some(musl), &["foo"]));
+
+        let test: &[&str] = &["test", "--frozen", "--no-fail-fast"];
+        assert_eq!(test_only_args(true), (vec![], test));
+
+        // Without a cached build the crate is built first, dev-dependencies included
+        let build: &[&str] = &["build", "--frozen", "--message-format=json"];
+        let build_tests: &[&str] = &["test", "--frozen", "--no-run", "--message-format=json"];
+        assert_eq!(test_only_args(false), (vec![build, build_tests], test));
+    }
+
+    #[test]
+    fn test_check_args() {
+        assert_eq!(
+            check_args(&CheckOptions::default()),
+            vec![
+                "check",
+                "--frozen",
+                "--all",
+                "--all-targets",
+                "--message-format=json"
+            ]
+        );
+        assert_eq!(
+            check_args(&CheckOptions { all_targets: false }),
+            vec!["check", "--frozen", "--all", "--message-format=json"]
+        );
+    }
+
+    #[test]
+    fn test_fetch_error() {
+        let override_result = |err: &Error| {
+            err.iter_chain()
+                .find_map(|cause| cause.downcast_ctx::<OverrideResult>())
+                .map(|OverrideResult(res)| res.clone())
+        };
+
+        let timeout = fetch_error(
+            Error::from(CommandError::Timeout(30))
+                .context("failed to download lazy_static 1.0.0")
+                .into(),
+        );
+        assert_eq!(
+            override_result(&timeout),
+            Some(TestResult::FetchError(format!(
+                "failed to download lazy_static 1.0.0: {}",
+                CommandError::Timeout(30)
+            )))
+        );
+
+        // Crates detected as broken while fetching them keep their result
+        let broken: Error = Error::from(CommandError::Timeout(30))
+            .context(OverrideResult(TestResult::BrokenCrate(
+                BrokenReason::MissingGitRepository,
+            )))
+            .into();
+        assert_eq!(
+            override_result(&fetch_error(broken)),
+            Some(TestResult::BrokenCrate(BrokenReason::MissingGitRepository))
+        );
+    }
+
+    #[test]
+    fn test_supports_package() {
+        let v = |v: &str| Version::parse(v).unwrap();
+
+        // Crates without a rust-version are never skipped
+        assert!(supports_package(&v("1.0.0"), None, &Edition::E2015));
+        assert!(supports_package(&v("1.70.0"), None, &Edition::E2015));
+
+        assert!(supports_package(
+            &v("1.70.0"),
+            Some(&v("1.70.0")),
+            &Edition::E2015
+        ));
+        assert!(supports_package(
+            &v("1.70.0"),
+            Some(&v("1.65.0")),
+            &Edition::E2021
+        ));
+        assert!(!supports_package(
+            &v("1.70.0"),
+            Some(&v("1.71.0")),
+            &Edition::E2021
+        ));
+        assert!(!supports_package(
+            &v("1.70.0"),
+            Some(&v("1.70.1")),
+            &Edition::E2021
+        ));
+
+        // Nightlies support the version they'll be released as
+        assert!(supports_package(
+            &v("1.71.0-nightly"),
+            Some(&v("1.71.0")),
+            &Edition::E2021
+        ));
+        assert!(!supports_package(
+            &v("1.71.0-nightly"),
+            Some(&v("1.72.0")),
+            &Edition::E2021
+        ));
+
+        // The edition implies a minimum version too
+        assert!(supports_package(&v("1.50.0"), None, &Edition::E2018));
+        assert!(!supports_package(&v("1.50.0"), None, &Edition::E2021));
+        assert!(!supports_package(
+            &v("1.50.0"),
+            Some(&v("1.40.0")),
+            &Edition::E2021
+        ));
+    }
+
+    #[test]
+    fn test_parse_cargo_version() {
+        assert_eq!(
+            parse_cargo_version("cargo 1.76.0 (c84b36747 2024-01-18)"),
+            Some(Version::new(1, 76, 0))
+        );
+        assert_eq!(
+            parse_cargo_version("cargo 1.78.0-nightly (7bb7b5395 2024-01-20)"),
+            Some(Version::parse("1.78.0-nightly").unwrap())
+        );
+        assert_eq!(parse_cargo_version("error: no such command"), None);
+    }
+
+    #[test]
+    fn test_shell_command() {
+        let env = [(
+            "RUSTFLAGS".to_string(),
+            "--cap-lints=forbid -Zfoo".to_string(),
+        )];
+        assert_eq!(
+            shell_command(
+                &env,
+                "stable",
+                &["build", "--frozen", "--message-format=json"]
+            ),
+            "RUSTFLAGS='--cap-lints=forbid -Zfoo' cargo +stable build --frozen \
+             --message-format=json"
+        );
+        // Secrets don't end up in the logs
+        let env = [
+            ("REGISTRY_TOKEN".to_string(), "hunter2".to_string()),
+            ("CARGO_NET_OFFLINE".to_string(), "true".to_string()),
+        ];
+        assert_eq!(
+            shell_command(&env, "beta", &["build"]),
+            "REGISTRY_TOKEN='<redacted>' CARGO_NET_OFFLINE=true cargo +beta build"
+        );
+        assert_eq!(
+            shell_command(&[], "nightly-2024-01-01", &["test", "--", "it's", ""]),
+            r#"cargo +nightly-2024-01-01 test -- 'it'\''s' ''"#
+        );
+
+        // The custom target, flags and environment of the toolchain are all part of the command
+        let toolchain: Toolchain =
+            "beta+target=x86_64-unknown-linux-musl+rustflags=-Zfoo+env=API_TOKEN=hunter2"
+                .parse()
+                .unwrap();
+        let env = command_env(&CapLints::Forbid, &toolchain, HashMap::new());
+        assert_eq!(
+            shell_command(
+                &env,
+                &toolchain.rustup_name(),
+                &cargo_args(&["test", "--frozen"], &toolchain)
+            ),
+            "RUSTFLAGS='--cap-lints=forbid -Zfoo' RUSTDOCFLAGS=--cap-lints=forbid \
+             API_TOKEN='<redacted>' cargo +beta test --frozen --target x86_64-unknown-linux-musl"
+        );
+    }
+}
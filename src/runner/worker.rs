@@ -2,6 +2,9 @@ use crate::crates::Crate;
 use crate::experiments::{Experiment, Mode};
 use crate::prelude::*;
 use crate::results::{BrokenReason, TestResult, WriteResults};
+use crate::runner::artifacts::ArtifactsStore;
+use crate::runner::build_cache::BuildCache;
+use crate::runner::fetch::FetchLimiter;
 use crate::runner::tasks::{Task, TaskStep};
 use crate::runner::OverrideResult;
 use crate::utils;
@@ -22,6 +25,9 @@ pub(super) struct Worker<'a, DB: WriteResults + Sync> {
     ex: &'a Experiment,
     config: &'a crate::config::Config,
     db: &'a DB,
+    fetch_limiter: &'a FetchLimiter,
+    artifacts: Option<&'a ArtifactsStore>,
+    build_cache: Option<&'a BuildCache>,
     target_dir_cleanup: AtomicBool,
     next_crate: &'a (dyn Fn() -> Fallible<Option<Crate>> + Send + Sync),
 }
@@ -33,17 +39,18 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
         ex: &'a Experiment,
         config: &'a crate::config::Config,
         db: &'a DB,
+        fetch_limiter: &'a FetchLimiter,
+        artifacts: Option<&'a ArtifactsStore>,
+        build_cache: Option<&'a BuildCache>,
         next_crate: &'a (dyn Fn() -> Fallible<Option<Crate>> + Send + Sync),
     ) -> Self {
         let mut build_dir = HashMap::new();
-        build_dir.insert(
-            &ex.toolchains[0],
-            Mutex::new(workspace.build_dir(&format!("{name}-tc1"))),
-        );
-        build_dir.insert(
-            &ex.toolchains[1],
-            Mutex::new(workspace.build_dir(&format!("{name}-tc2"))),
-        );
+        for (i, tc) in ex.all_toolchains().enumerate() {
+            build_dir.insert(
+                tc,
+                Mutex::new(workspace.build_dir(&format!("{name}-tc{}", i + 1))),
+            );
+        }
         Worker {
             build_dir,
             name,
@@ -52,6 +59,9 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
             config,
             next_crate,
             db,
+            fetch_limiter,
+            artifacts,
+            build_cache,
             target_dir_cleanup: AtomicBool::new(false),
         }
     }
@@ -64,10 +74,11 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
         &self,
         task: &Task,
         storage: &LogStorage,
-    ) -> Result<(), (failure::Error, TestResult)> {
+        start_result: Option<&TestResult>,
+    ) -> Result<Option<TestResult>, (failure::Error, TestResult)> {
         info!("running task: {:?}", task);
 
-        let mut res = Ok(());
+        let mut res = Ok(None);
         let max_attempts = 5;
         for run in 1..=max_attempts {
             // If we're running a task, we call ourselves healthy.
@@ -79,17 +90,21 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                 &self.build_dir,
                 self.ex,
                 self.db,
+                self.fetch_limiter,
+                self.artifacts,
+                self.build_cache,
+                start_result,
                 storage,
             );
 
-            // We retry task failing on the second toolchain (i.e., regressions). In
-            // the future we might expand this list further but for now this helps
-            // prevent spurious test failures and such.
+            // We retry task failing on the toolchains compared with the first one (i.e.,
+            // regressions). In the future we might expand this list further but for now this
+            // helps prevent spurious test failures and such.
             //
             // For now we make no distinction between build failures and test failures
             // here, but that may change if this proves too slow.
             let mut should_retry = false;
-            if res.is_err() && self.ex.toolchains.len() == 2 {
+            if res.is_err() {
                 let toolchain = match &task.step {
                     TaskStep::Prepare => None,
                     TaskStep::BuildAndTest { tc, .. }
@@ -97,10 +112,12 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                     | TaskStep::CheckOnly { tc, .. }
                     | TaskStep::Clippy { tc, .. }
                     | TaskStep::Rustdoc { tc, .. }
-                    | TaskStep::UnstableFeatures { tc } => Some(tc),
+                    | TaskStep::Miri { tc, .. }
+                    | TaskStep::UnstableFeatures { tc }
+                    | TaskStep::TestOnly { tc, .. } => Some(tc),
                 };
                 if let Some(toolchain) = toolchain {
-                    if toolchain == self.ex.toolchains.last().unwrap() {
+                    if *toolchain != self.ex.toolchains[0] {
                         should_retry = true;
                     }
                 }
@@ -111,27 +128,27 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
 
             log::info!("Retrying task {:?} [{run}/{max_attempts}]", task);
         }
-        if let Err(e) = res {
-            error!("task {:?} failed", task);
-            utils::report_failure(&e);
+        let e = match res {
+            Ok(result) => return Ok(result),
+            Err(e) => e,
+        };
+        error!("task {:?} failed", task);
+        utils::report_failure(&e);
 
-            let mut result = if self.config.is_broken(&task.krate) {
-                TestResult::BrokenCrate(BrokenReason::Unknown)
-            } else {
-                TestResult::Error
-            };
+        let mut result = if self.config.is_broken(&task.krate) {
+            TestResult::BrokenCrate(BrokenReason::Unknown)
+        } else {
+            TestResult::Error
+        };
 
-            for err in e.iter_chain() {
-                if let Some(OverrideResult(res)) = err.downcast_ctx() {
-                    result = res.clone();
-                    break;
-                }
+        for err in e.iter_chain() {
+            if let Some(OverrideResult(res)) = err.downcast_ctx() {
+                result = res.clone();
+                break;
             }
-
-            return Err((e, result));
         }
 
-        Ok(())
+        Err((e, result))
     }
 
     pub(super) fn run(&self) -> Fallible<()> {
@@ -148,7 +165,7 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
             info!("{} processing crate {}", self.name, krate);
 
             if !self.ex.ignore_blacklist && self.config.should_skip(&krate) {
-                for tc in &self.ex.toolchains {
+                for tc in self.ex.all_toolchains() {
                     // If a skipped crate is somehow sent to the agent (for example, when a crate was
                     // added to the experiment and *then* blacklisted) report the crate as skipped
                     // instead of silently ignoring it.
@@ -174,13 +191,13 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                 krate: krate.clone(),
                 step: TaskStep::Prepare,
             };
-            if let Err((err, test_result)) = &self.run_task(&prepare_task, &logs) {
+            if let Err((err, test_result)) = &self.run_task(&prepare_task, &logs, None) {
                 if let Err(e) =
                     prepare_task.mark_as_failed(self.ex, self.db, err, test_result, &logs)
                 {
                     crate::utils::report_failure(&e);
                 }
-                for tc in &self.ex.toolchains {
+                for tc in self.ex.all_toolchains() {
                     if let Err(e) = self.db.record_result(
                         self.ex,
                         tc,
@@ -199,7 +216,9 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                 continue;
             }
 
-            for tc in &self.ex.toolchains {
+            // Result of the start toolchain, to find out if the end toolchain regressed
+            let mut start_result = None;
+            for tc in self.ex.all_toolchains() {
                 let quiet = self.config.is_quiet(&krate);
                 let task = Task {
                     krate: krate.clone(),
@@ -208,7 +227,7 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                             tc: tc.clone(),
                             quiet,
                         },
-                        Mode::BuildAndTest
+                        Mode::BuildAndTest | Mode::TestOnly
                             if !self.ex.ignore_blacklist
                                 && self.config.should_skip_tests(&krate) =>
                         {
@@ -233,17 +252,35 @@ impl<'a, DB: WriteResults + Sync> Worker<'a, DB> {
                             tc: tc.clone(),
                             quiet,
                         },
+                        Mode::Miri => TaskStep::Miri {
+                            tc: tc.clone(),
+                            quiet,
+                        },
                         Mode::UnstableFeatures => TaskStep::UnstableFeatures { tc: tc.clone() },
+                        Mode::TestOnly => TaskStep::TestOnly {
+                            tc: tc.clone(),
+                            quiet,
+                        },
                     },
                 };
 
                 let storage = logs.clone();
-                if let Err((err, test_result)) = &self.run_task(&task, &storage) {
-                    if let Err(e) =
-                        task.mark_as_failed(self.ex, self.db, err, test_result, &storage)
-                    {
-                        crate::utils::report_failure(&e);
+                let compared_to = start_result
+                    .as_ref()
+                    .filter(|_| *tc == self.ex.toolchains[1]);
+                let result = match self.run_task(&task, &storage, compared_to) {
+                    Ok(result) => result,
+                    Err((err, test_result)) => {
+                        if let Err(e) =
+                            task.mark_as_failed(self.ex, self.db, &err, &test_result, &storage)
+                        {
+                            crate::utils::report_failure(&e);
+                        }
+                        Some(test_result)
                     }
+                };
+                if *tc == self.ex.toolchains[0] {
+                    start_result = result;
                 }
             }
         }
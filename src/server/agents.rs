@@ -5,8 +5,7 @@ use crate::prelude::*;
 use crate::server::tokens::Tokens;
 use chrono::Duration;
 use chrono::{DateTime, Utc};
-use std::collections::HashMap;
-use std::collections::HashSet;
+use std::collections::{BTreeMap, HashMap, HashSet};
 use std::sync::{Arc, Mutex};
 
 /// Number of seconds without an heartbeat after an agent should be considered unreachable.
@@ -56,7 +55,7 @@ impl Agent {
 
     pub fn status(&self) -> AgentStatus {
         if let Some(ref heartbeat) = self.last_heartbeat {
-            if Utc::now() - Duration::seconds(INACTIVE_AFTER) < *heartbeat {
+            if is_reachable(heartbeat) {
                 if self.experiment.is_some() {
                     return AgentStatus::Working;
                 } else {
@@ -73,11 +72,18 @@ impl Agent {
     }
 }
 
+/// Whether an agent whose last heartbeat was at the given time is still reachable.
+pub fn is_reachable(last_heartbeat: &DateTime<Utc>) -> bool {
+    Utc::now() - Duration::seconds(INACTIVE_AFTER) < *last_heartbeat
+}
+
 #[derive(Clone)]
 pub struct Agents {
     db: Database,
     // worker -> timestamp
     current_workers: Arc<Mutex<HashMap<String, (WorkerInfo, std::time::Instant)>>>,
+    // agent -> last heartbeat, mirroring the database to serve it without querying it
+    last_seen: Arc<Mutex<BTreeMap<String, Option<DateTime<Utc>>>>>,
 }
 
 #[derive(Deserialize)]
@@ -90,6 +96,7 @@ impl Agents {
         let agents = Agents {
             db,
             current_workers: Arc::new(Mutex::new(HashMap::new())),
+            last_seen: Arc::new(Mutex::new(BTreeMap::new())),
         };
         agents.synchronize(tokens)?;
         Ok(agents)
@@ -135,7 +142,21 @@ impl Agents {
             }
 
             Ok(())
-        })
+        })?;
+
+        *self.last_seen.lock().unwrap() = self
+            .db
+            .query("SELECT name, last_heartbeat FROM agents;", [], |r| {
+                Ok((r.get(0)?, r.get(1)?))
+            })?
+            .into_iter()
+            .collect();
+        Ok(())
+    }
+
+    /// Last heartbeat of each agent, without querying the database.
+    pub fn last_seen(&self) -> BTreeMap<String, Option<DateTime<Utc>>> {
+        self.last_seen.lock().unwrap().clone()
     }
 
     pub fn all(&self) -> Fallible<Vec<Agent>> {
@@ -182,11 +203,16 @@ impl Agents {
     }
 
     pub fn record_heartbeat(&self, agent: &str) -> Fallible<()> {
+        let now = Utc::now();
         let changes = self.db.execute(
             "UPDATE agents SET last_heartbeat = ?1 WHERE name = ?2;",
-            &[&Utc::now(), &agent],
+            &[&now, &agent],
         )?;
         assert_eq!(changes, 1);
+        self.last_seen
+            .lock()
+            .unwrap()
+            .insert(agent.to_string(), Some(now));
 
         Ok(())
     }
@@ -257,6 +283,12 @@ mod tests {
                 .collect::<Vec<_>>(),
             vec!["agent2".to_string(), "agent3".to_string()]
         );
+
+        // The in-memory list follows the registered agents
+        assert_eq!(
+            agents.last_seen().into_keys().collect::<Vec<_>>(),
+            vec!["agent2".to_string(), "agent3".to_string()]
+        );
     }
 
     #[test]
@@ -268,6 +300,7 @@ mod tests {
 
         let agent = agents.get("agent").unwrap().unwrap();
         assert!(agent.last_heartbeat.is_none());
+        assert_eq!(agents.last_seen()["agent"], None);
 
         agents.record_heartbeat("agent").unwrap();
 
@@ -278,6 +311,7 @@ mod tests {
 
         let agent = agents.get("agent").unwrap().unwrap();
         assert!(first_heartbeat <= agent.last_heartbeat.unwrap());
+        assert_eq!(agents.last_seen()["agent"], agent.last_heartbeat);
     }
 
     #[test]
@@ -125,9 +125,17 @@ impl Metrics {
     }
 }
 
+/// Metrics shared by the tests, as they can only be registered once in the process.
+#[cfg(test)]
+lazy_static! {
+    pub(crate) static ref TEST_METRICS: Metrics = Metrics::new().unwrap();
+}
+
 #[cfg(test)]
 mod tests {
-    use super::{Metrics, AGENT_WORK_METRIC, JOBS_METRIC, LAST_CRATES_UPDATE_METRIC};
+    use super::{
+        Metrics, AGENT_WORK_METRIC, JOBS_METRIC, LAST_CRATES_UPDATE_METRIC, TEST_METRICS as METRICS,
+    };
     use crate::actions::{Action, ActionsCtx, CreateExperiment, EditExperiment};
     use crate::config::Config;
     use crate::db::Database;
@@ -135,13 +143,8 @@ mod tests {
     use crate::server::agents::{Agent, Agents};
     use crate::server::tokens::Tokens;
     use chrono::Utc;
-    use lazy_static::lazy_static;
     use prometheus::proto::{Metric, MetricFamily};
 
-    lazy_static! {
-        static ref METRICS: Metrics = Metrics::new().unwrap();
-    }
-
     impl Metrics {
         fn get_metric_by_name(name: &str) -> Option<MetricFamily> {
             let families = prometheus::gather();
@@ -5,8 +5,11 @@ mod cronjobs;
 mod github;
 mod messages;
 mod metrics;
+mod notifications;
 mod reports;
 mod routes;
+mod shutdown;
+mod status;
 pub mod tokens;
 mod try_builds;
 
@@ -16,12 +19,15 @@ use crate::prelude::*;
 use crate::server::agents::Agents;
 use crate::server::auth::ACL;
 use crate::server::github::{GitHub, GitHubApi};
+use crate::server::shutdown::Shutdown;
+use crate::server::status::ServerStatus;
 use crate::server::tokens::{BotTokens, Tokens};
 use http::{header::HeaderValue, Response};
 use hyper::Body;
 use metrics::Metrics;
 use std::net::SocketAddr;
 use std::sync::{Arc, Mutex};
+use std::time::Duration;
 use warp::Filter;
 
 lazy_static! {
@@ -49,6 +55,8 @@ pub struct Data {
     pub record_progress_worker: routes::agent::RecordProgressThread,
     pub acl: ACL,
     pub metrics: Metrics,
+    pub status: ServerStatus,
+    pub shutdown: Shutdown,
 }
 
 #[derive(Clone)]
@@ -81,11 +89,16 @@ pub fn run(config: Config, bind: SocketAddr) -> Fallible<()> {
     let acl = ACL::new(&config, github_data.as_ref())?;
     let metrics = Metrics::new()?;
     info!("initialized metrics...");
+    let status = ServerStatus::default();
+    let shutdown = Shutdown::default();
+    let shutdown_timeout = Duration::from_secs(config.server.shutdown_timeout_secs);
 
     let data = Data {
         record_progress_worker: routes::agent::RecordProgressThread::new(
             db.clone(),
             metrics.clone(),
+            status.clone(),
+            config.clone(),
         ),
         config,
         tokens,
@@ -94,6 +107,8 @@ pub fn run(config: Config, bind: SocketAddr) -> Fallible<()> {
         reports_worker: reports::ReportsWorker::new(),
         acl,
         metrics,
+        status,
+        shutdown: shutdown.clone(),
     };
 
     let mutex = Arc::new(Mutex::new(data.clone()));
@@ -124,6 +139,12 @@ pub fn run(config: Config, bind: SocketAddr) -> Fallible<()> {
                 .unify()
                 .or(warp::path("metrics").and(routes::metrics::routes(data.clone())))
                 .unify()
+                .or(warp::path("status").and(routes::status::routes(data.clone())))
+                .unify()
+                .or(warp::path("experiments").and(routes::results::routes(data.clone())))
+                .unify()
+                .or(warp::path("crates").and(routes::crates::routes(data.clone())))
+                .unify()
                 .or(routes::ui::routes(data))
                 .unify(),
         )
@@ -140,9 +161,18 @@ pub fn run(config: Config, bind: SocketAddr) -> Fallible<()> {
     let rt = tokio::runtime::Builder::new_multi_thread()
         .enable_all()
         .build()?;
+    let signal = shutdown::shutdown_on(shutdown.clone(), shutdown::sigterm());
     rt.block_on(async move {
-        warp::serve(routes).run(bind).await;
+        let (_, server) = warp::serve(routes).bind_with_graceful_shutdown(bind, signal);
+        server.await;
     });
 
+    info!("waiting for the report uploads and result writes in flight...");
+    if shutdown.drain(shutdown_timeout) {
+        info!("all the work in flight completed");
+    } else {
+        warn!("exiting with the work in flight aborted");
+    }
+
     Ok(())
 }
@@ -0,0 +1,152 @@
+use crate::experiments::{CompletionWebhook, Experiment};
+use crate::prelude::*;
+use crate::report::TestResults;
+use crate::server::tokens::Tokens;
+use crate::utils;
+use hmac::{Hmac, Mac};
+use reqwest::header::CONTENT_TYPE;
+use reqwest::Method;
+use std::collections::BTreeMap;
+use std::thread;
+use std::time::Duration;
+
+/// Header containing the HMAC-SHA1 signature of the payload, in the same format as GitHub's
+/// `X-Hub-Signature`.
+pub const SIGNATURE_HEADER: &str = "X-Crater-Signature";
+
+const MAX_ATTEMPTS: u32 = 4;
+const RETRY_DELAY: Duration = Duration::from_secs(2);
+
+/// Body of the request sent to the completion webhook.
+#[derive(Debug, Serialize)]
+pub struct CompletionPayload<'a> {
+    pub experiment: &'a str,
+    pub status: &'static str,
+    /// Number of crates in each category of the report.
+    pub counts: BTreeMap<&'static str, u32>,
+    pub total: u32,
+    pub report_url: &'a str,
+}
+
+impl<'a> CompletionPayload<'a> {
+    pub fn new(ex: &'a Experiment, res: &TestResults, report_url: &'a str) -> Self {
+        CompletionPayload {
+            experiment: &ex.name,
+            status: ex.status.to_str(),
+            counts: res
+                .info
+                .iter()
+                .map(|(comparison, count)| (comparison.to_str(), *count))
+                .collect(),
+            total: res.info.values().sum(),
+            report_url,
+        }
+    }
+}
+
+pub fn signature(secret: &str, payload: &[u8]) -> String {
+    let mut mac = Hmac::<sha1::Sha1>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(payload);
+    format!("sha1={}", utils::hex::to_hex(&mac.finalize().into_bytes()))
+}
+
+fn send(webhook: &CompletionWebhook, body: &[u8]) -> Fallible<()> {
+    let mut req = utils::http::prepare_sync(Method::POST, &webhook.url)
+        .header(CONTENT_TYPE, "application/json")
+        .body(body.to_vec());
+    if let Some(secret) = &webhook.secret {
+        req = req.header(SIGNATURE_HEADER, signature(secret, body));
+    }
+    req.send()?.error_for_status()?;
+    Ok(())
+}
+
+/// Notify the webhook of the experiment, or the global one, that the experiment completed.
+///
+/// Failed requests are retried a few times, after which the error is only logged: the
+/// notification must never prevent an experiment from being completed.
+pub fn notify_completion(tokens: &Tokens, ex: &Experiment, res: &TestResults, report_url: &str) {
+    let Some(webhook) = ex.webhook.as_ref().or(tokens.completion_webhook.as_ref()) else {
+        return;
+    };
+
+    let body = match serde_json::to_vec(&CompletionPayload::new(ex, res, report_url)) {
+        Ok(body) => body,
+        Err(err) => {
+            utils::report_failure(&failure::Error::from(err));
+            return;
+        }
+    };
+
+    for attempt in 1..=MAX_ATTEMPTS {
+        match send(webhook, &body) {
+            Ok(()) => return,
+            Err(err) if attempt < MAX_ATTEMPTS => {
+                warn!(
+                    "failed to notify the completion of {} (attempt {}/{}): {}",
+                    ex.name, attempt, MAX_ATTEMPTS, err
+                );
+                thread::sleep(RETRY_DELAY * 2u32.pow(attempt - 1));
+            }
+            Err(err) => {
+                error!("giving up notifying the completion of {}", ex.name);
+                utils::report_failure(&err);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{signature, CompletionPayload};
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::db::Database;
+    use crate::experiments::{Experiment, Status};
+    use crate::report::{Comparison, TestResults};
+    use indexmap::IndexMap;
+
+    #[test]
+    fn test_completion_payload() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+
+        CreateExperiment::dummy("foo").apply(&ctx).unwrap();
+        let mut ex = Experiment::get(&db, "foo").unwrap().unwrap();
+        ex.set_status(&db, Status::Running).unwrap();
+        ex.set_status(&db, Status::GeneratingReport).unwrap();
+        ex.set_status(&db, Status::Completed).unwrap();
+
+        let mut info = IndexMap::new();
+        info.insert(Comparison::Regressed, 2);
+        info.insert(Comparison::SameTestPass, 5);
+        let res = TestResults {
+            categories: IndexMap::new(),
+            info,
+        };
+
+        let payload = CompletionPayload::new(&ex, &res, "https://example.com/foo/index.html");
+        let json: serde_json::Value =
+            serde_json::from_slice(&serde_json::to_vec(&payload).unwrap()).unwrap();
+        assert_eq!(
+            json,
+            serde_json::json!({
+                "experiment": "foo",
+                "status": "completed",
+                "counts": {"regressed": 2, "test-pass": 5},
+                "total": 7,
+                "report_url": "https://example.com/foo/index.html",
+            })
+        );
+    }
+
+    #[test]
+    fn test_signature() {
+        assert_eq!(
+            signature("hunter2", br#"{"experiment":"pr-1"}"#),
+            "sha1=1148f122c80cee0686f397e4108091f78abede71"
+        );
+    }
+}
@@ -1,47 +1,52 @@
 use crate::experiments::{Experiment, Status};
 use crate::prelude::*;
-use crate::report::{self, Comparison, TestResults};
+use crate::report::{self, Comparison, ReportFilter, ReportRuntime, TestResults};
 use crate::results::DatabaseDB;
 use crate::server::messages::{Label, Message};
+use crate::server::notifications;
 use crate::server::{Data, GithubData};
 use crate::utils;
 use std::sync::{Arc, Mutex};
 use std::thread::{self, Thread};
 use std::time::Duration;
 
-use super::tokens::BucketRegion;
-
 // Automatically wake up the reports generator thread every 10 minutes to check for new jobs
 const AUTOMATIC_THREAD_WAKEUP: u64 = 600;
 
-fn generate_report(data: &Data, ex: &Experiment, results: &DatabaseDB) -> Fallible<TestResults> {
-    let mut config = aws_config::from_env();
-    match &data.tokens.reports_bucket.region {
-        BucketRegion::S3 { region } => {
-            config = config.region(aws_sdk_s3::config::Region::new(region.to_owned()));
-        }
-        BucketRegion::Custom { url } => {
-            config = config.region(aws_sdk_s3::config::Region::from_static("us-east-1"));
-            config = config.endpoint_url(url.clone());
-        }
-    }
-    config = config.credentials_provider(data.tokens.reports_bucket.to_aws_credentials());
-    let config = tokio::runtime::Builder::new_current_thread()
-        .enable_all()
-        .build()?
-        .block_on(config.load());
-    let client = aws_sdk_s3::Client::new(&config);
-    let writer = report::S3Writer::create(
-        client,
-        data.tokens.reports_bucket.bucket.clone(),
-        ex.name.clone(),
+fn generate_report(
+    data: &Data,
+    runtime: &ReportRuntime,
+    ex: &Experiment,
+    results: &DatabaseDB,
+) -> Fallible<TestResults> {
+    let writer = data
+        .tokens
+        .reports_bucket
+        .writer(runtime, ex.name.clone())?
+        .with_abort_signal(data.shutdown.abort_signal())
+        .with_experiment(&ex.name);
+    let writer = report::BlockingWriter::new(writer, runtime.clone());
+
+    // Only the crates tested before the cap was reached or the experiment was cancelled are part
+    // of the report
+    let crates = if ex.capped_at.is_some() || ex.cancelled_at.is_some() {
+        ex.get_completed_crates(&data.db)?
+    } else {
+        ex.get_crates(&data.db)?
+    };
+    let res = report::gen(
+        results,
+        ex,
+        &crates,
+        &writer,
+        &data.config,
+        false,
+        &ReportFilter::All,
     )?;
 
-    let crates = ex.get_crates(&data.db)?;
-    let res = report::gen(results, ex, &crates, &writer, &data.config, false)?;
-
     //remove metrics about completed experiments
     data.metrics.on_complete_experiment(&ex.name)?;
+    data.status.untrack(&ex.name);
 
     Ok(res)
 }
@@ -49,6 +54,7 @@ fn generate_report(data: &Data, ex: &Experiment, results: &DatabaseDB) -> Fallib
 fn reports_thread(data: &Data, github_data: Option<&GithubData>) -> Fallible<()> {
     let timeout = Duration::from_secs(AUTOMATIC_THREAD_WAKEUP);
     let results = DatabaseDB::new(&data.db);
+    let runtime = ReportRuntime::new()?;
 
     loop {
         let mut ex = match Experiment::ready_for_report(&data.db)? {
@@ -62,14 +68,39 @@ fn reports_thread(data: &Data, github_data: Option<&GithubData>) -> Fallible<()>
         };
         let name = ex.name.clone();
 
+        let Some(_work) = data.shutdown.start_work() else {
+            // No report is started once the server is shutting down
+            std::thread::park_timeout(timeout);
+            continue;
+        };
+
         info!("generating report for experiment {}...", name);
-        ex.set_status(&data.db, Status::GeneratingReport)?;
+        if !ex.set_status_from(&data.db, ex.status, Status::GeneratingReport)? {
+            warn!(
+                "experiment {} changed to {} before its report started",
+                name, ex.status
+            );
+            continue;
+        }
 
-        match generate_report(data, &ex, &results) {
+        match generate_report(data, &runtime, &ex, &results) {
+            Err(err) if data.shutdown.is_aborted() => {
+                // The report is generated again once the server is back
+                warn!("report generation of {} aborted by the shutdown", name);
+                utils::report_failure(&err);
+                continue;
+            }
             Err(err) => {
-                ex.set_status(&data.db, Status::ReportFailed)?;
                 error!("failed to generate the report of {}", name);
                 utils::report_failure(&err);
+                if !ex.set_status_from(&data.db, Status::GeneratingReport, Status::ReportFailed)? {
+                    // Someone retried the report in the meantime, nothing to report on GitHub
+                    warn!(
+                        "experiment {} changed to {} during its report",
+                        name, ex.status
+                    );
+                    continue;
+                }
 
                 if let Some(github_data) = github_data {
                     if let Some(ref github_issue) = ex.github_issue {
@@ -100,9 +131,17 @@ fn reports_thread(data: &Data, github_data: Option<&GithubData>) -> Fallible<()>
                     .replace("{bucket}", &data.tokens.reports_bucket.bucket);
                 let report_url = format!("{base_url}/{name}/index.html");
 
-                ex.set_status(&data.db, Status::Completed)?;
+                if !ex.set_status_from(&data.db, Status::GeneratingReport, Status::Completed)? {
+                    // The report is generated again, and that run sends the notifications
+                    warn!(
+                        "experiment {} changed to {} during its report",
+                        name, ex.status
+                    );
+                    continue;
+                }
                 ex.set_report_url(&data.db, &report_url)?;
                 info!("report for the experiment {} generated successfully!", name);
+                notifications::notify_completion(&data.tokens, &ex, &res, &report_url);
 
                 let (regressed, fixed) = (
                     res.info.get(&Comparison::Regressed).unwrap_or(&0),
@@ -111,7 +150,7 @@ fn reports_thread(data: &Data, github_data: Option<&GithubData>) -> Fallible<()>
 
                 if let Some(github_data) = github_data {
                     if let Some(ref github_issue) = ex.github_issue {
-                        Message::new()
+                        let mut message = Message::new()
                             .line("tada", format!("Experiment **`{name}`** is completed!"))
                             .line(
                                 "bar_chart",
@@ -121,7 +160,23 @@ fn reports_thread(data: &Data, github_data: Option<&GithubData>) -> Fallible<()>
                                     fixed,
                                     res.info.values().sum::<u32>(),
                                 ),
-                            )
+                            );
+                        if let (Some(capped_at), Some(max)) = (ex.capped_at, ex.max_regressions) {
+                            message = message.line(
+                                "octagonal_sign",
+                                format!(
+                                    "The experiment was capped after {capped_at} regressions \
+                                     (limit: {max}), so not every crate was tested."
+                                ),
+                            );
+                        }
+                        if ex.cancelled_at.is_some() {
+                            message = message.line(
+                                "octagonal_sign",
+                                "The experiment was cancelled, so not every crate was tested.",
+                            );
+                        }
+                        message
                             .line(
                                 "newspaper",
                                 format!("[Open the full report]({report_url})."),
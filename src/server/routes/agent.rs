@@ -1,21 +1,27 @@
 use crate::agent::Capabilities;
+use crate::config::Config;
 use crate::experiments::{Assignee, Experiment};
 use crate::prelude::*;
-use crate::results::{DatabaseDB, EncodingType, ProgressData};
+use crate::results::{DatabaseDB, EncodingType, ProgressData, ReadResults};
 use crate::server::agents::WorkerInfo;
 use crate::server::api_types::{AgentConfig, ApiResponse};
 use crate::server::auth::{auth_filter, AuthDetails};
 use crate::server::messages::Message;
+use crate::server::shutdown::WorkGuard;
+use crate::server::status::ServerStatus;
 use crate::server::{Data, GithubData, HttpError};
 use crossbeam_channel::Sender;
 use failure::Compat;
 use http::Response;
 use hyper::Body;
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::sync::{Arc, Condvar, Mutex};
 use std::time::Instant;
 use warp::{Filter, Rejection};
 
+// Most results queued while a batch is being stored, to store in a single transaction.
+const RESULTS_BATCH_SIZE: usize = 32;
+
 #[derive(Deserialize)]
 #[serde(rename_all = "kebab-case")]
 pub struct ExperimentData<T> {
@@ -139,6 +145,7 @@ fn endpoint_next_experiment(
             }
         }
 
+        data.status.track(&data.db, &data.config, &ex)?;
         Some(ex)
     } else {
         None
@@ -150,16 +157,14 @@ fn endpoint_next_experiment(
 fn endpoint_next_crate(
     experiment: String,
     data: Arc<Data>,
-    _auth: AuthDetails,
+    auth: AuthDetails,
 ) -> Fallible<Response<Body>> {
+    let caps = Capabilities::for_agent(&data.db, &auth.name)?;
     let result: Option<crate::crates::Crate> =
         if let Some(ex) = Experiment::get(&data.db, &experiment)? {
-            let mut crates = ex.get_uncompleted_crates(&data.db, Some(1))?;
-            if crates.is_empty() {
-                None
-            } else {
-                Some(crates.remove(0))
-            }
+            ex.get_uncompleted_crates_for(&data.db, &data.config, &auth.name, &caps, Some(1))?
+                .into_iter()
+                .next()
         } else {
             None
         };
@@ -167,10 +172,120 @@ fn endpoint_next_crate(
     Ok(ApiResponse::Success { result }.into_response()?)
 }
 
+/// Update the status counters with a newly stored result.
+fn record_status(
+    status: &ServerStatus,
+    db: &crate::db::Database,
+    config: &Config,
+    ex: &Experiment,
+    data: &ProgressData,
+) -> Fallible<()> {
+    if status.track(db, config, ex)? {
+        // The result was already counted when loading the experiment's counters
+        return Ok(());
+    }
+
+    let result = &data.result;
+    // The results on the extra toolchains have no counterpart to be categorized with
+    let other = match ex.compared_toolchain(&result.toolchain) {
+        Some(other_toolchain) => DatabaseDB::new(db)
+            .load_test_result(ex, other_toolchain, &result.krate)?
+            .map(|other| (other_toolchain, other)),
+        None => None,
+    };
+    status.record_result(
+        config,
+        ex,
+        &result.krate,
+        (&result.toolchain, &result.result),
+        other.as_ref().map(|(toolchain, other)| (*toolchain, other)),
+    );
+    Ok(())
+}
+
+/// Store the results of an experiment sent by the agents, each one along with the name of the
+/// agent that sent it.
+fn record_progress(
+    db: &crate::db::Database,
+    metrics: &crate::server::metrics::Metrics,
+    status: &ServerStatus,
+    config: &Config,
+    mut ex: Experiment,
+    batch: &[(&String, &ProgressData)],
+) {
+    // The crates still running when the experiment was cancelled are dropped
+    if ex.cancelled_at.is_some() {
+        for (_, data) in batch {
+            log::info!(
+                "dropping the result of {} for the cancelled experiment {}",
+                data.result.krate,
+                ex.name
+            );
+        }
+        return;
+    }
+
+    let results = DatabaseDB::new(db)
+        .with_compression(config.compression)
+        .with_max_log_size(config.sandbox.stored_log_max_size.to_bytes());
+    let data = batch.iter().map(|(_, data)| *data).collect::<Vec<_>>();
+    let stored = match results.store_batch(&ex, &data, EncodingType::Plain) {
+        Ok(stored) => stored.into_iter().map(Ok).collect::<Vec<_>>(),
+        Err(e) => {
+            // Don't let a single bad result prevent the others from being stored
+            log::warn!(
+                "Failed to store a batch of {} results, storing them one by one: {:?}",
+                data.len(),
+                e
+            );
+            data.iter()
+                .map(|data| results.store(&ex, data, EncodingType::Plain))
+                .collect()
+        }
+    };
+
+    for ((agent, data), stored) in batch.iter().zip(stored) {
+        match &stored {
+            Err(e) => {
+                // Failing to record a result is basically fine -- this
+                // just means that we'll have to re-try this job.
+                log::error!("Failed to store result into database: {:?}", e);
+                crate::utils::report_failure(e);
+            }
+            Ok(true) => {
+                if let Err(e) = results.confirm_regression(&ex, config, &data.result.krate, agent) {
+                    log::error!("Failed to confirm the regression: {:?}", e);
+                    crate::utils::report_failure(&e);
+                }
+            }
+            // Results submitted again already moved the confirmation forward the first time
+            Ok(false) => {}
+        }
+
+        // Results submitted again by the agents are only counted once, and the ones which
+        // couldn't be stored will be submitted again
+        if let Ok(true) = stored {
+            metrics.record_completed_jobs(&ex.name, 1);
+            if let Err(e) = record_status(status, db, config, &ex, data) {
+                log::error!("Failed to update the status counters: {:?}", e);
+                crate::utils::report_failure(&e);
+            }
+        }
+    }
+
+    // The regressions are counted along with the status counters as the results are stored
+    if let Some(regressions) = status.regressions(&ex.name) {
+        if let Err(e) = ex.check_cap(db, regressions) {
+            log::error!("Failed to check the regressions cap: {:?}", e);
+            crate::utils::report_failure(&e);
+        }
+    }
+}
+
 #[derive(Clone)]
 pub struct RecordProgressThread {
-    // String is the worker name
-    queue: Sender<ExperimentData<ProgressData>>,
+    // String is the agent name, the guard keeps the shutdown waiting until the result is stored
+    queue: Sender<(String, ExperimentData<ProgressData>, WorkGuard)>,
     in_flight_requests: Arc<(Mutex<usize>, Condvar)>,
 }
 
@@ -178,6 +293,8 @@ impl RecordProgressThread {
     pub fn new(
         db: crate::db::Database,
         metrics: crate::server::metrics::Metrics,
+        status: ServerStatus,
+        config: Config,
     ) -> RecordProgressThread {
         // 64 message queue, after which we start load shedding automatically.
         let (tx, rx) = crossbeam_channel::bounded(64);
@@ -192,40 +309,52 @@ impl RecordProgressThread {
             // Panics should already be logged and otherwise there's not much we
             // can/should do.
             let _ = std::panic::catch_unwind(std::panic::AssertUnwindSafe(|| {
-                let result = rx.recv().unwrap();
+                // The results queued meanwhile are stored along with this one. Each of them keeps
+                // the shutdown waiting until it's stored, so no batch is left behind.
+                let first = rx.recv().unwrap();
+                let batch = std::iter::once(first)
+                    .chain(rx.try_iter().take(RESULTS_BATCH_SIZE - 1))
+                    .collect::<Vec<_>>();
                 this.block_until_idle();
 
                 let start = std::time::Instant::now();
 
-                if let Some(ex) = Experiment::get(&db, &result.experiment_name).unwrap() {
-                    let db = DatabaseDB::new(&db);
-                    if let Err(e) = db.store(&ex, &result.data, EncodingType::Plain) {
-                        // Failing to record a result is basically fine -- this
-                        // just means that we'll have to re-try this job.
-                        log::error!("Failed to store result into database: {:?}", e);
-                        crate::utils::report_failure(&e);
-                    }
-
-                    metrics.record_completed_jobs(&ex.name, 1);
-
-                    if let Err(e) = db.clear_stale_records() {
-                        // Not a hard failure. We can continue even if we failed
-                        // to clear records from already completed runs...
-                        log::error!("Failed to clear stale records: {:?}", e);
-                        crate::utils::report_failure(&e);
+                let mut experiments: BTreeMap<_, Vec<_>> = BTreeMap::new();
+                for (agent, result, _work) in &batch {
+                    experiments
+                        .entry(&result.experiment_name)
+                        .or_default()
+                        .push((agent, &result.data));
+                }
+                for (name, batch) in experiments {
+                    if let Some(ex) = Experiment::get(&db, name).unwrap() {
+                        record_progress(&db, &metrics, &status, &config, ex, &batch);
                     }
+                }
 
-                    metrics
-                        .crater_endpoint_time
-                        .with_label_values(&["record_progress_worker"])
-                        .observe(start.elapsed().as_secs_f64());
+                let results = DatabaseDB::new(&db);
+                if let Err(e) = results.clear_stale_records() {
+                    // Not a hard failure. We can continue even if we failed
+                    // to clear records from already completed runs...
+                    log::error!("Failed to clear stale records: {:?}", e);
+                    crate::utils::report_failure(&e);
                 }
+
+                metrics
+                    .crater_endpoint_time
+                    .with_label_values(&["record_progress_worker"])
+                    .observe(start.elapsed().as_secs_f64());
             }));
         });
 
         ret
     }
 
+    /// Number of results received from the agents and waiting to be stored.
+    pub fn backlog(&self) -> usize {
+        self.queue.len()
+    }
+
     pub fn block_until_idle(&self) {
         // Wait until there are zero in-flight requests.
         //
@@ -299,7 +428,7 @@ impl Drop for RequestGuard {
 fn endpoint_record_progress(
     result: ExperimentData<ProgressData>,
     data: Arc<Data>,
-    _auth: AuthDetails,
+    auth: AuthDetails,
 ) -> Fallible<Response<Body>> {
     let start = Instant::now();
 
@@ -307,7 +436,15 @@ fn endpoint_record_progress(
         .result_log_size
         .observe(result.data.result.log.len() as f64);
 
-    let ret = match data.record_progress_worker.queue.try_send(result) {
+    let Some(work) = data.shutdown.start_work() else {
+        // The agent sends the result again once the server is back
+        return Ok(ApiResponse::<()>::SlowDown.into_response()?);
+    };
+    let ret = match data
+        .record_progress_worker
+        .queue
+        .try_send((auth.name, result, work))
+    {
         Ok(()) => Ok(ApiResponse::Success { result: true }.into_response()?),
         Err(crossbeam_channel::TrySendError::Full(_)) => {
             data.metrics.crater_bounced_record_progress.inc_by(1);
@@ -385,3 +522,133 @@ async fn handle_errors(err: Rejection) -> Result<Response<Body>, Rejection> {
         None => Err(err),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::record_progress;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::agent::Capabilities;
+    use crate::config::Config;
+    use crate::crates::Crate;
+    use crate::db::Database;
+    use crate::experiments::{Confirmation, Experiment};
+    use crate::results::{
+        DatabaseDB, FailureReason, ProgressData, ReadResults, TaskResult, TestResult,
+    };
+    use crate::server::metrics::TEST_METRICS;
+    use crate::server::status::ServerStatus;
+    use crate::toolchain::{Toolchain, MAIN_TOOLCHAIN, TEST_TOOLCHAIN};
+    use std::collections::BTreeMap;
+
+    #[test]
+    fn test_resent_results_confirmation() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment {
+            confirm_regressions: true,
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let caps = Capabilities::new(&["linux"]);
+        let status = ServerStatus::default();
+
+        let krate = Crate::Local("beta-regression".into());
+        let progress = |toolchain: &Toolchain, result: TestResult| ProgressData {
+            result: TaskResult {
+                krate: krate.clone(),
+                toolchain: toolchain.clone(),
+                result,
+                log: String::new(),
+                duration_ms: None,
+            },
+            version: None,
+        };
+        let start = progress(&MAIN_TOOLCHAIN, TestResult::TestPass);
+        let end = progress(
+            &TEST_TOOLCHAIN,
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+        let agent = "agent-1".to_string();
+        let send = |batch: &[&ProgressData]| {
+            let batch = batch.iter().map(|data| (&agent, *data)).collect::<Vec<_>>();
+            let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+            record_progress(&db, &TEST_METRICS, &status, &config, ex, &batch);
+        };
+        let confirmation = || DatabaseDB::new(&db).load_confirmation(&ex, &krate).unwrap();
+
+        // Every result is sent twice by the agent, the regression still needs another agent
+        send(&[&start, &start]);
+        send(&[&end]);
+        assert_eq!(confirmation(), Some(Confirmation::Pending));
+        send(&[&start, &end, &end]);
+        assert_eq!(confirmation(), Some(Confirmation::Pending));
+
+        assert!(!ex
+            .get_uncompleted_crates_for(&db, &config, "agent-1", &caps, None)
+            .unwrap()
+            .contains(&krate));
+        assert!(ex
+            .get_uncompleted_crates_for(&db, &config, "agent-2", &caps, None)
+            .unwrap()
+            .contains(&krate));
+    }
+
+    #[test]
+    fn test_extra_toolchain_counters() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        let nightly: Toolchain = "nightly".parse().unwrap();
+        CreateExperiment {
+            extra_toolchains: vec![nightly.clone()],
+            ..CreateExperiment::dummy("dummy")
+        }
+        .apply(&ctx)
+        .unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let crates = ex.get_crates(&db).unwrap();
+        let status = ServerStatus::default();
+
+        let agent = "agent-1".to_string();
+        let send = |krate: &Crate, toolchain: &Toolchain, result: TestResult| {
+            let data = ProgressData {
+                result: TaskResult {
+                    krate: krate.clone(),
+                    toolchain: toolchain.clone(),
+                    result,
+                    log: String::new(),
+                    duration_ms: None,
+                },
+                version: None,
+            };
+            let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+            record_progress(&db, &TEST_METRICS, &status, &config, ex, &[(&agent, &data)]);
+        };
+        let build_fail = || TestResult::BuildFail(FailureReason::Unknown);
+
+        // The failure on the extra toolchain, stored in between, isn't compared with the start
+        send(&crates[0], &MAIN_TOOLCHAIN, TestResult::TestPass);
+        send(&crates[0], &nightly, build_fail());
+        send(&crates[0], &TEST_TOOLCHAIN, TestResult::TestPass);
+        send(&crates[1], &MAIN_TOOLCHAIN, TestResult::TestPass);
+        send(&crates[1], &TEST_TOOLCHAIN, build_fail());
+
+        let categories =
+            BTreeMap::from([("regressed".to_string(), 1), ("unchanged".to_string(), 1)]);
+        let experiments = status.experiments();
+        assert_eq!(experiments[0].completed, 1);
+        assert_eq!(experiments[0].categories, categories);
+        assert_eq!(status.regressions("dummy"), Some(1));
+
+        // The counters loaded from the database agree with the ones updated along the way
+        let loaded = ServerStatus::default();
+        assert!(loaded.track(&db, &config, &ex).unwrap());
+        assert_eq!(loaded.experiments()[0].categories, categories);
+        assert_eq!(loaded.regressions("dummy"), Some(1));
+    }
+}
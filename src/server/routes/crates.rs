@@ -0,0 +1,285 @@
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::db::Database;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{crate_category, crate_name_and_version, ToolchainResultV1};
+use crate::results::DatabaseDB;
+use crate::server::Data;
+use crate::toolchain::Toolchain;
+use chrono::{DateTime, Utc};
+use http::header::{HeaderValue, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use hyper::Body;
+use std::collections::{BTreeMap, HashMap};
+use std::sync::Arc;
+use warp::{Filter, Rejection};
+
+/// Number of experiments the history goes through when the query doesn't set a limit.
+const DEFAULT_LIMIT: u32 = 10;
+const MAX_LIMIT: u32 = 100;
+/// Category of the crates absent from an experiment, or not tested with both toolchains.
+const NOT_RUN: &str = "not-run";
+
+#[derive(Deserialize)]
+struct HistoryQuery {
+    /// Only include this version of the crate.
+    version: Option<String>,
+    /// Number of recent experiments to go through.
+    limit: Option<u32>,
+}
+
+/// Results of the crate in one of the recent experiments.
+#[derive(Debug, Serialize, PartialEq)]
+struct HistoryRecord {
+    experiment: String,
+    created_at: DateTime<Utc>,
+    /// Missing when the crate wasn't tested by the experiment.
+    version: Option<String>,
+    /// Summary category of the crate, or `not-run`.
+    category: String,
+    results: Vec<ToolchainResultV1>,
+}
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    warp::get()
+        .and(warp::path::param())
+        .and(warp::path("history"))
+        .and(warp::path::end())
+        .and(warp::query::<HistoryQuery>())
+        .and(data_filter)
+        .map(|name: String, query: HistoryQuery, data: Arc<Data>| {
+            match endpoint_history(name, query, data) {
+                Ok(resp) => resp,
+                Err(err) => {
+                    error!("error while loading the history of a crate");
+                    crate::utils::report_failure(&err);
+
+                    let mut resp = Response::new(format!("Error: {err}\n").into());
+                    *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    resp
+                }
+            }
+        })
+}
+
+fn endpoint_history(
+    name: String,
+    query: HistoryQuery,
+    data: Arc<Data>,
+) -> Fallible<Response<Body>> {
+    let limit = query.limit.unwrap_or(DEFAULT_LIMIT).min(MAX_LIMIT);
+    let records = crate_history(
+        &data.db,
+        &data.config,
+        &name,
+        query.version.as_deref(),
+        limit,
+    )?;
+
+    let mut resp = Response::new(serde_json::to_vec(&records)?.into());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(resp)
+}
+
+/// Results of the registry crate `name` in the `limit` most recent experiments, the newest
+/// first. Every experiment is listed, with a `not-run` record when it didn't test the crate.
+fn crate_history(
+    db: &Database,
+    config: &Config,
+    name: &str,
+    version: Option<&str>,
+    limit: u32,
+) -> Fallible<Vec<HistoryRecord>> {
+    let experiments = Experiment::recent(db, limit)?;
+
+    // Results of each version of the crate, grouped by experiment
+    let mut results: HashMap<String, BTreeMap<Crate, Vec<_>>> = HashMap::new();
+    for stored in DatabaseDB::new(db).load_registry_crate_results(name)? {
+        if let (Some(version), Crate::Registry(krate)) = (version, &stored.krate) {
+            if krate.version != version {
+                continue;
+            }
+        }
+        results
+            .entry(stored.experiment)
+            .or_default()
+            .entry(stored.krate)
+            .or_default()
+            .push((stored.toolchain, stored.result));
+    }
+
+    let mut records = Vec::new();
+    for ex in experiments {
+        let Some(tested) = results.get(&ex.name) else {
+            records.push(HistoryRecord {
+                experiment: ex.name.clone(),
+                created_at: ex.created_at,
+                version: version.map(String::from),
+                category: NOT_RUN.into(),
+                results: Vec::new(),
+            });
+            continue;
+        };
+
+        for (krate, crate_results) in tested {
+            let result_of = |toolchain: &Toolchain| {
+                crate_results
+                    .iter()
+                    .find(|(tc, _)| tc == toolchain)
+                    .map(|(_, result)| result)
+            };
+            let [start, end] = &ex.toolchains;
+            let category = match (result_of(start), result_of(end)) {
+                (Some(start), Some(end)) => crate_category(config, krate, start, end)
+                    .to_str()
+                    .to_string(),
+                _ => NOT_RUN.into(),
+            };
+
+            records.push(HistoryRecord {
+                experiment: ex.name.clone(),
+                created_at: ex.created_at,
+                version: crate_name_and_version(krate).1,
+                category,
+                results: ex
+                    .toolchains
+                    .iter()
+                    .map(|toolchain| ToolchainResultV1 {
+                        toolchain: toolchain.to_string(),
+                        result: result_of(toolchain).cloned(),
+                        repro: None,
+                    })
+                    .collect(),
+            });
+        }
+    }
+
+    Ok(records)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::crate_history;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::{Crate, RegistryCrate};
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::results::{
+        DatabaseDB, EncodingType, FailureReason, ProgressData, TaskResult, TestResult,
+    };
+    use crate::toolchain::Toolchain;
+
+    fn store(db: &Database, ex: &str, krate: &Crate, toolchain: &Toolchain, res: TestResult) {
+        let ex = Experiment::get(db, ex).unwrap().unwrap();
+        DatabaseDB::new(db)
+            .store(
+                &ex,
+                &ProgressData {
+                    result: TaskResult {
+                        krate: krate.clone(),
+                        toolchain: toolchain.clone(),
+                        result: res,
+                        log: String::new(),
+                        duration_ms: None,
+                    },
+                    version: None,
+                },
+                EncodingType::Plain,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_crate_history() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        for name in ["first", "second", "third"] {
+            CreateExperiment::dummy(name).apply(&ctx).unwrap();
+        }
+        let [start, end] = Experiment::get(&db, "first").unwrap().unwrap().toolchains;
+
+        let registry = |name: &str, version: &str| {
+            Crate::Registry(RegistryCrate {
+                name: name.into(),
+                version: version.into(),
+            })
+        };
+        let foo = registry("foo", "1.0.0");
+        store(&db, "first", &foo, &start, TestResult::TestPass);
+        store(&db, "first", &foo, &end, TestResult::TestPass);
+        // The second experiment doesn't test the crate, only one with a similar name
+        store(
+            &db,
+            "second",
+            &registry("foo-bar", "1.0.0"),
+            &start,
+            TestResult::TestPass,
+        );
+        store(
+            &db,
+            "second",
+            &registry("foo_bar", "1.0.0"),
+            &end,
+            TestResult::TestPass,
+        );
+        let foo_next = registry("foo", "1.1.0");
+        store(&db, "third", &foo_next, &start, TestResult::TestPass);
+        store(
+            &db,
+            "third",
+            &foo_next,
+            &end,
+            TestResult::BuildFail(FailureReason::Unknown),
+        );
+
+        let history = crate_history(&db, &config, "foo", None, 10).unwrap();
+        let summary = history
+            .iter()
+            .map(|record| {
+                (
+                    record.experiment.as_str(),
+                    record.version.as_deref(),
+                    record.category.as_str(),
+                )
+            })
+            .collect::<Vec<_>>();
+        assert_eq!(
+            summary,
+            vec![
+                ("third", Some("1.1.0"), "regressed"),
+                ("second", None, "not-run"),
+                ("first", Some("1.0.0"), "unchanged"),
+            ]
+        );
+        assert!(history[1].results.is_empty());
+        assert_eq!(history[0].results.len(), 2);
+        assert_eq!(history[0].results[0].toolchain, start.to_string());
+        assert_eq!(history[0].results[0].result, Some(TestResult::TestPass));
+        assert_eq!(
+            history[0].results[1].result,
+            Some(TestResult::BuildFail(FailureReason::Unknown))
+        );
+
+        // Other versions aren't run as far as the query is concerned
+        let history = crate_history(&db, &config, "foo", Some("1.0.0"), 10).unwrap();
+        let categories = history
+            .iter()
+            .map(|record| record.category.as_str())
+            .collect::<Vec<_>>();
+        assert_eq!(categories, vec!["not-run", "not-run", "unchanged"]);
+
+        // Only the most recent experiments are included
+        let history = crate_history(&db, &config, "foo", None, 2).unwrap();
+        assert_eq!(history.len(), 2);
+        assert_eq!(history[0].experiment, "third");
+    }
+}
@@ -1,4 +1,7 @@
 pub mod agent;
+pub mod crates;
 pub mod metrics;
+pub mod results;
+pub mod status;
 pub mod ui;
 pub mod webhooks;
@@ -0,0 +1,208 @@
+use crate::experiments::{Experiment, Status};
+use crate::prelude::*;
+use crate::report::{crate_category, crate_name_and_version, SummaryCategory};
+use crate::results::{DatabaseDB, StoredResult};
+use crate::server::Data;
+use chrono::{DateTime, Utc};
+use http::header::{HeaderValue, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use hyper::Body;
+use std::collections::BTreeMap;
+use std::sync::Arc;
+use std::time::Duration;
+use warp::{Filter, Rejection};
+
+/// Maximum number of results loaded from the database at once.
+const BATCH_SIZE: u32 = 500;
+/// How long to wait before checking again for new results once the stream caught up.
+const POLL_INTERVAL: Duration = Duration::from_secs(5);
+
+#[derive(Deserialize)]
+struct StreamQuery {
+    #[serde(default)]
+    cursor: i64,
+}
+
+/// A single line of the stream.
+#[derive(Debug, Serialize, PartialEq)]
+struct ResultRecord {
+    /// Pass it back as `?cursor=` to resume the stream after this record.
+    cursor: i64,
+    #[serde(rename = "crate")]
+    krate: String,
+    version: Option<String>,
+    toolchain: String,
+    result: String,
+    /// Only known once the crate was tested with both toolchains.
+    category: Option<SummaryCategory>,
+    timestamp: Option<DateTime<Utc>>,
+}
+
+impl ResultRecord {
+    fn new(data: &Data, ex: &Experiment, stored: StoredResult) -> Self {
+        let category = stored.previous.as_ref().map(|(toolchain, previous)| {
+            if *toolchain == ex.toolchains[0] {
+                crate_category(&data.config, &stored.krate, previous, &stored.result)
+            } else {
+                crate_category(&data.config, &stored.krate, &stored.result, previous)
+            }
+        });
+        let (krate, version) = crate_name_and_version(&stored.krate);
+
+        ResultRecord {
+            cursor: stored.cursor,
+            krate,
+            version,
+            toolchain: stored.toolchain.to_string(),
+            result: stored.result.to_string(),
+            category,
+            timestamp: stored.completed_at,
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct ListQuery {
+    /// Only list the experiments with this label, formatted as `key=value`.
+    label: String,
+}
+
+/// An experiment matching the label filter.
+#[derive(Debug, Serialize, PartialEq)]
+struct ExperimentRecord {
+    name: String,
+    status: String,
+    labels: BTreeMap<String, String>,
+    report_url: Option<String>,
+}
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    let list = warp::get()
+        .and(warp::path::end())
+        .and(warp::query::<ListQuery>())
+        .and(data_filter.clone())
+        .map(
+            |query: ListQuery, data: Arc<Data>| match endpoint_list(query, data) {
+                Ok(resp) => resp,
+                Err(err) => {
+                    error!("error while listing experiments");
+                    crate::utils::report_failure(&err);
+
+                    let mut resp = Response::new(format!("Error: {err}\n").into());
+                    *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    resp
+                }
+            },
+        );
+
+    let stream = warp::get()
+        .and(warp::path::param())
+        .and(warp::path("results.jsonl"))
+        .and(warp::path::end())
+        .and(warp::query::<StreamQuery>())
+        .and(data_filter)
+        .map(|name: String, query: StreamQuery, data: Arc<Data>| {
+            match endpoint_stream(name, query, data) {
+                Ok(resp) => resp,
+                Err(err) => {
+                    error!("error while streaming results");
+                    crate::utils::report_failure(&err);
+
+                    let mut resp = Response::new(format!("Error: {err}\n").into());
+                    *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                    resp
+                }
+            }
+        });
+
+    list.or(stream).unify()
+}
+
+fn endpoint_list(query: ListQuery, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let Some((key, value)) = query.label.split_once('=') else {
+        let mut resp = Response::new("Error: the label must be formatted as key=value\n".into());
+        *resp.status_mut() = StatusCode::BAD_REQUEST;
+        return Ok(resp);
+    };
+
+    let records = Experiment::with_label(&data.db, key, value)?
+        .into_iter()
+        .map(|ex| ExperimentRecord {
+            status: ex.status.to_string(),
+            name: ex.name,
+            labels: ex.labels,
+            report_url: ex.report_url,
+        })
+        .collect::<Vec<_>>();
+
+    let mut resp = Response::new(serde_json::to_vec(&records)?.into());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(resp)
+}
+
+/// Load the records stored after `cursor`, serialized as JSON lines.
+fn load_batch(data: &Data, ex: &Experiment, cursor: i64) -> Fallible<(i64, Vec<u8>)> {
+    let mut last = cursor;
+    let mut buffer = Vec::new();
+    for stored in DatabaseDB::new(&data.db).load_results_since(ex, cursor, BATCH_SIZE)? {
+        last = stored.cursor;
+        serde_json::to_writer(&mut buffer, &ResultRecord::new(data, ex, stored))?;
+        buffer.push(b'\n');
+    }
+    Ok((last, buffer))
+}
+
+/// Results are only read from the database when the client asks for more data, so a slow
+/// consumer never holds back the agents recording progress.
+fn endpoint_stream(name: String, query: StreamQuery, data: Arc<Data>) -> Fallible<Response<Body>> {
+    let ex = match Experiment::get(&data.db, &name)? {
+        Some(ex) => ex,
+        None => {
+            let mut resp = Response::new(format!("Error: experiment {name} not found\n").into());
+            *resp.status_mut() = StatusCode::NOT_FOUND;
+            return Ok(resp);
+        }
+    };
+
+    let stream =
+        futures_util::stream::unfold((data, ex, query.cursor), |(data, ex, cursor)| async move {
+            loop {
+                let (last, buffer) = match load_batch(&data, &ex, cursor) {
+                    Ok(batch) => batch,
+                    Err(err) => {
+                        crate::utils::report_failure(&err);
+                        return None;
+                    }
+                };
+                if !buffer.is_empty() {
+                    return Some((Ok::<_, std::io::Error>(buffer), (data, ex, last)));
+                }
+
+                // No new result can land once the experiment stopped running
+                let running = match Experiment::get(&data.db, &ex.name) {
+                    Ok(Some(ex)) => matches!(ex.status, Status::Queued | Status::Running),
+                    Ok(None) => false,
+                    Err(err) => {
+                        crate::utils::report_failure(&err);
+                        false
+                    }
+                };
+                if !running {
+                    return None;
+                }
+                tokio::time::sleep(POLL_INTERVAL).await;
+            }
+        });
+
+    let mut resp = Response::new(Body::wrap_stream(stream));
+    resp.headers_mut().insert(
+        CONTENT_TYPE,
+        HeaderValue::from_static("application/x-ndjson"),
+    );
+    Ok(resp)
+}
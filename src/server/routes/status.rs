@@ -0,0 +1,73 @@
+use crate::prelude::*;
+use crate::server::agents::is_reachable;
+use crate::server::status::ExperimentStatus;
+use crate::server::Data;
+use chrono::{DateTime, Utc};
+use http::header::{HeaderValue, CONTENT_TYPE};
+use http::{Response, StatusCode};
+use hyper::Body;
+use std::sync::Arc;
+use warp::{Filter, Rejection};
+
+#[derive(Serialize)]
+struct AgentRecord {
+    name: String,
+    last_seen: Option<DateTime<Utc>>,
+    connected: bool,
+}
+
+#[derive(Serialize)]
+struct StatusRecord {
+    experiments: Vec<ExperimentStatus>,
+    connected_agents: usize,
+    agents: Vec<AgentRecord>,
+    /// Results received from the agents and not stored yet.
+    upload_backlog: usize,
+}
+
+pub fn routes(
+    data: Arc<Data>,
+) -> impl Filter<Extract = (Response<Body>,), Error = Rejection> + Clone {
+    let data_filter = warp::any().map(move || data.clone());
+
+    warp::get()
+        .and(warp::path::end())
+        .and(data_filter)
+        .map(|data| match endpoint_status(data) {
+            Ok(resp) => resp,
+            Err(err) => {
+                error!("error while processing the status");
+                crate::utils::report_failure(&err);
+
+                let mut resp = Response::new(format!("Error: {err}\n").into());
+                *resp.status_mut() = StatusCode::INTERNAL_SERVER_ERROR;
+                resp
+            }
+        })
+}
+
+/// Served from the in-memory counters only, so it can be polled often.
+fn endpoint_status(data: Arc<Data>) -> Fallible<Response<Body>> {
+    let agents = data
+        .agents
+        .last_seen()
+        .into_iter()
+        .map(|(name, last_seen)| AgentRecord {
+            connected: last_seen.as_ref().is_some_and(is_reachable),
+            name,
+            last_seen,
+        })
+        .collect::<Vec<_>>();
+
+    let status = StatusRecord {
+        experiments: data.status.experiments(),
+        connected_agents: agents.iter().filter(|agent| agent.connected).count(),
+        agents,
+        upload_backlog: data.record_progress_worker.backlog(),
+    };
+
+    let mut resp = Response::new(serde_json::to_vec(&status)?.into());
+    resp.headers_mut()
+        .insert(CONTENT_TYPE, HeaderValue::from_static("application/json"));
+    Ok(resp)
+}
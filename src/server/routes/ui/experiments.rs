@@ -1,4 +1,4 @@
-use crate::experiments::{Experiment, Mode, Status};
+use crate::experiments::{Experiment, Status};
 use crate::prelude::*;
 use crate::server::routes::ui::{render_template, LayoutContext};
 use crate::server::{Data, HttpError};
@@ -15,6 +15,8 @@ struct ExperimentData {
     mode: &'static str,
     assigned_to: Option<String>,
     requirement: Option<String>,
+    sample_seed: Option<u64>,
+    skip_dev_deps: bool,
     progress: u8,
     priority: i32,
 }
@@ -27,6 +29,8 @@ impl ExperimentData {
             Status::NeedsReport => ("orange", "Needs report", false),
             Status::GeneratingReport => ("orange", "Generating report", false),
             Status::ReportFailed => ("red", "Report failed", false),
+            Status::Cancelled => ("red", "Cancelled", false),
+            Status::Failed => ("red", "Failed", false),
             Status::Completed => ("green", "Completed", false),
         };
 
@@ -34,17 +38,13 @@ impl ExperimentData {
             name: experiment.name.clone(),
             status_class,
             status_pretty,
-            mode: match experiment.mode {
-                Mode::BuildAndTest => "cargo test",
-                Mode::BuildOnly => "cargo build",
-                Mode::CheckOnly => "cargo check",
-                Mode::Clippy => "cargo clippy",
-                Mode::Rustdoc => "cargo doc",
-                Mode::UnstableFeatures => "unstable features",
-            },
+            mode: experiment.mode.command(),
             assigned_to: experiment.assigned_to.as_ref().map(|a| a.to_string()),
             priority: experiment.priority,
             requirement: experiment.requirement.clone(),
+            sample_seed: experiment.sample_seed,
+            // Ignored when the tests are run, as they need the dev-dependencies
+            skip_dev_deps: experiment.skip_dev_deps && !experiment.mode.runs_tests(),
             progress: if show_progress {
                 experiment.progress(&data.db)?
             } else {
@@ -66,6 +66,8 @@ pub fn endpoint_queue(data: Arc<Data>) -> Fallible<Response<Body>> {
     let mut needs_report = Vec::new();
     let mut generating_report = Vec::new();
     let mut report_failed = Vec::new();
+    let mut cancelled = Vec::new();
+    let mut failed = Vec::new();
 
     for experiment in &Experiment::unfinished(&data.db)? {
         // Don't include completed experiments in the queue
@@ -81,12 +83,16 @@ pub fn endpoint_queue(data: Arc<Data>) -> Fallible<Response<Body>> {
             Status::NeedsReport => needs_report.push(ex),
             Status::GeneratingReport => generating_report.push(ex),
             Status::ReportFailed => report_failed.push(ex),
+            Status::Cancelled => cancelled.push(ex),
+            Status::Failed => failed.push(ex),
             Status::Completed => unreachable!(),
         };
     }
 
     let mut experiments = Vec::new();
+    experiments.append(&mut failed);
     experiments.append(&mut report_failed);
+    experiments.append(&mut cancelled);
     experiments.append(&mut generating_report);
     experiments.append(&mut needs_report);
     experiments.append(&mut running);
@@ -1,4 +1,5 @@
 use crate::experiments::{Assignee, CapLints, DeferredCrateSelect, Mode};
+use crate::results::ResultKinds;
 use crate::toolchain::Toolchain;
 
 #[derive(Debug, thiserror::Error)]
@@ -113,6 +114,9 @@ generate_parser!(pub enum Command {
         ignore_blacklist: Option<bool> = "ignore-blacklist",
         assign: Option<Assignee> = "assign",
         requirement: Option<String> = "requirement",
+        max_regressions: Option<usize> = "max-regressions",
+        skip_dev_deps: Option<bool> = "skip-dev-deps",
+        confirm_regressions: Option<bool> = "confirm-regressions",
     })
 
     "check" => Check(CheckArgs {
@@ -125,6 +129,11 @@ generate_parser!(pub enum Command {
         ignore_blacklist: Option<bool> = "ignore-blacklist",
         assign: Option<Assignee> = "assign",
         requirement: Option<String> = "requirement",
+        max_regressions: Option<usize> = "max-regressions",
+    })
+
+    "cancel-experiment" => CancelExperiment(CancelExperimentArgs {
+        name: Option<String> = "name",
     })
 
     "abort" | "cancel" => Abort(AbortArgs {
@@ -141,6 +150,11 @@ generate_parser!(pub enum Command {
         name: Option<String> = "name",
     })
 
+    "requeue" => Requeue(RequeueArgs {
+        name: Option<String> = "name",
+        results: Option<ResultKinds> = "results",
+    })
+
     "reload-acl" => ReloadACL(ReloadACLArgs {})
 
     => Edit(EditArgs {
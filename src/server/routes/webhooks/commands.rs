@@ -2,14 +2,17 @@ use crate::actions::{self, Action, ActionsCtx};
 use crate::db::{Database, QueryUtils};
 use crate::experiments::{CapLints, CrateSelect, Experiment, GitHubIssue, Mode, Status};
 use crate::prelude::*;
+use crate::results::DatabaseDB;
 use crate::server::github::{GitHub, Issue, Repository};
 use crate::server::messages::{Label, Message};
 use crate::server::routes::webhooks::args::{
-    AbortArgs, CheckArgs, EditArgs, RetryArgs, RetryReportArgs, RunArgs,
+    AbortArgs, CancelExperimentArgs, CheckArgs, EditArgs, RequeueArgs, RetryArgs, RetryReportArgs,
+    RunArgs,
 };
 use crate::server::{Data, GithubData};
 use crate::toolchain::Toolchain;
 use rustwide::Toolchain as RustwideToolchain;
+use std::collections::BTreeMap;
 
 pub fn ping(data: &Data, github_data: &GithubData, issue: &Issue) -> Fallible<()> {
     Message::new()
@@ -44,6 +47,9 @@ pub fn check(
             ignore_blacklist: args.ignore_blacklist,
             assign: args.assign,
             requirement: args.requirement,
+            max_regressions: args.max_regressions,
+            skip_dev_deps: None,
+            confirm_regressions: None,
         },
     )
 }
@@ -76,6 +82,8 @@ pub fn run(
                 rustdocflags: None,
                 cargoflags: None,
                 ci_try: false,
+                local: None,
+                env: BTreeMap::new(),
                 patches: Vec::new(),
             });
             detected_end = Some(Toolchain {
@@ -85,6 +93,8 @@ pub fn run(
                 rustdocflags: None,
                 cargoflags: None,
                 ci_try: true,
+                local: None,
+                env: BTreeMap::new(),
                 patches: Vec::new(),
             });
             message = message.line(
@@ -148,6 +158,12 @@ pub fn run(
         ignore_blacklist: args.ignore_blacklist.unwrap_or(false),
         assign: args.assign,
         requirement: Some(requirement),
+        max_regressions: args.max_regressions,
+        webhook: None,
+        skip_dev_deps: args.skip_dev_deps.unwrap_or(false),
+        extra_toolchains: Vec::new(),
+        labels: BTreeMap::new(),
+        confirm_regressions: args.confirm_regressions.unwrap_or(false),
     }
     .apply(&ActionsCtx::new(&data.db, &data.config))?;
 
@@ -256,6 +272,66 @@ pub fn retry(
     }
 }
 
+pub fn requeue(
+    data: &Data,
+    github_data: &GithubData,
+    issue: &Issue,
+    args: RequeueArgs,
+) -> Fallible<()> {
+    let name = get_name(&data.db, issue, args.name)?;
+    let Some(results) = args.results else {
+        bail!("missing the kinds of results to requeue, for example `results=fetch-error,timeout`");
+    };
+
+    if let Some(mut experiment) = Experiment::get(&data.db, &name)? {
+        let requeued = DatabaseDB::new(&data.db).requeue_results(&experiment, &results)?;
+        if requeued > 0 {
+            experiment.requeue(&data.db)?;
+            data.reports_worker.wake();
+        }
+
+        Message::new()
+            .line(
+                "hammer_and_wrench",
+                format!(
+                    "Queued again the {requeued} crates of experiment **`{name}`** with \
+                     `{results}` results."
+                ),
+            )
+            .set_label(Label::ExperimentQueued)
+            .send(&issue.url, data, github_data)?;
+
+        Ok(())
+    } else {
+        bail!("an experiment named **`{}`** doesn't exist!", name);
+    }
+}
+
+pub fn cancel_experiment(
+    data: &Data,
+    github_data: &GithubData,
+    issue: &Issue,
+    args: CancelExperimentArgs,
+) -> Fallible<()> {
+    let name = get_name(&data.db, issue, args.name)?;
+
+    actions::CancelExperiment { name: name.clone() }
+        .apply(&ActionsCtx::new(&data.db, &data.config))?;
+    data.reports_worker.wake();
+
+    Message::new()
+        .line(
+            "octagonal_sign",
+            format!(
+                "Experiment **`{name}`** cancelled! The report of the crates tested so far will \
+                 be generated shortly."
+            ),
+        )
+        .send(&issue.url, data, github_data)?;
+
+    Ok(())
+}
+
 pub fn abort(
     data: &Data,
     github_data: &GithubData,
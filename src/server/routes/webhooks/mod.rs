@@ -138,6 +138,14 @@ fn process_command(
                 commands::retry(data, github_data, issue, args)?;
             }
 
+            Command::Requeue(args) => {
+                commands::requeue(data, github_data, issue, args)?;
+            }
+
+            Command::CancelExperiment(args) => {
+                commands::cancel_experiment(data, github_data, issue, args)?;
+            }
+
             Command::Abort(args) => {
                 commands::abort(data, github_data, issue, args)?;
             }
@@ -0,0 +1,213 @@
+use crate::prelude::*;
+use crate::report::AbortSignal;
+use std::future::Future;
+use std::sync::{Arc, Condvar, Mutex};
+use std::time::{Duration, Instant};
+
+/// How long the work still in flight once the uploads are aborted is given to clean up.
+const ABORT_GRACE_PERIOD: Duration = Duration::from_secs(30);
+
+#[derive(Default)]
+struct State {
+    draining: bool,
+    in_flight: usize,
+}
+
+/// Coordinates the graceful shutdown of the server: once it begins no new work is started, and
+/// the work already in flight (report uploads and result writes) is waited for before exiting.
+#[derive(Clone, Default)]
+pub struct Shutdown {
+    state: Arc<(Mutex<State>, Condvar)>,
+    abort: AbortSignal,
+}
+
+impl Shutdown {
+    /// Stop accepting new work.
+    pub fn begin(&self) {
+        self.state
+            .0
+            .lock()
+            .unwrap_or_else(|l| l.into_inner())
+            .draining = true;
+    }
+
+    pub fn is_draining(&self) -> bool {
+        self.state
+            .0
+            .lock()
+            .unwrap_or_else(|l| l.into_inner())
+            .draining
+    }
+
+    /// Whether the deadline passed and the uploads still in flight were told to abort.
+    pub fn is_aborted(&self) -> bool {
+        self.abort.is_aborted()
+    }
+
+    /// Signal triggered when the uploads still in flight have to be aborted.
+    pub fn abort_signal(&self) -> AbortSignal {
+        self.abort.clone()
+    }
+
+    /// Track a piece of work until the returned guard is dropped, or `None` if the server is
+    /// shutting down and no new work should be started.
+    pub fn start_work(&self) -> Option<WorkGuard> {
+        let mut state = self.state.0.lock().unwrap_or_else(|l| l.into_inner());
+        if state.draining {
+            return None;
+        }
+        state.in_flight += 1;
+        Some(WorkGuard {
+            shutdown: self.clone(),
+        })
+    }
+
+    /// Stop accepting new work and wait for the work in flight to complete, for at most
+    /// `deadline`. Past it the uploads still running are aborted, and given a short grace period
+    /// to clean up. Returns whether all the work completed before the deadline.
+    pub fn drain(&self, deadline: Duration) -> bool {
+        self.begin();
+        if self.wait_idle(deadline) {
+            return true;
+        }
+
+        warn!("the work in flight didn't complete in time, aborting the uploads");
+        self.abort.abort();
+        if !self.wait_idle(ABORT_GRACE_PERIOD) {
+            warn!("the aborted uploads didn't stop in time");
+        }
+        false
+    }
+
+    /// Wait until no work is in flight, for at most `timeout`.
+    fn wait_idle(&self, timeout: Duration) -> bool {
+        let start = Instant::now();
+        let mut state = self.state.0.lock().unwrap_or_else(|l| l.into_inner());
+        while state.in_flight > 0 {
+            let Some(remaining) = timeout.checked_sub(start.elapsed()) else {
+                return false;
+            };
+            state = self
+                .state
+                .1
+                .wait_timeout(state, remaining)
+                .unwrap_or_else(|l| l.into_inner())
+                .0;
+        }
+        true
+    }
+}
+
+/// Work tracked by `Shutdown`, completed when dropped.
+pub struct WorkGuard {
+    shutdown: Shutdown,
+}
+
+impl Drop for WorkGuard {
+    fn drop(&mut self) {
+        self.shutdown
+            .state
+            .0
+            .lock()
+            .unwrap_or_else(|l| l.into_inner())
+            .in_flight -= 1;
+        self.shutdown.state.1.notify_all();
+    }
+}
+
+/// Resolve once `signal` does, beginning the shutdown. Passed to the HTTP server, so it stops
+/// accepting connections at the same time.
+pub async fn shutdown_on<F: Future<Output = ()>>(shutdown: Shutdown, signal: F) {
+    signal.await;
+    info!("shutting down, no new work will be started");
+    shutdown.begin();
+}
+
+/// Resolve when the process receives SIGTERM.
+pub async fn sigterm() {
+    use tokio::signal::unix::{signal, SignalKind};
+
+    match signal(SignalKind::terminate()) {
+        Ok(mut sigterm) => {
+            sigterm.recv().await;
+        }
+        Err(err) => {
+            error!("failed to listen for SIGTERM: {}", err);
+            std::future::pending::<()>().await;
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::{shutdown_on, sigterm, Shutdown};
+    use std::process::Command;
+    use std::sync::atomic::{AtomicBool, Ordering};
+    use std::sync::Arc;
+    use std::thread;
+    use std::time::{Duration, Instant};
+
+    #[test]
+    fn test_signal_triggers_drain() {
+        let shutdown = Shutdown::default();
+        let work = shutdown.start_work().unwrap();
+
+        let rt = tokio::runtime::Builder::new_current_thread()
+            .enable_all()
+            .build()
+            .unwrap();
+        rt.block_on(async {
+            // Listening before the signal is sent, so it doesn't kill the test process
+            let signal = tokio::spawn(shutdown_on(shutdown.clone(), sigterm()));
+            tokio::task::yield_now().await;
+            assert!(!shutdown.is_draining());
+
+            let status = Command::new("kill")
+                .args(["-TERM", &std::process::id().to_string()])
+                .status()
+                .unwrap();
+            assert!(status.success());
+            tokio::time::timeout(Duration::from_secs(10), signal)
+                .await
+                .unwrap()
+                .unwrap();
+        });
+
+        // No new work is accepted, and the work in flight is waited for
+        assert!(shutdown.is_draining());
+        assert!(shutdown.start_work().is_none());
+        let handle = thread::spawn(move || {
+            thread::sleep(Duration::from_millis(100));
+            drop(work);
+        });
+        assert!(shutdown.drain(Duration::from_secs(10)));
+        assert!(!shutdown.is_aborted());
+        handle.join().unwrap();
+    }
+
+    #[test]
+    fn test_drain_aborts_after_deadline() {
+        let shutdown = Shutdown::default();
+        let work = shutdown.start_work().unwrap();
+        let aborted = Arc::new(AtomicBool::new(false));
+
+        // A long-running upload, only stopping once aborted
+        let abort = shutdown.abort_signal();
+        let upload_aborted = aborted.clone();
+        let upload = thread::spawn(move || {
+            let rt = tokio::runtime::Builder::new_current_thread()
+                .build()
+                .unwrap();
+            rt.block_on(abort.aborted());
+            upload_aborted.store(true, Ordering::SeqCst);
+            drop(work);
+        });
+
+        let start = Instant::now();
+        assert!(!shutdown.drain(Duration::from_millis(200)));
+        assert!(start.elapsed() >= Duration::from_millis(200));
+        assert!(shutdown.is_aborted());
+        assert!(aborted.load(Ordering::SeqCst));
+        upload.join().unwrap();
+    }
+}
@@ -0,0 +1,282 @@
+use crate::config::Config;
+use crate::crates::Crate;
+use crate::db::Database;
+use crate::experiments::Experiment;
+use crate::prelude::*;
+use crate::report::{compare, Comparison, SummaryCategory};
+use crate::results::{DatabaseDB, TestResult};
+use crate::toolchain::Toolchain;
+use std::collections::BTreeMap;
+use std::sync::{Arc, Mutex};
+
+/// Number of results loaded at once when counting the results of an experiment.
+const LOAD_BATCH_SIZE: u32 = 1000;
+
+/// Progress of an experiment, as served by the status endpoint.
+#[derive(Debug, Clone, PartialEq, Eq, Serialize)]
+pub struct ExperimentStatus {
+    pub name: String,
+    /// Number of crates tested by the experiment.
+    pub total: u32,
+    /// Number of crates with a result for every toolchain.
+    pub completed: u32,
+    pub pending: u32,
+    /// Number of completed crates in each summary category.
+    pub categories: BTreeMap<String, u32>,
+}
+
+struct ExperimentCounters {
+    toolchains: u32,
+    crates: u32,
+    results: u32,
+    categories: BTreeMap<String, u32>,
+    /// Number of completed crates which regressed, checked against the regressions cap.
+    regressions: usize,
+}
+
+impl ExperimentCounters {
+    fn load(db: &Database, config: &Config, ex: &Experiment) -> Fallible<Self> {
+        let (results, jobs) = ex.raw_progress(db)?;
+        let toolchains = ex.all_toolchains().count() as u32;
+        let mut counters = ExperimentCounters {
+            toolchains,
+            crates: jobs / toolchains.max(1),
+            results,
+            categories: BTreeMap::new(),
+            regressions: 0,
+        };
+
+        let results = DatabaseDB::new(db);
+        let mut cursor = 0;
+        loop {
+            let batch = results.load_results_since(ex, cursor, LOAD_BATCH_SIZE)?;
+            let Some(last) = batch.last() else {
+                break;
+            };
+            cursor = last.cursor;
+            for stored in &batch {
+                if let Some((toolchain, previous)) = &stored.previous {
+                    counters.count_category(
+                        config,
+                        ex,
+                        &stored.krate,
+                        (&stored.toolchain, &stored.result),
+                        (toolchain, previous),
+                    );
+                }
+            }
+        }
+
+        Ok(counters)
+    }
+
+    /// Count the category of the crate, if the results are the ones of the compared toolchains.
+    fn count_category(
+        &mut self,
+        config: &Config,
+        ex: &Experiment,
+        krate: &Crate,
+        (toolchain, result): (&Toolchain, &TestResult),
+        (other_toolchain, other): (&Toolchain, &TestResult),
+    ) {
+        if ex.compared_toolchain(toolchain) != Some(other_toolchain) {
+            return;
+        }
+        let (start, end) = if *toolchain == ex.toolchains[0] {
+            (result, other)
+        } else {
+            (other, result)
+        };
+        let comparison = compare(config, krate, Some(start), Some(end));
+        if comparison == Comparison::Regressed {
+            self.regressions += 1;
+        }
+        let category = SummaryCategory::from_results(comparison, start, end);
+        *self
+            .categories
+            .entry(category.to_str().to_string())
+            .or_insert(0) += 1;
+    }
+}
+
+/// Progress of the running experiments, loaded from the database the first time an experiment
+/// is seen and then updated as the agents report results, so it can be served without querying
+/// the database.
+#[derive(Clone, Default)]
+pub struct ServerStatus {
+    experiments: Arc<Mutex<BTreeMap<String, ExperimentCounters>>>,
+}
+
+impl ServerStatus {
+    /// Start tracking the experiment, if it isn't already. Returns whether its counters were
+    /// loaded from the database by this call, accounting for all the results stored so far.
+    pub fn track(&self, db: &Database, config: &Config, ex: &Experiment) -> Fallible<bool> {
+        if self.experiments.lock().unwrap().contains_key(&ex.name) {
+            return Ok(false);
+        }
+
+        // Loaded without holding the lock, as it goes through all the results
+        let counters = ExperimentCounters::load(db, config, ex)?;
+        self.experiments
+            .lock()
+            .unwrap()
+            .entry(ex.name.clone())
+            .or_insert(counters);
+        Ok(true)
+    }
+
+    /// Account for a newly stored result, along with the result of the same crate on the other
+    /// compared toolchain if it was already stored.
+    pub fn record_result(
+        &self,
+        config: &Config,
+        ex: &Experiment,
+        krate: &Crate,
+        result: (&Toolchain, &TestResult),
+        other: Option<(&Toolchain, &TestResult)>,
+    ) {
+        let mut experiments = self.experiments.lock().unwrap();
+        let Some(counters) = experiments.get_mut(&ex.name) else {
+            return;
+        };
+        counters.results += 1;
+        if let Some(other) = other {
+            counters.count_category(config, ex, krate, result, other);
+        }
+    }
+
+    /// Number of regressed crates of the experiment, or `None` if it isn't tracked.
+    pub fn regressions(&self, name: &str) -> Option<usize> {
+        self.experiments
+            .lock()
+            .unwrap()
+            .get(name)
+            .map(|counters| counters.regressions)
+    }
+
+    /// Stop tracking a completed experiment.
+    pub fn untrack(&self, name: &str) {
+        self.experiments.lock().unwrap().remove(name);
+    }
+
+    pub fn experiments(&self) -> Vec<ExperimentStatus> {
+        self.experiments
+            .lock()
+            .unwrap()
+            .iter()
+            .map(|(name, counters)| {
+                let completed =
+                    (counters.results / counters.toolchains.max(1)).min(counters.crates);
+                ExperimentStatus {
+                    name: name.clone(),
+                    total: counters.crates,
+                    completed,
+                    pending: counters.crates - completed,
+                    categories: counters.categories.clone(),
+                }
+            })
+            .collect()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::ServerStatus;
+    use crate::actions::{Action, ActionsCtx, CreateExperiment};
+    use crate::config::Config;
+    use crate::crates::Crate;
+    use crate::db::Database;
+    use crate::experiments::Experiment;
+    use crate::results::{
+        DatabaseDB, EncodingType, FailureReason, ProgressData, TaskResult, TestResult,
+    };
+    use crate::toolchain::Toolchain;
+    use std::collections::BTreeMap;
+
+    fn store(
+        db: &Database,
+        ex: &Experiment,
+        krate: &Crate,
+        toolchain: &Toolchain,
+        res: TestResult,
+    ) {
+        DatabaseDB::new(db)
+            .store(
+                ex,
+                &ProgressData {
+                    result: TaskResult {
+                        krate: krate.clone(),
+                        toolchain: toolchain.clone(),
+                        result: res,
+                        log: String::new(),
+                        duration_ms: None,
+                    },
+                    version: None,
+                },
+                EncodingType::Plain,
+            )
+            .unwrap();
+    }
+
+    #[test]
+    fn test_status_counters() {
+        let db = Database::temp().unwrap();
+        let config = Config::default();
+        let ctx = ActionsCtx::new(&db, &config);
+        crate::crates::lists::setup_test_lists(&db, &config).unwrap();
+        CreateExperiment::dummy("dummy").apply(&ctx).unwrap();
+        let ex = Experiment::get(&db, "dummy").unwrap().unwrap();
+        let crates = ex.get_crates(&db).unwrap();
+        let [start, end] = &ex.toolchains;
+
+        // Results stored before the experiment is tracked are loaded from the database
+        store(&db, &ex, &crates[0], start, TestResult::TestPass);
+        store(&db, &ex, &crates[0], end, TestResult::TestPass);
+
+        let status = ServerStatus::default();
+        assert!(status.track(&db, &config, &ex).unwrap());
+        let experiments = status.experiments();
+        assert_eq!(experiments.len(), 1);
+        assert_eq!(experiments[0].name, "dummy");
+        assert_eq!(experiments[0].total, crates.len() as u32);
+        assert_eq!(experiments[0].completed, 1);
+        assert_eq!(experiments[0].pending, crates.len() as u32 - 1);
+        assert_eq!(
+            experiments[0].categories,
+            BTreeMap::from([("unchanged".to_string(), 1)])
+        );
+
+        // Then the counters are updated without going through the database
+        let build_fail = TestResult::BuildFail(FailureReason::Unknown);
+        status.record_result(
+            &config,
+            &ex,
+            &crates[1],
+            (start, &TestResult::TestPass),
+            None,
+        );
+        status.record_result(
+            &config,
+            &ex,
+            &crates[1],
+            (end, &build_fail),
+            Some((start, &TestResult::TestPass)),
+        );
+        let experiments = status.experiments();
+        assert_eq!(experiments[0].completed, 2);
+        assert_eq!(experiments[0].pending, crates.len() as u32 - 2);
+        assert_eq!(
+            experiments[0].categories,
+            BTreeMap::from([("unchanged".to_string(), 1), ("regressed".to_string(), 1)])
+        );
+        assert_eq!(status.regressions("dummy"), Some(1));
+
+        // Tracking it again doesn't reload the counters
+        assert!(!status.track(&db, &config, &ex).unwrap());
+        assert_eq!(status.experiments()[0].completed, 2);
+
+        status.untrack("dummy");
+        assert!(status.experiments().is_empty());
+        assert_eq!(status.regressions("dummy"), None);
+    }
+}
@@ -1,7 +1,13 @@
+use crate::experiments::CompletionWebhook;
 use crate::prelude::*;
+use crate::report::{self, ReportRuntime};
+use aws_sdk_s3::types::StorageClass;
+use serde::de::{Deserializer, Error as _};
+use serde::Deserialize as _;
 use serde_derive::Deserialize;
 use std::collections::HashMap;
 use std::path::Path;
+use std::time::Duration;
 
 static TOKENS_PATH: &str = "tokens.toml";
 
@@ -27,6 +33,34 @@ pub struct ReportsBucket {
     pub public_url: String,
     pub access_key: String,
     pub secret_key: String,
+    /// Keep the reports private, linking to the logs with URLs presigned for this many seconds.
+    #[serde(default)]
+    pub presigned_urls_secs: Option<u64>,
+    /// Storage class of the uploaded reports, like `STANDARD_IA` or `INTELLIGENT_TIERING`. The
+    /// bucket's default is used when missing.
+    #[serde(default, deserialize_with = "deserialize_storage_class")]
+    pub storage_class: Option<StorageClass>,
+    /// Role assumed to upload the reports, with the credentials above, when the bucket belongs
+    /// to another account.
+    #[serde(default)]
+    pub role_arn: Option<String>,
+    /// External id required by the trust policy of the assumed role.
+    #[serde(default)]
+    pub external_id: Option<String>,
+}
+
+/// Storage classes unknown to the SDK are refused, instead of failing every upload.
+fn deserialize_storage_class<'de, D: Deserializer<'de>>(
+    deserializer: D,
+) -> Result<Option<StorageClass>, D::Error> {
+    let name = String::deserialize(deserializer)?;
+    if !StorageClass::values().contains(&name.as_str()) {
+        return Err(D::Error::custom(format!(
+            "invalid storage-class `{name}`, expected one of: {}",
+            StorageClass::values().join(", ")
+        )));
+    }
+    Ok(Some(StorageClass::from(name.as_str())))
 }
 
 impl ReportsBucket {
@@ -39,6 +73,48 @@ impl ReportsBucket {
             "crater-credentials",
         )
     }
+
+    /// Writer uploading under `prefix` of the bucket with the same region, credentials, ACL,
+    /// storage class and role as the reports.
+    pub fn writer(&self, runtime: &ReportRuntime, prefix: String) -> Fallible<report::S3Writer> {
+        let mut config = aws_config::from_env();
+        match &self.region {
+            BucketRegion::S3 { region } => {
+                config = config.region(aws_sdk_s3::config::Region::new(region.to_owned()));
+            }
+            BucketRegion::Custom { url } => {
+                config = config.region(aws_sdk_s3::config::Region::from_static("us-east-1"));
+                config = config.endpoint_url(url.clone());
+            }
+        }
+        config = config.credentials_provider(self.to_aws_credentials());
+        let config = runtime.block_on(config.load());
+        let client = aws_sdk_s3::Client::new(&config);
+        let mut writer = report::S3Writer::create(
+            client,
+            self.bucket.clone(),
+            prefix,
+            report::MultipartConfig::default(),
+        )?;
+        // Reports linking to presigned URLs are meant to stay private
+        if self.presigned_urls_secs.is_none() {
+            writer = writer.with_acl(aws_sdk_s3::types::ObjectCannedAcl::PublicRead);
+        }
+        if let Some(storage_class) = &self.storage_class {
+            writer = writer.with_storage_class(storage_class.clone());
+        }
+        if let Some(role_arn) = &self.role_arn {
+            let role = report::AssumeRole {
+                role_arn: role_arn.clone(),
+                external_id: self.external_id.clone(),
+            };
+            writer = writer.with_role(role, runtime)?;
+        }
+        if let Some(secs) = self.presigned_urls_secs {
+            writer = writer.with_presigned_urls(Duration::from_secs(secs))?;
+        }
+        Ok(writer)
+    }
 }
 
 #[derive(Debug, Clone, Deserialize)]
@@ -48,6 +124,9 @@ pub struct Tokens {
     pub bot: Option<BotTokens>,
     pub reports_bucket: ReportsBucket,
     pub agents: HashMap<String, String>,
+    /// Webhook notified when an experiment without its own webhook completes.
+    #[serde(default)]
+    pub completion_webhook: Option<CompletionWebhook>,
 }
 
 #[cfg(test)]
@@ -63,8 +142,13 @@ impl Default for Tokens {
                 public_url: String::new(),
                 access_key: String::new(),
                 secret_key: String::new(),
+                presigned_urls_secs: None,
+                storage_class: None,
+                role_arn: None,
+                external_id: None,
             },
             agents: HashMap::new(),
+            completion_webhook: None,
         }
     }
 }
@@ -77,3 +161,39 @@ impl Tokens {
         Ok(res)
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::ReportsBucket;
+    use aws_sdk_s3::types::StorageClass;
+
+    #[test]
+    fn test_storage_class() {
+        let bucket = |storage_class: &str| {
+            toml::from_str::<ReportsBucket>(&format!(
+                r#"
+                    region = {{ type = "s3", region = "us-west-1" }}
+                    bucket = "crater-reports"
+                    public-url = "https://crater-reports.s3.amazonaws.com"
+                    access-key = "access"
+                    secret-key = "secret"
+                    {storage_class}
+                "#
+            ))
+        };
+
+        assert_eq!(bucket("").unwrap().storage_class, None);
+        assert_eq!(
+            bucket(r#"storage-class = "STANDARD_IA""#)
+                .unwrap()
+                .storage_class,
+            Some(StorageClass::StandardIa)
+        );
+
+        // Typos are refused when the tokens are loaded, listing the valid classes
+        let err = bucket(r#"storage-class = "STANDARD-IA""#).unwrap_err();
+        let message = err.to_string();
+        assert!(message.contains("invalid storage-class `STANDARD-IA`"));
+        assert!(message.contains("INTELLIGENT_TIERING"));
+    }
+}
@@ -2,8 +2,12 @@ use crate::prelude::*;
 use crate::utils;
 use regex::Regex;
 use rustwide::Toolchain as RustwideToolchain;
+use std::collections::BTreeMap;
 use std::fmt;
+use std::fs;
+use std::path::{Path, PathBuf};
 use std::str::FromStr;
+use walkdir::WalkDir;
 
 #[cfg(test)]
 lazy_static! {
@@ -15,6 +19,8 @@ lazy_static! {
         rustdocflags: None,
         cargoflags: None,
         ci_try: false,
+        local: None,
+        env: BTreeMap::new(),
         patches: Vec::new(),
     };
 
@@ -26,6 +32,8 @@ lazy_static! {
         rustdocflags: None,
         cargoflags: None,
         ci_try: false,
+        local: None,
+        env: BTreeMap::new(),
         patches: Vec::new(),
     };
 }
@@ -38,6 +46,12 @@ pub struct Toolchain {
     pub rustdocflags: Option<String>,
     pub cargoflags: Option<String>,
     pub ci_try: bool,
+    #[serde(default)]
+    pub local: Option<LocalToolchain>,
+    /// Environment variables set when building with this toolchain, overriding the ones set by
+    /// crater itself.
+    #[serde(default)]
+    pub env: BTreeMap<String, String>,
     pub patches: Vec<CratePatch>,
 }
 
@@ -47,6 +61,44 @@ impl Toolchain {
 
         encode(&self.to_string(), &utils::FILENAME_ENCODE_SET).to_string()
     }
+
+    /// Name of the toolchain in rustup, to select it with `cargo +name`. CI toolchains are
+    /// installed by `rustup-toolchain-install-master` under their SHA.
+    pub fn rustup_name(&self) -> String {
+        if let Some(dist) = self.source.as_dist() {
+            dist.name().to_string()
+        } else if let Some(ci) = self.source.as_ci() {
+            ci.sha().to_string()
+        } else {
+            panic!("unsupported rustwide toolchain");
+        }
+    }
+
+    /// Name of the toolchain with the values of its secret environment variables redacted, to
+    /// be shown in the build logs.
+    pub fn redacted(&self) -> String {
+        let mut redacted = self.clone();
+        for (var, value) in redacted.env.iter_mut() {
+            *value = redact_env(var, value).into();
+        }
+        redacted.to_string()
+    }
+}
+
+lazy_static! {
+    static ref SECRET_ENV_RE: Regex = Regex::new(r"(?i)(^|_)(TOKEN|SECRET|PASSWORD|KEY)$").unwrap();
+}
+
+const REDACTED: &str = "<redacted>";
+
+/// Value of the environment variable `var` as it can be logged, redacted if its name looks like
+/// the one of a secret (like `GITHUB_TOKEN`).
+pub fn redact_env<'a>(var: &str, value: &'a str) -> &'a str {
+    if SECRET_ENV_RE.is_match(var) {
+        REDACTED
+    } else {
+        value
+    }
 }
 
 impl std::ops::Deref for Toolchain {
@@ -59,7 +111,15 @@ impl std::ops::Deref for Toolchain {
 
 impl fmt::Display for Toolchain {
     fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
-        if let Some(dist) = self.source.as_dist() {
+        if let Some(ref local) = self.local {
+            write!(
+                f,
+                "local#{}+rustc={}+cargo={}",
+                local.name,
+                local.rustc_path.display(),
+                local.cargo_path.display()
+            )?;
+        } else if let Some(dist) = self.source.as_dist() {
             write!(f, "{}", dist.name())?;
         } else if let Some(ci) = self.source.as_ci() {
             if self.ci_try {
@@ -87,6 +147,10 @@ impl fmt::Display for Toolchain {
             write!(f, "+cargoflags={flag}")?;
         }
 
+        for (var, value) in &self.env {
+            write!(f, "+env={var}={value}")?;
+        }
+
         for patch in self.patches.iter() {
             write!(f, "+patch={patch}")?;
         }
@@ -105,6 +169,8 @@ pub enum ToolchainParseError {
     InvalidFlag(String),
     #[error("invalid toolchain SHA: {0} is missing a `try#` or `master#` prefix")]
     PrefixMissing(String),
+    #[error("local toolchain {0} needs both the `+rustc` and `+cargo` flags")]
+    MissingLocalBinaries(String),
 }
 
 lazy_static! {
@@ -119,6 +185,7 @@ impl FromStr for Toolchain {
 
         let raw_source = parts.next().ok_or(ToolchainParseError::EmptyName)?;
         let mut ci_try = false;
+        let mut local_name = None;
         let source = if let Some(hash_idx) = raw_source.find('#') {
             let (source_name, sha_with_hash) = raw_source.split_at(hash_idx);
 
@@ -133,6 +200,10 @@ impl FromStr for Toolchain {
                     RustwideToolchain::ci(sha, false)
                 }
                 "master" => RustwideToolchain::ci(sha, false),
+                "local" => {
+                    local_name = Some(sha.to_string());
+                    RustwideToolchain::dist(&LocalToolchain::rustup_name(sha))
+                }
                 name => return Err(ToolchainParseError::InvalidSourceName(name.to_string())),
             }
         } else if raw_source.is_empty() {
@@ -148,8 +219,11 @@ impl FromStr for Toolchain {
         let mut rustflags = None;
         let mut rustdocflags = None;
         let mut cargoflags = None;
+        let mut env = BTreeMap::new();
         let mut patches: Vec<CratePatch> = vec![];
         let mut target = None;
+        let mut rustc_path = None;
+        let mut cargo_path = None;
         for part in parts {
             if let Some(equal_idx) = part.find('=') {
                 let (flag, value_with_equal) = part.split_at(equal_idx);
@@ -164,7 +238,15 @@ impl FromStr for Toolchain {
                     "rustdocflags" => rustdocflags = Some(value),
                     "cargoflags" => cargoflags = Some(value),
                     "patch" => patches.push(value.parse()?),
+                    "env" => match value.split_once('=') {
+                        Some((var, value)) if !var.is_empty() => {
+                            env.insert(var.to_string(), value.to_string());
+                        }
+                        _ => return Err(ToolchainParseError::InvalidFlag(part.to_string())),
+                    },
                     "target" => target = Some(value),
+                    "rustc" if local_name.is_some() => rustc_path = Some(value.into()),
+                    "cargo" if local_name.is_some() => cargo_path = Some(value.into()),
                     unknown => return Err(ToolchainParseError::InvalidFlag(unknown.to_string())),
                 }
             } else {
@@ -172,6 +254,16 @@ impl FromStr for Toolchain {
             }
         }
 
+        let local = match (local_name, rustc_path, cargo_path) {
+            (Some(name), Some(rustc_path), Some(cargo_path)) => Some(LocalToolchain {
+                name,
+                rustc_path,
+                cargo_path,
+            }),
+            (Some(name), _, _) => return Err(ToolchainParseError::MissingLocalBinaries(name)),
+            (None, _, _) => None,
+        };
+
         Ok(Toolchain {
             source,
             target,
@@ -179,11 +271,96 @@ impl FromStr for Toolchain {
             rustdocflags,
             cargoflags,
             ci_try,
+            local,
+            env,
             patches,
         })
     }
 }
 
+/// A compiler built locally, used instead of one installed by rustup.
+#[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
+pub struct LocalToolchain {
+    pub name: String,
+    pub rustc_path: PathBuf,
+    pub cargo_path: PathBuf,
+}
+
+impl LocalToolchain {
+    /// Name of the toolchain in rustup, prefixed so it can't clash with the dist toolchains.
+    fn rustup_name(name: &str) -> String {
+        format!("crater-local-{name}")
+    }
+
+    /// Check that both binaries exist and are executable.
+    pub fn validate(&self) -> Fallible<()> {
+        for (binary, path) in [("rustc", &self.rustc_path), ("cargo", &self.cargo_path)] {
+            let metadata = fs::metadata(path).with_context(|_| {
+                format!(
+                    "the {binary} of local toolchain {} can't be found at {}",
+                    self.name,
+                    path.display()
+                )
+            })?;
+            if !metadata.is_file() || !is_executable(&metadata) {
+                bail!(
+                    "the {} of local toolchain {} at {} is not an executable",
+                    binary,
+                    self.name,
+                    path.display()
+                );
+            }
+        }
+        Ok(())
+    }
+
+    /// Copy the sysroot containing rustc into `toolchains_dir`, the toolchains directory of a
+    /// rustup home, along with cargo. Rustup then treats it as an installed custom toolchain.
+    pub(crate) fn install(&self, toolchains_dir: &Path) -> Fallible<()> {
+        let sysroot = self
+            .rustc_path
+            .parent()
+            .and_then(Path::parent)
+            .ok_or_else(|| err_msg(format!("{} is not in a sysroot", self.rustc_path.display())))?;
+        let dest = toolchains_dir.join(Self::rustup_name(&self.name));
+        if dest.exists() {
+            fs::remove_dir_all(&dest)?;
+        }
+
+        for entry in WalkDir::new(sysroot) {
+            let entry = entry?;
+            let target = dest.join(entry.path().strip_prefix(sysroot)?);
+            if entry.file_type().is_dir() {
+                fs::create_dir_all(&target)?;
+            } else {
+                fs::copy(entry.path(), &target)
+                    .with_context(|_| format!("failed to copy {}", entry.path().display()))?;
+            }
+        }
+
+        // Local builds of rustc don't necessarily have cargo next to them
+        let cargo_name = self
+            .cargo_path
+            .file_name()
+            .ok_or_else(|| err_msg(format!("{} is not a file", self.cargo_path.display())))?;
+        fs::copy(&self.cargo_path, dest.join("bin").join(cargo_name))?;
+
+        Ok(())
+    }
+}
+
+#[cfg(unix)]
+fn is_executable(metadata: &fs::Metadata) -> bool {
+    use std::os::unix::fs::PermissionsExt;
+
+    metadata.permissions().mode() & 0o111 != 0
+}
+
+#[cfg(not(unix))]
+fn is_executable(_metadata: &fs::Metadata) -> bool {
+    true
+}
+
 #[derive(Serialize, Deserialize, PartialEq, Eq, Hash, Debug, Clone)]
 pub struct CratePatch {
     pub name: String,
@@ -217,8 +394,10 @@ impl fmt::Display for CratePatch {
 
 #[cfg(test)]
 mod tests {
-    use super::{CratePatch, Toolchain};
+    use super::{redact_env, CratePatch, LocalToolchain, Toolchain};
     use rustwide::Toolchain as RustwideToolchain;
+    use std::collections::BTreeMap;
+    use std::fs;
     use std::str::FromStr;
 
     #[test]
@@ -234,6 +413,8 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -245,6 +426,8 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -256,6 +439,8 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -267,6 +452,8 @@ mod tests {
                         rustdocflags: Some("-Zunstable-options -wjson".to_string()),
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -278,6 +465,8 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: Some("foo bar".to_string()),
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: Vec::new(),
                     });
 
@@ -289,6 +478,8 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: vec![CratePatch {
                             name: "example".to_string(),
                             repo: "https://git.example.com/some/repo".to_string(),
@@ -304,6 +495,8 @@ mod tests {
                         rustdocflags: None,
                         cargoflags: None,
                         ci_try: $ci_try,
+                        local: None,
+                        env: BTreeMap::new(),
                         patches: vec![CratePatch {
                             name: "example".to_string(),
                             repo: "https://git.example.com/some/repo".to_string(),
@@ -348,6 +541,23 @@ mod tests {
             },
         };
 
+        // Test local toolchains
+        test_from_str!("local#stage1+rustc=/build/stage1/bin/rustc+cargo=/build/cargo+target=wasm32-unknown-unknown" => Toolchain {
+            source: RustwideToolchain::dist("crater-local-stage1"),
+            target: Some("wasm32-unknown-unknown".to_string()),
+            rustflags: None,
+            rustdocflags: None,
+            cargoflags: None,
+            ci_try: false,
+            local: Some(LocalToolchain {
+                name: "stage1".to_string(),
+                rustc_path: "/build/stage1/bin/rustc".into(),
+                cargo_path: "/build/cargo".into(),
+            }),
+            env: BTreeMap::new(),
+            patches: Vec::new(),
+        });
+
         // Test invalid reprs
         assert!(Toolchain::from_str("").is_err());
         assert!(Toolchain::from_str("master#").is_err());
@@ -360,5 +570,95 @@ mod tests {
         assert!(Toolchain::from_str("stable+patch=").is_err());
         assert!(Toolchain::from_str("try#1234+target=").is_err());
         assert!(Toolchain::from_str("0000000000000000000000000000000000000000").is_err());
+        assert!(Toolchain::from_str("local#stage1+rustc=/build/stage1/bin/rustc").is_err());
+        assert!(Toolchain::from_str("stable+rustc=/build/stage1/bin/rustc").is_err());
+        assert!(Toolchain::from_str("stable+env=FOO").is_err());
+        assert!(Toolchain::from_str("stable+env==bar").is_err());
+    }
+
+    #[test]
+    fn test_env() {
+        let toolchain = Toolchain::from_str(
+            "nightly+rustflags=-Zfoo+env=RUSTC_BOOTSTRAP=1+env=REGISTRY_TOKEN=hunter2",
+        )
+        .unwrap();
+        assert_eq!(
+            toolchain.env,
+            BTreeMap::from([
+                ("REGISTRY_TOKEN".to_string(), "hunter2".to_string()),
+                ("RUSTC_BOOTSTRAP".to_string(), "1".to_string()),
+            ])
+        );
+        // The variables are part of the name, so they're recorded along with the experiment
+        assert_eq!(
+            Toolchain::from_str(&toolchain.to_string()).unwrap(),
+            toolchain
+        );
+
+        // While the secrets are redacted from the logs
+        assert_eq!(
+            toolchain.redacted(),
+            "nightly+rustflags=-Zfoo+env=REGISTRY_TOKEN=<redacted>+env=RUSTC_BOOTSTRAP=1"
+        );
+        assert_eq!(redact_env("GITHUB_TOKEN", "hunter2"), "<redacted>");
+        assert_eq!(redact_env("aws_secret", "hunter2"), "<redacted>");
+        assert_eq!(redact_env("CARGO_NET_OFFLINE", "true"), "true");
+        assert_eq!(redact_env("TOKENIZER_THREADS", "4"), "4");
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn test_local_toolchain() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let build = tempfile::tempdir().unwrap();
+        let sysroot = build.path().join("stage1");
+        fs::create_dir_all(sysroot.join("bin")).unwrap();
+        fs::create_dir_all(sysroot.join("lib").join("rustlib")).unwrap();
+        fs::write(sysroot.join("lib").join("librustc_driver.so"), "").unwrap();
+        for binary in [
+            sysroot.join("bin").join("rustc"),
+            build.path().join("cargo"),
+        ] {
+            fs::write(&binary, "#!/bin/sh\n").unwrap();
+            fs::set_permissions(&binary, fs::Permissions::from_mode(0o755)).unwrap();
+        }
+
+        let local = LocalToolchain {
+            name: "stage1".into(),
+            rustc_path: sysroot.join("bin").join("rustc"),
+            cargo_path: build.path().join("cargo"),
+        };
+        local.validate().unwrap();
+
+        let missing = LocalToolchain {
+            cargo_path: build.path().join("missing"),
+            ..local.clone()
+        };
+        assert!(missing.validate().is_err());
+
+        fs::set_permissions(&local.cargo_path, fs::Permissions::from_mode(0o644)).unwrap();
+        let err = local.validate().unwrap_err();
+        assert!(err.to_string().contains("is not an executable"));
+        fs::set_permissions(&local.cargo_path, fs::Permissions::from_mode(0o755)).unwrap();
+
+        // The runner builds with `cargo +crater-local-stage1`, which rustup resolves to the copy
+        let toolchain = Toolchain::from_str(&format!(
+            "local#stage1+rustc={}+cargo={}",
+            local.rustc_path.display(),
+            local.cargo_path.display()
+        ))
+        .unwrap();
+        assert_eq!(
+            toolchain.source.as_dist().unwrap().name(),
+            "crater-local-stage1"
+        );
+
+        let toolchains = tempfile::tempdir().unwrap();
+        local.install(toolchains.path()).unwrap();
+        let installed = toolchains.path().join("crater-local-stage1");
+        assert!(installed.join("bin").join("rustc").is_file());
+        assert!(installed.join("bin").join("cargo").is_file());
+        assert!(installed.join("lib").join("librustc_driver.so").is_file());
     }
 }
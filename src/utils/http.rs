@@ -1,9 +1,15 @@
 use crate::prelude::*;
+use chrono::{DateTime, Utc};
 use reqwest::blocking::{Client, ClientBuilder, RequestBuilder, Response};
+use reqwest::header::{HeaderMap, ETAG, IF_NONE_MATCH, RETRY_AFTER, USER_AGENT};
 use reqwest::redirect::Policy;
-use reqwest::{header::USER_AGENT, Method, StatusCode};
+use reqwest::{Method, StatusCode};
+use std::thread;
+use std::time::Duration;
 
 const MAX_REDIRECTS: usize = 4;
+const MAX_THROTTLED_ATTEMPTS: u32 = 5;
+const THROTTLED_DELAY: Duration = Duration::from_secs(5);
 
 #[derive(Debug, thiserror::Error)]
 #[error("request to {url} returned status code {status}")]
@@ -31,7 +37,83 @@ pub(crate) fn prepare_sync(method: Method, url: &str) -> RequestBuilder {
 
 pub(crate) fn get_sync(url: &str) -> Fallible<Response> {
     let resp = prepare_sync(Method::GET, url).send()?;
+    check_status(url, resp)
+}
+
+/// Delay requested by the `Retry-After` header, either as a number of seconds or as a date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    let value = headers.get(RETRY_AFTER)?.to_str().ok()?.trim();
+    if let Ok(secs) = value.parse::<u64>() {
+        return Some(Duration::from_secs(secs));
+    }
+    let date = DateTime::parse_from_rfc2822(value).ok()?;
+    Some(
+        (date.with_timezone(&Utc) - Utc::now())
+            .to_std()
+            .unwrap_or_default(),
+    )
+}
+
+/// Send the request created by `build`, sending it again after the delay asked by the server
+/// when it replies with 429 Too Many Requests.
+pub(crate) fn send_throttled<F: Fn() -> RequestBuilder>(build: F) -> Fallible<Response> {
+    let mut attempt = 1;
+    loop {
+        let resp = build().send()?;
+        if resp.status() != StatusCode::TOO_MANY_REQUESTS || attempt == MAX_THROTTLED_ATTEMPTS {
+            return Ok(resp);
+        }
 
+        let delay = retry_after(resp.headers()).unwrap_or(THROTTLED_DELAY * attempt);
+        warn!(
+            "request to {} throttled, retrying in {}s (attempt {})",
+            resp.url(),
+            delay.as_secs(),
+            attempt
+        );
+        thread::sleep(delay);
+        attempt += 1;
+    }
+}
+
+/// Response of a conditional request made by `get_sync_conditional`.
+pub(crate) enum Conditional {
+    /// The resource didn't change since it was fetched with the provided ETag.
+    NotModified,
+    Modified {
+        body: String,
+        etag: Option<String>,
+    },
+}
+
+/// Fetch `url` unless it still matches `etag`, giving up after `timeout`.
+pub(crate) fn get_sync_conditional(
+    url: &str,
+    etag: Option<&str>,
+    timeout: Duration,
+) -> Fallible<Conditional> {
+    let mut req = prepare_sync(Method::GET, url).timeout(timeout);
+    if let Some(etag) = etag {
+        req = req.header(IF_NONE_MATCH, etag);
+    }
+    let resp = req.send()?;
+    if etag.is_some() && resp.status() == StatusCode::NOT_MODIFIED {
+        return Ok(Conditional::NotModified);
+    }
+
+    let resp = check_status(url, resp)?;
+    let etag = resp
+        .headers()
+        .get(ETAG)
+        .and_then(|etag| etag.to_str().ok())
+        .map(String::from);
+    Ok(Conditional::Modified {
+        body: resp.text()?,
+        etag,
+    })
+}
+
+fn check_status(url: &str, resp: Response) -> Fallible<Response> {
     // Return an error if the response wasn't a 200 OK
     match resp.status() {
         StatusCode::OK => Ok(resp),
@@ -42,3 +124,32 @@ pub(crate) fn get_sync(url: &str) -> Fallible<Response> {
         .into()),
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::retry_after;
+    use reqwest::header::{HeaderMap, HeaderValue, RETRY_AFTER};
+    use std::time::Duration;
+
+    fn headers(retry: &str) -> HeaderMap {
+        let mut headers = HeaderMap::new();
+        headers.insert(RETRY_AFTER, HeaderValue::from_str(retry).unwrap());
+        headers
+    }
+
+    #[test]
+    fn test_retry_after() {
+        assert_eq!(retry_after(&HeaderMap::new()), None);
+        assert_eq!(retry_after(&headers("120")), Some(Duration::from_secs(120)));
+        assert_eq!(retry_after(&headers("soon")), None);
+
+        // Dates in the past don't delay the request
+        assert_eq!(
+            retry_after(&headers("Wed, 21 Oct 2015 07:28:00 GMT")),
+            Some(Duration::ZERO)
+        );
+        let later = (chrono::Utc::now() + chrono::Duration::seconds(60)).to_rfc2822();
+        let delay = retry_after(&headers(&later)).unwrap();
+        assert!(delay > Duration::from_secs(50) && delay <= Duration::from_secs(60));
+    }
+}
@@ -123,12 +123,30 @@ impl Compare for Reports {
                 {
                     // drop experiment field as it contains non deterministic values
                     map.remove("ex");
+                    // same for the build times
+                    map.remove("build_times");
                     Value::Object(map)
                 } else {
                     panic!("invalid json report");
                 }
             }
-            Self::Raw => serde_json::from_slice(&input).expect("invalid json report"),
+            Self::Raw => {
+                let mut parsed: Value =
+                    serde_json::from_slice(&input).expect("invalid json report");
+                // drop the build times as they're not deterministic
+                if let Some(Value::Array(crates)) = parsed.get_mut("crates") {
+                    for run in crates
+                        .iter_mut()
+                        .filter_map(|krate| krate.get_mut("runs")?.as_object_mut())
+                        .flat_map(|runs| runs.values_mut())
+                    {
+                        if let Value::Object(run) = run {
+                            run.remove("duration_ms");
+                        }
+                    }
+                }
+                parsed
+            }
         };
         let mut actual_report = serde_json::to_vec_pretty(&parsed_report).unwrap();
         actual_report.push(b'\n');